@@ -0,0 +1,268 @@
+use super::lexer::{parse, Token};
+
+// The operand stack of calculator functions holds at most 100 entries.
+const STACK_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    Number(f64),
+    Operator(String),
+    If(Vec<Instruction>),
+    IfElse(Vec<Instruction>, Vec<Instruction>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Number(f64),
+    Bool(bool),
+}
+
+// A PostScript calculator program, the body of a Type 4 function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+// Reads the instructions of a procedure up to its closing brace.
+fn read_block(tokens: &mut impl Iterator<Item = Token>) -> Option<Vec<Instruction>> {
+    let mut ret = Vec::new();
+    let mut blocks = Vec::new();
+    loop {
+        match tokens.next()? {
+            Token::ProcEnd => break,
+            Token::ProcStart => blocks.push(read_block(tokens)?),
+            Token::Number(n) => ret.push(Instruction::Number(n)),
+            Token::Operator(op) if op == "if" => {
+                let block = blocks.pop()?;
+                ret.push(Instruction::If(block));
+            }
+            Token::Operator(op) if op == "ifelse" => {
+                let (second, first) = (blocks.pop()?, blocks.pop()?);
+                ret.push(Instruction::IfElse(first, second));
+            }
+            Token::Operator(op) => ret.push(Instruction::Operator(op)),
+            _ => return None,
+        }
+    }
+    // Procedures only appear as operands of if and ifelse.
+    blocks.is_empty().then_some(ret)
+}
+
+fn pop_number(stack: &mut Vec<Operand>) -> Option<f64> {
+    match stack.pop()? {
+        Operand::Number(n) => Some(n),
+        Operand::Bool(_) => None,
+    }
+}
+
+fn pop_int(stack: &mut Vec<Operand>) -> Option<i64> {
+    pop_number(stack).map(|x| x as i64)
+}
+
+fn pop_bool(stack: &mut Vec<Operand>) -> Option<bool> {
+    match stack.pop()? {
+        Operand::Bool(x) => Some(x),
+        Operand::Number(_) => None,
+    }
+}
+
+fn execute(instructions: &[Instruction], stack: &mut Vec<Operand>) -> Option<()> {
+    use Operand::{Bool, Number};
+    for instruction in instructions {
+        let op = match instruction {
+            Instruction::Number(n) => {
+                stack.push(Number(*n));
+                if stack.len() > STACK_LIMIT {
+                    return None;
+                }
+                continue;
+            }
+            Instruction::If(block) => {
+                if pop_bool(stack)? {
+                    execute(block, stack)?;
+                }
+                continue;
+            }
+            Instruction::IfElse(first, second) => {
+                execute(if pop_bool(stack)? { first } else { second }, stack)?;
+                continue;
+            }
+            Instruction::Operator(op) => op.as_str(),
+        };
+        match op {
+            "abs" | "neg" | "ceiling" | "floor" | "round" | "truncate" | "sqrt" | "sin" | "cos" | "ln" | "log" | "cvi" | "cvr" => {
+                let x = pop_number(stack)?;
+                stack.push(Number(match op {
+                    "abs" => x.abs(),
+                    "neg" => -x,
+                    "ceiling" => x.ceil(),
+                    "floor" => x.floor(),
+                    // Halfway values round up, unlike f64::round.
+                    "round" => (x + 0.5).floor(),
+                    "truncate" | "cvi" => x.trunc(),
+                    "sqrt" => x.sqrt(),
+                    // Angles are in degrees.
+                    "sin" => x.to_radians().sin(),
+                    "cos" => x.to_radians().cos(),
+                    "ln" => x.ln(),
+                    "log" => x.log10(),
+                    _ => x,
+                }));
+            }
+            "add" | "sub" | "mul" | "div" | "exp" | "atan" => {
+                let (b, a) = (pop_number(stack)?, pop_number(stack)?);
+                stack.push(Number(match op {
+                    "add" => a + b,
+                    "sub" => a - b,
+                    "mul" => a * b,
+                    "div" if b != 0. => a / b,
+                    "exp" => a.powf(b),
+                    "atan" => {
+                        let angle = a.atan2(b).to_degrees();
+                        if angle < 0. { angle + 360. } else { angle }
+                    }
+                    _ => return None,
+                }));
+            }
+            "idiv" | "mod" | "bitshift" => {
+                let (b, a) = (pop_int(stack)?, pop_int(stack)?);
+                stack.push(Number(match op {
+                    "idiv" => a.checked_div(b)?,
+                    "mod" => a.checked_rem(b)?,
+                    _ if b >= 0 => a.checked_shl(b as u32).unwrap_or(0),
+                    _ => a.checked_shr(b.unsigned_abs() as u32).unwrap_or(0),
+                } as f64));
+            }
+            "and" | "or" | "xor" => match (stack.pop()?, stack.pop()?) {
+                (Bool(b), Bool(a)) => stack.push(Bool(match op {
+                    "and" => a && b,
+                    "or" => a || b,
+                    _ => a != b,
+                })),
+                (Number(b), Number(a)) => {
+                    let (a, b) = (a as i64, b as i64);
+                    stack.push(Number(match op {
+                        "and" => a & b,
+                        "or" => a | b,
+                        _ => a ^ b,
+                    } as f64));
+                }
+                _ => return None,
+            },
+            "not" => match stack.pop()? {
+                Bool(x) => stack.push(Bool(!x)),
+                Number(x) => stack.push(Number(!(x as i64) as f64)),
+            },
+            "eq" | "ne" => {
+                let (b, a) = (stack.pop()?, stack.pop()?);
+                stack.push(Bool((a == b) == (op == "eq")));
+            }
+            "gt" | "ge" | "lt" | "le" => {
+                let (b, a) = (pop_number(stack)?, pop_number(stack)?);
+                stack.push(Bool(match op {
+                    "gt" => a > b,
+                    "ge" => a >= b,
+                    "lt" => a < b,
+                    _ => a <= b,
+                }));
+            }
+            "true" => stack.push(Bool(true)),
+            "false" => stack.push(Bool(false)),
+            "pop" => {
+                stack.pop()?;
+            }
+            "dup" => stack.push(*stack.last()?),
+            "exch" => {
+                let n = stack.len();
+                if n < 2 {
+                    return None;
+                }
+                stack.swap(n - 1, n - 2);
+            }
+            "copy" => {
+                let n = pop_int(stack)?;
+                let start = stack.len().checked_sub(usize::try_from(n).ok()?)?;
+                stack.extend_from_within(start..);
+            }
+            "index" => {
+                let n = usize::try_from(pop_int(stack)?).ok()?;
+                let i = stack.len().checked_sub(n + 1)?;
+                stack.push(stack[i]);
+            }
+            "roll" => {
+                let (j, n) = (pop_int(stack)?, usize::try_from(pop_int(stack)?).ok()?);
+                let start = stack.len().checked_sub(n)?;
+                if n > 0 {
+                    // Positive amounts roll towards the top of the stack.
+                    stack[start..].rotate_right(j.rem_euclid(n as i64) as usize);
+                }
+            }
+            _ => return None,
+        }
+        if stack.len() > STACK_LIMIT {
+            return None;
+        }
+    }
+    Some(())
+}
+
+impl Program {
+    // Parses a program enclosed in braces.
+    pub fn parse(src: &[u8]) -> Option<Program> {
+        let mut tokens = parse(src);
+        if tokens.next()? != Token::ProcStart {
+            return None;
+        }
+        let instructions = read_block(&mut tokens)?;
+        Some(Program { instructions })
+    }
+
+    // Runs the program with `input` on the stack, returning the stack afterwards.
+    pub fn run(&self, input: &[f64]) -> Option<Vec<f64>> {
+        let mut stack = input.iter().map(|&x| Operand::Number(x)).collect();
+        execute(&self.instructions, &mut stack)?;
+        stack.into_iter().map(|x| match x {
+            Operand::Number(n) => Some(n),
+            Operand::Bool(_) => None,
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src: &[u8], input: &[f64]) -> Option<Vec<f64>> {
+        Program::parse(src)?.run(input)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(run(b"{ 2 mul 1 add }", &[0.25]), Some(vec![1.5]));
+        assert_eq!(run(b"{ exch sub }", &[1., 3.]), Some(vec![2.]));
+        assert_eq!(run(b"{ 7 2 idiv 7 2 mod -2.5 round 1 3 bitshift }", &[]), Some(vec![3., 1., -2., 8.]));
+        assert_eq!(run(b"{ 0 1 atan 90 sin 2 3 exp }", &[]), Some(vec![0., 1., 8.]));
+        assert_eq!(run(b"{ 1 0 div }", &[]), None);
+    }
+
+    #[test]
+    fn test_stack() {
+        assert_eq!(run(b"{ 1 index 2 copy }", &[1., 2.]), Some(vec![1., 2., 1., 2., 1.]));
+        assert_eq!(run(b"{ 3 1 roll }", &[1., 2., 3.]), Some(vec![3., 1., 2.]));
+        assert_eq!(run(b"{ 3 -1 roll pop dup }", &[1., 2., 3.]), Some(vec![2., 3., 3.]));
+        assert_eq!(run(b"{ pop pop }", &[1.]), None);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        // A tint transform from one colorant to CMYK, clamped at one half.
+        let program = b"{ dup 0.5 gt { pop 0.5 } if 0 exch 0 0 }";
+        assert_eq!(run(program, &[0.75]), Some(vec![0., 0.5, 0., 0.]));
+        assert_eq!(run(program, &[0.25]), Some(vec![0., 0.25, 0., 0.]));
+        let program = b"{ 0.5 lt { 1 } { 2 0.5 ge not { 3 } { 4 } ifelse } ifelse }";
+        assert_eq!(run(program, &[0.]), Some(vec![1.]));
+        assert_eq!(run(program, &[1.]), Some(vec![4.]));
+        assert_eq!(run(b"{ true false xor 1 2 eq or { 1 } if }", &[]), Some(vec![1.]));
+        assert_eq!(run(b"{ { 1 } }", &[]), None);
+    }
+}
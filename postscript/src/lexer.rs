@@ -12,6 +12,9 @@ pub enum Token {
     DictEnd,
     ListStart,
     ListEnd,
+    // Braces around PostScript procedures, as in Type 4 functions.
+    ProcStart,
+    ProcEnd,
     StreamStart,
     StreamEnd,
     ObjectStart((usize, usize)),
@@ -19,6 +22,93 @@ pub enum Token {
     Ref((usize, usize)),
     Number(f64),
     XRef,
+    InlineImage(InlineImage),
+    // The bytes read by RD or -| in Type 1 fonts, as charstrings are.
+    Binary(Vec<u8>),
+}
+
+// A BI ... ID ... EI inline image, with abbreviated keys and names expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineImage {
+    pub dict: Vec<(String, Vec<Token>)>,
+    pub data: Vec<u8>,
+}
+
+const INLINE_KEYS: &[(&str, &str)] = &[
+    ("BPC", "BitsPerComponent"),
+    ("CS", "ColorSpace"),
+    ("D", "Decode"),
+    ("DP", "DecodeParms"),
+    ("F", "Filter"),
+    ("H", "Height"),
+    ("IM", "ImageMask"),
+    ("I", "Interpolate"),
+    ("L", "Length"),
+    ("W", "Width"),
+];
+
+const INLINE_NAMES: &[(&str, &str)] = &[
+    ("G", "DeviceGray"),
+    ("RGB", "DeviceRGB"),
+    ("CMYK", "DeviceCMYK"),
+    ("I", "Indexed"),
+    ("AHx", "ASCIIHexDecode"),
+    ("A85", "ASCII85Decode"),
+    ("LZW", "LZWDecode"),
+    ("Fl", "FlateDecode"),
+    ("RL", "RunLengthDecode"),
+    ("CCF", "CCITTFaxDecode"),
+    ("DCT", "DCTDecode"),
+];
+
+pub fn expand_inline_key(key: &str) -> &str {
+    INLINE_KEYS.iter().find(|x| x.0 == key).map_or(key, |x| x.1)
+}
+
+pub fn abbreviate_inline_key(key: &str) -> &str {
+    INLINE_KEYS.iter().find(|x| x.1 == key).map_or(key, |x| x.0)
+}
+
+pub fn abbreviate_inline_name(name: &str) -> &str {
+    INLINE_NAMES.iter().find(|x| x.1 == name).map_or(name, |x| x.0)
+}
+
+impl InlineImage {
+    pub fn get(&self, key: &str) -> Option<&[Token]> {
+        self.dict.iter().find(|x| x.0 == key).map(|x| &x.1[..])
+    }
+
+    fn number(&self, key: &str) -> Option<usize> {
+        match self.get(key)? {
+            [Token::Number(n)] if *n >= 0. => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    // The data length stated by /Length, or implied by the dimensions of unfiltered data.
+    fn expected_length(&self) -> Option<usize> {
+        if let Some(length) = self.number("Length") {
+            return Some(length);
+        }
+        if self.get("Filter").is_some_and(|x| !x.is_empty() && x != [Token::ListStart, Token::ListEnd]) {
+            return None;
+        }
+        let mask = self.get("ImageMask") == Some(&[Token::Operator("true".into())]);
+        let components = match self.get("ColorSpace") {
+            _ if mask => 1,
+            Some([Token::Key(name)]) => match name.as_str() {
+                "DeviceGray" | "CalGray" | "Indexed" => 1,
+                "DeviceRGB" | "CalRGB" | "Lab" => 3,
+                "DeviceCMYK" => 4,
+                _ => return None,
+            },
+            Some([Token::ListStart, Token::Key(name), ..]) if name == "Indexed" => 1,
+            _ => return None,
+        };
+        let bpc = if mask { 1 } else { self.number("BitsPerComponent")? };
+        let (width, height) = (self.number("Width")?, self.number("Height")?);
+        Some((width * components * bpc).div_ceil(8) * height)
+    }
 }
 
 impl fmt::Debug for Token {
@@ -32,6 +122,8 @@ impl fmt::Debug for Token {
             DictEnd => write!(f, "DictEnd"),
             ListStart => write!(f, "ListStart"),
             ListEnd => write!(f, "ListEnd"),
+            ProcStart => write!(f, "{{"),
+            ProcEnd => write!(f, "}}"),
             StreamStart => write!(f, "StreamStart"),
             StreamEnd => write!(f, "StreamEnd"),
             ObjectStart(id) => write!(f, "ObjectStart{id:?}"),
@@ -40,6 +132,8 @@ impl fmt::Debug for Token {
             Number(n) => write!(f, "Number({n})"),
             XRef => write!(f, "xref"),
             Operator(s) => write!(f, "{s}"),
+            InlineImage(image) => write!(f, "InlineImage({:?}, {} bytes)", image.dict, image.data.len()),
+            Binary(data) => write!(f, "Binary({} bytes)", data.len()),
         }
     }
 }
@@ -71,6 +165,9 @@ pub struct State {
     comments: Vec<(usize, Vec<u8>)>,
     usize_stack: VecDeque<usize>,
     tokens_waiting: VecDeque<Token>,
+    inline_image_offset: usize,
+    // The number before the last token, the length of the data RD reads.
+    last_number: Option<f64>,
 }
 
 pub fn parse(src: &[u8]) -> State {
@@ -101,6 +198,99 @@ impl State {
             }
         }
     }
+    // Offset of the BI operator of the last inline image read.
+    pub fn inline_image_offset(&self) -> usize {
+        self.inline_image_offset
+    }
+    // Reads an inline image once its BI operator has been lexed, up to and including EI.
+    fn read_inline_image(&mut self) -> Token {
+        let mut dict = Vec::new();
+        let mut key = None;
+        let mut value = Vec::new();
+        let mut depth = 0;
+        while let Some(token) = self.get_next_token() {
+            match token {
+                Token::Operator(op) if op == "ID" => break,
+                Token::Key(k) if depth == 0 && key.is_none() => key = Some(expand_inline_key(&k).to_string()),
+                token => {
+                    match token {
+                        Token::ListStart | Token::DictStart => depth += 1,
+                        Token::ListEnd | Token::DictEnd => depth -= 1,
+                        _ => {}
+                    }
+                    value.push(token);
+                    if depth <= 0 {
+                        if let Some(key) = key.take() {
+                            if key == "ColorSpace" || key == "Filter" {
+                                for x in value.iter_mut() {
+                                    if let Token::Key(name) = x {
+                                        if let Some(expanded) = INLINE_NAMES.iter().find(|x| x.0 == name) {
+                                            *name = expanded.1.into();
+                                        }
+                                    }
+                                }
+                            }
+                            dict.push((key, std::mem::take(&mut value)));
+                        }
+                        value.clear();
+                        depth = 0;
+                    }
+                }
+            }
+        }
+        let mut image = InlineImage { dict, data: Vec::new() };
+        // A single whitespace byte separates ID from the data.
+        let start = (self.index + 1).min(self.store.len());
+        let is_boundary = |x: Option<&u8>| x.is_none_or(|x| x.is_ascii_whitespace() || DELIMITERS.contains(x));
+        let is_end = |store: &[u8], i: usize| {
+            store[i ..].starts_with(b"EI") && is_boundary(store.get(i + 2))
+        };
+        let skip_whitespace = |store: &[u8], mut i: usize| {
+            while store.get(i).is_some_and(|x| x.is_ascii_whitespace()) {
+                i += 1;
+            }
+            i
+        };
+        let end = image.expected_length()
+            .map(|x| (start + x).min(self.store.len()))
+            .filter(|&x| is_end(&self.store, skip_whitespace(&self.store, x)))
+            .or_else(|| {
+                // EI between whitespace, followed by something that lexes as ASCII.
+                (start .. self.store.len()).find(|&i| {
+                    self.store[i - 1].is_ascii_whitespace() && is_end(&self.store, i)
+                        && self.store[i + 2 ..].iter().take(32).all(|x| x.is_ascii())
+                }).map(|i| i - 1)
+            })
+            .unwrap_or(self.store.len());
+        image.data = self.store[start .. end].to_vec();
+        self.index = skip_whitespace(&self.store, end);
+        if is_end(&self.store, self.index) {
+            self.index += 2;
+        }
+        Token::InlineImage(image)
+    }
+    // Reads the binary data of RD, separated from it by a single space.
+    fn read_binary(&mut self) -> Token {
+        let length = self.last_number.take().unwrap_or(0.).max(0.) as usize;
+        let start = (self.index + 1).min(self.store.len());
+        self.index = (start + length).min(self.store.len());
+        Token::Binary(self.store[start .. self.index].to_vec())
+    }
+    // Decrypts the rest of the data once eexec has been lexed, in binary or in hex.
+    fn read_eexec(&mut self) {
+        while self.store.get(self.index).is_some_and(|x| x.is_ascii_whitespace()) {
+            self.index += 1;
+        }
+        let encrypted = &self.store[self.index ..];
+        let hex = encrypted.iter().take(4).all(|x| x.is_ascii_hexdigit());
+        let mut plain = if hex { decrypt(&from_hex(encrypted), EEXEC_KEY, 4) } else { decrypt(encrypted, EEXEC_KEY, 4) };
+        // The encrypted part ends by closing the file, before the zeros padding it.
+        if let Some(end) = plain.windows(9).position(|x| x == b"closefile") {
+            plain.truncate(end + 9);
+        }
+        self.store.truncate(self.index);
+        self.store.extend(plain);
+    }
     pub fn swallow(&mut self, t: Token) {
         self.tokens_waiting.push_front(t);
     }
@@ -110,7 +300,7 @@ impl State {
         enum Ctx {
             Comment(usize, Vec<u8>),
             Key(usize, String),
-            String(usize, Vec<u8>),
+            String(usize, Vec<u8>, usize),
             None,
         }
 
@@ -144,17 +334,18 @@ impl State {
 
             let byte = curr[0];
 
-            if byte == b'<' && Some(&b'<') != curr.get(1) {
+            if byte == b'<' && Some(&b'<') != curr.get(1) && matches!(curr_ctx, Ctx::None) {
                 let mut data = Vec::new();
                 let mut factor = 0;
+                let mut digits = 0;
                 for i in 1 .. curr.len() {
                     let b = curr[i];
                     if b == b'>' {
-                        if factor == 0 {
-                            token.replace(Token::BytesLiteral(data));
-                            return i + 1;
+                        if digits % 2 == 1 {
+                            data.push(factor * 16);
                         }
-                        return 0;
+                        token.replace(Token::BytesLiteral(data));
+                        return i + 1;
                     }
                     if b >= b'0' && b <= b'9' {
                         factor = factor * 16 + (b - b'0');
@@ -165,10 +356,14 @@ impl State {
                     else if b >= b'A' && b <= b'F' {
                         factor = factor * 16 + (b - b'A' + 10);
                     }
+                    else if b.is_ascii_whitespace() {
+                        continue;
+                    }
                     else {
                         return 0;
                     }
-                    if i % 2 == 0 {
+                    digits += 1;
+                    if digits % 2 == 0 {
                         data.push(factor);
                         factor = 0;
                     }
@@ -181,18 +376,27 @@ impl State {
                     return 1;
                 }
                 ctx @ &mut Ctx::Comment(..) => prev_ctx = take(ctx),
-                &mut Ctx::String(_, ref mut string_content) if byte != b')' => {
+                &mut Ctx::String(_, ref mut string_content, ref mut depth) if byte != b')' || *depth > 0 => {
+                    match byte {
+                        b'(' => *depth += 1,
+                        b')' => *depth -= 1,
+                        b'\\' => {
+                            let (len, escaped) = unescape(&curr[1..]);
+                            string_content.extend(escaped);
+                            return 1 + len;
+                        }
+                        _ => {}
+                    }
                     string_content.push(byte);
                     return 1;
                 }
                 ctx @ &mut Ctx::String(..) => prev_ctx = take(ctx),
-                &mut Ctx::Key(_, ref mut key_content) if !byte.is_ascii_whitespace() && curr.len() > 1 => {
-                    if curr.len() == 2 {
-                        key_content.push(curr[0] as char);
-                        key_content.push(curr[1] as char);
+                &mut Ctx::Key(_, ref mut key_content) if !byte.is_ascii_whitespace() && !DELIMITERS.contains(&byte) => {
+                    key_content.push(byte as char);
+                    if curr.len() > 1 {
                         return 1;
                     }
-                    key_content.push(byte as char);
+                    token.replace(Token::Key(take(key_content)));
                     return 1;
                 }
                 ctx @ &mut Ctx::Key(..) => prev_ctx = take(ctx),
@@ -204,18 +408,21 @@ impl State {
                     comments.push((base, comment_content));
                     return 1;
                 }
-                Ctx::String(_, string_content) => {
+                Ctx::String(_, string_content, _) => {
                     token.replace(Token::StringLiteral(string_content));
                     return 1;
                 }
                 Ctx::Key(_, key_content) => {
                     token.replace(Token::Key(key_content));
-                    return 1;
+                    return if byte.is_ascii_whitespace() { 1 } else { 0 };
                 }
                 Ctx::None => {}
             }
 
-            if !byte.is_ascii() { panic!("non-ascii, index {index}"); }
+            // Binary data outside of strings, as after an encrypted section, ends the tokens.
+            if !byte.is_ascii() {
+                return 0;
+            }
             let c = curr[0] as char;
             
             if c == '%' {
@@ -224,7 +431,7 @@ impl State {
             }
 
             if c == '(' {
-                curr_ctx = Ctx::String(index, Vec::new());
+                curr_ctx = Ctx::String(index, Vec::new(), 0);
                 return 1;
             }
 
@@ -243,12 +450,27 @@ impl State {
                 return 1;
             }
 
+            if c == '{' {
+                token.replace(Token::ProcStart);
+                return 1;
+            }
+
             if curr.starts_with(b"\nstream\n") {
                 token.replace(Token::StreamStart);
                 return "\nstream\n".len();
             }
 
-            if c == '-' {
+            // The -| and |- abbreviations of RD and ND in Type 1 fonts, and | for NP.
+            if c == '|' || curr.starts_with(b"-|") {
+                let len = curr.iter().take_while(|&&x| x == b'|' || x == b'-').count();
+                while !usize_stack.is_empty() {
+                    tokens_waiting.push_back(Token::Number(usize_stack.pop_front().unwrap() as _));
+                }
+                token.replace(Token::Operator(String::from_utf8_lossy(&curr[.. len]).into()));
+                return len;
+            }
+
+            if c == '-' || c == '.' {
                 let (len, n) = if let Some((len, n)) = parse_number(curr) {
                     (len, n)
                 } else {
@@ -323,7 +545,8 @@ impl State {
                 return 0;
             }
 
-            if curr.starts_with(b"R") {
+            // R alone, not the start of an operator like RG.
+            if curr.starts_with(b"R") && curr.get(1).is_none_or(|x| x.is_ascii_whitespace() || DELIMITERS.contains(x)) {
                 if usize_stack.len() >= 2 {
                     while usize_stack.len() > 2 {
                         tokens_waiting.push_back(Token::Number(usize_stack.pop_front().unwrap() as _));
@@ -350,6 +573,14 @@ impl State {
                 return 1;
             }
 
+            if c == '}' {
+                while !usize_stack.is_empty() {
+                    tokens_waiting.push_back(Token::Number(usize_stack.pop_front().unwrap() as _));
+                }
+                tokens_waiting.push_back(Token::ProcEnd);
+                return 1;
+            }
+
             if curr.starts_with(b">>") {
                 while !usize_stack.is_empty() {
                     tokens_waiting.push_back(Token::Number(usize_stack.pop_front().unwrap() as _));
@@ -368,12 +599,17 @@ impl State {
                 return b"xref\n".len();
             }
             
-            if c.is_whitespace() {
+            if c.is_whitespace() || c == '\0' {
+                return 1;
+            }
+
+            if c == '\'' || c == '"' {
+                token.replace(Token::Operator(c.into()));
                 return 1;
             }
 
             if c.is_alphabetic() {
-                let slice = curr.iter().cloned().take_while(|x| x.is_ascii_alphabetic());
+                let slice = curr.iter().cloned().take_while(|x| x.is_ascii_alphanumeric() || *x == b'*');
 
                 let operator = String::from_utf8(slice.collect()).unwrap();
                 let len = operator.len();
@@ -394,6 +630,9 @@ impl State {
             let step = proc(curr, &mut token, &mut self.comments, self.index, &mut self.tokens_waiting, &mut self.usize_stack);
             self.index += step;
             if let Some(token) = token {
+                while let Some(x) = self.usize_stack.pop_front() {
+                    self.tokens_waiting.push_back(Token::Number(x as _));
+                }
                 return match self.pop_stacks() {
                     None => Some(token),
                     Some(x) => {
@@ -455,6 +694,49 @@ impl State {
     }
 }
 
+const DELIMITERS: &[u8] = b"()<>[]{}/%";
+
+const EEXEC_KEY: u16 = 55665;
+
+// Type 1 decryption, dropping the `skip` random bytes the plaintext starts with.
+pub fn decrypt(data: &[u8], key: u16, skip: usize) -> Vec<u8> {
+    let mut r = key;
+    let mut ret = Vec::with_capacity(data.len());
+    for &c in data {
+        ret.push(c ^ (r >> 8) as u8);
+        r = (c as u16).wrapping_add(r).wrapping_mul(52845).wrapping_add(22719);
+    }
+    ret.split_off(skip.min(ret.len()))
+}
+
+fn from_hex(data: &[u8]) -> Vec<u8> {
+    let digits = data.iter().filter_map(|&x| (x as char).to_digit(16)).collect::<Vec<_>>();
+    digits.chunks(2).map(|x| (x[0] * 16 + x.get(1).unwrap_or(&0)) as u8).collect()
+}
+
+fn unescape(src: &[u8]) -> (usize, Option<u8>) {
+    let byte = if let Some(&x) = src.first() {
+        x
+    } else {
+        return (0, None);
+    };
+    match byte {
+        b'n' => (1, Some(b'\n')),
+        b'r' => (1, Some(b'\r')),
+        b't' => (1, Some(b'\t')),
+        b'b' => (1, Some(8)),
+        b'f' => (1, Some(12)),
+        b'\r' if src.get(1) == Some(&b'\n') => (2, None),
+        b'\r' | b'\n' => (1, None),
+        b'0' ..= b'7' => {
+            let len = src.iter().take(3).take_while(|x| (b'0' ..= b'7').contains(x)).count();
+            let n = src[..len].iter().fold(0u32, |n, x| n * 8 + (x - b'0') as u32);
+            (len, Some(n as u8))
+        }
+        x => (1, Some(x)),
+    }
+}
+
 fn parse_number(src: &[u8]) -> Option<(usize, f64)> {
     let len = src.iter().position(|x| x != &b'.' && x != &b'-' && !x.is_ascii_digit()).unwrap_or(src.len());
     src[..len].iter().map(|&x| x as char).collect::<String>().parse().ok().map(|x| (len, x))
@@ -463,7 +745,21 @@ fn parse_number(src: &[u8]) -> Option<(usize, f64)> {
 impl Iterator for State {
     type Item = Token;
     fn next(&mut self) -> Option<Token> {
-        self.get_next_token()
+        match self.get_next_token()? {
+            Token::Operator(op) if op == "BI" => {
+                self.inline_image_offset = self.index - 2;
+                Some(self.read_inline_image())
+            }
+            Token::Operator(op) if op == "RD" || op == "-|" => Some(self.read_binary()),
+            Token::Operator(op) if op == "eexec" => {
+                self.read_eexec();
+                Some(Token::Operator(op))
+            }
+            token => {
+                self.last_number = if let Token::Number(n) = token { Some(n) } else { None };
+                Some(token)
+            }
+        }
     }
 }
 
@@ -495,6 +791,103 @@ mod tests {
     fn test_bytestring() {
         let mut state = parse(b"<200d0a>");
         assert_eq!(&state.next().unwrap(), " \r\n");
+        let mut state = parse(b"<20 0d 0>");
+        assert_eq!(&state.next().unwrap(), " \r\0");
+        let mut state = parse(b"<4F60>");
+        assert_eq!(state.next().unwrap(), BytesLiteral(vec![0x4f, 0x60]));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let mut state = parse(br"(a\(b\)c) (\050NIPS\051) (x(y)z) (\101\
+B)");
+        assert_eq!(&state.next().unwrap(), "a(b)c");
+        assert_eq!(&state.next().unwrap(), "(NIPS)");
+        assert_eq!(&state.next().unwrap(), "x(y)z");
+        assert_eq!(&state.next().unwrap(), "AB");
+    }
+
+    #[test]
+    fn test_operators() {
+        let state = parse(b"T* (a) ' 1 2 (b) \" 0 0 d0 /Span<</MCID 0>>BDC .5 w 1 0 0 RG");
+        let list = helper![
+            Operator ["T*"]
+            StringLiteral ["a"]
+            Operator ["'"]
+            Number [1, 2]
+            StringLiteral ["b"]
+            Operator ["\""]
+            Number [0, 0]
+            Operator ["d0"]
+            Key ["Span"]
+            DictStart $
+            Key ["MCID"]
+            Number [0]
+            DictEnd $
+            Operator ["BDC"]
+            Number [0.5]
+            Operator ["w"]
+            Number [1, 0, 0]
+            Operator ["RG"]
+        ];
+        assert_eq!(state.collect::<Vec<Token>>(), list);
+    }
+
+    #[test]
+    fn test_procedures() {
+        let state = parse(b"{ 2 index 1{0}if }");
+        let list = helper![
+            ProcStart $
+            Number [2]
+            Operator ["index"]
+            Number [1]
+            ProcStart $
+            Number [0]
+            ProcEnd $
+            Operator ["if"]
+            ProcEnd $
+        ];
+        assert_eq!(state.collect::<Vec<Token>>(), list);
+    }
+
+    #[test]
+    fn test_type1_binary() {
+        let state = parse(b"/a 3 RD \xff{) ND dup 0 2 -| \x80\x81 | /b 1 RD }  |-");
+        let list = vec![
+            Key("a".into()), Number(3.), Binary(b"\xff{)".to_vec()), Operator("ND".into()),
+            Operator("dup".into()), Number(0.), Number(2.), Binary(b"\x80\x81".to_vec()), Operator("|".into()),
+            Key("b".into()), Number(1.), Binary(b"}".to_vec()), Operator("|-".into()),
+        ];
+        assert_eq!(state.collect::<Vec<Token>>(), list);
+        // The rest of the data is decrypted after eexec, here in hex.
+        let mut data = b"currentfile eexec\n".to_vec();
+        let mut r = 55665u16;
+        for &p in b"abcd/Private closefile\x80" {
+            let c = p ^ (r >> 8) as u8;
+            r = (c as u16).wrapping_add(r).wrapping_mul(52845).wrapping_add(22719);
+            data.extend(format!("{c:02x}").bytes());
+        }
+        let list = vec![Operator("currentfile".into()), Operator("eexec".into()), Key("Private".into()), Operator("closefile".into())];
+        assert_eq!(parse(&data).collect::<Vec<Token>>(), list);
+    }
+
+    #[test]
+    fn test_inline_image() {
+        let image = |src: &[u8]| match parse(src).collect::<Vec<_>>()[..] {
+            [Operator(ref q), InlineImage(ref image), Operator(ref w)] if q == "q" && w == "Q" => image.clone(),
+            ref x => panic!("unexpected tokens {x:?}"),
+        };
+        // The length follows from the dimensions, even with EI inside the data.
+        let x = image(b"q BI /W 3 /H 2 /BPC 8 /CS /G ID \x00 EI \xffEI Q");
+        assert_eq!(x.data, b"\x00 EI \xff");
+        assert_eq!(x.get("ColorSpace"), Some(&[Key("DeviceGray".into())][..]));
+        assert_eq!(x.get("Width"), Some(&[Number(3.)][..]));
+        // /L takes precedence over the dimensions.
+        assert_eq!(image(b"q BI /W 9 /H 9 /BPC 8 /CS /RGB /L 2 ID ab\nEI Q").data, b"ab");
+        // Filtered data is ended by the first EI that looks like one.
+        let x = image(b"q BI /W 1 /H 1 /F [/AHx /Fl] ID \xffEI\x80 EI\xff EI\nQ");
+        assert_eq!(x.data, b"\xffEI\x80 EI\xff");
+        assert_eq!(x.get("Filter"), Some(&[ListStart, Key("ASCIIHexDecode".into()), Key("FlateDecode".into()), ListEnd][..]));
     }
 
     #[test]
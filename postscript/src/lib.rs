@@ -1,2 +1,4 @@
+pub mod calculator;
 pub mod lexer;
 pub mod parser;
+pub mod type1;
@@ -0,0 +1,132 @@
+use super::types::{Operand, Type, ARRAY, FLOAT, INTEGER, KEY, STRING};
+
+// Operand signature of a content stream operator. With `variadic` set, the
+// last operand type repeats one or more times.
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub operands: &'static [Type],
+    pub variadic: bool,
+}
+
+const fn fixed(name: &'static str, operands: &'static [Type]) -> Builtin {
+    Builtin { name, operands, variadic: false }
+}
+
+const fn variadic(name: &'static str, operands: &'static [Type]) -> Builtin {
+    Builtin { name, operands, variadic: true }
+}
+
+// Marked content properties: a resource name or an inline dictionary.
+const NAME_OR_DICT: Type = Type::Any;
+
+pub const BUILTINS: &[Builtin] = &[
+    // General graphics state
+    fixed("w", &[FLOAT]),
+    fixed("J", &[INTEGER]),
+    fixed("j", &[INTEGER]),
+    fixed("M", &[FLOAT]),
+    fixed("d", &[ARRAY, FLOAT]),
+    fixed("ri", &[KEY]),
+    fixed("i", &[FLOAT]),
+    fixed("gs", &[KEY]),
+    // Special graphics state
+    fixed("q", &[]),
+    fixed("Q", &[]),
+    fixed("cm", &[FLOAT, FLOAT, FLOAT, FLOAT, FLOAT, FLOAT]),
+    // Path construction
+    fixed("m", &[FLOAT, FLOAT]),
+    fixed("l", &[FLOAT, FLOAT]),
+    fixed("c", &[FLOAT, FLOAT, FLOAT, FLOAT, FLOAT, FLOAT]),
+    fixed("v", &[FLOAT, FLOAT, FLOAT, FLOAT]),
+    fixed("y", &[FLOAT, FLOAT, FLOAT, FLOAT]),
+    fixed("h", &[]),
+    fixed("re", &[FLOAT, FLOAT, FLOAT, FLOAT]),
+    // Path painting
+    fixed("S", &[]),
+    fixed("s", &[]),
+    fixed("f", &[]),
+    fixed("F", &[]),
+    fixed("f*", &[]),
+    fixed("B", &[]),
+    fixed("B*", &[]),
+    fixed("b", &[]),
+    fixed("b*", &[]),
+    fixed("n", &[]),
+    // Clipping paths
+    fixed("W", &[]),
+    fixed("W*", &[]),
+    // Text objects
+    fixed("BT", &[]),
+    fixed("ET", &[]),
+    // Text state
+    fixed("Tc", &[FLOAT]),
+    fixed("Tw", &[FLOAT]),
+    fixed("Tz", &[FLOAT]),
+    fixed("TL", &[FLOAT]),
+    fixed("Tf", &[KEY, FLOAT]),
+    fixed("Tr", &[INTEGER]),
+    fixed("Ts", &[FLOAT]),
+    // Text positioning
+    fixed("Td", &[FLOAT, FLOAT]),
+    fixed("TD", &[FLOAT, FLOAT]),
+    fixed("Tm", &[FLOAT, FLOAT, FLOAT, FLOAT, FLOAT, FLOAT]),
+    fixed("T*", &[]),
+    // Text showing
+    fixed("Tj", &[STRING]),
+    fixed("TJ", &[ARRAY]),
+    fixed("'", &[STRING]),
+    fixed("\"", &[FLOAT, FLOAT, STRING]),
+    // Type 3 fonts
+    fixed("d0", &[FLOAT, FLOAT]),
+    fixed("d1", &[FLOAT, FLOAT, FLOAT, FLOAT, FLOAT, FLOAT]),
+    // Color
+    fixed("CS", &[KEY]),
+    fixed("cs", &[KEY]),
+    variadic("SC", &[FLOAT]),
+    variadic("sc", &[FLOAT]),
+    variadic("SCN", &[Type::Any]),
+    variadic("scn", &[Type::Any]),
+    fixed("G", &[FLOAT]),
+    fixed("g", &[FLOAT]),
+    fixed("RG", &[FLOAT, FLOAT, FLOAT]),
+    fixed("rg", &[FLOAT, FLOAT, FLOAT]),
+    fixed("K", &[FLOAT, FLOAT, FLOAT, FLOAT]),
+    fixed("k", &[FLOAT, FLOAT, FLOAT, FLOAT]),
+    // Shading patterns
+    fixed("sh", &[KEY]),
+    // Inline images are read by the lexer as a whole, see ContentOp::InlineImage.
+    // XObjects
+    fixed("Do", &[KEY]),
+    // Marked content
+    fixed("MP", &[KEY]),
+    fixed("DP", &[KEY, NAME_OR_DICT]),
+    fixed("BMC", &[KEY]),
+    fixed("BDC", &[KEY, NAME_OR_DICT]),
+    fixed("EMC", &[]),
+    // Compatibility
+    fixed("BX", &[]),
+    fixed("EX", &[]),
+];
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|x| x.name == name)
+}
+
+impl Builtin {
+    // Checks operand count and types, returning a description of the first mismatch.
+    pub fn check(&self, operands: &[Operand]) -> Result<(), String> {
+        let required = self.operands.len();
+        if operands.len() < required || (!self.variadic && operands.len() > required) {
+            let expected = if self.variadic { format!("at least {required}") } else { required.to_string() };
+            return Err(format!("{} expects {expected} operands, got {}", self.name, operands.len()));
+        }
+        for (i, operand) in operands.iter().enumerate() {
+            let expected = self.operands[i.min(self.operands.len() - 1)];
+            if !expected.matches(operand) {
+                return Err(format!("{} expects {expected} as operand {}, got {}", self.name, i + 1, operand.type_name()));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+use super::Operation;
+use super::builtin::lookup;
+use super::types::Operand;
+use crate::lexer::{abbreviate_inline_key, abbreviate_inline_name, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextElement {
+    Text(Vec<u8>),
+    // Displacement in thousandths of text space, subtracted from the position.
+    Adjust(f64),
+}
+
+// Marked content properties, inline or named in the Properties resources.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Properties {
+    Name(String),
+    Dict(Vec<(String, Operand)>),
+}
+
+// A content stream operation with typed operands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentOp {
+    SetLineWidth(f64),
+    SetLineCap(i64),
+    SetLineJoin(i64),
+    SetMiterLimit(f64),
+    SetDash(Vec<f64>, f64),
+    SetRenderingIntent(String),
+    SetFlatness(f64),
+    SetExtGState(String),
+    Save,
+    Restore,
+    Transform([f64; 6]),
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    CurveToV(f64, f64, f64, f64),
+    CurveToY(f64, f64, f64, f64),
+    ClosePath,
+    Rectangle(f64, f64, f64, f64),
+    Stroke,
+    CloseStroke,
+    Fill,
+    FillCompat,
+    FillEvenOdd,
+    FillStroke,
+    FillStrokeEvenOdd,
+    CloseFillStroke,
+    CloseFillStrokeEvenOdd,
+    EndPath,
+    Clip,
+    ClipEvenOdd,
+    BeginText,
+    EndText,
+    SetCharSpacing(f64),
+    SetWordSpacing(f64),
+    SetHorizontalScaling(f64),
+    SetLeading(f64),
+    SetFont(String, f64),
+    SetRenderMode(i64),
+    SetRise(f64),
+    MoveText(f64, f64),
+    MoveTextSetLeading(f64, f64),
+    SetTextMatrix([f64; 6]),
+    NextLine,
+    ShowText(Vec<u8>),
+    ShowTextArray(Vec<TextElement>),
+    NextLineShowText(Vec<u8>),
+    NextLineSpacingShowText(f64, f64, Vec<u8>),
+    SetGlyphWidth(f64, f64),
+    SetGlyphWidthBBox([f64; 6]),
+    SetStrokeColorSpace(String),
+    SetFillColorSpace(String),
+    SetStrokeColor(Vec<f64>),
+    SetFillColor(Vec<f64>),
+    SetStrokeColorN(Vec<f64>, Option<String>),
+    SetFillColorN(Vec<f64>, Option<String>),
+    SetStrokeGray(f64),
+    SetFillGray(f64),
+    SetStrokeRGB(f64, f64, f64),
+    SetFillRGB(f64, f64, f64),
+    SetStrokeCMYK(f64, f64, f64, f64),
+    SetFillCMYK(f64, f64, f64, f64),
+    PaintShading(String),
+    // BI ... ID ... EI with expanded keys and the raw image data.
+    InlineImage(Vec<(String, Operand)>, Vec<u8>),
+    PaintXObject(String),
+    MarkPoint(String),
+    MarkPointProperties(String, Properties),
+    BeginMarkedContent(String),
+    BeginMarkedContentProperties(String, Properties),
+    EndMarkedContent,
+    BeginCompat,
+    EndCompat,
+}
+
+fn numbers(items: &[Operand]) -> Option<Vec<f64>> {
+    items.iter().map(|x| if let &Operand::Number(n) = x { Some(n) } else { None }).collect()
+}
+
+fn inline_image(dict: &[(String, Vec<Token>)]) -> Result<Vec<(String, Operand)>, String> {
+    dict.iter().map(|(key, tokens)| match &Operand::from_tokens(tokens)?[..] {
+        [value] => Ok((key.clone(), value.clone())),
+        _ => Err(format!("inline image entry /{key} expects one value")),
+    }).collect()
+}
+
+// Components with an optional trailing pattern name, as taken by SCN and scn.
+fn color_n(items: &[Operand]) -> Option<(Vec<f64>, Option<String>)> {
+    match items {
+        [rest @ .., Operand::Key(name)] => Some((numbers(rest)?, Some(name.clone()))),
+        _ => Some((numbers(items)?, None)),
+    }
+}
+
+fn properties(operand: &Operand) -> Result<Properties, String> {
+    match operand {
+        Operand::Key(name) => Ok(Properties::Name(name.clone())),
+        Operand::Dict(entries) => Ok(Properties::Dict(entries.clone())),
+        x => Err(format!("expected properties name or dict, got {}", x.type_name())),
+    }
+}
+
+impl ContentOp {
+    pub fn from_operation(op: &Operation) -> Result<ContentOp, String> {
+        use ContentOp::*;
+        match (&*op.op, &op.tokens[..]) {
+            ("BI", [Token::InlineImage(image)]) => return Ok(InlineImage(inline_image(&image.dict)?, image.data.clone())),
+            ("ID" | "EI", _) => return Err(format!("{} without matching BI", op.op)),
+            _ => {}
+        }
+        let builtin = lookup(&op.op).ok_or_else(|| format!("unknown operator {}", op.op))?;
+        let operands = Operand::from_tokens(&op.tokens)?;
+        builtin.check(&operands)?;
+        // The signature check guarantees the operand types below.
+        let n = |i: usize| if let Operand::Number(x) = operands[i] { x } else { 0. };
+        let name = |i: usize| if let Operand::Key(s) = &operands[i] { s.clone() } else { String::new() };
+        let string = |i: usize| if let Operand::String(s) = &operands[i] { s.clone() } else { Vec::new() };
+        let matrix = || [n(0), n(1), n(2), n(3), n(4), n(5)];
+        let malformed = |what: &str| format!("{} expects {what}", op.op);
+        Ok(match &*op.op {
+            "w" => SetLineWidth(n(0)),
+            "J" => SetLineCap(n(0) as i64),
+            "j" => SetLineJoin(n(0) as i64),
+            "M" => SetMiterLimit(n(0)),
+            "d" => match &operands[0] {
+                Operand::Array(items) => SetDash(numbers(items).ok_or_else(|| malformed("an array of numbers"))?, n(1)),
+                _ => unreachable!(),
+            },
+            "ri" => SetRenderingIntent(name(0)),
+            "i" => SetFlatness(n(0)),
+            "gs" => SetExtGState(name(0)),
+            "q" => Save,
+            "Q" => Restore,
+            "cm" => Transform(matrix()),
+            "m" => MoveTo(n(0), n(1)),
+            "l" => LineTo(n(0), n(1)),
+            "c" => CurveTo(n(0), n(1), n(2), n(3), n(4), n(5)),
+            "v" => CurveToV(n(0), n(1), n(2), n(3)),
+            "y" => CurveToY(n(0), n(1), n(2), n(3)),
+            "h" => ClosePath,
+            "re" => Rectangle(n(0), n(1), n(2), n(3)),
+            "S" => Stroke,
+            "s" => CloseStroke,
+            "f" => Fill,
+            "F" => FillCompat,
+            "f*" => FillEvenOdd,
+            "B" => FillStroke,
+            "B*" => FillStrokeEvenOdd,
+            "b" => CloseFillStroke,
+            "b*" => CloseFillStrokeEvenOdd,
+            "n" => EndPath,
+            "W" => Clip,
+            "W*" => ClipEvenOdd,
+            "BT" => BeginText,
+            "ET" => EndText,
+            "Tc" => SetCharSpacing(n(0)),
+            "Tw" => SetWordSpacing(n(0)),
+            "Tz" => SetHorizontalScaling(n(0)),
+            "TL" => SetLeading(n(0)),
+            "Tf" => SetFont(name(0), n(1)),
+            "Tr" => SetRenderMode(n(0) as i64),
+            "Ts" => SetRise(n(0)),
+            "Td" => MoveText(n(0), n(1)),
+            "TD" => MoveTextSetLeading(n(0), n(1)),
+            "Tm" => SetTextMatrix(matrix()),
+            "T*" => NextLine,
+            "Tj" => ShowText(string(0)),
+            "TJ" => match &operands[0] {
+                Operand::Array(items) => ShowTextArray(items.iter().map(|x| match x {
+                    &Operand::Number(n) => Ok(TextElement::Adjust(n)),
+                    Operand::String(s) => Ok(TextElement::Text(s.clone())),
+                    _ => Err(malformed("an array of strings and numbers")),
+                }).collect::<Result<_, _>>()?),
+                _ => unreachable!(),
+            },
+            "'" => NextLineShowText(string(0)),
+            "\"" => NextLineSpacingShowText(n(0), n(1), string(2)),
+            "d0" => SetGlyphWidth(n(0), n(1)),
+            "d1" => SetGlyphWidthBBox(matrix()),
+            "CS" => SetStrokeColorSpace(name(0)),
+            "cs" => SetFillColorSpace(name(0)),
+            "SC" => SetStrokeColor(numbers(&operands).unwrap_or_default()),
+            "sc" => SetFillColor(numbers(&operands).unwrap_or_default()),
+            "SCN" | "scn" => {
+                let (components, pattern) = color_n(&operands).ok_or_else(|| malformed("numbers and an optional pattern name"))?;
+                match &*op.op {
+                    "SCN" => SetStrokeColorN(components, pattern),
+                    _ => SetFillColorN(components, pattern),
+                }
+            }
+            "G" => SetStrokeGray(n(0)),
+            "g" => SetFillGray(n(0)),
+            "RG" => SetStrokeRGB(n(0), n(1), n(2)),
+            "rg" => SetFillRGB(n(0), n(1), n(2)),
+            "K" => SetStrokeCMYK(n(0), n(1), n(2), n(3)),
+            "k" => SetFillCMYK(n(0), n(1), n(2), n(3)),
+            "sh" => PaintShading(name(0)),
+            "Do" => PaintXObject(name(0)),
+            "MP" => MarkPoint(name(0)),
+            "DP" => MarkPointProperties(name(0), properties(&operands[1])?),
+            "BMC" => BeginMarkedContent(name(0)),
+            "BDC" => BeginMarkedContentProperties(name(0), properties(&operands[1])?),
+            "EMC" => EndMarkedContent,
+            "BX" => BeginCompat,
+            "EX" => EndCompat,
+            _ => return Err(format!("unsupported operator {}", op.op)),
+        })
+    }
+
+    pub fn operator(&self) -> &'static str {
+        use ContentOp::*;
+        match self {
+            SetLineWidth(..) => "w",
+            SetLineCap(..) => "J",
+            SetLineJoin(..) => "j",
+            SetMiterLimit(..) => "M",
+            SetDash(..) => "d",
+            SetRenderingIntent(..) => "ri",
+            SetFlatness(..) => "i",
+            SetExtGState(..) => "gs",
+            Save => "q",
+            Restore => "Q",
+            Transform(..) => "cm",
+            MoveTo(..) => "m",
+            LineTo(..) => "l",
+            CurveTo(..) => "c",
+            CurveToV(..) => "v",
+            CurveToY(..) => "y",
+            ClosePath => "h",
+            Rectangle(..) => "re",
+            Stroke => "S",
+            CloseStroke => "s",
+            Fill => "f",
+            FillCompat => "F",
+            FillEvenOdd => "f*",
+            FillStroke => "B",
+            FillStrokeEvenOdd => "B*",
+            CloseFillStroke => "b",
+            CloseFillStrokeEvenOdd => "b*",
+            EndPath => "n",
+            Clip => "W",
+            ClipEvenOdd => "W*",
+            BeginText => "BT",
+            EndText => "ET",
+            SetCharSpacing(..) => "Tc",
+            SetWordSpacing(..) => "Tw",
+            SetHorizontalScaling(..) => "Tz",
+            SetLeading(..) => "TL",
+            SetFont(..) => "Tf",
+            SetRenderMode(..) => "Tr",
+            SetRise(..) => "Ts",
+            MoveText(..) => "Td",
+            MoveTextSetLeading(..) => "TD",
+            SetTextMatrix(..) => "Tm",
+            NextLine => "T*",
+            ShowText(..) => "Tj",
+            ShowTextArray(..) => "TJ",
+            NextLineShowText(..) => "'",
+            NextLineSpacingShowText(..) => "\"",
+            SetGlyphWidth(..) => "d0",
+            SetGlyphWidthBBox(..) => "d1",
+            SetStrokeColorSpace(..) => "CS",
+            SetFillColorSpace(..) => "cs",
+            SetStrokeColor(..) => "SC",
+            SetFillColor(..) => "sc",
+            SetStrokeColorN(..) => "SCN",
+            SetFillColorN(..) => "scn",
+            SetStrokeGray(..) => "G",
+            SetFillGray(..) => "g",
+            SetStrokeRGB(..) => "RG",
+            SetFillRGB(..) => "rg",
+            SetStrokeCMYK(..) => "K",
+            SetFillCMYK(..) => "k",
+            PaintShading(..) => "sh",
+            InlineImage(..) => "BI",
+            PaintXObject(..) => "Do",
+            MarkPoint(..) => "MP",
+            MarkPointProperties(..) => "DP",
+            BeginMarkedContent(..) => "BMC",
+            BeginMarkedContentProperties(..) => "BDC",
+            EndMarkedContent => "EMC",
+            BeginCompat => "BX",
+            EndCompat => "EX",
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        use ContentOp::*;
+        let numbers = |x: &[f64]| x.iter().map(|&n| Operand::Number(n)).collect::<Vec<_>>();
+        let name = |s: &String| Operand::Key(s.clone());
+        let properties = |p: &Properties| match p {
+            Properties::Name(s) => Operand::Key(s.clone()),
+            Properties::Dict(entries) => Operand::Dict(entries.clone()),
+        };
+        match self {
+            &SetLineWidth(x) | &SetMiterLimit(x) | &SetFlatness(x) | &SetCharSpacing(x) | &SetWordSpacing(x)
+            | &SetHorizontalScaling(x) | &SetLeading(x) | &SetRise(x) | &SetStrokeGray(x) | &SetFillGray(x) => numbers(&[x]),
+            &SetLineCap(x) | &SetLineJoin(x) | &SetRenderMode(x) => numbers(&[x as f64]),
+            SetDash(array, phase) => vec![Operand::Array(numbers(array)), Operand::Number(*phase)],
+            SetRenderingIntent(s) | SetExtGState(s) | SetStrokeColorSpace(s) | SetFillColorSpace(s)
+            | PaintShading(s) | PaintXObject(s) | MarkPoint(s) | BeginMarkedContent(s) => vec![name(s)],
+            Transform(m) | SetTextMatrix(m) | SetGlyphWidthBBox(m) => numbers(m),
+            &MoveTo(x, y) | &LineTo(x, y) | &MoveText(x, y) | &MoveTextSetLeading(x, y) | &SetGlyphWidth(x, y) => numbers(&[x, y]),
+            &CurveTo(a, b, c, d, e, f) => numbers(&[a, b, c, d, e, f]),
+            &CurveToV(a, b, c, d) | &CurveToY(a, b, c, d) | &Rectangle(a, b, c, d)
+            | &SetStrokeCMYK(a, b, c, d) | &SetFillCMYK(a, b, c, d) => numbers(&[a, b, c, d]),
+            &SetStrokeRGB(r, g, b) | &SetFillRGB(r, g, b) => numbers(&[r, g, b]),
+            SetFont(font, size) => vec![name(font), Operand::Number(*size)],
+            ShowText(s) | NextLineShowText(s) => vec![Operand::String(s.clone())],
+            ShowTextArray(elements) => vec![Operand::Array(elements.iter().map(|x| match x {
+                TextElement::Text(s) => Operand::String(s.clone()),
+                &TextElement::Adjust(n) => Operand::Number(n),
+            }).collect())],
+            NextLineSpacingShowText(aw, ac, s) => vec![Operand::Number(*aw), Operand::Number(*ac), Operand::String(s.clone())],
+            SetStrokeColor(c) | SetFillColor(c) => numbers(c),
+            SetStrokeColorN(c, pattern) | SetFillColorN(c, pattern) => {
+                let mut ret = numbers(c);
+                ret.extend(pattern.iter().map(name));
+                ret
+            }
+            InlineImage(..) => Vec::new(),
+            MarkPointProperties(tag, p) | BeginMarkedContentProperties(tag, p) => vec![name(tag), properties(p)],
+            Save | Restore | ClosePath | Stroke | CloseStroke | Fill | FillCompat | FillEvenOdd | FillStroke
+            | FillStrokeEvenOdd | CloseFillStroke | CloseFillStrokeEvenOdd | EndPath | Clip | ClipEvenOdd
+            | BeginText | EndText | NextLine | EndMarkedContent | BeginCompat | EndCompat => Vec::new(),
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        if let ContentOp::InlineImage(dict, data) = self {
+            out.extend(b"BI");
+            for (key, value) in dict {
+                out.push(b' ');
+                Operand::Key(abbreviate_inline_key(key).into()).write(out);
+                out.push(b' ');
+                match (key.as_str(), value) {
+                    ("ColorSpace" | "Filter", Operand::Key(name)) => Operand::Key(abbreviate_inline_name(name).into()),
+                    ("ColorSpace" | "Filter", Operand::Array(items)) => Operand::Array(items.iter().map(|x| match x {
+                        Operand::Key(name) => Operand::Key(abbreviate_inline_name(name).into()),
+                        x => x.clone(),
+                    }).collect()),
+                    _ => value.clone(),
+                }.write(out);
+            }
+            out.extend(b" ID ");
+            out.extend(data);
+            out.extend(b"\nEI");
+            return;
+        }
+        for operand in self.operands() {
+            operand.write(out);
+            out.push(b' ');
+        }
+        out.extend(self.operator().bytes());
+    }
+}
+
+// Converts operations into typed ones, collecting malformed operations with
+// their index instead of failing. Unknown operators between BX and EX are
+// skipped silently, as the compatibility section allows.
+pub fn convert(operations: &[Operation]) -> (Vec<ContentOp>, Vec<(usize, String)>) {
+    let mut ops = Vec::new();
+    let mut errors = Vec::new();
+    let mut compat = 0usize;
+    for (i, op) in operations.iter().enumerate() {
+        match ContentOp::from_operation(op) {
+            Ok(x) => {
+                match x {
+                    ContentOp::BeginCompat => compat += 1,
+                    ContentOp::EndCompat => compat = compat.saturating_sub(1),
+                    _ => {}
+                }
+                ops.push(x);
+            }
+            Err(_) if compat > 0 && lookup(&op.op).is_none() => {}
+            Err(e) => errors.push((i, e)),
+        }
+    }
+    (ops, errors)
+}
+
+pub fn serialize(ops: &[ContentOp]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for op in ops {
+        op.write(&mut out);
+        out.push(b'\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::parse as lexer;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_convert() {
+        let source = b"q 1 0 0 1 10 20 cm /Span <</MCID 3>> BDC BT /F1 12 Tf [(a) -250 (b)] TJ ET EMC [3 1] 0 d 0 0 m 5 5 l S /P0 scn Q";
+        let (ops, errors) = convert(&parse(lexer(source)));
+        assert!(errors.is_empty());
+        assert_eq!(ops[1], ContentOp::Transform([1., 0., 0., 1., 10., 20.]));
+        assert_eq!(ops[2], ContentOp::BeginMarkedContentProperties("Span".into(), Properties::Dict(vec![("MCID".into(), Operand::Number(3.))])));
+        assert_eq!(ops[5], ContentOp::ShowTextArray(vec![
+            TextElement::Text(b"a".to_vec()),
+            TextElement::Adjust(-250.),
+            TextElement::Text(b"b".to_vec()),
+        ]));
+        assert_eq!(ops[8], ContentOp::SetDash(vec![3., 1.], 0.));
+        assert_eq!(ops[12], ContentOp::SetFillColorN(Vec::new(), Some("P0".into())));
+
+        let serialized = serialize(&ops);
+        assert_eq!(convert(&parse(lexer(&serialized))).0, ops);
+    }
+
+    #[test]
+    fn test_inline_image() {
+        let (ops, errors) = convert(&parse(lexer(b"BI /W 1 /H 1 /IM true /D [1 0] ID \x80\nEI 0 g")));
+        assert!(errors.is_empty());
+        assert_eq!(ops[0], ContentOp::InlineImage(vec![
+            ("Width".into(), Operand::Number(1.)),
+            ("Height".into(), Operand::Number(1.)),
+            ("ImageMask".into(), Operand::Bool(true)),
+            ("Decode".into(), Operand::Array(vec![Operand::Number(1.), Operand::Number(0.)])),
+        ], vec![0x80]));
+        let serialized = serialize(&ops);
+        assert!(serialized.starts_with(b"BI /W 1 /H 1 /IM true /D [1 0] ID \x80\nEI"));
+        assert_eq!(convert(&parse(lexer(&serialized))).0, ops);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let (ops, errors) = convert(&parse(lexer(b"1 m /F1 Tf 1 2 l BX 1 foo EX bar 1.5 Tr")));
+        assert_eq!(ops, vec![ContentOp::LineTo(1., 2.), ContentOp::BeginCompat, ContentOp::EndCompat]);
+        assert_eq!(errors, vec![
+            (0, "m expects 2 operands, got 1".into()),
+            (1, "Tf expects 2 operands, got 1".into()),
+            (6, "unknown operator bar".into()),
+            (7, "Tr expects integer as operand 1, got number".into()),
+        ]);
+    }
+}
@@ -1,13 +1,23 @@
 use std::collections::HashMap;
 use super::lexer::{Token::{self, *}, State};
 
+pub mod builtin;
+pub mod content;
+pub mod type_check;
+pub mod types;
+
 #[derive(Clone, Debug)]
 pub struct Operation {
     pub op: String,
     pub tokens: Vec<Token>,
 }
 
-pub fn parse(mut state: State) -> Vec<Operation> {
+pub fn parse(state: State) -> Vec<Operation> {
+    parse_with_offsets(state).into_iter().map(|x| x.1).collect()
+}
+
+// Operations with the byte offset of their operator.
+pub fn parse_with_offsets(mut state: State) -> Vec<(usize, Operation)> {
     let mut tokens = Vec::new();
 
     let mut ret = Vec::new();
@@ -15,10 +25,17 @@ pub fn parse(mut state: State) -> Vec<Operation> {
     while let Some(x) = state.next() {
         use std::mem::take;
         if let Operator(op) = x {
-            ret.push(Operation {
+            let offset = state.index() - op.len();
+            ret.push((offset, Operation {
                 op,
                 tokens: take(&mut tokens),
-            });
+            }));
+        } else if let InlineImage(_) = x {
+            // An inline image makes up a BI operation of its own.
+            ret.push((state.inline_image_offset(), Operation {
+                op: "BI".into(),
+                tokens: vec![x],
+            }));
         } else {
             tokens.push(x);
         }
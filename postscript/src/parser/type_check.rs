@@ -0,0 +1,168 @@
+use super::content::{ContentOp, Properties};
+use super::builtin::lookup;
+use super::parse_with_offsets;
+use crate::lexer::parse as lexer;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub offset: usize,
+    pub message: String,
+}
+
+// Resource names by category, as in a /Resources dictionary: "Font", "XObject",
+// "ExtGState", "ColorSpace", "Pattern", "Shading" and "Properties".
+pub type ResourceNames = HashMap<String, HashSet<String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Block {
+    Save,
+    Text,
+    MarkedContent,
+    Compat,
+}
+
+impl Block {
+    fn describe(&self) -> &'static str {
+        match self {
+            Block::Save => "q",
+            Block::Text => "BT",
+            Block::MarkedContent => "BMC/BDC",
+            Block::Compat => "BX",
+        }
+    }
+}
+
+fn is_text_only(op: &ContentOp) -> bool {
+    use ContentOp::*;
+    matches!(op, MoveText(..) | MoveTextSetLeading(..) | SetTextMatrix(..) | NextLine
+        | ShowText(..) | ShowTextArray(..) | NextLineShowText(..) | NextLineSpacingShowText(..))
+}
+
+fn resource(op: &ContentOp) -> Option<(&'static str, &str)> {
+    use ContentOp::*;
+    match op {
+        SetFont(name, _) => Some(("Font", name)),
+        PaintXObject(name) => Some(("XObject", name)),
+        SetExtGState(name) => Some(("ExtGState", name)),
+        SetStrokeColorSpace(name) | SetFillColorSpace(name)
+            if !matches!(&**name, "DeviceGray" | "DeviceRGB" | "DeviceCMYK" | "Pattern") => Some(("ColorSpace", name)),
+        SetStrokeColorN(_, Some(name)) | SetFillColorN(_, Some(name)) => Some(("Pattern", name)),
+        PaintShading(name) => Some(("Shading", name)),
+        MarkPointProperties(_, Properties::Name(name)) | BeginMarkedContentProperties(_, Properties::Name(name)) => {
+            Some(("Properties", name))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    stack: Vec<(Block, usize)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn warn(&mut self, offset: usize, message: String) {
+        self.diagnostics.push(Diagnostic { offset, message });
+    }
+
+    fn within(&self, block: Block) -> bool {
+        self.stack.iter().any(|x| x.0 == block)
+    }
+
+    // Pops up to the innermost `block`, reporting blocks left open inside it.
+    fn close(&mut self, block: Block, name: &str, offset: usize) {
+        match self.stack.iter().rposition(|x| x.0 == block) {
+            Some(i) => {
+                for (inner, at) in self.stack.split_off(i).into_iter().skip(1) {
+                    self.warn(at, format!("{} not closed before {name}", inner.describe()));
+                }
+            }
+            None => self.warn(offset, format!("{name} without matching {}", block.describe())),
+        }
+    }
+}
+
+// Checks operator signatures, the nesting of BT/ET, q/Q and BMC/EMC, stray ID/EI,
+// and with `resources` given, that named resources exist.
+pub fn check(content: &[u8], resources: Option<&ResourceNames>) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    for (offset, operation) in parse_with_offsets(lexer(content)) {
+        let op = match ContentOp::from_operation(&operation) {
+            Ok(op) => op,
+            Err(_) if checker.within(Block::Compat) && lookup(&operation.op).is_none() => continue,
+            Err(e) => {
+                checker.warn(offset, e);
+                continue;
+            }
+        };
+        let name = operation.op.as_str();
+        let in_text = checker.within(Block::Text);
+        match &op {
+            ContentOp::Save if in_text => checker.warn(offset, "q inside a text object".into()),
+            ContentOp::BeginText if in_text => checker.warn(offset, "BT inside a text object".into()),
+            x if is_text_only(x) && !in_text => checker.warn(offset, format!("{name} outside a text object")),
+            _ => {}
+        }
+        match op {
+            ContentOp::Save => checker.stack.push((Block::Save, offset)),
+            ContentOp::Restore => checker.close(Block::Save, name, offset),
+            ContentOp::BeginText if !in_text => checker.stack.push((Block::Text, offset)),
+            ContentOp::EndText => checker.close(Block::Text, name, offset),
+            ContentOp::BeginMarkedContent(_) | ContentOp::BeginMarkedContentProperties(..) => checker.stack.push((Block::MarkedContent, offset)),
+            ContentOp::EndMarkedContent => checker.close(Block::MarkedContent, name, offset),
+            ContentOp::BeginCompat => checker.stack.push((Block::Compat, offset)),
+            ContentOp::EndCompat => checker.close(Block::Compat, name, offset),
+            ref x => {
+                if let (Some(resources), Some((category, resource))) = (resources, resource(x)) {
+                    if !resources.get(category).is_some_and(|x| x.contains(resource)) {
+                        checker.warn(offset, format!("{name} refers to /{resource}, missing from /{category} resources"));
+                    }
+                }
+            }
+        }
+    }
+    for (block, offset) in std::mem::take(&mut checker.stack) {
+        checker.warn(offset, format!("{} not closed at end of content", block.describe()));
+    }
+    checker.diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(content: &[u8], resources: Option<&ResourceNames>) -> Vec<(usize, String)> {
+        check(content, resources).into_iter().map(|x| (x.offset, x.message)).collect()
+    }
+
+    #[test]
+    fn test_nesting() {
+        assert!(messages(b"q BT /F1 12 Tf (a) Tj ET /P BMC EMC Q", None).is_empty());
+        assert_eq!(messages(b"q BT (a) Tj Q ET", None), vec![
+            (2, "BT not closed before Q".into()),
+            (14, "ET without matching BT".into()),
+        ]);
+        assert_eq!(messages(b"(a) Tj /P BMC q", None), vec![
+            (4, "Tj outside a text object".into()),
+            (10, "BMC/BDC not closed at end of content".into()),
+            (14, "q not closed at end of content".into()),
+        ]);
+        assert!(messages(b"BI /W 1 /H 1 ID \x00\xff EI 1 w", None).is_empty());
+        assert_eq!(messages(b"/W 1 ID \x00 EI", None), vec![
+            (5, "ID without matching BI".into()),
+            (10, "EI without matching BI".into()),
+        ]);
+    }
+
+    #[test]
+    fn test_signatures_and_resources() {
+        let resources = HashMap::from([("Font".to_string(), HashSet::from(["F1".to_string()]))]);
+        assert_eq!(messages(b"BT /F2 12 Tf /F1 Tf ET /Im0 Do BX 1 foo EX", Some(&resources)), vec![
+            (10, "Tf refers to /F2, missing from /Font resources".into()),
+            (17, "Tf expects 2 operands, got 1".into()),
+            (28, "Do refers to /Im0, missing from /XObject resources".into()),
+        ]);
+    }
+}
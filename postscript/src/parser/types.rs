@@ -1,9 +1,13 @@
+use crate::lexer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Type {
     Any,
     Primitive(Primitive),
     Compound(Compound),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Primitive {
     Integer,
     Float,
@@ -12,7 +16,174 @@ pub enum Primitive {
     Operator,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Compound {
-    name: String,
-    generics: Vec<Type>,
+    pub name: &'static str,
+    pub generics: &'static [Type],
+}
+
+pub const INTEGER: Type = Type::Primitive(Primitive::Integer);
+pub const FLOAT: Type = Type::Primitive(Primitive::Float);
+pub const KEY: Type = Type::Primitive(Primitive::Key);
+pub const STRING: Type = Type::Primitive(Primitive::String);
+pub const ARRAY: Type = Type::Compound(Compound { name: "Array", generics: &[Type::Any] });
+pub const DICT: Type = Type::Compound(Compound { name: "Dict", generics: &[Type::Any] });
+
+// An operand of a content stream operator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Number(f64),
+    Bool(bool),
+    Key(String),
+    String(Vec<u8>),
+    Array(Vec<Operand>),
+    Dict(Vec<(String, Operand)>),
+}
+
+impl Type {
+    pub fn matches(&self, operand: &Operand) -> bool {
+        match (self, operand) {
+            (Type::Any, _) => true,
+            (Type::Primitive(Primitive::Integer), Operand::Number(n)) => n.fract() == 0.,
+            (Type::Primitive(Primitive::Float), Operand::Number(_)) => true,
+            (Type::Primitive(Primitive::Key), Operand::Key(_)) => true,
+            (Type::Primitive(Primitive::String), Operand::String(_)) => true,
+            (Type::Compound(Compound { name: "Array", generics }), Operand::Array(items)) => {
+                items.iter().all(|x| generics.iter().any(|t| t.matches(x)))
+            }
+            (Type::Compound(Compound { name: "Dict", generics }), Operand::Dict(entries)) => {
+                entries.iter().all(|(_, x)| generics.iter().any(|t| t.matches(x)))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Primitive(Primitive::Integer) => write!(f, "integer"),
+            Type::Primitive(Primitive::Float) => write!(f, "number"),
+            Type::Primitive(Primitive::Key) => write!(f, "name"),
+            Type::Primitive(Primitive::String) => write!(f, "string"),
+            Type::Primitive(Primitive::Operator) => write!(f, "operator"),
+            Type::Compound(Compound { name, .. }) => write!(f, "{}", name.to_lowercase()),
+        }
+    }
+}
+
+impl Operand {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Operand::Number(_) => "number",
+            Operand::Bool(_) => "boolean",
+            Operand::Key(_) => "name",
+            Operand::String(_) => "string",
+            Operand::Array(_) => "array",
+            Operand::Dict(_) => "dict",
+        }
+    }
+
+    // Groups lexer tokens into operands, nesting arrays and dictionaries.
+    pub fn from_tokens(tokens: &[Token]) -> Result<Vec<Operand>, String> {
+        let mut pos = 0;
+        let mut ret = Vec::new();
+        while pos < tokens.len() {
+            ret.push(Operand::read(tokens, &mut pos)?);
+        }
+        Ok(ret)
+    }
+
+    fn read(tokens: &[Token], pos: &mut usize) -> Result<Operand, String> {
+        let token = tokens.get(*pos).ok_or("unexpected end of operands")?;
+        *pos += 1;
+        match token {
+            Token::Number(n) => Ok(Operand::Number(*n)),
+            Token::Operator(x) if x == "true" || x == "false" => Ok(Operand::Bool(x == "true")),
+            Token::Key(s) => Ok(Operand::Key(s.clone())),
+            Token::StringLiteral(s) | Token::BytesLiteral(s) => Ok(Operand::String(s.clone())),
+            Token::ListStart => {
+                let mut items = Vec::new();
+                while tokens.get(*pos) != Some(&Token::ListEnd) {
+                    items.push(Operand::read(tokens, pos)?);
+                }
+                *pos += 1;
+                Ok(Operand::Array(items))
+            }
+            Token::DictStart => {
+                let mut entries = Vec::new();
+                while tokens.get(*pos) != Some(&Token::DictEnd) {
+                    match Operand::read(tokens, pos)? {
+                        Operand::Key(key) => entries.push((key, Operand::read(tokens, pos)?)),
+                        x => return Err(format!("expected a name as dictionary key, got {}", x.type_name())),
+                    }
+                }
+                *pos += 1;
+                Ok(Operand::Dict(entries))
+            }
+            x => Err(format!("unexpected token {x:?}")),
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Operand::Number(n) => out.extend(format_number(*n).bytes()),
+            Operand::Bool(x) => out.extend(x.to_string().bytes()),
+            Operand::Key(s) => {
+                out.push(b'/');
+                for &byte in s.as_bytes() {
+                    if byte.is_ascii_graphic() && !b"#()<>[]{}/%".contains(&byte) {
+                        out.push(byte);
+                    } else {
+                        out.extend(format!("#{byte:02X}").bytes());
+                    }
+                }
+            }
+            Operand::String(s) => {
+                out.push(b'(');
+                for &byte in s {
+                    match byte {
+                        b'(' | b')' | b'\\' => out.extend([b'\\', byte]),
+                        b'\n' => out.extend(b"\\n"),
+                        b'\r' => out.extend(b"\\r"),
+                        _ => out.push(byte),
+                    }
+                }
+                out.push(b')');
+            }
+            Operand::Array(items) => {
+                out.push(b'[');
+                for (i, x) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(b' ');
+                    }
+                    x.write(out);
+                }
+                out.push(b']');
+            }
+            Operand::Dict(entries) => {
+                out.extend(b"<<");
+                for (key, x) in entries {
+                    Operand::Key(key.clone()).write(out);
+                    out.push(b' ');
+                    x.write(out);
+                }
+                out.extend(b">>");
+            }
+        }
+    }
+}
+
+// Shortest decimal form, without exponents which content streams don't allow.
+pub fn format_number(n: f64) -> String {
+    if n.fract() == 0. && n.abs() < 1e15 {
+        return format!("{}", n as i64);
+    }
+    let s = format!("{n:.6}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".into(),
+        _ => s.into(),
+    }
 }
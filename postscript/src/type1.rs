@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+use super::lexer::{self, decrypt, Token};
+
+const CHARSTRING_KEY: u16 = 4330;
+
+// Removes the segment headers of a PFB file.
+fn strip_pfb(data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::new();
+    let mut pos = 0;
+    while data.get(pos) == Some(&0x80) && data.get(pos + 1).is_some_and(|&x| x == 1 || x == 2) {
+        let Some(length) = data.get(pos + 2 .. pos + 6) else { break };
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        ret.extend_from_slice(data.get(pos + 6 .. pos + 6 + length).unwrap_or(&data[pos + 6 ..]));
+        pos += 6 + length;
+    }
+    ret
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Glyph {
+    pub segments: Vec<Segment>,
+    pub width: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Type1Font {
+    pub name: String,
+    // The /Weight of the FontInfo dictionary, such as Bold.
+    pub weight: String,
+    pub font_matrix: [f64; 6],
+    pub font_bbox: [f64; 4],
+    // The /Encoding array; empty when the font uses StandardEncoding.
+    pub encoding: HashMap<u8, String>,
+    pub standard_encoding: bool,
+    pub glyph_names: Vec<String>,
+    pub charstrings: HashMap<String, Vec<u8>>,
+    pub subrs: Vec<Vec<u8>>,
+}
+
+impl Type1Font {
+    pub fn parse(data: &[u8]) -> Option<Type1Font> {
+        let stripped;
+        let data = if data.first() == Some(&0x80) {
+            stripped = strip_pfb(data);
+            &stripped[..]
+        } else {
+            data
+        };
+        let mut font = Type1Font { font_matrix: [0.001, 0., 0., 0.001, 0., 0.], ..Type1Font::default() };
+        // The lexer switches to the decrypted private part once it reaches eexec.
+        let tokens = lexer::parse(data).collect::<Vec<_>>();
+        font.parse_tokens(&tokens);
+        if font.charstrings.is_empty() {
+            return None;
+        }
+        Some(font)
+    }
+
+    fn parse_tokens(&mut self, tokens: &[Token]) {
+        let numbers = |from: usize| tokens[from ..].iter()
+            .skip_while(|x| !matches!(x, Token::Number(_)))
+            .map_while(|x| if let Token::Number(n) = x { Some(*n) } else { None })
+            .collect::<Vec<_>>();
+        let mut len_iv = 4;
+        let mut in_charstrings = false;
+        for (i, token) in tokens.iter().enumerate() {
+            let recent = &tokens[i.saturating_sub(3) .. i];
+            match token {
+                Token::Key(key) => match key.as_str() {
+                    "FontName" => if let Some(Token::Key(name)) = tokens.get(i + 1) {
+                        self.name = name.clone();
+                    },
+                    "Weight" => if let Some(Token::StringLiteral(weight)) = tokens.get(i + 1) {
+                        self.weight = String::from_utf8_lossy(weight).into();
+                    },
+                    "FontMatrix" => if let Ok(m) = numbers(i + 1).get(.. 6).unwrap_or_default().try_into() {
+                        self.font_matrix = m;
+                    },
+                    "FontBBox" => if let Ok(b) = numbers(i + 1).get(.. 4).unwrap_or_default().try_into() {
+                        self.font_bbox = b;
+                    },
+                    "Encoding" => self.parse_encoding(&tokens[i + 1 ..]),
+                    "lenIV" => if let Some(Token::Number(n)) = tokens.get(i + 1) {
+                        len_iv = *n as i64;
+                    },
+                    "CharStrings" => in_charstrings = true,
+                    _ => {}
+                },
+                Token::Binary(binary) => {
+                    let charstring = if len_iv < 0 { binary.clone() } else { decrypt(binary, CHARSTRING_KEY, len_iv as usize) };
+                    match recent {
+                        [.., Token::Key(name), Token::Number(_)] if in_charstrings => {
+                            let new = self.charstrings.insert(name.clone(), charstring).is_none();
+                            self.glyph_names.extend(new.then(|| name.clone()));
+                        }
+                        [Token::Operator(dup), Token::Number(index), Token::Number(_)] if dup == "dup" && !in_charstrings => {
+                            let index = *index as usize;
+                            if index < 65536 && self.subrs.len() <= index {
+                                self.subrs.resize(index + 1, Vec::new());
+                            }
+                            if let Some(subr) = self.subrs.get_mut(index) {
+                                *subr = charstring;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_encoding(&mut self, tokens: &[Token]) {
+        if let Some(Token::Operator(name)) = tokens.first() {
+            self.standard_encoding = name == "StandardEncoding";
+            return;
+        }
+        // dup <code> /<name> put
+        for w in tokens.windows(4) {
+            match w {
+                [Token::Operator(dup), Token::Number(code), Token::Key(name), Token::Operator(put)] if dup == "dup" && put == "put" => {
+                    self.encoding.insert(*code as u8, name.clone());
+                }
+                [Token::Operator(def), ..] if def == "def" || def == "readonly" => break,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn glyph(&self, name: &str, standard_encoding: fn(u8) -> Option<&'static str>) -> Option<Glyph> {
+        self.glyph_at(name, standard_encoding, 0)
+    }
+
+    fn glyph_at(&self, name: &str, standard_encoding: fn(u8) -> Option<&'static str>, depth: usize) -> Option<Glyph> {
+        let charstring = self.charstrings.get(name)?;
+        let mut interpreter = Interpreter { subrs: &self.subrs, ..Interpreter::default() };
+        interpreter.run(charstring, 0);
+        interpreter.close();
+        let mut glyph = Glyph { segments: interpreter.segments, width: interpreter.width };
+        if let Some((asb, adx, ady, base, accent)) = interpreter.seac.filter(|_| depth < 2) {
+            let base = standard_encoding(base).and_then(|x| self.glyph_at(x, standard_encoding, depth + 1));
+            let accent = standard_encoding(accent).and_then(|x| self.glyph_at(x, standard_encoding, depth + 1));
+            if let Some(base) = base {
+                glyph.segments = base.segments;
+            }
+            if let Some(accent) = accent {
+                let (dx, dy) = (adx - asb, ady);
+                glyph.segments.extend(accent.segments.into_iter().map(|s| match s {
+                    Segment::MoveTo(x, y) => Segment::MoveTo(x + dx, y + dy),
+                    Segment::LineTo(x, y) => Segment::LineTo(x + dx, y + dy),
+                    Segment::CurveTo(x1, y1, x2, y2, x3, y3) => Segment::CurveTo(x1 + dx, y1 + dy, x2 + dx, y2 + dy, x3 + dx, y3 + dy),
+                    Segment::Close => Segment::Close,
+                }));
+            }
+        }
+        Some(glyph)
+    }
+}
+
+// Type 1 charstring interpreter.
+#[derive(Default)]
+struct Interpreter<'a> {
+    subrs: &'a [Vec<u8>],
+    stack: Vec<f64>,
+    // Results of OtherSubrs, read back with pop.
+    ps_stack: Vec<f64>,
+    segments: Vec<Segment>,
+    x: f64,
+    y: f64,
+    open: bool,
+    width: f64,
+    flex: Option<Vec<(f64, f64)>>,
+    seac: Option<(f64, f64, f64, u8, u8)>,
+}
+
+impl Interpreter<'_> {
+    fn close(&mut self) {
+        if self.open {
+            self.segments.push(Segment::Close);
+            self.open = false;
+        }
+    }
+
+    fn move_to(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+        match &mut self.flex {
+            Some(points) => points.push((x, y)),
+            None => self.close(),
+        }
+    }
+
+    fn line_to(&mut self, x: f64, y: f64) {
+        if !self.open {
+            self.segments.push(Segment::MoveTo(self.x, self.y));
+            self.open = true;
+        }
+        self.x = x;
+        self.y = y;
+        self.segments.push(Segment::LineTo(x, y));
+    }
+
+    fn curve_by(&mut self, d: [f64; 6]) {
+        let (x1, y1) = (self.x + d[0], self.y + d[1]);
+        let (x2, y2) = (x1 + d[2], y1 + d[3]);
+        self.curve_to(x1, y1, x2, y2, x2 + d[4], y2 + d[5]);
+    }
+
+    fn curve_to(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, x3: f64, y3: f64) {
+        if !self.open {
+            self.segments.push(Segment::MoveTo(self.x, self.y));
+            self.open = true;
+        }
+        self.x = x3;
+        self.y = y3;
+        self.segments.push(Segment::CurveTo(x1, y1, x2, y2, x3, y3));
+    }
+
+    // Returns true once the charstring has ended.
+    fn run(&mut self, data: &[u8], depth: usize) -> bool {
+        if depth > 10 {
+            return true;
+        }
+        let mut pos = 0;
+        while let Some(&v) = data.get(pos) {
+            pos += 1;
+            let byte = |p: usize| data.get(p).cloned().unwrap_or(0) as i32;
+            match v {
+                32 ..= 246 => self.stack.push(v as f64 - 139.),
+                247 ..= 250 => {
+                    self.stack.push(((v as i32 - 247) * 256 + byte(pos) + 108) as f64);
+                    pos += 1;
+                }
+                251 ..= 254 => {
+                    self.stack.push((-(v as i32 - 251) * 256 - byte(pos) - 108) as f64);
+                    pos += 1;
+                }
+                255 => {
+                    self.stack.push((byte(pos) << 24 | byte(pos + 1) << 16 | byte(pos + 2) << 8 | byte(pos + 3)) as f64);
+                    pos += 4;
+                }
+                12 => {
+                    pos += 1;
+                    if self.escape(byte(pos - 1) as u8) {
+                        return true;
+                    }
+                }
+                10 => {
+                    let index = self.stack.pop().unwrap_or(-1.);
+                    if index >= 0. {
+                        if let Some(subr) = self.subrs.get(index as usize) {
+                            if self.run(subr, depth + 1) {
+                                return true;
+                            }
+                        }
+                    }
+                }
+                11 => return false,
+                14 => {
+                    self.stack.clear();
+                    return true;
+                }
+                _ => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let (x, y) = (self.x, self.y);
+                    match (v, &stack[..]) {
+                        (13, &[sbx, wx, ..]) => {
+                            self.width = wx;
+                            self.x = sbx;
+                            self.y = 0.;
+                        }
+                        (21, &[.., dx, dy]) => self.move_to(x + dx, y + dy),
+                        (22, &[.., dx]) => self.move_to(x + dx, y),
+                        (4, &[.., dy]) => self.move_to(x, y + dy),
+                        (5, &[.., dx, dy]) => self.line_to(x + dx, y + dy),
+                        (6, &[.., dx]) => self.line_to(x + dx, y),
+                        (7, &[.., dy]) => self.line_to(x, y + dy),
+                        (8, &[.., a, b, c, d, e, f]) => self.curve_by([a, b, c, d, e, f]),
+                        (30, &[.., dy1, dx2, dy2, dx3]) => self.curve_by([0., dy1, dx2, dy2, dx3, 0.]),
+                        (31, &[.., dx1, dx2, dy2, dy3]) => self.curve_by([dx1, 0., dx2, dy2, 0., dy3]),
+                        (9, _) => {
+                            self.close();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn escape(&mut self, op: u8) -> bool {
+        match op {
+            6 => {
+                if let [asb, adx, ady, base, accent] = self.stack[..] {
+                    self.seac = Some((asb, adx, ady, base as u8, accent as u8));
+                }
+                self.stack.clear();
+                return true;
+            }
+            7 => {
+                if let [sbx, sby, wx, _] = self.stack[..] {
+                    self.width = wx;
+                    self.x = sbx;
+                    self.y = sby;
+                }
+                self.stack.clear();
+            }
+            12 => {
+                if let (Some(b), Some(a)) = (self.stack.pop(), self.stack.pop()) {
+                    self.stack.push(if b != 0. { a / b } else { 0. });
+                }
+            }
+            16 => {
+                let other = self.stack.pop().unwrap_or(0.) as i32;
+                let count = (self.stack.pop().unwrap_or(0.).max(0.) as usize).min(self.stack.len());
+                let args = self.stack.split_off(self.stack.len() - count);
+                match other {
+                    // Flex: start, add a point, and end with the two curves.
+                    1 => self.flex = Some(Vec::new()),
+                    2 => {}
+                    0 => {
+                        if let Some(points) = self.flex.take() {
+                            if let [_, a, b, c, d, e, f, ..] = points[..] {
+                                self.curve_to(a.0, a.1, b.0, b.1, c.0, c.1);
+                                self.curve_to(d.0, d.1, e.0, e.1, f.0, f.1);
+                            }
+                        }
+                        self.ps_stack = vec![self.y, self.x];
+                    }
+                    _ => self.ps_stack = args.into_iter().rev().collect(),
+                }
+            }
+            17 => {
+                let value = self.ps_stack.pop().unwrap_or(0.);
+                self.stack.push(value);
+            }
+            33 => {
+                if let [.., x, y] = self.stack[..] {
+                    self.x = x;
+                    self.y = y;
+                }
+                self.stack.clear();
+            }
+            _ => self.stack.clear(),
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypt(data: &[u8], key: u16) -> Vec<u8> {
+        let mut r = key;
+        data.iter().map(|&p| {
+            let c = p ^ (r >> 8) as u8;
+            r = (c as u16).wrapping_add(r).wrapping_mul(52845).wrapping_add(22719);
+            c
+        }).collect()
+    }
+
+    #[test]
+    fn test_type1() {
+        // 0 500 hsbw 100 100 rmoveto 200 hlineto 200 vlineto closepath endchar
+        let square = encrypt(&[0, 0, 0, 0, 139, 248, 136, 13, 239, 239, 21, 247, 92, 6, 247, 92, 7, 9, 14], CHARSTRING_KEY);
+        let mut private = b"    dup /Private 8 dict dup begin /lenIV 4 def /Subrs 0 array\n2 index /CharStrings 2 dict dup begin\n/square ".to_vec();
+        private.extend(format!("{} RD ", square.len()).bytes());
+        private.extend(&square);
+        private.extend(b" ND\nend\n");
+        let mut data = b"%!PS-AdobeFont-1.0: Test\n/FontName /Test def\n/FontMatrix [0.001 0 0 0.001 0 0] readonly def\n/Encoding 256 array\n0 1 255 {1 index exch /.notdef put} for\ndup 65 /square put\nreadonly def\ncurrentfile eexec\n".to_vec();
+        data.extend(encrypt(&private, 55665));
+
+        let font = Type1Font::parse(&data).unwrap();
+        assert_eq!(font.name, "Test");
+        assert_eq!(font.encoding[&65], "square");
+        assert_eq!(font.glyph_names, vec!["square"]);
+        let glyph = font.glyph("square", |_| None).unwrap();
+        assert_eq!(glyph.width, 500.);
+        assert_eq!(glyph.segments, vec![
+            Segment::MoveTo(100., 100.),
+            Segment::LineTo(300., 100.),
+            Segment::LineTo(300., 300.),
+            Segment::Close,
+        ]);
+    }
+}
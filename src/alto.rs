@@ -0,0 +1,76 @@
+use super::hocr::{escape, flip, page_box};
+use super::layout::Block;
+
+// Tenths of a millimetre per PDF point, the mm10 unit declared in the description.
+const MM10: f64 = 254. / 72.;
+
+fn position(bbox: (f64, f64, f64, f64)) -> String {
+    format!(
+        "HPOS=\"{:.2}\" VPOS=\"{:.2}\" WIDTH=\"{:.2}\" HEIGHT=\"{:.2}\"",
+        bbox.0 * MM10, bbox.1 * MM10, (bbox.2 - bbox.0) * MM10, (bbox.3 - bbox.1) * MM10,
+    )
+}
+
+pub fn to_alto(pages: &[Vec<Block>], media_boxes: &[Option<(f64, f64, f64, f64)>]) -> String {
+    let mut ret = String::new();
+    ret.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ret.push_str("<alto xmlns=\"http://www.loc.gov/standards/alto/ns-v4#\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" ");
+    ret.push_str("xsi:schemaLocation=\"http://www.loc.gov/standards/alto/ns-v4# http://www.loc.gov/standards/alto/v4/alto-4-2.xsd\">\n");
+    ret.push_str("<Description>\n<MeasurementUnit>mm10</MeasurementUnit>\n<Processing ID=\"processing_1\">\n<processingSoftware>\n");
+    ret.push_str(&format!(
+        "<softwareName>{}</softwareName>\n<softwareVersion>{}</softwareVersion>\n",
+        env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),
+    ));
+    ret.push_str("</processingSoftware>\n</Processing>\n</Description>\n<Layout>\n");
+
+    for (p, blocks) in pages.iter().enumerate() {
+        let page = page_box(blocks, media_boxes.get(p).cloned().flatten());
+        let (n, mut line_id, mut word_id) = (p + 1, 0, 0);
+        let (width, height) = ((page.2 - page.0) * MM10, (page.3 - page.1) * MM10);
+        ret.push_str(&format!(
+            "<Page ID=\"page_{n}\" PHYSICAL_IMG_NR=\"{n}\" WIDTH=\"{width:.2}\" HEIGHT=\"{height:.2}\">\n",
+        ));
+        ret.push_str(&format!("<PrintSpace {}>\n", position(flip(page, page))));
+        for (b, block) in blocks.iter().enumerate() {
+            ret.push_str(&format!("<TextBlock ID=\"block_{n}_{}\" {}>\n", b + 1, position(flip(block.bbox(), page))));
+            for line in &block.lines {
+                line_id += 1;
+                let bbox = flip((line.x0, line.y0, line.x1, line.y1), page);
+                ret.push_str(&format!("<TextLine ID=\"line_{n}_{line_id}\" {}>\n", position(bbox)));
+                for (i, word) in line.words().iter().enumerate() {
+                    word_id += 1;
+                    if i > 0 {
+                        ret.push_str("<SP/>\n");
+                    }
+                    ret.push_str(&format!(
+                        "<String ID=\"string_{n}_{word_id}\" {} CONTENT=\"{}\"/>\n",
+                        position(flip(word.bbox, page)),
+                        escape(&word.text),
+                    ));
+                }
+                ret.push_str("</TextLine>\n");
+            }
+            ret.push_str("</TextBlock>\n");
+        }
+        ret.push_str("</PrintSpace>\n</Page>\n");
+    }
+    ret.push_str("</Layout>\n</alto>\n");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::layout::get_lines;
+    use super::super::operation::TextPaintingOperation;
+
+    #[test]
+    fn test_alto() {
+        let span = TextPaintingOperation::test_span(72., 720., "Tom & Jerry");
+        let pages = vec![vec![Block { lines: get_lines(vec![span]) }]];
+        let alto = to_alto(&pages, &[Some((0., 0., 612., 792.))]);
+        assert!(alto.contains("<Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"2159.00\" HEIGHT=\"2794.00\">"));
+        assert!(alto.contains("<String ID=\"string_1_1\" HPOS=\"254.00\" VPOS=\"225.78\" WIDTH=\"52.92\" HEIGHT=\"35.28\" CONTENT=\"Tom\"/>\n<SP/>"));
+        assert!(alto.contains("CONTENT=\"&amp;\""));
+    }
+}
@@ -0,0 +1,344 @@
+// CCITT Group 3 and Group 4 fax data as in CCITTFaxDecode, decoded one coding line at
+// a time against the line above. Uncompressed mode isn't supported.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    // Negative for two-dimensional coding alone, 0 for one-dimensional coding alone, and
+    // positive for a mix of both with a tag bit before every line.
+    pub k: i64,
+    pub columns: usize,
+    // Lines to decode, or 0 to decode up to the end of data.
+    pub rows: usize,
+    pub encoded_byte_align: bool,
+    pub black_is_1: bool,
+    pub end_of_block: bool,
+}
+
+impl Default for Params {
+    fn default() -> Params {
+        Params { k: 0, columns: 1728, rows: 0, encoded_byte_align: false, black_is_1: false, end_of_block: true }
+    }
+}
+
+// Codes of white runs as their lengths in bits and values: terminating codes of runs of
+// 0 to 63, then makeup codes of 64 to 1728.
+const WHITE: [(u32, u32); 91] = [
+    (8, 0b00110101), (6, 0b000111), (4, 0b0111), (4, 0b1000), (4, 0b1011), (4, 0b1100),
+    (4, 0b1110), (4, 0b1111), (5, 0b10011), (5, 0b10100), (5, 0b00111), (5, 0b01000),
+    (6, 0b001000), (6, 0b000011), (6, 0b110100), (6, 0b110101), (6, 0b101010), (6, 0b101011),
+    (7, 0b0100111), (7, 0b0001100), (7, 0b0001000), (7, 0b0010111), (7, 0b0000011), (7, 0b0000100),
+    (7, 0b0101000), (7, 0b0101011), (7, 0b0010011), (7, 0b0100100), (7, 0b0011000),
+    (8, 0b00000010), (8, 0b00000011), (8, 0b00011010), (8, 0b00011011), (8, 0b00010010),
+    (8, 0b00010011), (8, 0b00010100), (8, 0b00010101), (8, 0b00010110), (8, 0b00010111),
+    (8, 0b00101000), (8, 0b00101001), (8, 0b00101010), (8, 0b00101011), (8, 0b00101100),
+    (8, 0b00101101), (8, 0b00000100), (8, 0b00000101), (8, 0b00001010), (8, 0b00001011),
+    (8, 0b01010010), (8, 0b01010011), (8, 0b01010100), (8, 0b01010101), (8, 0b00100100),
+    (8, 0b00100101), (8, 0b01011000), (8, 0b01011001), (8, 0b01011010), (8, 0b01011011),
+    (8, 0b01001010), (8, 0b01001011), (8, 0b00110010), (8, 0b00110011), (8, 0b00110100),
+    (5, 0b11011), (5, 0b10010), (6, 0b010111), (7, 0b0110111), (8, 0b00110110), (8, 0b00110111),
+    (8, 0b01100100), (8, 0b01100101), (8, 0b01101000), (8, 0b01100111), (9, 0b011001100),
+    (9, 0b011001101), (9, 0b011010010), (9, 0b011010011), (9, 0b011010100), (9, 0b011010101),
+    (9, 0b011010110), (9, 0b011010111), (9, 0b011011000), (9, 0b011011001), (9, 0b011011010),
+    (9, 0b011011011), (9, 0b010011000), (9, 0b010011001), (9, 0b010011010), (6, 0b011000),
+    (9, 0b010011011),
+];
+
+// Codes of black runs, in the same order.
+const BLACK: [(u32, u32); 91] = [
+    (10, 0b0000110111), (3, 0b010), (2, 0b11), (2, 0b10), (3, 0b011), (4, 0b0011), (4, 0b0010),
+    (5, 0b00011), (6, 0b000101), (6, 0b000100), (7, 0b0000100), (7, 0b0000101), (7, 0b0000111),
+    (8, 0b00000100), (8, 0b00000111), (9, 0b000011000), (10, 0b0000010111), (10, 0b0000011000),
+    (10, 0b0000001000), (11, 0b00001100111), (11, 0b00001101000), (11, 0b00001101100),
+    (11, 0b00000110111), (11, 0b00000101000), (11, 0b00000010111), (11, 0b00000011000),
+    (12, 0b000011001010), (12, 0b000011001011), (12, 0b000011001100), (12, 0b000011001101),
+    (12, 0b000001101000), (12, 0b000001101001), (12, 0b000001101010), (12, 0b000001101011),
+    (12, 0b000011010010), (12, 0b000011010011), (12, 0b000011010100), (12, 0b000011010101),
+    (12, 0b000011010110), (12, 0b000011010111), (12, 0b000001101100), (12, 0b000001101101),
+    (12, 0b000011011010), (12, 0b000011011011), (12, 0b000001010100), (12, 0b000001010101),
+    (12, 0b000001010110), (12, 0b000001010111), (12, 0b000001100100), (12, 0b000001100101),
+    (12, 0b000001010010), (12, 0b000001010011), (12, 0b000000100100), (12, 0b000000110111),
+    (12, 0b000000111000), (12, 0b000000100111), (12, 0b000000101000), (12, 0b000001011000),
+    (12, 0b000001011001), (12, 0b000000101011), (12, 0b000000101100), (12, 0b000001011010),
+    (12, 0b000001100110), (12, 0b000001100111), (10, 0b0000001111), (12, 0b000011001000),
+    (12, 0b000011001001), (12, 0b000001011011), (12, 0b000000110011), (12, 0b000000110100),
+    (12, 0b000000110101), (13, 0b0000001101100), (13, 0b0000001101101), (13, 0b0000001001010),
+    (13, 0b0000001001011), (13, 0b0000001001100), (13, 0b0000001001101), (13, 0b0000001110010),
+    (13, 0b0000001110011), (13, 0b0000001110100), (13, 0b0000001110101), (13, 0b0000001110110),
+    (13, 0b0000001110111), (13, 0b0000001010010), (13, 0b0000001010011), (13, 0b0000001010100),
+    (13, 0b0000001010101), (13, 0b0000001011010), (13, 0b0000001011011), (13, 0b0000001100100),
+    (13, 0b0000001100101),
+];
+
+// Makeup codes of runs of 1792 to 2560, for both colors.
+const EXTENDED: [(u32, u32); 13] = [
+    (11, 0b00000001000), (11, 0b00000001100), (11, 0b00000001101), (12, 0b000000010010),
+    (12, 0b000000010011), (12, 0b000000010100), (12, 0b000000010101), (12, 0b000000010110),
+    (12, 0b000000010111), (12, 0b000000011100), (12, 0b000000011101), (12, 0b000000011110),
+    (12, 0b000000011111),
+];
+
+const EOL: u32 = 1;
+
+// Lookup tables of 13 bit prefixes, giving the length of their code and the run.
+struct Codes {
+    white: Vec<(u32, usize)>,
+    black: Vec<(u32, usize)>,
+}
+
+impl Codes {
+    fn new() -> Codes {
+        let table = |codes: &[(u32, u32)]| {
+            let mut table = vec![(0, 0); 1 << 13];
+            let runs = (0 .. 64).chain((1 ..= 27).map(|x| x * 64)).zip(codes).chain((28 .. 41).map(|x| x * 64).zip(&EXTENDED));
+            for (run, &(bits, code)) in runs {
+                let start = (code << (13 - bits)) as usize;
+                table[start .. start + (1 << (13 - bits))].fill((bits, run));
+            }
+            table
+        };
+        Codes { white: table(&WHITE), black: table(&BLACK) }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    // The next n bits, with zeros past the end of data.
+    fn peek(&self, n: usize) -> u32 {
+        (0 .. n).fold(0, |a, i| {
+            let bit = self.position + i;
+            let byte = self.data.get(bit / 8).copied().unwrap_or(0);
+            a << 1 | (byte >> (7 - bit % 8) & 1) as u32
+        })
+    }
+
+    fn read(&mut self, n: usize) -> u32 {
+        let value = self.peek(n);
+        self.position += n;
+        value
+    }
+
+    fn done(&self) -> bool {
+        self.position >= self.data.len() * 8
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.next_multiple_of(8);
+    }
+
+    // A run of one color: makeup codes up to a terminating code.
+    fn run(&mut self, codes: &Codes, white: bool) -> Option<usize> {
+        let table = if white { &codes.white } else { &codes.black };
+        let mut ret = 0;
+        loop {
+            let (bits, run) = table[self.peek(13) as usize];
+            if bits == 0 || self.done() {
+                return None;
+            }
+            self.position += bits as usize;
+            ret += run;
+            if run < 64 {
+                return Some(ret);
+            }
+        }
+    }
+}
+
+// Lines are the ends of their runs, white and black in turn, starting with a white run
+// that may be empty.
+fn add(line: &mut Vec<usize>, end: usize, black: bool, columns: usize) {
+    let end = end.min(columns);
+    if end > line[line.len() - 1] {
+        if (line.len().is_multiple_of(2)) == black {
+            *line.last_mut().unwrap() = end;
+        } else {
+            line.push(end);
+        }
+    }
+}
+
+fn decode_1d(bits: &mut Bits, codes: &Codes, columns: usize) -> Option<Vec<usize>> {
+    let mut line = vec![0];
+    let (mut x, mut white) = (0, true);
+    while x < columns {
+        x += bits.run(codes, white)?;
+        add(&mut line, x, !white, columns);
+        white = !white;
+    }
+    Some(line)
+}
+
+fn decode_2d(bits: &mut Bits, codes: &Codes, reference: &[usize], columns: usize) -> Option<Vec<usize>> {
+    let mut line = vec![0];
+    // The position of a0, with -1 before the first pixel, and its color.
+    let (mut a0, mut white) = (-1isize, true);
+    let mut i = 0;
+    while a0 < columns as isize {
+        // b1 is the first change on the reference line after a0 to the color opposite a0,
+        // where changes at even indices are to black.
+        while i > 0 && reference[i - 1] as isize > a0 {
+            i -= 1;
+        }
+        while reference[i] as isize <= a0 && reference[i] < columns || (i % 2 == 0) != white {
+            i += 1;
+        }
+        let (b1, b2) = (reference[i] as isize, reference[i + 1] as isize);
+        let code = bits.peek(7);
+        let a1 = if code >> 6 == 1 {
+            bits.position += 1;
+            b1
+        } else if code >> 4 == 0b011 || code >> 4 == 0b010 {
+            bits.position += 3;
+            b1 + if code >> 4 == 0b011 { 1 } else { -1 }
+        } else if code >> 4 == 0b001 {
+            // Horizontal mode: two runs, of the color of a0 then the other.
+            bits.position += 3;
+            let start = a0.max(0) as usize;
+            let a1 = start + bits.run(codes, white)?;
+            let a2 = a1 + bits.run(codes, !white)?;
+            add(&mut line, a1, !white, columns);
+            add(&mut line, a2, white, columns);
+            a0 = a2 as isize;
+            continue;
+        } else if code >> 3 == 0b0001 {
+            // Pass mode: a0 moves under b2 and keeps its color.
+            bits.position += 4;
+            add(&mut line, b2 as usize, !white, columns);
+            a0 = b2;
+            continue;
+        } else if code >> 1 == 0b000011 || code >> 1 == 0b000010 {
+            bits.position += 6;
+            b1 + if code >> 1 == 0b000011 { 2 } else { -2 }
+        } else if code == 0b0000011 || code == 0b0000010 {
+            bits.position += 7;
+            b1 + if code == 0b0000011 { 3 } else { -3 }
+        } else {
+            return None;
+        };
+        if a1 < a0.max(0) || a1 > columns as isize {
+            return None;
+        }
+        add(&mut line, a1 as usize, !white, columns);
+        a0 = a1;
+        white = !white;
+    }
+    Some(line)
+}
+
+// Decodes to rows of 1 bit samples, with 0 for black unless `black_is_1`. Rows damaged
+// or missing up to `height` are left white; a height of 0 decodes up to the end of data.
+pub fn decode(data: &[u8], params: &Params, height: usize) -> Vec<u8> {
+    let codes = Codes::new();
+    let columns = params.columns.max(1);
+    let stride = columns.div_ceil(8);
+    let rows = match (params.rows, height) {
+        (0, 0) => usize::MAX,
+        (rows, 0) => rows,
+        (0, height) => height,
+        (rows, height) => rows.min(height),
+    };
+    let (white, black) = if params.black_is_1 { (0, 1) } else { (1, 0) };
+    let mut ret = vec![if white == 1 { 0xff } else { 0 }; stride * height];
+    let mut bits = Bits { data, position: 0 };
+    // Changes of the line above; the line above the first is white.
+    let mut reference = vec![columns; 3];
+    for row in 0 .. rows {
+        if params.encoded_byte_align {
+            bits.align();
+        }
+        // Lines of one-dimensional data may follow end-of-line codes and fill bits, and six
+        // in a row end the data. Two end two-dimensional data.
+        let mut eols = 0;
+        if params.k >= 0 {
+            while bits.peek(12) == 0 && !bits.done() {
+                bits.position += 1;
+            }
+            while bits.peek(12) == EOL {
+                bits.position += 12;
+                eols += 1;
+                // In mixed data, the codes ending it are each followed by a tag bit.
+                if params.k > 0 && bits.peek(13) & 0xfff == EOL {
+                    bits.position += 1;
+                }
+                while bits.peek(12) == 0 && !bits.done() {
+                    bits.position += 1;
+                }
+            }
+        } else if bits.peek(24) == EOL << 12 | EOL {
+            eols = 2;
+        }
+        let end = if params.k < 0 { 2 } else { 6 };
+        if params.end_of_block && eols >= end || bits.done() {
+            break;
+        }
+        let two_dimensional = match params.k {
+            k if k < 0 => true,
+            0 => false,
+            _ => bits.read(1) == 0,
+        };
+        let line = match two_dimensional {
+            true => decode_2d(&mut bits, &codes, &reference, columns),
+            false => decode_1d(&mut bits, &codes, columns),
+        };
+        let Some(mut line) = line else { break };
+        let last_black = line.len() % 2 == 0;
+        add(&mut line, columns, last_black, columns);
+        if ret.len() < (row + 1) * stride {
+            ret.resize((row + 1) * stride, if white == 1 { 0xff } else { 0 });
+        }
+        let out = &mut ret[row * stride .. (row + 1) * stride];
+        let mut x = 0;
+        for (i, &end) in line.iter().enumerate() {
+            let value = if i % 2 == 0 { white } else { black };
+            for pixel in x .. end {
+                out[pixel / 8] = out[pixel / 8] & !(0x80 >> (pixel % 8)) | value << (7 - pixel % 8);
+            }
+            x = end;
+        }
+        line.extend([columns, columns]);
+        reference = line;
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Packs a string of 0s and 1s, ignoring spaces.
+    fn pack(bits: &str) -> Vec<u8> {
+        let bits = bits.bytes().filter(|&x| x != b' ').collect::<Vec<_>>();
+        bits.chunks(8).map(|x| x.iter().enumerate().fold(0, |a, (i, &x)| a | (x - b'0') << (7 - i))).collect()
+    }
+
+    #[test]
+    fn test_1d() {
+        // White 2, black 3 and white 3, then a white line; then white 64 + 6 and black 30.
+        let params = Params { columns: 8, ..Params::default() };
+        assert_eq!(decode(&pack("0111 10 1000 10011"), &params, 2), vec![0b1100_0111, 0xff]);
+        let params = Params { columns: 100, black_is_1: true, ..Params::default() };
+        let row = decode(&pack("11011 1110 000001101000"), &params, 1);
+        assert_eq!(row[8 .. 13], [0b0000_0011, 0xff, 0xff, 0xff, 0xf0]);
+    }
+
+    #[test]
+    fn test_2d() {
+        // Horizontal and vertical modes, vertical modes to the right, and a pass.
+        let rows = ["001 0111 10 1", "011 011 1", "0001 1"];
+        let expected = vec![0b1100_0111, 0b1110_0011, 0xff];
+        let params = Params { k: -1, columns: 8, ..Params::default() };
+        let eofb = "000000000001 000000000001";
+        assert_eq!(decode(&pack(&format!("{} {eofb}", rows.concat())), &params, 4), [&expected[..], &[0xff]].concat());
+        let params = Params { encoded_byte_align: true, ..params };
+        let aligned = rows.iter().flat_map(|x| pack(x)).collect::<Vec<_>>();
+        assert_eq!(decode(&aligned, &params, 3), expected);
+        // Lines after end-of-line codes, tagged one or two dimensional, up to the end of data.
+        let params = Params { k: 2, columns: 8, ..Params::default() };
+        let eol = "000000000001";
+        let data = format!("{eol} 1 0111 10 1000 {eol} 0 {} {}", rows[1], format!("{eol} 1").repeat(6));
+        assert_eq!(decode(&pack(&data), &params, 2), expected[.. 2]);
+    }
+}
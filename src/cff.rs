@@ -0,0 +1,803 @@
+use super::encoding::standard_encoding;
+use super::operation::Matrix;
+use super::path::Segment;
+use std::collections::HashMap;
+
+pub const CHARSET: u16 = 15;
+pub const ENCODING: u16 = 16;
+pub const CHARSTRINGS: u16 = 17;
+pub const FONT_BBOX: u16 = 5;
+pub const PRIVATE: u16 = 18;
+pub const SUBRS: u16 = 19;
+pub const DEFAULT_WIDTH: u16 = 20;
+pub const NOMINAL_WIDTH: u16 = 21;
+pub const VSINDEX: u16 = 22;
+pub const VSTORE: u16 = 24;
+pub const FONT_MATRIX: u16 = 1207;
+pub const ROS: u16 = 1230;
+pub const FD_ARRAY: u16 = 1236;
+pub const FD_SELECT: u16 = 1237;
+
+fn offset(data: &[u8], pos: usize, size: usize) -> Option<usize> {
+    let bytes = data.get(pos .. pos + size)?;
+    Some(bytes.iter().fold(0, |acc, &b| acc << 8 | b as usize))
+}
+
+// Returns the items of the INDEX at `pos` and the position right after it.
+pub fn read_index(data: &[u8], pos: usize) -> Option<(Vec<&[u8]>, usize)> {
+    index(data, pos, 2)
+}
+
+// CFF2 INDEXes have a 4 byte count.
+fn index(data: &[u8], pos: usize, count_size: usize) -> Option<(Vec<&[u8]>, usize)> {
+    let count = offset(data, pos, count_size)?;
+    if count == 0 {
+        return Some((Vec::new(), pos + count_size));
+    }
+    let size = *data.get(pos + count_size)? as usize;
+    if !(1 ..= 4).contains(&size) || count > data.len() {
+        return None;
+    }
+    let start = pos + count_size + 1;
+    let base = start + (count + 1) * size - 1;
+    let mut offsets = Vec::with_capacity(count + 1);
+    for i in 0 ..= count {
+        offsets.push(base + offset(data, start + i * size, size)?);
+    }
+    let mut ret = Vec::with_capacity(count);
+    for w in offsets.windows(2) {
+        ret.push(data.get(w[0] .. w[1])?);
+    }
+    Some((ret, offsets[count]))
+}
+
+fn real(data: &[u8], pos: &mut usize) -> f64 {
+    let mut s = String::new();
+    'outer: while let Some(&b) = data.get(*pos) {
+        *pos += 1;
+        for nibble in [b >> 4, b & 15] {
+            match nibble {
+                0 ..= 9 => s.push((b'0' + nibble) as char),
+                0xa => s.push('.'),
+                0xb => s.push('E'),
+                0xc => s.push_str("E-"),
+                0xe => s.push('-'),
+                0xf => break 'outer,
+                _ => {}
+            }
+        }
+    }
+    s.parse().unwrap_or(0.)
+}
+
+// Operators are keyed by their byte, escaped operators by 1200 + second byte.
+pub fn parse_dict(data: &[u8]) -> HashMap<u16, Vec<f64>> {
+    let mut ret = HashMap::new();
+    let mut operands = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let b0 = data[pos];
+        pos += 1;
+        let byte = |p: usize| data.get(p).cloned().unwrap_or(0) as i32;
+        match b0 {
+            0 ..= 21 => {
+                let op = if b0 == 12 {
+                    pos += 1;
+                    1200 + byte(pos - 1) as u16
+                } else {
+                    b0 as u16
+                };
+                ret.insert(op, std::mem::take(&mut operands));
+            }
+            28 => {
+                operands.push((byte(pos) << 8 | byte(pos + 1)) as i16 as f64);
+                pos += 2;
+            }
+            29 => {
+                operands.push((byte(pos) << 24 | byte(pos + 1) << 16 | byte(pos + 2) << 8 | byte(pos + 3)) as f64);
+                pos += 4;
+            }
+            30 => operands.push(real(data, &mut pos)),
+            32 ..= 246 => operands.push(b0 as f64 - 139.),
+            247 ..= 250 => {
+                operands.push(((b0 as i32 - 247) * 256 + byte(pos) + 108) as f64);
+                pos += 1;
+            }
+            251 ..= 254 => {
+                operands.push((-(b0 as i32 - 251) * 256 - byte(pos) - 108) as f64);
+                pos += 1;
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Private<'a> {
+    pub subrs: Vec<&'a [u8]>,
+    pub default_width: f64,
+    pub nominal_width: f64,
+    pub vsindex: usize,
+}
+
+pub struct Cff<'a> {
+    pub data: &'a [u8],
+    pub version: u8,
+    pub name: String,
+    pub top: HashMap<u16, Vec<f64>>,
+    pub strings: Vec<&'a [u8]>,
+    pub global_subrs: Vec<&'a [u8]>,
+    pub charstrings: Vec<&'a [u8]>,
+    privates: Vec<Private<'a>>,
+    // Number of regions of every item variation data in a CFF2 variation store.
+    regions: Vec<usize>,
+}
+
+impl<'a> Cff<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Cff<'a>> {
+        let version = *data.first()?;
+        let header_size = *data.get(2)? as usize;
+        let (name, top, strings, global_subrs, count_size) = if version == 2 {
+            let length = offset(data, 3, 2)?;
+            let top = parse_dict(data.get(header_size .. header_size + length)?);
+            let (global_subrs, _) = index(data, header_size + length, 4)?;
+            (String::new(), top, Vec::new(), global_subrs, 4)
+        } else {
+            let (names, pos) = read_index(data, header_size)?;
+            let (tops, pos) = read_index(data, pos)?;
+            let (strings, pos) = read_index(data, pos)?;
+            let (global_subrs, _) = read_index(data, pos)?;
+            (String::from_utf8_lossy(names.first()?).into(), parse_dict(tops.first()?), strings, global_subrs, 2)
+        };
+        let charstrings = match top.get(&CHARSTRINGS).and_then(|x| x.first()) {
+            Some(&at) => index(data, at as usize, count_size)?.0,
+            None => Vec::new(),
+        };
+        let mut cff = Cff { data, version, name, top, strings, global_subrs, charstrings, privates: Vec::new(), regions: Vec::new() };
+        cff.privates = match cff.top.get(&FD_ARRAY).and_then(|x| x.first()) {
+            Some(&at) => index(data, at as usize, count_size)?.0.into_iter().map(|x| cff.load_private(&parse_dict(x))).collect(),
+            None => vec![cff.load_private(&cff.top)],
+        };
+        cff.regions = cff.variation_regions().unwrap_or_default();
+        Some(cff)
+    }
+
+    fn load_private(&self, dict: &HashMap<u16, Vec<f64>>) -> Private<'a> {
+        let (size, at) = match dict.get(&PRIVATE).map(|x| &x[..]) {
+            Some(&[size, at]) => (size as usize, at as usize),
+            _ => return Private::default(),
+        };
+        let Some(private) = self.data.get(at .. at + size).map(parse_dict) else { return Private::default() };
+        let value = |key| private.get(&key).and_then(|x| x.first()).cloned();
+        let count_size = if self.version == 2 { 4 } else { 2 };
+        Private {
+            subrs: value(SUBRS).and_then(|x| index(self.data, at + x as usize, count_size)).map(|x| x.0).unwrap_or_default(),
+            default_width: value(DEFAULT_WIDTH).unwrap_or(0.),
+            nominal_width: value(NOMINAL_WIDTH).unwrap_or(0.),
+            vsindex: value(VSINDEX).unwrap_or(0.) as usize,
+        }
+    }
+
+    fn variation_regions(&self) -> Option<Vec<usize>> {
+        let store = *self.top.get(&VSTORE)?.first()? as usize + 2;
+        let count = offset(self.data, store + 6, 2)?;
+        (0 .. count).map(|i| {
+            let at = store + offset(self.data, store + 8 + 4 * i, 4)?;
+            offset(self.data, at + 4, 2)
+        }).collect()
+    }
+
+    pub fn is_cid(&self) -> bool {
+        self.top.contains_key(&ROS)
+    }
+
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
+        match self.top.get(&FONT_BBOX).map(|x| &x[..]) {
+            Some(&[a, b, c, d]) => (a, b, c, d),
+            _ => (0., 0., 0., 0.),
+        }
+    }
+
+    // Standard strings come first, then the String INDEX.
+    pub fn string(&self, sid: usize) -> Option<String> {
+        if sid < STANDARD_STRINGS.len() {
+            return Some(STANDARD_STRINGS[sid].into());
+        }
+        self.strings.get(sid - STANDARD_STRINGS.len()).map(|x| String::from_utf8_lossy(x).into())
+    }
+
+    pub fn units_per_em(&self) -> f64 {
+        match self.top.get(&FONT_MATRIX).and_then(|x| x.first()) {
+            Some(&a) if a > 0. => (1. / a).round(),
+            _ => 1000.,
+        }
+    }
+
+    pub fn font_matrix(&self) -> [f64; 6] {
+        match self.top.get(&FONT_MATRIX).map(|x| &x[..]) {
+            Some(&[a, b, c, d, e, f]) => [a, b, c, d, e, f],
+            _ => [0.001, 0., 0., 0.001, 0., 0.],
+        }
+    }
+
+    // The SID (or CID for CID-keyed fonts) of every glyph.
+    pub fn charset(&self) -> Vec<u16> {
+        let n = self.charstrings.len();
+        let at = match self.top.get(&CHARSET).and_then(|x| x.first()) {
+            Some(&at) if at > 2. && self.version == 1 => at as usize,
+            _ => return (0 .. n as u16).collect(),
+        };
+        let mut ret = vec![0];
+        let mut pos = at + 1;
+        let format = self.data.get(at).cloned().unwrap_or(3);
+        while ret.len() < n {
+            let Some(first) = offset(self.data, pos, 2) else { break };
+            match format {
+                0 => {
+                    ret.push(first as u16);
+                    pos += 2;
+                }
+                1 | 2 => {
+                    let size = format as usize;
+                    let Some(left) = offset(self.data, pos + 2, size) else { break };
+                    ret.extend((first ..= first + left).map(|x| x as u16));
+                    pos += 2 + size;
+                }
+                _ => break,
+            }
+        }
+        ret.truncate(n);
+        ret
+    }
+
+    pub fn glyph_names(&self) -> Vec<Option<String>> {
+        if self.is_cid() || self.version == 2 {
+            return vec![None; self.charstrings.len()];
+        }
+        self.charset().into_iter().map(|x| self.string(x as usize)).collect()
+    }
+
+    pub fn gid_for_cid(&self, cid: u32) -> Option<u16> {
+        if !self.is_cid() {
+            return Some(cid as u16).filter(|&x| (x as usize) < self.charstrings.len());
+        }
+        self.charset().iter().position(|&x| x as u32 == cid).map(|x| x as u16)
+    }
+
+    // The built-in encoding as code to glyph.
+    pub fn encoding(&self) -> HashMap<u8, u16> {
+        let mut ret = HashMap::new();
+        if self.is_cid() || self.version == 2 {
+            return ret;
+        }
+        let charset = self.charset();
+        let by_sid = |sid: u16| charset.iter().position(|&x| x == sid).map(|x| x as u16);
+        let at = match self.top.get(&ENCODING).and_then(|x| x.first()) {
+            Some(&at) if at > 1. => at as usize,
+            // Expert encoded fonts are left without a built-in mapping.
+            Some(&1.) => return ret,
+            _ => {
+                for code in 0 ..= 255 {
+                    let gid = standard_encoding(code)
+                        .and_then(|name| STANDARD_STRINGS.iter().position(|&x| x == name))
+                        .and_then(|sid| by_sid(sid as u16));
+                    if let Some(gid) = gid {
+                        ret.insert(code, gid);
+                    }
+                }
+                return ret;
+            }
+        };
+        let Some(&format) = self.data.get(at) else { return ret };
+        let count = self.data.get(at + 1).cloned().unwrap_or(0) as usize;
+        let mut pos = at + 2;
+        match format & 0x7f {
+            0 => {
+                for gid in 1 ..= count {
+                    if let Some(&code) = self.data.get(pos) {
+                        ret.insert(code, gid as u16);
+                    }
+                    pos += 1;
+                }
+            }
+            1 => {
+                let mut gid = 1;
+                for _ in 0 .. count {
+                    let (Some(&first), Some(&left)) = (self.data.get(pos), self.data.get(pos + 1)) else { break };
+                    for code in first as usize ..= first as usize + left as usize {
+                        ret.insert(code as u8, gid);
+                        gid += 1;
+                    }
+                    pos += 2;
+                }
+            }
+            _ => return ret,
+        }
+        if format & 0x80 != 0 {
+            let count = self.data.get(pos).cloned().unwrap_or(0) as usize;
+            for i in 0 .. count {
+                let at = pos + 1 + 3 * i;
+                if let (Some(&code), Some(sid)) = (self.data.get(at), offset(self.data, at + 1, 2)) {
+                    if let Some(gid) = by_sid(sid as u16) {
+                        ret.insert(code, gid);
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    fn fd_index(&self, gid: u16) -> usize {
+        let Some(&at) = self.top.get(&FD_SELECT).and_then(|x| x.first()) else { return 0 };
+        let (at, gid) = (at as usize, gid as usize);
+        let fd = match self.data.get(at) {
+            Some(0) => self.data.get(at + 1 + gid).map(|&x| x as usize),
+            Some(3) => (0 .. offset(self.data, at + 1, 2).unwrap_or(0))
+                .take_while(|i| offset(self.data, at + 3 + 3 * i, 2).is_some_and(|x| x <= gid))
+                .last()
+                .and_then(|i| self.data.get(at + 5 + 3 * i).map(|&x| x as usize)),
+            Some(4) => (0 .. offset(self.data, at + 1, 4).unwrap_or(0))
+                .take_while(|i| offset(self.data, at + 5 + 6 * i, 4).is_some_and(|x| x <= gid))
+                .last()
+                .and_then(|i| offset(self.data, at + 9 + 6 * i, 2)),
+            _ => None,
+        };
+        fd.filter(|&x| x < self.privates.len()).unwrap_or(0)
+    }
+
+    fn private_for(&self, gid: u16) -> Option<&Private<'a>> {
+        self.privates.get(self.fd_index(gid))
+    }
+
+    // Advance widths read from the optional first operand of every charstring.
+    pub fn widths(&self) -> Vec<f64> {
+        self.charstrings.iter().enumerate().map(|(gid, x)| {
+            let (default, nominal) = self.private_for(gid as u16).map_or((0., 0.), |p| (p.default_width, p.nominal_width));
+            charstring_width(x).map_or(default, |w| nominal + w)
+        }).collect()
+    }
+
+    // Glyph outline in font units.
+    pub fn outline(&self, gid: u16) -> Vec<Segment> {
+        self.outline_at(gid, 0)
+    }
+
+    fn outline_at(&self, gid: u16, depth: usize) -> Vec<Segment> {
+        let mut interpreter = Interpreter::new(self, gid);
+        interpreter.run(self.charstrings.get(gid as usize).cloned().unwrap_or_default(), 0);
+        interpreter.close();
+        let mut segments = interpreter.segments;
+        if let Some((dx, dy, base, accent)) = interpreter.seac.filter(|_| depth < 2) {
+            let encoding = self.encoding();
+            let glyph = |code: u8| standard_encoding(code)
+                .and_then(|name| STANDARD_STRINGS.iter().position(|&x| x == name))
+                .and_then(|sid| self.charset().iter().position(|&x| x as usize == sid))
+                .or_else(|| encoding.get(&code).map(|&x| x as usize));
+            if let Some(base) = glyph(base).filter(|&x| x != gid as usize) {
+                segments = self.outline_at(base as u16, depth + 1);
+            }
+            if let Some(accent) = glyph(accent).filter(|&x| x != gid as usize) {
+                segments.extend(self.outline_at(accent as u16, depth + 1).into_iter().map(|x| x.transform(&Matrix::translate(dx, dy))));
+            }
+        }
+        segments
+    }
+}
+
+fn subr_bias(count: usize) -> usize {
+    match count {
+        0 ..= 1239 => 107,
+        1240 ..= 33899 => 1131,
+        _ => 32768,
+    }
+}
+
+// Type 2 charstring interpreter.
+struct Interpreter<'a, 'b> {
+    cff: &'b Cff<'a>,
+    private: Option<&'b Private<'a>>,
+    stack: Vec<f64>,
+    segments: Vec<Segment>,
+    x: f64,
+    y: f64,
+    open: bool,
+    stems: usize,
+    width_done: bool,
+    vsindex: usize,
+    seac: Option<(f64, f64, u8, u8)>,
+}
+
+impl<'a, 'b> Interpreter<'a, 'b> {
+    fn new(cff: &'b Cff<'a>, gid: u16) -> Interpreter<'a, 'b> {
+        let private = cff.private_for(gid);
+        Interpreter {
+            cff,
+            private,
+            stack: Vec::new(),
+            segments: Vec::new(),
+            x: 0.,
+            y: 0.,
+            open: false,
+            stems: 0,
+            // CFF2 charstrings carry no width.
+            width_done: cff.version == 2,
+            vsindex: private.map_or(0, |x| x.vsindex),
+            seac: None,
+        }
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.segments.push(Segment::Close);
+            self.open = false;
+        }
+    }
+
+    fn move_by(&mut self, dx: f64, dy: f64) {
+        self.close();
+        self.x += dx;
+        self.y += dy;
+    }
+
+    fn line_by(&mut self, dx: f64, dy: f64) {
+        if !self.open {
+            self.segments.push(Segment::MoveTo(self.x, self.y));
+            self.open = true;
+        }
+        self.x += dx;
+        self.y += dy;
+        self.segments.push(Segment::LineTo(self.x, self.y));
+    }
+
+    fn curve_by(&mut self, d: [f64; 6]) {
+        if !self.open {
+            self.segments.push(Segment::MoveTo(self.x, self.y));
+            self.open = true;
+        }
+        let (x1, y1) = (self.x + d[0], self.y + d[1]);
+        let (x2, y2) = (x1 + d[2], y1 + d[3]);
+        self.x = x2 + d[4];
+        self.y = y2 + d[5];
+        self.segments.push(Segment::CurveTo(x1, y1, x2, y2, self.x, self.y));
+    }
+
+    // Drops the advance width in front of the first stack-clearing operator.
+    fn take_width(&mut self, has_width: bool) {
+        if !self.width_done {
+            self.width_done = true;
+            if has_width && !self.stack.is_empty() {
+                self.stack.remove(0);
+            }
+        }
+    }
+
+    // Returns true once the charstring has ended.
+    fn run(&mut self, data: &[u8], depth: usize) -> bool {
+        if depth > 10 {
+            return true;
+        }
+        let mut pos = 0;
+        while let Some(&b0) = data.get(pos) {
+            pos += 1;
+            let byte = |p: usize| data.get(p).cloned().unwrap_or(0) as i32;
+            let n = self.stack.len();
+            match b0 {
+                28 => {
+                    self.stack.push((byte(pos) << 8 | byte(pos + 1)) as i16 as f64);
+                    pos += 2;
+                }
+                32 ..= 246 => self.stack.push(b0 as f64 - 139.),
+                247 ..= 250 => {
+                    self.stack.push(((b0 as i32 - 247) * 256 + byte(pos) + 108) as f64);
+                    pos += 1;
+                }
+                251 ..= 254 => {
+                    self.stack.push((-(b0 as i32 - 251) * 256 - byte(pos) - 108) as f64);
+                    pos += 1;
+                }
+                255 => {
+                    self.stack.push((byte(pos) << 24 | byte(pos + 1) << 16 | byte(pos + 2) << 8 | byte(pos + 3)) as f64 / 65536.);
+                    pos += 4;
+                }
+                1 | 3 | 18 | 23 => {
+                    self.take_width(n % 2 == 1);
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                }
+                19 | 20 => {
+                    self.take_width(n % 2 == 1);
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    pos += self.stems.div_ceil(8);
+                }
+                21 => {
+                    self.take_width(n > 2);
+                    if let [.., dx, dy] = self.stack[..] {
+                        self.move_by(dx, dy);
+                    }
+                    self.stack.clear();
+                }
+                22 | 4 => {
+                    self.take_width(n > 1);
+                    if let Some(&d) = self.stack.last() {
+                        if b0 == 22 { self.move_by(d, 0.) } else { self.move_by(0., d) }
+                    }
+                    self.stack.clear();
+                }
+                5 => {
+                    for d in std::mem::take(&mut self.stack).chunks_exact(2) {
+                        self.line_by(d[0], d[1]);
+                    }
+                }
+                6 | 7 => {
+                    let mut horizontal = b0 == 6;
+                    for d in std::mem::take(&mut self.stack) {
+                        if horizontal { self.line_by(d, 0.) } else { self.line_by(0., d) }
+                        horizontal = !horizontal;
+                    }
+                }
+                8 | 24 | 25 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let (lines_before, lines_after) = match b0 {
+                        24 => (0, 2),
+                        25 => (stack.len().saturating_sub(6), 0),
+                        _ => (0, 0),
+                    };
+                    for d in stack[.. lines_before].chunks_exact(2) {
+                        self.line_by(d[0], d[1]);
+                    }
+                    let curves = &stack[lines_before .. stack.len().saturating_sub(lines_after).max(lines_before)];
+                    for d in curves.chunks_exact(6) {
+                        self.curve_by([d[0], d[1], d[2], d[3], d[4], d[5]]);
+                    }
+                    if let (2, [.., dx, dy]) = (lines_after, &stack[..]) {
+                        self.line_by(*dx, *dy);
+                    }
+                }
+                26 | 27 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let (mut first, rest) = if stack.len() % 2 == 1 { (stack[0], &stack[1 ..]) } else { (0., &stack[..]) };
+                    for d in rest.chunks_exact(4) {
+                        if b0 == 27 {
+                            self.curve_by([d[0], first, d[1], d[2], d[3], 0.]);
+                        } else {
+                            self.curve_by([first, d[0], d[1], d[2], 0., d[3]]);
+                        }
+                        first = 0.;
+                    }
+                }
+                30 | 31 => {
+                    let stack = std::mem::take(&mut self.stack);
+                    let mut horizontal = b0 == 31;
+                    let mut i = 0;
+                    while i + 4 <= stack.len() {
+                        let last = if stack.len() - i == 5 { stack[i + 4] } else { 0. };
+                        let d = &stack[i .. i + 4];
+                        if horizontal {
+                            self.curve_by([d[0], 0., d[1], d[2], last, d[3]]);
+                        } else {
+                            self.curve_by([0., d[0], d[1], d[2], d[3], last]);
+                        }
+                        i += 4;
+                        horizontal = !horizontal;
+                    }
+                }
+                10 | 29 => {
+                    let subrs = if b0 == 10 { self.private.map_or(&[][..], |x| &x.subrs[..]) } else { &self.cff.global_subrs[..] };
+                    let index = self.stack.pop().unwrap_or(0.) as i64 + subr_bias(subrs.len()) as i64;
+                    if let Some(subr) = usize::try_from(index).ok().and_then(|i| subrs.get(i)) {
+                        if self.run(subr, depth + 1) {
+                            return true;
+                        }
+                    }
+                }
+                11 => return false,
+                14 => {
+                    self.take_width(n == 1 || n == 5);
+                    if let [dx, dy, base, accent] = self.stack[..] {
+                        self.seac = Some((dx, dy, base as u8, accent as u8));
+                    }
+                    self.stack.clear();
+                    return true;
+                }
+                15 => self.vsindex = self.stack.pop().unwrap_or(0.) as usize,
+                16 => {
+                    // Keep the default values and drop the region deltas.
+                    let count = self.stack.pop().unwrap_or(0.) as usize;
+                    let regions = self.cff.regions.get(self.vsindex).cloned().unwrap_or(0);
+                    let keep = self.stack.len().saturating_sub(count * regions);
+                    self.stack.truncate(keep);
+                }
+                12 => {
+                    let b1 = byte(pos);
+                    pos += 1;
+                    self.escape(b1 as u8);
+                }
+                _ => self.stack.clear(),
+            }
+        }
+        false
+    }
+
+    fn escape(&mut self, op: u8) {
+        let stack = std::mem::take(&mut self.stack);
+        match (op, &stack[..]) {
+            (35, &[a, b, c, d, e, f, g, h, i, j, k, l, _]) => {
+                self.curve_by([a, b, c, d, e, f]);
+                self.curve_by([g, h, i, j, k, l]);
+            }
+            (34, &[dx1, dx2, dy2, dx3, dx4, dx5, dx6]) => {
+                self.curve_by([dx1, 0., dx2, dy2, dx3, 0.]);
+                self.curve_by([dx4, 0., dx5, -dy2, dx6, 0.]);
+            }
+            (36, &[dx1, dy1, dx2, dy2, dx3, dx4, dx5, dy5, dx6]) => {
+                self.curve_by([dx1, dy1, dx2, dy2, dx3, 0.]);
+                self.curve_by([dx4, 0., dx5, dy5, dx6, -(dy1 + dy2 + dy5)]);
+            }
+            (37, &[a, b, c, d, e, f, g, h, i, j, k]) => {
+                let dx = a + c + e + g + i;
+                let dy = b + d + f + h + j;
+                let last = if dx.abs() > dy.abs() { [k, -dy] } else { [-dx, k] };
+                self.curve_by([a, b, c, d, e, f]);
+                self.curve_by([g, h, i, j, last[0], last[1]]);
+            }
+            (9, &[.., a]) => self.stack = [&stack[.. stack.len() - 1], &[a.abs()]].concat(),
+            (10, &[.., a, b]) => self.stack = [&stack[.. stack.len() - 2], &[a + b]].concat(),
+            (11, &[.., a, b]) => self.stack = [&stack[.. stack.len() - 2], &[a - b]].concat(),
+            (12, &[.., a, b]) if b != 0. => self.stack = [&stack[.. stack.len() - 2], &[a / b]].concat(),
+            (14, &[.., a]) => self.stack = [&stack[.. stack.len() - 1], &[-a]].concat(),
+            (18, &[.., _]) => self.stack = stack[.. stack.len() - 1].to_vec(),
+            (24, &[.., a, b]) => self.stack = [&stack[.. stack.len() - 2], &[a * b]].concat(),
+            (26, &[.., a]) => self.stack = [&stack[.. stack.len() - 1], &[a.abs().sqrt()]].concat(),
+            (27, &[.., a]) => self.stack = [&stack[..], &[a]].concat(),
+            (28, &[.., a, b]) => self.stack = [&stack[.. stack.len() - 2], &[b, a]].concat(),
+            _ => {}
+        }
+    }
+}
+
+fn charstring_width(data: &[u8]) -> Option<f64> {
+    let mut operands = Vec::new();
+    let mut pos = 0;
+    while let Some(&b0) = data.get(pos) {
+        pos += 1;
+        let byte = |p: usize| data.get(p).cloned().unwrap_or(0) as i32;
+        let has_width = match b0 {
+            28 => {
+                operands.push((byte(pos) << 8 | byte(pos + 1)) as i16 as f64);
+                pos += 2;
+                continue;
+            }
+            32 ..= 246 => {
+                operands.push(b0 as f64 - 139.);
+                continue;
+            }
+            247 ..= 250 => {
+                operands.push(((b0 as i32 - 247) * 256 + byte(pos) + 108) as f64);
+                pos += 1;
+                continue;
+            }
+            251 ..= 254 => {
+                operands.push((-(b0 as i32 - 251) * 256 - byte(pos) - 108) as f64);
+                pos += 1;
+                continue;
+            }
+            255 => {
+                operands.push((byte(pos) << 24 | byte(pos + 1) << 16 | byte(pos + 2) << 8 | byte(pos + 3)) as f64 / 65536.);
+                pos += 4;
+                continue;
+            }
+            // Stem hints and hint masks take pairs.
+            1 | 3 | 18 | 23 | 19 | 20 => operands.len() % 2 == 1,
+            21 => operands.len() == 3,
+            4 | 22 => operands.len() == 2,
+            14 => operands.len() == 1 || operands.len() == 5,
+            _ => false,
+        };
+        return if has_width { operands.first().cloned() } else { None };
+    }
+    None
+}
+
+pub const STANDARD_STRINGS: &[&str] = &[
+    ".notdef", "space", "exclam", "quotedbl", "numbersign", "dollar", "percent", "ampersand", "quoteright",
+    "parenleft", "parenright", "asterisk", "plus", "comma", "hyphen", "period", "slash", "zero", "one", "two",
+    "three", "four", "five", "six", "seven", "eight", "nine", "colon", "semicolon", "less", "equal", "greater",
+    "question", "at", "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R",
+    "S", "T", "U", "V", "W", "X", "Y", "Z", "bracketleft", "backslash", "bracketright", "asciicircum",
+    "underscore", "quoteleft", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p",
+    "q", "r", "s", "t", "u", "v", "w", "x", "y", "z", "braceleft", "bar", "braceright", "asciitilde",
+    "exclamdown", "cent", "sterling", "fraction", "yen", "florin", "section", "currency", "quotesingle",
+    "quotedblleft", "guillemotleft", "guilsinglleft", "guilsinglright", "fi", "fl", "endash", "dagger",
+    "daggerdbl", "periodcentered", "paragraph", "bullet", "quotesinglbase", "quotedblbase", "quotedblright",
+    "guillemotright", "ellipsis", "perthousand", "questiondown", "grave", "acute", "circumflex", "tilde",
+    "macron", "breve", "dotaccent", "dieresis", "ring", "cedilla", "hungarumlaut", "ogonek", "caron", "emdash",
+    "AE", "ordfeminine", "Lslash", "Oslash", "OE", "ordmasculine", "ae", "dotlessi", "lslash", "oslash", "oe",
+    "germandbls", "onesuperior", "logicalnot", "mu", "trademark", "Eth", "onehalf", "plusminus", "Thorn",
+    "onequarter", "divide", "brokenbar", "degree", "thorn", "threequarters", "twosuperior", "registered",
+    "minus", "eth", "multiply", "threesuperior", "copyright", "Aacute", "Acircumflex", "Adieresis", "Agrave",
+    "Aring", "Atilde", "Ccedilla", "Eacute", "Ecircumflex", "Edieresis", "Egrave", "Iacute", "Icircumflex",
+    "Idieresis", "Igrave", "Ntilde", "Oacute", "Ocircumflex", "Odieresis", "Ograve", "Otilde", "Scaron",
+    "Uacute", "Ucircumflex", "Udieresis", "Ugrave", "Yacute", "Ydieresis", "Zcaron", "aacute", "acircumflex",
+    "adieresis", "agrave", "aring", "atilde", "ccedilla", "eacute", "ecircumflex", "edieresis", "egrave",
+    "iacute", "icircumflex", "idieresis", "igrave", "ntilde", "oacute", "ocircumflex", "odieresis", "ograve",
+    "otilde", "scaron", "uacute", "ucircumflex", "udieresis", "ugrave", "yacute", "ydieresis", "zcaron",
+    "exclamsmall", "Hungarumlautsmall", "dollaroldstyle", "dollarsuperior", "ampersandsmall", "Acutesmall",
+    "parenleftsuperior", "parenrightsuperior", "twodotenleader", "onedotenleader", "zerooldstyle",
+    "oneoldstyle", "twooldstyle", "threeoldstyle", "fouroldstyle", "fiveoldstyle", "sixoldstyle",
+    "sevenoldstyle", "eightoldstyle", "nineoldstyle", "commasuperior", "threequartersemdash",
+    "periodsuperior", "questionsmall", "asuperior", "bsuperior", "centsuperior", "dsuperior", "esuperior",
+    "isuperior", "lsuperior", "msuperior", "nsuperior", "osuperior", "rsuperior", "ssuperior", "tsuperior",
+    "ff", "ffi", "ffl", "parenleftinferior", "parenrightinferior", "Circumflexsmall", "hyphensuperior",
+    "Gravesmall", "Asmall", "Bsmall", "Csmall", "Dsmall", "Esmall", "Fsmall", "Gsmall", "Hsmall", "Ismall",
+    "Jsmall", "Ksmall", "Lsmall", "Msmall", "Nsmall", "Osmall", "Psmall", "Qsmall", "Rsmall", "Ssmall",
+    "Tsmall", "Usmall", "Vsmall", "Wsmall", "Xsmall", "Ysmall", "Zsmall", "colonmonetary", "onefitted",
+    "rupiah", "Tildesmall", "exclamdownsmall", "centoldstyle", "Lslashsmall", "Scaronsmall", "Zcaronsmall",
+    "Dieresissmall", "Brevesmall", "Caronsmall", "Dotaccentsmall", "Macronsmall",
+    "figuredash", "hypheninferior", "Ogoneksmall", "Ringsmall", "Cedillasmall", "questiondownsmall",
+    "oneeighth", "threeeighths", "fiveeighths", "seveneighths", "onethird", "twothirds", "zerosuperior",
+    "foursuperior", "fivesuperior", "sixsuperior", "sevensuperior", "eightsuperior", "ninesuperior",
+    "zeroinferior", "oneinferior", "twoinferior", "threeinferior", "fourinferior", "fiveinferior",
+    "sixinferior", "seveninferior", "eightinferior", "nineinferior", "centinferior", "dollarinferior",
+    "periodinferior", "commainferior", "Agravesmall", "Aacutesmall", "Acircumflexsmall", "Atildesmall",
+    "Adieresissmall", "Aringsmall", "AEsmall", "Ccedillasmall", "Egravesmall", "Eacutesmall",
+    "Ecircumflexsmall", "Edieresissmall", "Igravesmall", "Iacutesmall", "Icircumflexsmall",
+    "Idieresissmall", "Ethsmall", "Ntildesmall", "Ogravesmall", "Oacutesmall", "Ocircumflexsmall",
+    "Otildesmall", "Odieresissmall", "OEsmall", "Oslashsmall", "Ugravesmall", "Uacutesmall",
+    "Ucircumflexsmall", "Udieresissmall", "Yacutesmall", "Thornsmall", "Ydieresissmall", "001.000",
+    "001.001", "001.002", "001.003", "Black", "Bold", "Book", "Light", "Medium", "Regular", "Roman",
+    "Semibold",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dict() {
+        // 100 -200 300 400 FontBBox, 1000 CharStrings, escaped operator 12 7.
+        let dict = parse_dict(&[239, 251, 92, 247, 192, 29, 0, 0, 1, 144, 5, 28, 3, 232, 17, 30, 0x2a, 0x5f, 12, 7]);
+        assert_eq!(dict[&FONT_BBOX], vec![100., -200., 300., 400.]);
+        assert_eq!(dict[&CHARSTRINGS], vec![1000.]);
+        assert_eq!(dict[&1207], vec![2.5]);
+        assert_eq!(STANDARD_STRINGS.len(), 391);
+    }
+
+    #[test]
+    fn test_index() {
+        let data = [0, 2, 1, 1, 3, 6, b'a', b'b', b'c', b'd', b'e', 9];
+        let (items, end) = read_index(&data, 0).unwrap();
+        assert_eq!(items, vec![&b"ab"[..], &b"cde"[..]]);
+        assert_eq!(end, 11);
+    }
+
+    #[test]
+    fn test_outline() {
+        let mut data = vec![1, 0, 4, 1];
+        data.extend([0, 1, 1, 1, 2, b'T']);
+        data.extend([0, 1, 1, 1, 12, 28, 0, 47, 17, 28, 0, 4, 28, 0, 30, 18]);
+        data.extend([0, 0, 0, 0]);
+        data.extend([28, 0, 4, 19]);
+        data.extend([0, 1, 1, 1, 9, 239, 139, 139, 239, 39, 139, 5, 11]);
+        data.extend([0, 3, 1, 1, 2, 10, 19, 14]);
+        data.extend([248, 136, 139, 139, 21, 32, 10, 14]);
+        data.extend([149, 159, 21, 169, 179, 189, 199, 31, 14]);
+        let cff = Cff::parse(&data).unwrap();
+        assert_eq!(cff.widths(), vec![0., 500., 0.]);
+        assert_eq!(cff.glyph_names()[2].as_deref(), Some("exclam"));
+        assert_eq!(cff.encoding().get(&b'!'), Some(&2));
+        assert_eq!(cff.outline(1), vec![
+            Segment::MoveTo(0., 0.),
+            Segment::LineTo(100., 0.),
+            Segment::LineTo(100., 100.),
+            Segment::LineTo(0., 100.),
+            Segment::Close,
+        ]);
+        assert_eq!(cff.outline(2), vec![
+            Segment::MoveTo(10., 20.),
+            Segment::CurveTo(40., 20., 80., 70., 80., 130.),
+            Segment::Close,
+        ]);
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{arg, command, ArgAction, ArgMatches};
+use clap::{arg, command, ArgAction, ArgMatches, Command};
 
 pub fn parse_options() -> ArgMatches {
     let matches = command!() // requires `cargo` feature
@@ -8,6 +8,73 @@ pub fn parse_options() -> ArgMatches {
             .required(false)
             .action(ArgAction::SetTrue)
         )
+        .arg(
+            arg!(-l --layout "Print texts of every page in reading order")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(-F --no_furniture "Leave out running heads, footers and page numbers")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(-P --page <N> "Only export page N, starting from 1")
+            .required(false)
+        )
+        .arg(
+            arg!(-j --json "Print texts of every page with fonts, colors and bounding boxes as JSON")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(-e --export <FORMAT> "Print the document as hocr, alto, markdown or html")
+            .required(false)
+        )
+        .arg(
+            arg!(-T --tables <FORMAT> "Print tables of every page as csv or json")
+            .required(false)
+        )
+        .arg(
+            arg!(--fonts "Print embedded fonts with their subset prefix, type and encoding")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(--extract_fonts <DIR> "Write embedded font programs into DIR")
+            .required(false)
+        )
+        .arg(
+            arg!(--svg <DIR> "Write every page as a standalone SVG file into DIR")
+            .required(false)
+        )
+        .arg(
+            arg!(--png <DIR> "Render every page into DIR as PNG")
+            .required(false)
+        )
+        .arg(
+            arg!(--dpi <DPI> "Resolution of rendered pages, 72 by default")
+            .required(false)
+        )
+        .arg(
+            arg!(--lint "Check content streams against operator signatures, nesting rules and resources")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(-s --search <QUERY> "Print pages and quads of every match")
+            .required(false)
+        )
+        .arg(
+            arg!(-i --ignore_case "Search case-insensitively")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(-x --regex "Treat the search query as a regular expression")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
         .arg(
             arg!(-o --operations ... "Print operations")
             .required(false)
@@ -57,6 +124,19 @@ pub fn parse_options() -> ArgMatches {
             .required(false)
             .action(ArgAction::SetTrue)
         )
+        .subcommand(
+            Command::new("to-markdown")
+            .about("Convert the document to Markdown")
+            .arg(arg!(FILE: "File to convert").required(true))
+        )
+        .subcommand(
+            Command::new("extract-images")
+            .about("Write the images of every page into DIR, with where they are placed")
+            .arg(arg!(FILE: "File to read").required(true))
+            .arg(arg!(DIR: "Directory to write into").required(true))
+            .arg(arg!(-P --page <N> "Only extract images of page N, starting from 1").required(false))
+        )
+        .args_conflicts_with_subcommands(true)
         .get_matches();
     matches
 }
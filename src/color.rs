@@ -0,0 +1,439 @@
+use super::function::Function;
+use super::parser::{Value, PDF};
+use super::pattern::Pattern;
+use super::util::{number, numbers, u16_at, u32_at};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// The white point of the ICC profile connection space.
+const D50: [f64; 3] = [0.9642, 1., 0.8249];
+const D65: [f64; 3] = [0.95047, 1., 1.08883];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorSpace {
+    DeviceGray,
+    DeviceRGB,
+    DeviceCMYK,
+    CalGray { white: [f64; 3], gamma: f64 },
+    CalRGB { white: [f64; 3], gamma: [f64; 3], matrix: [f64; 9] },
+    Lab { white: [f64; 3], range: [f64; 4] },
+    // Profiles other than matrix/TRC ones convert through the alternate space.
+    ICCBased { n: usize, range: Vec<f64>, profile: Option<Profile>, alternate: Box<ColorSpace> },
+    Indexed { base: Box<ColorSpace>, hival: usize, lookup: Vec<u8> },
+    // Separation is DeviceN with a single colorant. Without a usable tint transform,
+    // tints are shown as shades of gray.
+    DeviceN { n: usize, alternate: Box<ColorSpace>, tint: Option<Function> },
+    // The underlying space of uncolored patterns.
+    Pattern(Option<Box<ColorSpace>>),
+}
+
+// A color as set in a content stream, with the space named as in the stream and
+// its sRGB equivalent. Colors in a pattern space have the pattern too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Color {
+    pub space: String,
+    pub components: Vec<f64>,
+    pub rgb: [u8; 3],
+    pub pattern: Option<Rc<Pattern>>,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color { space: "DeviceGray".into(), components: vec![0.], rgb: [0, 0, 0], pattern: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+    Gamma(f64),
+    Table(Vec<f64>),
+    // An ICC parametric curve, by function type and parameters.
+    Parametric(u16, Vec<f64>),
+}
+
+impl Curve {
+    fn eval(&self, x: f64) -> f64 {
+        let x = x.clamp(0., 1.);
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(table) => {
+                let pos = x * (table.len() - 1) as f64;
+                let i = (pos as usize).min(table.len() - 2);
+                table[i] + (pos - i as f64) * (table[i + 1] - table[i])
+            }
+            Curve::Parametric(kind, p) => {
+                let p = |i: usize| p.get(i).copied().unwrap_or(0.);
+                let (g, a, b, c, d, e, f) = (p(0), p(1), p(2), p(3), p(4), p(5), p(6));
+                let power = |x: f64| (a * x + b).max(0.).powf(g);
+                match kind {
+                    0 => x.powf(g),
+                    1 => if a != 0. && x >= -b / a { power(x) } else { 0. },
+                    2 => if a != 0. && x >= -b / a { power(x) + c } else { c },
+                    3 => if x >= d { power(x) } else { c * x },
+                    _ => if x >= d { power(x) + e } else { c * x + f },
+                }
+            }
+        }
+    }
+}
+
+// The parts of a gray or matrix/TRC RGB ICC profile needed to convert to XYZ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    curves: Vec<Curve>,
+    // Columns are the XYZ values of the red, green and blue colorants.
+    matrix: Option<[f64; 9]>,
+}
+
+fn s15_fixed16(data: &[u8], i: usize) -> Option<f64> {
+    Some(u32_at(data, i)? as i32 as f64 / 65536.)
+}
+
+impl Profile {
+    pub fn parse(data: &[u8]) -> Option<Profile> {
+        let count = u32_at(data, 128)? as usize;
+        let mut tags = HashMap::new();
+        for i in 0 .. count.min(256) {
+            let entry = data.get(132 + i * 12 .. 144 + i * 12)?;
+            let offset = u32_at(entry, 4)? as usize;
+            let size = u32_at(entry, 8)? as usize;
+            tags.insert(&entry[..4], data.get(offset .. offset.checked_add(size)?)?);
+        }
+        let curve = |sig: &[u8]| -> Option<Curve> {
+            let tag = tags.get(sig)?;
+            match &tag[..4] {
+                b"curv" => match u32_at(tag, 8)? {
+                    0 => Some(Curve::Gamma(1.)),
+                    1 => Some(Curve::Gamma(u16_at(tag, 12)? as f64 / 256.)),
+                    n => {
+                        let table = (0 .. n as usize).map(|i| Some(u16_at(tag, 12 + i * 2)? as f64 / 65535.)).collect::<Option<Vec<_>>>()?;
+                        Some(Curve::Table(table))
+                    }
+                },
+                b"para" => {
+                    let kind = u16_at(tag, 8)?;
+                    let n = [1, 3, 4, 5, 7].get(kind as usize)?;
+                    let params = (0 .. *n).map(|i| s15_fixed16(tag, 12 + i * 4)).collect::<Option<Vec<_>>>()?;
+                    Some(Curve::Parametric(kind, params))
+                }
+                _ => None,
+            }
+        };
+        let xyz = |sig: &[u8]| -> Option<[f64; 3]> {
+            let tag = tags.get(sig).filter(|x| x.starts_with(b"XYZ "))?;
+            Some([s15_fixed16(tag, 8)?, s15_fixed16(tag, 12)?, s15_fixed16(tag, 16)?])
+        };
+        if data.get(20 .. 24)? != b"XYZ " {
+            return None;
+        }
+        match data.get(16 .. 20)? {
+            b"GRAY" => Some(Profile { curves: vec![curve(b"kTRC")?], matrix: None }),
+            b"RGB " => {
+                let (r, g, b) = (xyz(b"rXYZ")?, xyz(b"gXYZ")?, xyz(b"bXYZ")?);
+                Some(Profile {
+                    curves: vec![curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?],
+                    matrix: Some([r[0], g[0], b[0], r[1], g[1], b[1], r[2], g[2], b[2]]),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn to_xyz(&self, components: &[f64]) -> [f64; 3] {
+        let linear = self.curves.iter().enumerate().map(|(i, x)| x.eval(component(components, i))).collect::<Vec<_>>();
+        match self.matrix {
+            Some(m) => multiply(&m, [linear[0], linear[1], linear[2]]),
+            None => D50.map(|x| x * linear[0]),
+        }
+    }
+}
+
+fn component(components: &[f64], i: usize) -> f64 {
+    components.get(i).copied().unwrap_or(0.)
+}
+
+fn multiply(m: &[f64; 9], v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0] * v[0] + m[1] * v[1] + m[2] * v[2],
+        m[3] * v[0] + m[4] * v[1] + m[5] * v[2],
+        m[6] * v[0] + m[7] * v[1] + m[8] * v[2],
+    ]
+}
+
+// Converts XYZ relative to `white` to sRGB, adapting the white point with the Bradford transform.
+fn xyz_to_srgb(xyz: [f64; 3], white: [f64; 3]) -> [f64; 3] {
+    const BRADFORD: [f64; 9] = [0.8951, 0.2664, -0.1614, -0.7502, 1.7135, 0.0367, 0.0389, -0.0685, 1.0296];
+    const BRADFORD_INVERSE: [f64; 9] = [0.9869929, -0.1470543, 0.1599627, 0.4323053, 0.5183603, 0.0492912, -0.0085287, 0.0400428, 0.9684867];
+    const XYZ_TO_SRGB: [f64; 9] = [3.2404542, -1.5371385, -0.4985314, -0.969266, 1.8760108, 0.041556, 0.0556434, -0.2040259, 1.0572252];
+    let (source, target) = (multiply(&BRADFORD, white), multiply(&BRADFORD, D65));
+    let lms = multiply(&BRADFORD, xyz);
+    let adapted = multiply(&BRADFORD_INVERSE, [0, 1, 2].map(|i| lms[i] * target[i] / source[i]));
+    multiply(&XYZ_TO_SRGB, adapted).map(|x| {
+        let x = x.clamp(0., 1.);
+        if x <= 0.0031308 { 12.92 * x } else { 1.055 * x.powf(1. / 2.4) - 0.055 }
+    })
+}
+
+// Strings hold bytes as chars, one per byte.
+fn string_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::String(s) => Some(s.chars().map(|x| x as u8).collect()),
+        _ => None,
+    }
+}
+
+impl ColorSpace {
+    // A color space given by name or array, with names other than those of the device
+    // spaces looked up in the ColorSpace resources.
+    pub fn load(pdf: &PDF, value: &Value, resources: &HashMap<String, Value>) -> Option<ColorSpace> {
+        ColorSpace::load_nested(pdf, value, resources, 0)
+    }
+
+    fn load_nested(pdf: &PDF, value: &Value, resources: &HashMap<String, Value>, depth: usize) -> Option<ColorSpace> {
+        if depth > 8 {
+            return None;
+        }
+        let load = |x: &Value| ColorSpace::load_nested(pdf, x, &HashMap::new(), depth + 1);
+        let list = match pdf.resolve(value) {
+            Value::Key(name) => return match name.as_str() {
+                "DeviceGray" | "G" => Some(ColorSpace::DeviceGray),
+                "DeviceRGB" | "RGB" => Some(ColorSpace::DeviceRGB),
+                "DeviceCMYK" | "CMYK" => Some(ColorSpace::DeviceCMYK),
+                "Pattern" => Some(ColorSpace::Pattern(None)),
+                _ => {
+                    let spaces = resources.get("ColorSpace").and_then(|x| pdf.resolve_dict(x))?;
+                    load(spaces.get(name)?)
+                }
+            },
+            Value::List(list) => list,
+            _ => return None,
+        };
+        let Some(Value::Key(family)) = list.first().map(|x| pdf.resolve(x)) else { return None };
+        let white = |dict: &HashMap<String, Value>| match numbers(pdf, dict.get("WhitePoint")).as_deref() {
+            Some(&[x, y, z]) if y > 0. => [x, y, z],
+            _ => D65,
+        };
+        match family.as_str() {
+            "CalGray" => {
+                let dict = pdf.resolve_dict(list.get(1)?)?;
+                Some(ColorSpace::CalGray { white: white(dict), gamma: number(pdf, dict.get("Gamma")).unwrap_or(1.) })
+            }
+            "CalRGB" => {
+                let dict = pdf.resolve_dict(list.get(1)?)?;
+                let gamma = numbers(pdf, dict.get("Gamma")).and_then(|x| x.try_into().ok()).unwrap_or([1.; 3]);
+                let matrix = numbers(pdf, dict.get("Matrix")).and_then(|x| x.try_into().ok()).unwrap_or([1., 0., 0., 0., 1., 0., 0., 0., 1.]);
+                Some(ColorSpace::CalRGB { white: white(dict), gamma, matrix })
+            }
+            "Lab" => {
+                let dict = pdf.resolve_dict(list.get(1)?)?;
+                let range = numbers(pdf, dict.get("Range")).and_then(|x| x.try_into().ok()).unwrap_or([-100., 100., -100., 100.]);
+                Some(ColorSpace::Lab { white: white(dict), range })
+            }
+            "ICCBased" => {
+                let &Value::Ref(m, g) = list.get(1)? else { return None };
+                let stream = pdf.get(&(m, g))?;
+                let dict = stream.dict();
+                let n = number(pdf, dict.get("N"))? as usize;
+                let alternate = match dict.get("Alternate").and_then(load) {
+                    Some(x) => x,
+                    None => match n {
+                        1 => ColorSpace::DeviceGray,
+                        4 => ColorSpace::DeviceCMYK,
+                        _ => ColorSpace::DeviceRGB,
+                    },
+                };
+                let range = numbers(pdf, dict.get("Range")).filter(|x| x.len() == n * 2).unwrap_or([0., 1.].repeat(n));
+                let profile = Profile::parse(stream.stream()).filter(|x| x.curves.len() == n);
+                Some(ColorSpace::ICCBased { n, range, profile, alternate: Box::new(alternate) })
+            }
+            "Indexed" | "I" => {
+                let base = load(list.get(1)?)?;
+                let hival = number(pdf, list.get(2))? as usize;
+                let lookup = match list.get(3)? {
+                    &Value::Ref(m, n) => match pdf.get(&(m, n))? {
+                        x if x.stream().is_empty() => string_bytes(pdf.resolve(x.value()))?,
+                        x => x.stream().to_vec(),
+                    },
+                    x => string_bytes(x)?,
+                };
+                Some(ColorSpace::Indexed { base: Box::new(base), hival, lookup })
+            }
+            "Separation" | "DeviceN" => {
+                let n = match pdf.resolve(list.get(1)?) {
+                    Value::List(names) => names.len(),
+                    _ => 1,
+                };
+                let alternate = load(list.get(2)?)?;
+                let tint = Function::load(pdf, list.get(3)?);
+                Some(ColorSpace::DeviceN { n, alternate: Box::new(alternate), tint })
+            }
+            "Pattern" => Some(ColorSpace::Pattern(list.get(1).and_then(load).map(Box::new))),
+            _ => load(list.first()?),
+        }
+    }
+
+    pub fn components(&self) -> usize {
+        match self {
+            ColorSpace::DeviceGray | ColorSpace::CalGray { .. } | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::DeviceRGB | ColorSpace::CalRGB { .. } | ColorSpace::Lab { .. } => 3,
+            ColorSpace::DeviceCMYK => 4,
+            ColorSpace::ICCBased { n, .. } | ColorSpace::DeviceN { n, .. } => *n,
+            ColorSpace::Pattern(base) => base.as_ref().map_or(0, |x| x.components()),
+        }
+    }
+
+    // Bounds of each component, as in the default Decode array of images.
+    pub fn ranges(&self) -> Vec<f64> {
+        match self {
+            ColorSpace::Lab { range, .. } => vec![0., 100., range[0], range[1], range[2], range[3]],
+            ColorSpace::ICCBased { range, .. } => range.clone(),
+            ColorSpace::Indexed { hival, .. } => vec![0., *hival as f64],
+            _ => [0., 1.].repeat(self.components()),
+        }
+    }
+
+    // The color a space starts out with: black, or full tint of every colorant.
+    pub fn initial_color(&self) -> Vec<f64> {
+        match self {
+            ColorSpace::DeviceCMYK => vec![0., 0., 0., 1.],
+            ColorSpace::DeviceN { n, .. } => vec![1.; *n],
+            ColorSpace::Pattern(_) => Vec::new(),
+            _ => self.ranges().chunks(2).map(|x| 0f64.clamp(x[0], x[1])).collect(),
+        }
+    }
+
+    // sRGB components between 0 and 1.
+    pub fn to_rgb(&self, components: &[f64]) -> [f64; 3] {
+        let c = |i| component(components, i);
+        match self {
+            ColorSpace::DeviceGray => [c(0); 3].map(|x| x.clamp(0., 1.)),
+            ColorSpace::DeviceRGB => [c(0), c(1), c(2)].map(|x| x.clamp(0., 1.)),
+            ColorSpace::DeviceCMYK => {
+                let k = 1. - c(3).clamp(0., 1.);
+                [c(0), c(1), c(2)].map(|x| (1. - x.clamp(0., 1.)) * k)
+            }
+            ColorSpace::CalGray { white, gamma } => {
+                let y = c(0).clamp(0., 1.).powf(*gamma);
+                xyz_to_srgb(white.map(|x| x * y), *white)
+            }
+            ColorSpace::CalRGB { white, gamma, matrix } => {
+                let v = [0, 1, 2].map(|i| c(i).clamp(0., 1.).powf(gamma[i]));
+                // The matrix lists the XYZ of each component in turn.
+                let columns = [matrix[0], matrix[3], matrix[6], matrix[1], matrix[4], matrix[7], matrix[2], matrix[5], matrix[8]];
+                xyz_to_srgb(multiply(&columns, v), *white)
+            }
+            ColorSpace::Lab { white, range } => {
+                let (l, a, b) = (c(0).clamp(0., 100.), c(1).clamp(range[0], range[1]), c(2).clamp(range[2], range[3]));
+                let m = (l + 16.) / 116.;
+                let g = |x: f64| if x >= 6. / 29. { x * x * x } else { 108. / 841. * (x - 4. / 29.) };
+                xyz_to_srgb([white[0] * g(m + a / 500.), white[1] * g(m), white[2] * g(m - b / 200.)], *white)
+            }
+            ColorSpace::ICCBased { profile: Some(profile), range, .. } => {
+                let normalized = range.chunks(2).enumerate().map(|(i, x)| (c(i) - x[0]) / (x[1] - x[0])).collect::<Vec<_>>();
+                xyz_to_srgb(profile.to_xyz(&normalized), D50)
+            }
+            ColorSpace::ICCBased { alternate, .. } => alternate.to_rgb(components),
+            ColorSpace::Indexed { base, hival, lookup } => {
+                let n = base.components();
+                let i = c(0).round().clamp(0., *hival as f64) as usize * n;
+                match lookup.get(i .. i + n) {
+                    Some(entry) => {
+                        let ranges = base.ranges();
+                        let values = entry.iter().enumerate().map(|(j, &x)| {
+                            ranges[j * 2] + x as f64 / 255. * (ranges[j * 2 + 1] - ranges[j * 2])
+                        }).collect::<Vec<_>>();
+                        base.to_rgb(&values)
+                    }
+                    None => [0.; 3],
+                }
+            }
+            ColorSpace::DeviceN { alternate, tint: Some(tint), .. } => alternate.to_rgb(&tint.eval(components)),
+            ColorSpace::DeviceN { .. } => [1. - components.iter().fold(0f64, |a, &x| a.max(x)).clamp(0., 1.); 3],
+            ColorSpace::Pattern(base) => base.as_ref().map_or([0.; 3], |x| x.to_rgb(components)),
+        }
+    }
+
+    pub fn rgb(&self, components: &[f64]) -> [u8; 3] {
+        self.to_rgb(components).map(|x| (x * 255.).round() as u8)
+    }
+
+    // A color in this space, which is called `name` in the content stream.
+    pub fn color(&self, name: &str, components: Vec<f64>) -> Color {
+        Color { space: name.into(), rgb: self.rgb(&components), components, pattern: None }
+    }
+}
+
+impl Color {
+    // The color painting with a pattern, with the components of uncolored patterns.
+    // Outputs that can't show the pattern use a color standing for it.
+    pub fn with_pattern(mut self, pattern: Option<Rc<Pattern>>) -> Color {
+        if let Some(rgb) = pattern.as_ref().and_then(|x| x.rgb()) {
+            self.rgb = rgb;
+        }
+        self.pattern = pattern;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device() {
+        assert_eq!(ColorSpace::DeviceGray.rgb(&[0.5]), [128, 128, 128]);
+        assert_eq!(ColorSpace::DeviceRGB.rgb(&[1., 0., 0.]), [255, 0, 0]);
+        assert_eq!(ColorSpace::DeviceCMYK.rgb(&[0., 0., 0., 1.]), [0, 0, 0]);
+        assert_eq!(ColorSpace::DeviceCMYK.rgb(&[0., 1., 1., 0.]), [255, 0, 0]);
+        assert_eq!(ColorSpace::DeviceCMYK.initial_color(), vec![0., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn test_cie() {
+        // CalRGB with the sRGB primaries and a D65 white point.
+        let srgb = ColorSpace::CalRGB {
+            white: D65,
+            gamma: [1.; 3],
+            matrix: [0.4124, 0.2126, 0.0193, 0.3576, 0.7152, 0.1192, 0.1805, 0.0722, 0.9505],
+        };
+        assert_eq!(srgb.rgb(&[1., 1., 1.]), [255, 255, 255]);
+        assert_eq!(srgb.rgb(&[0.216, 0., 0.]), [128, 0, 0]);
+        let lab = ColorSpace::Lab { white: D50, range: [-100., 100., -100., 100.] };
+        assert_eq!(lab.rgb(&[100., 0., 0.]), [255, 255, 255]);
+        assert_eq!(lab.rgb(&[0., 0., 0.]), [0, 0, 0]);
+        let [r, g, b] = lab.rgb(&[50., 60., 40.]);
+        assert!(r > 180 && g < 80 && b < 80);
+        let gray = ColorSpace::CalGray { white: D50, gamma: 2.2 };
+        assert_eq!(gray.rgb(&[1.]), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_indexed_and_tint() {
+        let indexed = ColorSpace::Indexed { base: Box::new(ColorSpace::DeviceRGB), hival: 1, lookup: vec![0, 0, 0, 255, 128, 0] };
+        assert_eq!(indexed.rgb(&[1.]), [255, 128, 0]);
+        assert_eq!(indexed.rgb(&[7.]), [255, 128, 0]);
+        let tint = Function::Exponential { domain: vec![0., 1.], c0: vec![0., 0., 0., 0.], c1: vec![0., 1., 1., 0.], n: 1. };
+        let red = ColorSpace::DeviceN { n: 1, alternate: Box::new(ColorSpace::DeviceCMYK), tint: Some(tint) };
+        assert_eq!(red.rgb(&red.initial_color()), [255, 0, 0]);
+        assert_eq!(red.rgb(&[0.5]), [255, 128, 128]);
+        let unknown = ColorSpace::DeviceN { n: 1, alternate: Box::new(ColorSpace::DeviceCMYK), tint: None };
+        assert_eq!(unknown.rgb(&[0.25]), [191, 191, 191]);
+    }
+
+    #[test]
+    fn test_profile() {
+        // A gray profile with a gamma of 2.0 in a curv tag.
+        let mut data = vec![0; 128];
+        data[16 .. 24].copy_from_slice(b"GRAYXYZ ");
+        data.extend(1u32.to_be_bytes());
+        data.extend(b"kTRC");
+        data.extend(144u32.to_be_bytes());
+        data.extend(14u32.to_be_bytes());
+        data.extend(b"curv\0\0\0\0\0\0\0\x01\x02\x00");
+        let profile = Profile::parse(&data).unwrap();
+        let space = ColorSpace::ICCBased { n: 1, range: vec![0., 1.], profile: Some(profile), alternate: Box::new(ColorSpace::DeviceGray) };
+        assert_eq!(space.rgb(&[1.]), [255, 255, 255]);
+        // 0.5 squared is 0.25 in linear light, which sRGB encodes as 137.
+        assert_eq!(space.rgb(&[0.5]), [137, 137, 137]);
+    }
+}
@@ -0,0 +1,592 @@
+use super::operation::{Matrix, TextState, TextPaintingOperation};
+use super::path::{Clip, GraphicsState, Path, PathBuilder, Segment};
+use super::font::{Font, load_fonts};
+use super::function;
+use super::util;
+use super::color::ColorSpace;
+use super::pattern::{Pattern, Shading};
+use super::image::Image;
+use super::transparency::{BlendMode, Group, SoftMask, Transparency};
+use super::parser::{Object, Value, PDF};
+use super::text::handle_text_operation;
+use postscript::parser::parse;
+use postscript::parser::content::ContentOp;
+use postscript::lexer::parse as lexer;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+// An image painted into the unit square of `matrix`.
+#[derive(Debug, Clone)]
+pub struct PlacedImage {
+    pub image: Image,
+    pub matrix: Matrix,
+    pub clip: Vec<Clip>,
+    // Number of paths painted before the image, for stacking.
+    pub paths_before: usize,
+    pub alpha: f64,
+    pub transparency: Transparency,
+}
+
+impl PlacedImage {
+    // The area the image covers on the page.
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
+        let corners = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)].map(|(x, y)| self.matrix.apply(x, y));
+        corners.iter().fold((f64::MAX, f64::MAX, f64::MIN, f64::MIN), |a, &(x, y)| (a.0.min(x), a.1.min(y), a.2.max(x), a.3.max(y)))
+    }
+}
+
+// A glyph of a font other than Type 3, painted like `path` once the outline of
+// the glyph is filled in.
+#[derive(Debug, Clone)]
+pub struct ShownGlyph {
+    pub font: String,
+    pub code: u32,
+    pub matrix: Matrix,
+    pub path: Path,
+}
+
+// A path of a Type 3 glyph, with the name of the font drawing it among those of
+// `load_content_fonts`.
+#[derive(Debug, Clone)]
+pub struct GlyphPath {
+    pub font: String,
+    pub path: Path,
+    // Numbers of paths and images painted before the glyph, for stacking.
+    pub paths_before: usize,
+    pub images_before: usize,
+}
+
+// A path or image, as painted by `PageContent::graphics`.
+pub enum Graphic<'a> {
+    Path(&'a Path),
+    Image(&'a PlacedImage),
+}
+
+#[derive(Debug, Default)]
+pub struct PageContent {
+    pub texts: Vec<TextPaintingOperation>,
+    pub paths: Vec<Path>,
+    pub glyphs: Vec<GlyphPath>,
+    pub images: Vec<PlacedImage>,
+    pub shown: Vec<ShownGlyph>,
+}
+
+impl PageContent {
+    // The paths, images and Type 3 glyphs in painting order.
+    pub fn graphics(&self) -> Vec<Graphic<'_>> {
+        let mut ret = Vec::new();
+        let mut images = self.images.iter().peekable();
+        let mut glyphs = self.glyphs.iter().peekable();
+        let mut images_done = 0;
+        for i in 0 ..= self.paths.len() {
+            loop {
+                if let Some(glyph) = glyphs.next_if(|x| x.paths_before <= i && x.images_before <= images_done) {
+                    ret.push(Graphic::Path(&glyph.path));
+                } else if let Some(image) = images.next_if(|x| x.paths_before <= i) {
+                    ret.push(Graphic::Image(image));
+                    images_done += 1;
+                } else {
+                    break;
+                }
+            }
+            ret.extend(self.paths.get(i).map(Graphic::Path));
+        }
+        ret
+    }
+}
+
+// What a content stream draws with.
+struct Context<'a> {
+    pdf: &'a PDF,
+    resources: &'a HashMap<String, Value>,
+    // Resources of the page or pattern, to which the names of fonts refer.
+    root: &'a HashMap<String, Value>,
+    // Object number of the form whose resources are `resources`, or 0 for `root`.
+    owner: usize,
+    fonts: &'a HashMap<String, Font>,
+    // Forms being painted, outermost first.
+    forms: Vec<(usize, usize)>,
+    // Color spaces loaded so far, by name.
+    spaces: RefCell<HashMap<String, Rc<ColorSpace>>>,
+    patterns: RefCell<HashMap<String, Option<Rc<Pattern>>>>,
+    // Soft masks of ExtGState dictionaries, by name, with the CTM they were painted with.
+    masks: RefCell<HashMap<String, (Matrix, Rc<SoftMask>)>>,
+}
+
+impl<'a> Context<'a> {
+    fn new(pdf: &'a PDF, resources: &'a HashMap<String, Value>, fonts: &'a HashMap<String, Font>) -> Context<'a> {
+        Context {
+            pdf, resources, root: resources, owner: 0, fonts, forms: Vec::new(),
+            spaces: RefCell::new(HashMap::new()), patterns: RefCell::new(HashMap::new()), masks: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Unknown spaces are taken as DeviceGray, as in the initial graphics state.
+    fn color_space(&self, name: &str) -> Rc<ColorSpace> {
+        let mut spaces = self.spaces.borrow_mut();
+        spaces.entry(name.into()).or_insert_with(|| {
+            let space = ColorSpace::load(self.pdf, &Value::Key(name.into()), self.resources);
+            Rc::new(space.unwrap_or(ColorSpace::DeviceGray))
+        }).clone()
+    }
+
+    fn pattern(&self, name: &str) -> Option<Rc<Pattern>> {
+        let mut patterns = self.patterns.borrow_mut();
+        patterns.entry(name.into()).or_insert_with(|| {
+            let patterns = self.resources.get("Pattern").and_then(|x| self.pdf.resolve_dict(x))?;
+            Pattern::load(self.pdf, patterns.get(name)?, self.resources).map(Rc::new)
+        }).clone()
+    }
+
+    fn shading(&self, name: &str) -> Option<Shading> {
+        let shadings = self.resources.get("Shading").and_then(|x| self.pdf.resolve_dict(x))?;
+        Shading::load(self.pdf, shadings.get(name)?, self.resources)
+    }
+
+    fn ext_g_state(&self, name: &str) -> Option<&HashMap<String, Value>> {
+        let states = self.resources.get("ExtGState").and_then(|x| self.pdf.resolve_dict(x))?;
+        self.pdf.resolve_dict(states.get(name)?)
+    }
+
+    // The soft mask of ExtGState `name` painted with `ctm`, reused while the CTM stays.
+    fn soft_mask(&self, name: &str, value: &Value, ctm: Matrix) -> Option<Rc<SoftMask>> {
+        if let Some((_, mask)) = self.masks.borrow().get(name).filter(|x| x.0 == ctm) {
+            return Some(mask.clone());
+        }
+        let mask = Rc::new(soft_mask(value, self, ctm)?);
+        self.masks.borrow_mut().insert(name.into(), (ctm, mask.clone()));
+        Some(mask)
+    }
+
+    fn xobject(&self, name: &str) -> Option<&Object> {
+        let xobjects = self.resources.get("XObject").and_then(|x| self.pdf.resolve_dict(x))?;
+        match xobjects.get(name)? {
+            &Value::Ref(m, n) => self.pdf.get(&(m, n)),
+            _ => None,
+        }
+    }
+}
+
+fn run(content: &[u8], context: &Context, state: &mut TextState, gs: &mut GraphicsState, page: &mut PageContent) {
+    let mut builder = PathBuilder::default();
+    let mut stack = Vec::new();
+    for op in parse(lexer(content)) {
+        // Operations with malformed operands are skipped, as unknown ones are.
+        let Ok(op) = ContentOp::from_operation(&op) else { continue };
+        match op {
+            ContentOp::Save => stack.push((state.save(), gs.clone())),
+            ContentOp::Restore => if let Some((saved, saved_gs)) = stack.pop() {
+                state.restore(saved);
+                *gs = saved_gs;
+            },
+            ContentOp::Transform([a, b, c, d, e, f]) => state.concat_ctm(Matrix::new(a, b, c, d, e, f)),
+            ContentOp::SetFillGray(g) => set_device_fill(context, state, "DeviceGray", vec![g]),
+            ContentOp::SetFillRGB(r, g, b) => set_device_fill(context, state, "DeviceRGB", vec![r, g, b]),
+            ContentOp::SetFillCMYK(c, m, y, k) => set_device_fill(context, state, "DeviceCMYK", vec![c, m, y, k]),
+            ContentOp::SetFillColorSpace(name) => state.set_color_space(&name, context.color_space(&name)),
+            ContentOp::SetStrokeColorSpace(name) => gs.set_stroke_space(&name, context.color_space(&name)),
+            ContentOp::SetFillColorN(components, Some(name)) => state.set_fill_pattern(components, context.pattern(&name)),
+            ContentOp::SetStrokeColorN(components, Some(name)) => gs.set_stroke_pattern(components, context.pattern(&name)),
+            ContentOp::SetFillColor(components) | ContentOp::SetFillColorN(components, None) => if !components.is_empty() {
+                state.set_fill_color(components);
+            },
+            ContentOp::PaintShading(name) => if let Some(shading) = context.shading(&name) {
+                let ctm = state.get_ctm();
+                let segments = shading_area(&shading, &ctm, &gs.clip);
+                // The background only applies to shadings painted as patterns.
+                let pattern = Pattern::Shading { shading: Box::new(Shading { background: None, ..shading }), matrix: ctm };
+                let fill = ColorSpace::Pattern(None).color("Pattern", Vec::new()).with_pattern(Some(Rc::new(pattern)));
+                page.paths.push(Path { segments, fill: true, ..gs.path(&fill, &ctm) });
+            },
+            ContentOp::PaintXObject(name) => {
+                let Some(xobject) = context.xobject(&name) else { continue };
+                if xobject.dict().get("Subtype").is_some_and(|x| x == "Form") {
+                    paint_form(xobject, context, state, gs, page);
+                    continue;
+                }
+                let image = Some(xobject)
+                    .filter(|x| x.dict().get("Subtype").is_some_and(|x| x == "Image"))
+                    .and_then(|x| Image::from_xobject(context.pdf, x, context.resources, state.get_fill_color()));
+                if let Some(image) = image {
+                    page.images.push(placed_image(image, state, gs, page));
+                }
+            }
+            ContentOp::InlineImage(dict, data) => {
+                if let Some(image) = Image::from_inline(context.pdf, &dict, &data, context.resources, state.get_fill_color()) {
+                    page.images.push(placed_image(image, state, gs, page));
+                }
+            }
+            ContentOp::SetExtGState(name) => {
+                let Some(dict) = context.ext_g_state(&name) else { continue };
+                set_ext_g_state(&name, dict, context, state.get_ctm(), gs);
+            }
+            ContentOp::SetFont(ref name, _) => {
+                handle_text_operation(&op, state, context.fonts);
+                state.set_font_key(font_key(context.pdf, context.root, context.resources, context.owner, name));
+            }
+            _ => {
+                if gs.handle_operation(&op) {
+                    continue;
+                }
+                if let Some(path) = builder.handle_path_operation(&op, &state.get_ctm(), gs, state.get_fill_color()) {
+                    page.paths.push(path);
+                }
+                let name = state.get_font_key().to_string();
+                let shown = handle_text_operation(&op, state, context.fonts);
+                match context.fonts.get(&name) {
+                    Some(font) if font.subtype == "Type3" => for (code, m) in shown {
+                        let glyph = draw_type3_glyph(context, font, code, m, state, gs);
+                        let (paths_before, images_before) = (page.paths.len(), page.images.len());
+                        page.glyphs.extend(glyph.paths.into_iter().map(|path| {
+                            GlyphPath { font: name.clone(), path, paths_before, images_before }
+                        }));
+                        page.images.extend(glyph.images.into_iter().map(|x| PlacedImage { paths_before, ..x }));
+                    },
+                    Some(_) if !shown.is_empty() => {
+                        // Render modes fill, stroke, or both, each optionally adding to the clip.
+                        let mode = state.get_render_mode() % 4;
+                        let path = Path {
+                            fill: mode == 0 || mode == 2,
+                            stroke: mode == 1 || mode == 2,
+                            ..gs.path(state.get_fill_color(), &state.get_ctm())
+                        };
+                        if mode != 3 {
+                            page.shown.extend(shown.into_iter().map(|(code, matrix)| {
+                                ShownGlyph { font: name.clone(), code, matrix, path: path.clone() }
+                            }));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn set_device_fill(context: &Context, state: &mut TextState, name: &str, components: Vec<f64>) {
+    state.set_color_space(name, context.color_space(name));
+    state.set_fill_color(components);
+}
+
+fn placed_image(image: Image, state: &TextState, gs: &GraphicsState, page: &PageContent) -> PlacedImage {
+    PlacedImage {
+        image,
+        matrix: state.get_ctm(),
+        clip: gs.clip.clone(),
+        paths_before: page.paths.len(),
+        alpha: gs.fill_alpha,
+        transparency: gs.transparency.clone(),
+    }
+}
+
+// Applies the transparency parameters of an ExtGState dictionary.
+fn set_ext_g_state(name: &str, dict: &HashMap<String, Value>, context: &Context, ctm: Matrix, gs: &mut GraphicsState) {
+    let pdf = context.pdf;
+    let number = |key| match dict.get(key).map(|x| pdf.resolve(x)) {
+        Some(&Value::Number(n)) => Some(n.clamp(0., 1.)),
+        _ => None,
+    };
+    if let Some(alpha) = number("ca") {
+        gs.fill_alpha = alpha;
+    }
+    if let Some(alpha) = number("CA") {
+        gs.stroke_alpha = alpha;
+    }
+    // The blend mode may be a list of modes, of which the first one known applies.
+    let blend = match dict.get("BM").map(|x| pdf.resolve(x)) {
+        Some(Value::Key(name)) => BlendMode::from_name(name),
+        Some(Value::List(list)) => list.iter().find_map(|x| match pdf.resolve(x) {
+            Value::Key(name) => BlendMode::from_name(name),
+            _ => None,
+        }),
+        _ => None,
+    };
+    if let Some(blend) = blend {
+        gs.transparency.blend = blend;
+    }
+    match dict.get("SMask") {
+        Some(value) if *pdf.resolve(value) == *"None" => gs.transparency.soft_mask = None,
+        Some(value) => gs.transparency.soft_mask = context.soft_mask(name, value, ctm),
+        None => {}
+    }
+}
+
+// A soft mask dictionary, with its group painted in the CTM in effect when it is set.
+fn soft_mask(value: &Value, context: &Context, ctm: Matrix) -> Option<SoftMask> {
+    let pdf = context.pdf;
+    let dict = pdf.resolve_dict(value)?;
+    let &Value::Ref(m, n) = dict.get("G")? else { return None };
+    let form = pdf.get(&(m, n))?;
+    let luminosity = !dict.get("S").is_some_and(|x| *pdf.resolve(x) == *"Alpha");
+    // The backdrop is in the color space of the group, black by default.
+    let space = form.dict().get("Group")
+        .and_then(|x| pdf.resolve_dict(x))
+        .and_then(|x| x.get("CS"))
+        .and_then(|x| ColorSpace::load(pdf, x, context.resources));
+    let backdrop = match (space, util::numbers(pdf, dict.get("BC"))) {
+        (Some(space), Some(components)) => space.rgb(&components),
+        _ => [0; 3],
+    };
+    let transfer = dict.get("TR").filter(|x| *pdf.resolve(x) != *"Identity").and_then(|x| function::Function::load(pdf, x));
+    let mut state = TextState::default();
+    state.concat_ctm(ctm);
+    let mut content = PageContent::default();
+    paint_form(form, context, &mut state, &mut GraphicsState::default(), &mut content);
+    Some(SoftMask { luminosity, content, backdrop, transfer })
+}
+
+// Paints a form XObject in the coordinate system of its matrix, clipped to its bounding
+// box. Forms without resources use those of the content painting them, and forms
+// already being painted are skipped. Transparency groups start a group for their
+// content unless compositing it together would change nothing.
+fn paint_form(form: &Object, context: &Context, state: &mut TextState, gs: &mut GraphicsState, page: &mut PageContent) {
+    let (pdf, dict) = (context.pdf, form.dict());
+    if context.forms.contains(&form.id()) {
+        return;
+    }
+    let (resources, owner) = match dict.get("Resources").and_then(|x| pdf.resolve_dict(x)) {
+        Some(resources) => (resources, form.id().0),
+        None => (context.resources, context.owner),
+    };
+    let (saved, saved_gs) = (state.save(), gs.clone());
+    if let Some(&[a, b, c, d, e, f]) = util::numbers(pdf, dict.get("Matrix")).as_deref() {
+        state.concat_ctm(Matrix::new(a, b, c, d, e, f));
+    }
+    let mut bbox = None;
+    if let Some(&[x0, y0, x1, y1]) = util::numbers(pdf, dict.get("BBox")).as_deref() {
+        let ctm = state.get_ctm();
+        let corners = [(x0, y0), (x1, y0), (x1, y1), (x0, y1)].map(|(x, y)| ctm.apply(x, y));
+        gs.clip.push(Clip { segments: rectangle(corners), even_odd: false });
+        bbox = Some(corners.iter().fold([f64::MAX, f64::MAX, f64::MIN, f64::MIN], |a, &(x, y)| [a[0].min(x), a[1].min(y), a[2].max(x), a[3].max(y)]));
+    }
+    let group = dict.get("Group").and_then(|x| pdf.resolve_dict(x)).filter(|x| x.get("S").is_some_and(|x| x == "Transparency"));
+    if let Some(group) = group {
+        let flag = |key| matches!(group.get(key).map(|x| pdf.resolve(x)), Some(Value::Bool(true)));
+        let (isolated, knockout) = (flag("I"), flag("K"));
+        let transparency = &gs.transparency;
+        if isolated || knockout || gs.fill_alpha < 1. || transparency.blend != BlendMode::Normal || transparency.soft_mask.is_some() {
+            let group = Group { isolated, knockout, bbox, alpha: gs.fill_alpha, transparency: transparency.clone() };
+            gs.transparency = Transparency { group: Some(Rc::new(group)), ..Transparency::default() };
+            (gs.fill_alpha, gs.stroke_alpha) = (1., 1.);
+        }
+    }
+    let mut forms = context.forms.clone();
+    forms.push(form.id());
+    let inner = Context {
+        resources, owner, forms,
+        spaces: RefCell::new(HashMap::new()), patterns: RefCell::new(HashMap::new()), masks: RefCell::new(HashMap::new()),
+        ..*context
+    };
+    let painted = (page.paths.len(), page.images.len(), state.paintings().len());
+    run(form.stream(), &inner, state, gs, page);
+    // Text of forms painting graphics too is taken as the labels of a figure.
+    if painted.0 < page.paths.len() || painted.1 < page.images.len() {
+        state.mark_figure(painted.2);
+    }
+    state.restore(saved);
+    *gs = saved_gs;
+}
+
+// The name font `name` of `resources`, those of form `owner`, is looked up by: the name
+// itself, unless it names a font other than that of the same name among the `root`
+// resources, where the object number of the font is added, or that of the form for
+// fonts given directly.
+fn font_key(pdf: &PDF, root: &HashMap<String, Value>, resources: &HashMap<String, Value>, owner: usize, name: &str) -> String {
+    let font = |resources: &HashMap<String, Value>| resources.get("Font").and_then(|x| pdf.resolve_dict(x))?.get(name).cloned();
+    match font(resources) {
+        Some(value) if font(root).as_ref() != Some(&value) => match value {
+            Value::Ref(m, _) => format!("{name}+{m}"),
+            _ => format!("{name}+{owner}"),
+        },
+        _ => name.into(),
+    }
+}
+
+// Fonts of `resources` and of the forms they paint, either as XObjects or as the groups
+// of soft masks, by the names text shown in them carries.
+pub fn load_content_fonts(pdf: &PDF, resources: &HashMap<String, Value>) -> HashMap<String, Font> {
+    let mut ret = load_fonts(pdf, resources);
+    let mut seen = HashSet::new();
+    let mut stack = vec![(resources, 0)];
+    while let Some((current, owner)) = stack.pop() {
+        let dict = |key| current.get(key).and_then(|x| pdf.resolve_dict(x)).into_iter().flat_map(|x| x.values());
+        let groups = dict("ExtGState").filter_map(|x| pdf.resolve_dict(x)?.get("SMask")).filter_map(|x| pdf.resolve_dict(x)?.get("G"));
+        for value in dict("XObject").chain(groups) {
+            let &Value::Ref(m, n) = value else { continue };
+            let Some(form) = pdf.get(&(m, n)).filter(|x| x.dict().get("Subtype").is_some_and(|x| x == "Form")) else { continue };
+            if !seen.insert((m, n)) {
+                continue;
+            }
+            let (inner, owner) = match form.dict().get("Resources").and_then(|x| pdf.resolve_dict(x)) {
+                Some(inner) => (inner, m),
+                None => (current, owner),
+            };
+            for (name, font) in inner.get("Font").and_then(|x| pdf.resolve_dict(x)).into_iter().flatten() {
+                let key = font_key(pdf, resources, inner, owner, name);
+                if let (false, Some(dict)) = (ret.contains_key(&key), pdf.resolve_dict(font)) {
+                    ret.insert(key, Font::load(pdf, dict));
+                }
+            }
+            stack.push((inner, owner));
+        }
+    }
+    ret
+}
+
+// Runs the CharProc of a glyph with the font matrix applied to the glyph's rendering matrix,
+// painting in the current fill color and clip. The resources of the font are used, or else
+// those of the page. Text inside CharProcs is not followed, so glyphs never nest.
+fn draw_type3_glyph(context: &Context, font: &Font, code: u32, rendering: Matrix, outer: &TextState, outer_gs: &GraphicsState) -> PageContent {
+    let mut glyph = PageContent::default();
+    let Some(proc) = font.char_proc(code) else { return glyph };
+    let mut state = TextState::default();
+    let fill = outer.get_fill_color();
+    state.set_color_space(&fill.space, outer.get_fill_space());
+    state.set_fill_pattern(fill.components.clone(), fill.pattern.clone());
+    state.concat_ctm(font.font_matrix().multiply(&rendering));
+    let mut gs = GraphicsState {
+        clip: outer_gs.clip.clone(),
+        fill_alpha: outer_gs.fill_alpha,
+        stroke_alpha: outer_gs.stroke_alpha,
+        transparency: outer_gs.transparency.clone(),
+        ..GraphicsState::default()
+    };
+    let fonts = HashMap::new();
+    let context = Context::new(context.pdf, font.resources().unwrap_or(context.root), &fonts);
+    run(proc, &context, &mut state, &mut gs, &mut glyph);
+    glyph
+}
+
+// The area painted by sh: the bounding box of the shading, or else that of the clip.
+fn shading_area(shading: &Shading, ctm: &Matrix, clip: &[Clip]) -> Vec<Segment> {
+    let corners = match shading.bbox {
+        Some([x0, y0, x1, y1]) => [(x0, y0), (x1, y0), (x1, y1), (x0, y1)].map(|(x, y)| ctm.apply(x, y)),
+        None => {
+            let (mut x0, mut y0, mut x1, mut y1) = (-1e5, -1e5, 1e5, 1e5);
+            for clip in clip {
+                let bbox = Path { segments: clip.segments.clone(), ..Path::default() }.bbox();
+                (x0, y0, x1, y1) = (bbox.0.max(x0), bbox.1.max(y0), bbox.2.min(x1), bbox.3.min(y1));
+            }
+            [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+        }
+    };
+    rectangle(corners)
+}
+
+fn rectangle(corners: [(f64, f64); 4]) -> Vec<Segment> {
+    let mut ret = vec![Segment::MoveTo(corners[0].0, corners[0].1)];
+    ret.extend(corners[1..].iter().map(|&(x, y)| Segment::LineTo(x, y)));
+    ret.push(Segment::Close);
+    ret
+}
+
+// The content of a tiling pattern, in pattern space.
+pub fn get_pattern_content(pdf: &PDF, pattern: &Object) -> PageContent {
+    let resources = pdf.get_page_resources(pattern);
+    let fonts = load_content_fonts(pdf, resources);
+    let context = Context::new(pdf, resources, &fonts);
+    let mut state = TextState::default();
+    let mut ret = PageContent::default();
+    run(pattern.stream(), &context, &mut state, &mut GraphicsState::default(), &mut ret);
+    ret.texts = state.drain();
+    ret
+}
+
+pub fn get_page_content(pdf: &PDF, page: &Object) -> PageContent {
+    let resources = pdf.get_page_resources(page);
+    let fonts = load_content_fonts(pdf, resources);
+    let content = pdf.get_page_contents(page);
+    let context = Context::new(pdf, resources, &fonts);
+    let mut state = TextState::default();
+    let mut ret = PageContent::default();
+    run(&content, &context, &mut state, &mut GraphicsState::default(), &mut ret);
+    ret.texts = state.drain();
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_page_pdf;
+    use crate::text::get_page_texts;
+
+    #[test]
+    fn test_type3_glyph() {
+        let pdf = test_page_pdf("<< /Font << /F1 5 0 R >> >>", b"BT /F1 10 Tf 20 50 Td (a) Tj ET", &[
+            ("<< /Type /Font /Subtype /Type3 /FontBBox [0 0 750 750] /FontMatrix [0.002 0 0 0.001 0 0] /CharProcs << /square 6 0 R >> /Encoding << /Differences [97 /square] >> >>", b""),
+            ("<< >>", b"800 0 0 0 750 750 d1 0 0 750 750 re f"),
+        ]);
+        let content = get_page_content(&pdf, pdf.get_page_list()[0]);
+        assert_eq!(content.glyphs.len(), 1);
+        let GlyphPath { font, path, .. } = &content.glyphs[0];
+        assert_eq!(font, "F1");
+        assert!(path.fill);
+        assert_eq!(path.bbox(), (20., 50., 35., 57.5));
+        // The glyph advances by its d1 width through the font matrix.
+        assert_eq!(content.texts[0].width, 16.);
+    }
+
+    #[test]
+    fn test_type3_images() {
+        let pdf = test_page_pdf("<< /Font << /F1 5 0 R >> >>", b"BT /F1 10 Tf 20 50 Td (ab) Tj ET", &[
+            ("<< /Type /Font /Subtype /Type3 /FontBBox [0 0 1000 1000] /FontMatrix [0.001 0 0 0.001 0 0] /CharProcs << /stencil 6 0 R /form 7 0 R >> /Encoding << /Differences [97 /stencil /form] >> /Resources << /XObject << /Im 8 0 R >> >> >>", b""),
+            ("<< >>", b"1000 0 0 0 1000 1000 d1 1000 0 0 1000 0 0 cm BI /Width 8 /Height 8 /ImageMask true /BitsPerComponent 1 ID \x00\xff\x00\xff\x00\xff\x00\xff EI"),
+            ("<< >>", b"1000 0 0 0 1000 1000 d1 1000 0 0 1000 0 0 cm /Im Do"),
+            ("<< /Subtype /Image /Width 1 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8 >>", b"\x80"),
+        ]);
+        let content = get_page_content(&pdf, pdf.get_page_list()[0]);
+        // Images of CharProcs are placed through the glyph matrix, with XObjects from the font resources.
+        assert_eq!(content.images.len(), 2);
+        assert_eq!(content.images[0].bbox(), (20., 50., 30., 60.));
+        assert_eq!(content.images[1].bbox(), (30., 50., 40., 60.));
+    }
+
+    #[test]
+    fn test_graphics() {
+        let content = b"0 0 100 100 re f BT /F1 10 Tf 20 50 Td (a) Tj ET BI /Width 1 /Height 1 /ImageMask true ID \x00 EI 0 0 5 5 re f";
+        let pdf = test_page_pdf("<< /Font << /F1 5 0 R >> >>", content, &[
+            ("<< /Type /Font /Subtype /Type3 /FontBBox [0 0 750 750] /FontMatrix [0.002 0 0 0.001 0 0] /CharProcs << /square 6 0 R >> /Encoding << /Differences [97 /square] >> >>", b""),
+            ("<< >>", b"800 0 0 0 750 750 d1 0 0 750 750 re f"),
+        ]);
+        let content = get_page_content(&pdf, pdf.get_page_list()[0]);
+        // Type 3 glyphs stack between the paths and images painted around them.
+        let order = content.graphics().iter().map(|x| match x {
+            Graphic::Path(path) => path.bbox().2,
+            Graphic::Image(_) => 0.,
+        }).collect::<Vec<_>>();
+        assert_eq!(order, [100., 35., 0., 5.]);
+    }
+
+    #[test]
+    fn test_forms() {
+        let resources = "<< /Font << /F1 5 0 R >> /XObject << /Fa 6 0 R /Fb 8 0 R /Fc 9 0 R >> >>";
+        let pdf = test_page_pdf(resources, b"BT /F1 10 Tf 20 50 Td (a) Tj ET /Fa Do /Fb Do /Fc Do", &[
+            ("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>", b""),
+            // A form painting itself, with a font named as that of the page.
+            (
+                "<< /Subtype /Form /Matrix [2 0 0 2 10 10] /BBox [0 0 20 20] /Resources << /Font << /F1 7 0 R >> /XObject << /Fm 6 0 R >> >> >>",
+                b"BT /F1 5 Tf 1 1 Td (b) Tj ET /Fm Do 0 0 100 100 re f",
+            ),
+            ("<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>", b""),
+            // Forms without resources, and with a font given directly.
+            ("<< /Subtype /Form >>", b"BT /F1 5 Tf (c) Tj ET"),
+            ("<< /Subtype /Form /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Times-Roman >> >> >> >>", b"BT /F1 5 Tf (d) Tj ET"),
+        ]);
+        let page = pdf.get_page_list()[0];
+        let texts = get_page_texts(&pdf, page);
+        assert_eq!(texts.iter().map(|x| &*x.text).collect::<Vec<_>>(), ["a", "b", "c", "d"]);
+        // Text and paths of the form are placed by its matrix and clipped to its box.
+        assert_eq!((texts[1].x, texts[1].y, texts[1].font_size), (12., 12., 10.));
+        let paths = get_page_content(&pdf, page).paths;
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].bbox(), (10., 10., 210., 210.));
+        assert_eq!(paths[0].clip[0].segments, rectangle([(10., 10.), (50., 10.), (50., 50.), (10., 50.)]));
+        // Spans keep the resource name, and fonts are found by their keys.
+        assert!(texts.iter().all(|x| x.font == "F1"));
+        let fonts = load_content_fonts(&pdf, pdf.get_page_resources(page));
+        let base_fonts = texts.iter().map(|x| &*fonts[&x.font_key].base_font).collect::<Vec<_>>();
+        assert_eq!(base_fonts, ["Helvetica", "Courier", "Helvetica", "Times-Roman"]);
+    }
+}
+
@@ -0,0 +1,164 @@
+use super::otf::wrap_cff;
+use super::parser::{Object, PDF, Value};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct EmbeddedFont {
+    // Object number of the font program stream.
+    pub id: (usize, usize),
+    pub base_font: String,
+    pub subset_prefix: Option<String>,
+    pub name: String,
+    pub font_type: String,
+    pub kind: String,
+    pub subtype: String,
+    pub encoding: String,
+    length1: usize,
+    length2: usize,
+    pub data: Vec<u8>,
+}
+
+pub fn split_subset_prefix(base_font: &str) -> (Option<String>, String) {
+    match base_font.split_once('+') {
+        Some((prefix, name)) if prefix.len() == 6 && prefix.chars().all(|c| c.is_ascii_uppercase()) => {
+            (Some(prefix.into()), name.into())
+        }
+        _ => (None, base_font.into()),
+    }
+}
+
+fn encoding_name(pdf: &PDF, dict: &HashMap<String, Value>) -> String {
+    match dict.get("Encoding").map(|x| pdf.resolve(x)) {
+        Some(Value::Key(name)) => name.clone(),
+        Some(Value::Dict(encoding)) => {
+            let differences = encoding.contains_key("Differences");
+            match encoding.get("BaseEncoding").map(|x| pdf.resolve(x)) {
+                Some(Value::Key(base)) if differences => format!("{base}+Differences"),
+                Some(Value::Key(base)) => base.clone(),
+                _ if differences => "Differences".into(),
+                _ => "Builtin".into(),
+            }
+        }
+        Some(_) => "Embedded CMap".into(),
+        None => "Builtin".into(),
+    }
+}
+
+impl EmbeddedFont {
+    fn load(pdf: &PDF, font: &HashMap<String, Value>) -> Option<EmbeddedFont> {
+        let key = |dict: &HashMap<String, Value>, key: &str| match dict.get(key).map(|x| pdf.resolve(x)) {
+            Some(Value::Key(s)) => s.clone(),
+            _ => String::new(),
+        };
+        let descendant = match font.get("DescendantFonts").map(|x| pdf.resolve(x)) {
+            Some(Value::List(list)) => list.first().and_then(|x| pdf.resolve_dict(x)),
+            _ => None,
+        };
+        let descriptor = descendant.unwrap_or(font).get("FontDescriptor").and_then(|x| pdf.resolve_dict(x))?;
+        let (kind, program) = ["FontFile", "FontFile2", "FontFile3"].iter().find_map(|&kind| match descriptor.get(kind) {
+            Some(&Value::Ref(m, n)) => pdf.get(&(m, n)).map(|x| (kind, x)),
+            _ => None,
+        })?;
+        let base_font = key(font, "BaseFont");
+        let (subset_prefix, name) = split_subset_prefix(&base_font);
+        let length = |key: &str| match program.dict().get(key).map(|x| pdf.resolve(x)) {
+            Some(&Value::Number(n)) => n as usize,
+            _ => 0,
+        };
+        Some(EmbeddedFont {
+            id: program.id(),
+            subset_prefix,
+            name,
+            font_type: key(font, "Subtype"),
+            kind: kind.into(),
+            subtype: key(program.dict(), "Subtype"),
+            encoding: encoding_name(pdf, font),
+            length1: length("Length1"),
+            length2: length("Length2"),
+            data: program.stream().to_vec(),
+            base_font,
+        })
+    }
+
+    fn is_hex_type1(&self) -> bool {
+        let binary = self.data.get(self.length1 ..).unwrap_or_default();
+        let binary = binary.iter().skip_while(|x| x.is_ascii_whitespace()).take(4).collect::<Vec<_>>();
+        binary.len() == 4 && binary.iter().all(|x| x.is_ascii_hexdigit())
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match (&*self.kind, &*self.subtype) {
+            ("FontFile", _) if self.is_hex_type1() => "pfa",
+            ("FontFile", _) => "pfb",
+            ("FontFile2", _) => "ttf",
+            ("FontFile3", "OpenType") => "otf",
+            _ => "cff",
+        }
+    }
+
+    // The program as written to a file of `extension()`; Type 1 fonts get PFB segment headers.
+    pub fn file_data(&self) -> Vec<u8> {
+        if self.extension() != "pfb" {
+            return self.data.clone();
+        }
+        let length1 = self.length1.min(self.data.len());
+        let length2 = if self.length2 == 0 { self.data.len() - length1 } else { self.length2.min(self.data.len() - length1) };
+        let mut trailer = self.data[length1 + length2 ..].to_vec();
+        if trailer.iter().all(|x| x.is_ascii_whitespace() || *x == 0) {
+            trailer = format!("\n{}cleartomark\n", format!("{}\n", "0".repeat(64)).repeat(8)).into_bytes();
+        }
+        let mut out = Vec::new();
+        for (kind, segment) in [(1, &self.data[.. length1]), (2, &self.data[length1 .. length1 + length2]), (1, &trailer[..])] {
+            out.extend_from_slice(&[0x80, kind]);
+            out.extend_from_slice(&(segment.len() as u32).to_le_bytes());
+            out.extend_from_slice(segment);
+        }
+        out.extend_from_slice(&[0x80, 3]);
+        out
+    }
+
+    // Bare CFF programs wrapped into an OpenType font.
+    pub fn opentype(&self) -> Option<Vec<u8>> {
+        match self.extension() {
+            "otf" => Some(self.data.clone()),
+            "cff" => wrap_cff(&self.data),
+            _ => None,
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        let name = self.base_font.replace(|c: char| !c.is_ascii_alphanumeric() && c != '+' && c != '-', "_");
+        format!("{}-{name}", self.id.0)
+    }
+}
+
+fn is_font(obj: &Object) -> bool {
+    matches!(obj.dict().get("Type"), Some(Value::Key(s)) if s == "Font")
+}
+
+// Every font program in the document, once per stream, in object order.
+pub fn get_embedded_fonts(pdf: &PDF) -> Vec<EmbeddedFont> {
+    let mut objects = pdf.get_objects().values().filter(|x| is_font(x)).collect::<Vec<_>>();
+    objects.sort_by_key(|x| x.id());
+    // CIDFonts are reached through their Type0 parent, which holds the encoding.
+    objects.sort_by_key(|x| matches!(x.dict().get("Subtype"), Some(Value::Key(s)) if s.starts_with("CIDFontType")));
+    let mut seen = HashSet::new();
+    let mut ret = objects.into_iter()
+        .filter_map(|x| EmbeddedFont::load(pdf, x.dict()))
+        .filter(|x| seen.insert(x.id))
+        .collect::<Vec<_>>();
+    ret.sort_by_key(|x| x.id);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subset_prefix() {
+        assert_eq!(split_subset_prefix("ABCDEF+Times-Roman"), (Some("ABCDEF".into()), "Times-Roman".into()));
+        assert_eq!(split_subset_prefix("Times-Roman"), (None, "Times-Roman".into()));
+        assert_eq!(split_subset_prefix("abc+Times"), (None, "abc+Times".into()));
+    }
+}
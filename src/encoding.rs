@@ -0,0 +1,204 @@
+const ASCII_NAMES: [&str; 95] = [
+    "space", "exclam", "quotedbl", "numbersign", "dollar", "percent", "ampersand", "quotesingle",
+    "parenleft", "parenright", "asterisk", "plus", "comma", "hyphen", "period", "slash",
+    "zero", "one", "two", "three", "four", "five", "six", "seven",
+    "eight", "nine", "colon", "semicolon", "less", "equal", "greater", "question",
+    "at", "A", "B", "C", "D", "E", "F", "G",
+    "H", "I", "J", "K", "L", "M", "N", "O",
+    "P", "Q", "R", "S", "T", "U", "V", "W",
+    "X", "Y", "Z", "bracketleft", "backslash", "bracketright", "asciicircum", "underscore",
+    "grave", "a", "b", "c", "d", "e", "f", "g",
+    "h", "i", "j", "k", "l", "m", "n", "o",
+    "p", "q", "r", "s", "t", "u", "v", "w",
+    "x", "y", "z", "braceleft", "bar", "braceright", "asciitilde",
+];
+
+const LATIN1_NAMES: [&str; 96] = [
+    "nbspace", "exclamdown", "cent", "sterling", "currency", "yen", "brokenbar", "section",
+    "dieresis", "copyright", "ordfeminine", "guillemotleft", "logicalnot", "sfthyphen", "registered", "macron",
+    "degree", "plusminus", "twosuperior", "threesuperior", "acute", "mu", "paragraph", "periodcentered",
+    "cedilla", "onesuperior", "ordmasculine", "guillemotright", "onequarter", "onehalf", "threequarters", "questiondown",
+    "Agrave", "Aacute", "Acircumflex", "Atilde", "Adieresis", "Aring", "AE", "Ccedilla",
+    "Egrave", "Eacute", "Ecircumflex", "Edieresis", "Igrave", "Iacute", "Icircumflex", "Idieresis",
+    "Eth", "Ntilde", "Ograve", "Oacute", "Ocircumflex", "Otilde", "Odieresis", "multiply",
+    "Oslash", "Ugrave", "Uacute", "Ucircumflex", "Udieresis", "Yacute", "Thorn", "germandbls",
+    "agrave", "aacute", "acircumflex", "atilde", "adieresis", "aring", "ae", "ccedilla",
+    "egrave", "eacute", "ecircumflex", "edieresis", "igrave", "iacute", "icircumflex", "idieresis",
+    "eth", "ntilde", "ograve", "oacute", "ocircumflex", "otilde", "odieresis", "divide",
+    "oslash", "ugrave", "uacute", "ucircumflex", "udieresis", "yacute", "thorn", "ydieresis",
+];
+
+const STANDARD_HIGH: &[(u8, &str)] = &[
+    (39, "quoteright"), (96, "quoteleft"),
+    (161, "exclamdown"), (162, "cent"), (163, "sterling"), (164, "fraction"), (165, "yen"), (166, "florin"),
+    (167, "section"), (168, "currency"), (169, "quotesingle"), (170, "quotedblleft"), (171, "guillemotleft"),
+    (172, "guilsinglleft"), (173, "guilsinglright"), (174, "fi"), (175, "fl"), (177, "endash"), (178, "dagger"),
+    (179, "daggerdbl"), (180, "periodcentered"), (182, "paragraph"), (183, "bullet"), (184, "quotesinglbase"),
+    (185, "quotedblbase"), (186, "quotedblright"), (187, "guillemotright"), (188, "ellipsis"), (189, "perthousand"),
+    (191, "questiondown"), (193, "grave"), (194, "acute"), (195, "circumflex"), (196, "tilde"), (197, "macron"),
+    (198, "breve"), (199, "dotaccent"), (200, "dieresis"), (202, "ring"), (203, "cedilla"), (205, "hungarumlaut"),
+    (206, "ogonek"), (207, "caron"), (208, "emdash"), (225, "AE"), (227, "ordfeminine"), (232, "Lslash"),
+    (233, "Oslash"), (234, "OE"), (235, "ordmasculine"), (241, "ae"), (245, "dotlessi"), (248, "lslash"),
+    (249, "oslash"), (250, "oe"), (251, "germandbls"),
+];
+
+const WIN_ANSI_HIGH: &[(u8, &str)] = &[
+    (128, "Euro"), (130, "quotesinglbase"), (131, "florin"), (132, "quotedblbase"), (133, "ellipsis"),
+    (134, "dagger"), (135, "daggerdbl"), (136, "circumflex"), (137, "perthousand"), (138, "Scaron"),
+    (139, "guilsinglleft"), (140, "OE"), (142, "Zcaron"), (145, "quoteleft"), (146, "quoteright"),
+    (147, "quotedblleft"), (148, "quotedblright"), (149, "bullet"), (150, "endash"), (151, "emdash"),
+    (152, "tilde"), (153, "trademark"), (154, "scaron"), (155, "guilsinglright"), (156, "oe"),
+    (158, "zcaron"), (159, "Ydieresis"),
+];
+
+const MAC_ROMAN_HIGH: [&str; 128] = [
+    "Adieresis", "Aring", "Ccedilla", "Eacute", "Ntilde", "Odieresis", "Udieresis", "aacute",
+    "agrave", "acircumflex", "adieresis", "atilde", "aring", "ccedilla", "eacute", "egrave",
+    "ecircumflex", "edieresis", "iacute", "igrave", "icircumflex", "idieresis", "ntilde", "oacute",
+    "ograve", "ocircumflex", "odieresis", "otilde", "uacute", "ugrave", "ucircumflex", "udieresis",
+    "dagger", "degree", "cent", "sterling", "section", "bullet", "paragraph", "germandbls",
+    "registered", "copyright", "trademark", "acute", "dieresis", "notequal", "AE", "Oslash",
+    "infinity", "plusminus", "lessequal", "greaterequal", "yen", "mu", "partialdiff", "summation",
+    "product", "pi", "integral", "ordfeminine", "ordmasculine", "Omega", "ae", "oslash",
+    "questiondown", "exclamdown", "logicalnot", "radical", "florin", "approxequal", "Delta", "guillemotleft",
+    "guillemotright", "ellipsis", "nbspace", "Agrave", "Atilde", "Otilde", "OE", "oe",
+    "endash", "emdash", "quotedblleft", "quotedblright", "quoteleft", "quoteright", "divide", "lozenge",
+    "ydieresis", "Ydieresis", "fraction", "currency", "guilsinglleft", "guilsinglright", "fi", "fl",
+    "daggerdbl", "periodcentered", "quotesinglbase", "quotedblbase", "perthousand", "Acircumflex", "Ecircumflex", "Aacute",
+    "Edieresis", "Egrave", "Iacute", "Icircumflex", "Idieresis", "Igrave", "Oacute", "Ocircumflex",
+    "apple", "Ograve", "Uacute", "Ucircumflex", "Ugrave", "dotlessi", "circumflex", "tilde",
+    "macron", "breve", "dotaccent", "ring", "cedilla", "hungarumlaut", "ogonek", "caron",
+];
+
+const OTHER_GLYPHS: &[(&str, &str)] = &[
+    ("quoteleft", "\u{2018}"), ("quoteright", "\u{2019}"), ("quotedblleft", "\u{201C}"), ("quotedblright", "\u{201D}"),
+    ("quotesinglbase", "\u{201A}"), ("quotedblbase", "\u{201E}"), ("guilsinglleft", "\u{2039}"), ("guilsinglright", "\u{203A}"),
+    ("endash", "\u{2013}"), ("emdash", "\u{2014}"), ("bullet", "\u{2022}"), ("ellipsis", "\u{2026}"),
+    ("dagger", "\u{2020}"), ("daggerdbl", "\u{2021}"), ("perthousand", "\u{2030}"), ("trademark", "\u{2122}"),
+    ("Euro", "\u{20AC}"), ("florin", "\u{0192}"), ("fraction", "\u{2044}"), ("circumflex", "\u{02C6}"),
+    ("tilde", "\u{02DC}"), ("breve", "\u{02D8}"), ("dotaccent", "\u{02D9}"), ("ring", "\u{02DA}"),
+    ("hungarumlaut", "\u{02DD}"), ("ogonek", "\u{02DB}"), ("caron", "\u{02C7}"), ("dotlessi", "\u{0131}"),
+    ("dotlessj", "\u{0237}"), ("Lslash", "\u{0141}"), ("lslash", "\u{0142}"), ("OE", "\u{0152}"), ("oe", "\u{0153}"),
+    ("Scaron", "\u{0160}"), ("scaron", "\u{0161}"), ("Zcaron", "\u{017D}"), ("zcaron", "\u{017E}"),
+    ("Ydieresis", "\u{0178}"), ("fi", "fi"), ("fl", "fl"), ("ff", "ff"), ("ffi", "ffi"), ("ffl", "ffl"),
+    ("minus", "\u{2212}"), ("periodcentered", "\u{00B7}"), ("dotmath", "\u{22C5}"), ("asteriskmath", "\u{2217}"),
+    ("infinity", "\u{221E}"), ("notequal", "\u{2260}"), ("lessequal", "\u{2264}"), ("greaterequal", "\u{2265}"),
+    ("approxequal", "\u{2248}"), ("equivalence", "\u{2261}"), ("similar", "\u{223C}"), ("proportional", "\u{221D}"),
+    ("partialdiff", "\u{2202}"), ("summation", "\u{2211}"), ("product", "\u{220F}"), ("integral", "\u{222B}"),
+    ("radical", "\u{221A}"), ("nabla", "\u{2207}"), ("gradient", "\u{2207}"), ("element", "\u{2208}"),
+    ("notelement", "\u{2209}"), ("union", "\u{222A}"), ("intersection", "\u{2229}"), ("propersubset", "\u{2282}"),
+    ("propersuperset", "\u{2283}"), ("reflexsubset", "\u{2286}"), ("reflexsuperset", "\u{2287}"), ("emptyset", "\u{2205}"),
+    ("logicaland", "\u{2227}"), ("logicalor", "\u{2228}"), ("universal", "\u{2200}"), ("existential", "\u{2203}"),
+    ("arrowleft", "\u{2190}"), ("arrowup", "\u{2191}"), ("arrowright", "\u{2192}"), ("arrowdown", "\u{2193}"),
+    ("arrowboth", "\u{2194}"), ("arrowdblleft", "\u{21D0}"), ("arrowdblright", "\u{21D2}"), ("arrowdblboth", "\u{21D4}"),
+    ("angleleft", "\u{2329}"), ("angleright", "\u{232A}"), ("circleplus", "\u{2295}"), ("circlemultiply", "\u{2297}"),
+    ("prime", "\u{2032}"), ("lozenge", "\u{25CA}"), ("Delta", "\u{2206}"), ("Omega", "\u{2126}"),
+    ("apple", "\u{F8FF}"), ("openbullet", "\u{25E6}"), ("checkmark", "\u{2713}"), ("club", "\u{2663}"),
+    ("diamond", "\u{2666}"), ("heart", "\u{2665}"), ("spade", "\u{2660}"),
+    ("alpha", "\u{03B1}"), ("beta", "\u{03B2}"), ("gamma", "\u{03B3}"), ("delta", "\u{03B4}"),
+    ("epsilon", "\u{03B5}"), ("zeta", "\u{03B6}"), ("eta", "\u{03B7}"), ("theta", "\u{03B8}"),
+    ("iota", "\u{03B9}"), ("kappa", "\u{03BA}"), ("lambda", "\u{03BB}"), ("nu", "\u{03BD}"),
+    ("xi", "\u{03BE}"), ("omicron", "\u{03BF}"), ("pi", "\u{03C0}"), ("rho", "\u{03C1}"),
+    ("sigma", "\u{03C3}"), ("sigma1", "\u{03C2}"), ("tau", "\u{03C4}"), ("upsilon", "\u{03C5}"),
+    ("phi", "\u{03C6}"), ("chi", "\u{03C7}"), ("psi", "\u{03C8}"), ("omega", "\u{03C9}"),
+    ("phi1", "\u{03D5}"), ("theta1", "\u{03D1}"), ("omega1", "\u{03D6}"), ("epsilon1", "\u{03F5}"),
+    ("Alpha", "\u{0391}"), ("Beta", "\u{0392}"), ("Gamma", "\u{0393}"), ("Epsilon", "\u{0395}"),
+    ("Zeta", "\u{0396}"), ("Eta", "\u{0397}"), ("Theta", "\u{0398}"), ("Iota", "\u{0399}"),
+    ("Kappa", "\u{039A}"), ("Lambda", "\u{039B}"), ("Mu", "\u{039C}"), ("Nu", "\u{039D}"),
+    ("Xi", "\u{039E}"), ("Omicron", "\u{039F}"), ("Pi", "\u{03A0}"), ("Rho", "\u{03A1}"),
+    ("Sigma", "\u{03A3}"), ("Tau", "\u{03A4}"), ("Upsilon", "\u{03A5}"), ("Phi", "\u{03A6}"),
+    ("Chi", "\u{03A7}"), ("Psi", "\u{03A8}"),
+];
+
+pub fn standard_encoding(code: u8) -> Option<&'static str> {
+    if let Some(&(_, name)) = STANDARD_HIGH.iter().find(|x| x.0 == code) {
+        return Some(name);
+    }
+    match code {
+        32 ..= 126 => Some(ASCII_NAMES[code as usize - 32]),
+        _ => None,
+    }
+}
+
+pub fn win_ansi_encoding(code: u8) -> Option<&'static str> {
+    match code {
+        32 ..= 126 => Some(ASCII_NAMES[code as usize - 32]),
+        160 ..= 255 => Some(LATIN1_NAMES[code as usize - 160]),
+        _ => WIN_ANSI_HIGH.iter().find(|x| x.0 == code).map(|x| x.1),
+    }
+}
+
+pub fn mac_roman_encoding(code: u8) -> Option<&'static str> {
+    match code {
+        32 ..= 126 => Some(ASCII_NAMES[code as usize - 32]),
+        128 ..= 255 => Some(MAC_ROMAN_HIGH[code as usize - 128]),
+        _ => None,
+    }
+}
+
+pub fn get_encoding(name: &str) -> Option<fn(u8) -> Option<&'static str>> {
+    match name {
+        "StandardEncoding" => Some(standard_encoding),
+        "WinAnsiEncoding" => Some(win_ansi_encoding),
+        "MacRomanEncoding" | "MacExpertEncoding" => Some(mac_roman_encoding),
+        _ => None,
+    }
+}
+
+pub fn glyph_to_unicode(name: &str) -> Option<String> {
+    let name = name.split('.').next().unwrap_or(name);
+    if name.is_empty() {
+        return None;
+    }
+    if name.contains('_') {
+        return name.split('_').map(glyph_to_unicode).collect();
+    }
+    if let Some(i) = ASCII_NAMES.iter().position(|&x| x == name) {
+        return Some(((i + 32) as u8 as char).into());
+    }
+    if let Some(i) = LATIN1_NAMES.iter().position(|&x| x == name) {
+        return char::from_u32(i as u32 + 160).map(Into::into);
+    }
+    if let Some(&(_, s)) = OTHER_GLYPHS.iter().find(|x| x.0 == name) {
+        return Some(s.into());
+    }
+    if let Some(hex) = name.strip_prefix("uni") {
+        if hex.len() >= 4 && hex.len() % 4 == 0 {
+            let mut units = Vec::new();
+            for i in (0 .. hex.len()).step_by(4) {
+                units.push(u16::from_str_radix(hex.get(i .. i + 4)?, 16).ok()?);
+            }
+            return String::from_utf16(&units).ok();
+        }
+    }
+    if let Some(hex) = name.strip_prefix('u') {
+        if (4 ..= 6).contains(&hex.len()) {
+            return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(Into::into);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glyph_names() {
+        assert_eq!(glyph_to_unicode("A").unwrap(), "A");
+        assert_eq!(glyph_to_unicode("eacute").unwrap(), "\u{e9}");
+        assert_eq!(glyph_to_unicode("fi").unwrap(), "fi");
+        assert_eq!(glyph_to_unicode("uni4F60").unwrap(), "\u{4F60}");
+        assert_eq!(glyph_to_unicode("f_f_i").unwrap(), "ffi");
+        assert_eq!(glyph_to_unicode("a.sc").unwrap(), "a");
+        assert_eq!(glyph_to_unicode("nonsense"), None);
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(standard_encoding(39), Some("quoteright"));
+        assert_eq!(win_ansi_encoding(39), Some("quotesingle"));
+        assert_eq!(win_ansi_encoding(150), Some("endash"));
+        assert_eq!(mac_roman_encoding(0xD0), Some("endash"));
+    }
+}
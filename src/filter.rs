@@ -0,0 +1,299 @@
+// Stream filters other than the image formats, applied by the parser to the data of
+// every stream and by inline images to theirs.
+
+use super::ccitt;
+use super::parser::{Value, PDF};
+use super::util::{flag, number};
+use std::collections::HashMap;
+use std::io::Read;
+
+// Names of the filters of a stream, in the order to apply them.
+pub fn filters(pdf: &PDF, dict: &HashMap<String, Value>) -> Vec<String> {
+    match dict.get("Filter").map(|x| pdf.resolve(x)) {
+        Some(Value::Key(name)) => vec![name.clone()],
+        Some(Value::List(list)) => list.iter().filter_map(|x| match pdf.resolve(x) {
+            Value::Key(name) => Some(name.clone()),
+            _ => None,
+        }).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Parameters of the filter at `index`, given alone or in a list with one for each filter.
+pub fn decode_parms<'a>(pdf: &'a PDF, dict: &'a HashMap<String, Value>, index: usize) -> Option<&'a HashMap<String, Value>> {
+    match pdf.resolve(dict.get("DecodeParms")?) {
+        Value::List(list) => pdf.resolve_dict(list.get(index)?),
+        value => pdf.resolve_dict(value),
+    }
+}
+
+fn ascii_hex(data: &[u8]) -> Vec<u8> {
+    let digits = data.iter().take_while(|&&x| x != b'>').filter_map(|&x| (x as char).to_digit(16)).collect::<Vec<_>>();
+    digits.chunks(2).map(|x| (x[0] * 16 + x.get(1).unwrap_or(&0)) as u8).collect()
+}
+
+fn ascii85(data: &[u8]) -> Option<Vec<u8>> {
+    let mut ret = Vec::new();
+    let mut group = Vec::with_capacity(5);
+    for &x in data.strip_prefix(b"<~").unwrap_or(data) {
+        match x {
+            b'~' => break,
+            b'z' if group.is_empty() => ret.extend([0; 4]),
+            b'!' ..= b'u' => group.push((x - b'!') as u64),
+            _ if x.is_ascii_whitespace() => continue,
+            _ => return None,
+        }
+        if group.len() == 5 {
+            let value = group.drain(..).fold(0, |a, x| a * 85 + x);
+            ret.extend((value as u32).to_be_bytes());
+        }
+    }
+    // A final group of n digits, padded with the highest digit, gives n - 1 bytes.
+    if !group.is_empty() {
+        let n = group.len();
+        group.resize(5, 84);
+        let value = group.iter().fold(0, |a, x| a * 85 + x);
+        ret.extend(&(value as u32).to_be_bytes()[.. n - 1]);
+    }
+    Some(ret)
+}
+
+// Runs of 2 to 128 copies of a byte, and of 1 to 128 bytes copied, each after its length.
+fn run_length(data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while let Some(&length) = data.get(i) {
+        match length {
+            128 => break,
+            0 ..= 127 => {
+                let end = (i + 2 + length as usize).min(data.len());
+                ret.extend(&data[i + 1 .. end]);
+                i = end;
+            }
+            _ => {
+                ret.extend(std::iter::repeat_n(data.get(i + 1).copied().unwrap_or(0), 257 - length as usize));
+                i += 2;
+            }
+        }
+    }
+    ret
+}
+
+// Codes of 9 to 12 bits for strings added to a table as they're read, after the 256
+// single bytes, a code clearing the table and a code ending the data. The code length
+// grows one code early unless `early_change` is off.
+fn lzw(data: &[u8], early_change: bool) -> Vec<u8> {
+    let mut ret = Vec::new();
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut previous: Option<Vec<u8>> = None;
+    let (mut buffer, mut bits, mut width) = (0u32, 0, 9);
+    for &byte in data {
+        buffer = buffer << 8 | byte as u32;
+        bits += 8;
+        while bits >= width {
+            bits -= width;
+            let code = (buffer >> bits) as usize & ((1 << width) - 1);
+            buffer &= (1 << bits) - 1;
+            let entry = match code {
+                256 => {
+                    table.clear();
+                    (previous, width) = (None, 9);
+                    continue;
+                }
+                257 => return ret,
+                0 ..= 255 => vec![code as u8],
+                _ => match (table.get(code - 258), &previous) {
+                    (Some(entry), _) => entry.clone(),
+                    // The code being added, a string ending with its own first byte.
+                    (None, Some(previous)) if code - 258 == table.len() => [&previous[..], &previous[.. 1]].concat(),
+                    _ => return ret,
+                },
+            };
+            if let Some(previous) = previous.filter(|_| table.len() + 258 < 4096) {
+                table.push([&previous[..], &entry[.. 1]].concat());
+            }
+            ret.extend(&entry);
+            previous = Some(entry);
+            width = match table.len() + 258 + early_change as usize {
+                .. 512 => 9,
+                512 .. 1024 => 10,
+                1024 .. 2048 => 11,
+                _ => 12,
+            };
+        }
+    }
+    ret
+}
+
+// Rows of samples stored as differences from the sample to the left with the TIFF
+// predictor, or with the PNG predictors named at the start of every row, restored.
+fn predict(pdf: &PDF, parms: Option<&HashMap<String, Value>>, data: Vec<u8>) -> Option<Vec<u8>> {
+    let get = |key| parms.and_then(|x| x.get(key));
+    let predictor = number(pdf, get("Predictor")).unwrap_or(1.) as usize;
+    if predictor < 2 {
+        return Some(data);
+    }
+    let colors = number(pdf, get("Colors")).unwrap_or(1.) as usize;
+    let bits = number(pdf, get("BitsPerComponent")).unwrap_or(8.) as usize;
+    let columns = number(pdf, get("Columns")).unwrap_or(1.) as usize;
+    if !matches!(bits, 1 | 2 | 4 | 8 | 16) || colors == 0 || columns == 0 {
+        return None;
+    }
+    let stride = (colors * bits * columns).div_ceil(8);
+    if predictor == 2 {
+        let mut data = data;
+        for row in data.chunks_mut(stride) {
+            if bits == 16 {
+                for i in colors .. (colors * columns).min(row.len() / 2) {
+                    let value = u16::from_be_bytes([row[i * 2], row[i * 2 + 1]]);
+                    let left = u16::from_be_bytes([row[(i - colors) * 2], row[(i - colors) * 2 + 1]]);
+                    row[i * 2 .. i * 2 + 2].copy_from_slice(&value.wrapping_add(left).to_be_bytes());
+                }
+                continue;
+            }
+            let mask = ((1u16 << bits) - 1) as u8;
+            for i in colors .. (colors * columns).min(row.len() * 8 / bits) {
+                let (left, bit) = ((i - colors) * bits, i * bits);
+                let left = row[left / 8] >> (8 - bits - left % 8) & mask;
+                let shift = 8 - bits - bit % 8;
+                let value = (row[bit / 8] >> shift).wrapping_add(left) & mask;
+                row[bit / 8] = row[bit / 8] & !(mask << shift) | value << shift;
+            }
+        }
+        return Some(data);
+    }
+    // Bytes back to the same sample of the pixel to the left, at least one.
+    let step = (colors * bits).div_ceil(8);
+    let mut ret = Vec::with_capacity(data.len());
+    let mut above = vec![0u8; stride];
+    for chunk in data.chunks(stride + 1) {
+        let (&kind, row) = chunk.split_first()?;
+        let mut row = row.to_vec();
+        row.resize(stride, 0);
+        for i in 0 .. stride {
+            let left = if i >= step { row[i - step] } else { 0 };
+            let corner = if i >= step { above[i - step] } else { 0 };
+            let up = above[i];
+            row[i] = row[i].wrapping_add(match kind {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => {
+                    let estimate = left as i16 + up as i16 - corner as i16;
+                    let (a, b, c) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - corner as i16).abs());
+                    if a <= b && a <= c { left } else if b <= c { up } else { corner }
+                }
+                _ => return None,
+            });
+        }
+        ret.extend(&row);
+        above = row;
+    }
+    Some(ret)
+}
+
+// Data decoded with the filter `name` and its parameters, or None for image formats,
+// unknown filters and data that can't be decoded.
+pub fn decode_filter(pdf: &PDF, name: &str, parms: Option<&HashMap<String, Value>>, data: &[u8]) -> Option<Vec<u8>> {
+    let get = |key| parms.and_then(|x| x.get(key));
+    match name {
+        "FlateDecode" => {
+            let mut buf = Vec::new();
+            // Data decoded up to damage at the end is kept.
+            let ok = flate2::read::ZlibDecoder::new(data).read_to_end(&mut buf).is_ok();
+            predict(pdf, parms, (ok || !buf.is_empty()).then_some(buf)?)
+        }
+        "LZWDecode" => {
+            let early_change = number(pdf, get("EarlyChange")).unwrap_or(1.) != 0.;
+            predict(pdf, parms, lzw(data, early_change))
+        }
+        "ASCIIHexDecode" => Some(ascii_hex(data)),
+        "ASCII85Decode" => ascii85(data),
+        "RunLengthDecode" => Some(run_length(data)),
+        "CCITTFaxDecode" => {
+            let params = ccitt::Params {
+                k: number(pdf, get("K")).unwrap_or(0.) as i64,
+                columns: number(pdf, get("Columns")).unwrap_or(1728.) as usize,
+                rows: number(pdf, get("Rows")).unwrap_or(0.) as usize,
+                encoded_byte_align: flag(pdf, get("EncodedByteAlign")).unwrap_or(false),
+                black_is_1: flag(pdf, get("BlackIs1")).unwrap_or(false),
+                end_of_block: flag(pdf, get("EndOfBlock")).unwrap_or(true),
+            };
+            Some(ccitt::decode(data, &params, params.rows))
+        }
+        _ => None,
+    }
+}
+
+// The data of a stream with its leading filters decoded here applied, and the number of
+// filters applied. Image formats and filters failing to decode stop the pipeline.
+pub fn decode_stream(pdf: &PDF, dict: &HashMap<String, Value>, data: &[u8]) -> (Vec<u8>, usize) {
+    let mut data = data.to_vec();
+    let filters = filters(pdf, dict);
+    for (i, filter) in filters.iter().enumerate() {
+        let mut parms = decode_parms(pdf, dict, i).cloned();
+        // Images give the number of rows of fax data as their height.
+        if filter == "CCITTFaxDecode" && parms.as_ref().is_none_or(|x| !x.contains_key("Rows")) {
+            if let Some(height) = dict.get("Height") {
+                parms.get_or_insert_default().insert("Rows".into(), pdf.resolve(height).clone());
+            }
+        }
+        match decode_filter(pdf, filter, parms.as_ref(), &data) {
+            Some(decoded) => data = decoded,
+            None => return (data, i),
+        }
+    }
+    (data, filters.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::test_pdf;
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_decode_streams() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"jpeg").unwrap();
+        let jpeg = encoder.finish().unwrap();
+        let pdf = test_pdf(&[
+            ("<< /Type /Catalog >>", b""),
+            ("<< /Filter [/ASCIIHexDecode /CCITTFaxDecode] /DecodeParms [null << /K -1 /Columns 8 >>] >>", b"2f5b8c004004>"),
+            ("<< /Filter [/FlateDecode /DCTDecode] >>", &jpeg),
+        ]);
+        // Fax data out of an image, without a row count, is decoded up to the end of block.
+        let fax = pdf.get(&(2, 0)).unwrap();
+        assert_eq!(fax.stream(), [0b1100_0111, 0b1110_0011, 0xff]);
+        assert!(!fax.dict().contains_key("Filter") && !fax.dict().contains_key("DecodeParms"));
+        // Image formats are left to images.
+        let image = pdf.get(&(3, 0)).unwrap();
+        assert_eq!(image.stream(), b"jpeg");
+        assert_eq!(filters(&pdf, image.dict()), ["DCTDecode"]);
+    }
+
+    #[test]
+    fn test_decode_filter() {
+        let pdf = test_pdf(&[("<< /Type /Catalog >>", b"")]);
+        let decode = |name, data: &[u8]| decode_filter(&pdf, name, None, data).unwrap();
+        assert_eq!(decode("ASCII85Decode", b"<~9jqo^BlbD-BleB1DJ+*+F(f,q~>"), b"Man is distinguished");
+        assert_eq!(decode("ASCII85Decode", b"z@:\nB~>"), b"\0\0\0\0ab");
+        assert_eq!(decode("RunLengthDecode", &[2, b'a', b'b', b'c', 254, b'x', 128, b'y']), b"abcxxx");
+        assert_eq!(decode("LZWDecode", &[0x80, 0x0b, 0x60, 0x50, 0x22, 0x0c, 0x0c, 0x85, 0x01]), b"-----A---B");
+    }
+
+    #[test]
+    fn test_predict() {
+        let pdf = test_pdf(&[("<< /Type /Catalog >>", b"")]);
+        let parms = |entries: &[(&str, f64)]| entries.iter().map(|&(k, v)| (k.to_string(), Value::Number(v))).collect::<HashMap<_, _>>();
+        let tiff = parms(&[("Predictor", 2.), ("Columns", 4.)]);
+        assert_eq!(predict(&pdf, Some(&tiff), vec![1, 1, 1, 1]).unwrap(), [1, 2, 3, 4]);
+        let tiff = parms(&[("Predictor", 2.), ("Columns", 4.), ("BitsPerComponent", 4.)]);
+        assert_eq!(predict(&pdf, Some(&tiff), vec![0x11, 0x11]).unwrap(), [0x12, 0x34]);
+        // Sub, Up and Paeth rows.
+        let png = parms(&[("Predictor", 15.), ("Columns", 2.)]);
+        assert_eq!(predict(&pdf, Some(&png), vec![1, 5, 1, 2, 1, 1, 4, 0, 1]).unwrap(), [5, 6, 6, 7, 6, 8]);
+    }
+}
+
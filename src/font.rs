@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use super::parser::{Value, PDF};
+use super::encoding::{get_encoding, glyph_to_unicode, mac_roman_encoding, standard_encoding};
+use super::operation::Matrix;
+use super::path::Segment;
+use super::program::Program;
+use postscript::lexer::{parse as lexer, Token};
+
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub code: u32,
+    pub text: String,
+    pub width: f64,
+    pub is_space: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FontProgram {
+    pub kind: String,
+    pub subtype: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Font {
+    pub base_font: String,
+    pub subtype: String,
+    pub ascent: f64,
+    pub descent: f64,
+    pub bold: bool,
+    pub italic: bool,
+    pub program: Option<FontProgram>,
+    code_length: usize,
+    first_char: u32,
+    widths: Vec<f64>,
+    cid_widths: HashMap<u32, f64>,
+    default_width: f64,
+    encoding: HashMap<u32, String>,
+    differences: HashMap<u32, String>,
+    has_base_encoding: bool,
+    flags: u32,
+    cid_to_gid: Option<Vec<u16>>,
+    to_unicode: HashMap<u32, String>,
+    font_matrix: Matrix,
+    char_procs: HashMap<u32, Vec<u8>>,
+    // The /Resources of a Type 3 font, used by its CharProcs.
+    resources: Option<HashMap<String, Value>>,
+    proc_widths: HashMap<u32, f64>,
+}
+
+impl Font {
+    pub fn load(pdf: &PDF, dict: &HashMap<String, Value>) -> Font {
+        let name = |key: &str| match dict.get(key).map(|x| pdf.resolve(x)) {
+            Some(Value::Key(s)) => s.clone(),
+            _ => String::new(),
+        };
+        let mut font = Font {
+            base_font: name("BaseFont"),
+            subtype: name("Subtype"),
+            code_length: 1,
+            ascent: 0.8,
+            descent: -0.2,
+            ..Font::default()
+        };
+
+        let mut descriptor_source = dict;
+        // Type 3 glyph space maps to text space through the font matrix instead of by 1/1000.
+        let mut width_scale = 0.001;
+        if font.subtype == "Type3" {
+            if let Some(Value::List(m)) = dict.get("FontMatrix").map(|x| pdf.resolve(x)) {
+                let m = m.iter().filter_map(|x| if let &Value::Number(n) = pdf.resolve(x) { Some(n) } else { None }).collect::<Vec<_>>();
+                if let &[a, b, c, d, e, f] = &m[..] {
+                    font.font_matrix = Matrix::new(a, b, c, d, e, f);
+                }
+            }
+            width_scale = font.font_matrix.a;
+            if let Some(Value::List(b)) = dict.get("FontBBox").map(|x| pdf.resolve(x)) {
+                let b = b.iter().filter_map(|x| if let &Value::Number(n) = pdf.resolve(x) { Some(n) } else { None }).collect::<Vec<_>>();
+                if let &[_, y0, _, y1] = &b[..] {
+                    if y1 > y0 {
+                        font.ascent = y1 * font.font_matrix.d;
+                        font.descent = y0 * font.font_matrix.d;
+                    }
+                }
+            }
+        }
+
+        if font.subtype == "Type0" {
+            font.code_length = 2;
+            font.default_width = 1.;
+            let descendant = match dict.get("DescendantFonts").map(|x| pdf.resolve(x)) {
+                Some(Value::List(list)) => list.first().and_then(|x| pdf.resolve_dict(x)),
+                _ => None,
+            };
+            if let Some(descendant) = descendant {
+                if let Some(Value::Number(n)) = descendant.get("DW").map(|x| pdf.resolve(x)) {
+                    font.default_width = n / 1000.;
+                }
+                if let Some(Value::List(w)) = descendant.get("W").map(|x| pdf.resolve(x)) {
+                    font.cid_widths = parse_cid_widths(pdf, w);
+                }
+                if let Some(&Value::Ref(m, n)) = descendant.get("CIDToGIDMap") {
+                    if let Some(obj) = pdf.get(&(m, n)) {
+                        font.cid_to_gid = Some(obj.stream().chunks_exact(2).map(|x| u16::from_be_bytes([x[0], x[1]])).collect());
+                    }
+                }
+                descriptor_source = descendant;
+            }
+        } else {
+            if let Some(Value::Number(n)) = dict.get("FirstChar").map(|x| pdf.resolve(x)) {
+                font.first_char = *n as u32;
+            }
+            if let Some(Value::List(w)) = dict.get("Widths").map(|x| pdf.resolve(x)) {
+                font.widths = w.iter().map(|x| match pdf.resolve(x) {
+                    Value::Number(n) => n * width_scale,
+                    _ => 0.,
+                }).collect();
+            }
+            font.default_width = if font.base_font.contains("Courier") { 0.6 } else { 0.5 };
+            (font.encoding, font.differences, font.has_base_encoding) = simple_encoding(pdf, dict);
+            if font.subtype == "Type3" {
+                font.load_char_procs(pdf, dict);
+            }
+        }
+
+        if let Some(descriptor) = descriptor_source.get("FontDescriptor").and_then(|x| pdf.resolve_dict(x)) {
+            let number = |key: &str| match descriptor.get(key).map(|x| pdf.resolve(x)) {
+                Some(&Value::Number(n)) => Some(n / 1000.),
+                _ => None,
+            };
+            if let Some(n) = number("MissingWidth") {
+                font.default_width = n;
+            }
+            if let Some(n) = number("Ascent").filter(|&x| x != 0.) {
+                font.ascent = n;
+            }
+            if let Some(n) = number("Descent").filter(|&x| x != 0.) {
+                font.descent = n;
+            }
+            font.flags = number("Flags").map_or(0, |x| (x * 1000.).round() as u32);
+            font.bold = number("FontWeight").is_some_and(|x| x * 1000. >= 600.) || font.flags & (1 << 18) != 0;
+            font.italic = number("ItalicAngle").is_some_and(|x| x != 0.) || font.flags & (1 << 6) != 0;
+            for kind in ["FontFile", "FontFile2", "FontFile3"] {
+                if let Some(&Value::Ref(m, n)) = descriptor.get(kind) {
+                    if let Some(obj) = pdf.get(&(m, n)) {
+                        let subtype = match obj.dict().get("Subtype") {
+                            Some(Value::Key(s)) => s.clone(),
+                            _ => String::new(),
+                        };
+                        font.program = Some(FontProgram { kind: kind.into(), subtype, data: obj.stream().to_vec() });
+                    }
+                }
+            }
+        }
+
+        // Without a named base encoding, Type 1 fonts use the encoding built into the program.
+        if font.subtype != "Type0" && font.subtype != "TrueType" && !font.has_base_encoding {
+            if let Some(program) = font.parse_program().filter(|x| x.sfnt.is_none()) {
+                let builtin = (0 ..= 255u8).filter_map(|code| {
+                    let name = program.builtin_encoding(code).and_then(|x| program.glyph_name(x))?;
+                    Some((code as u32, name.to_string()))
+                }).collect::<HashMap<_, _>>();
+                if !builtin.is_empty() {
+                    font.encoding = builtin;
+                    font.encoding.extend(font.differences.clone());
+                }
+            }
+        }
+
+        let style = font.base_font.rsplit('+').next().unwrap_or_default().to_lowercase();
+        font.bold |= ["bold", "black", "heavy", "semibold", "demi"].iter().any(|x| style.contains(x));
+        // Medium faces may be named for a weight of 500 and still be the bold of a family.
+        if !font.bold {
+            font.bold = font.parse_program().and_then(|x| x.weight()).is_some_and(|x| x >= 600);
+        }
+        font.italic |= ["italic", "oblique"].iter().any(|x| style.contains(x));
+
+        if let Some(&Value::Ref(m, n)) = dict.get("ToUnicode") {
+            if let Some(obj) = pdf.get(&(m, n)) {
+                let (to_unicode, code_length) = parse_to_unicode(obj.stream());
+                font.to_unicode = to_unicode;
+                if font.subtype == "Type0" {
+                    if let Some(len) = code_length {
+                        font.code_length = len;
+                    }
+                }
+            }
+        }
+
+        font
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Glyph> {
+        let mut ret = Vec::new();
+        for chunk in bytes.chunks(self.code_length) {
+            let code = chunk.iter().fold(0u32, |n, &x| n * 256 + x as u32);
+            ret.push(Glyph {
+                code,
+                text: self.to_unicode(code),
+                width: self.width(code),
+                is_space: self.code_length == 1 && code == 32,
+            });
+        }
+        ret
+    }
+
+    pub fn width(&self, code: u32) -> f64 {
+        if self.subtype == "Type0" {
+            return *self.cid_widths.get(&code).unwrap_or(&self.default_width);
+        }
+        code.checked_sub(self.first_char)
+            .and_then(|i| self.widths.get(i as usize))
+            .or_else(|| self.proc_widths.get(&code))
+            .cloned()
+            .unwrap_or(self.default_width)
+    }
+
+    pub fn to_unicode(&self, code: u32) -> String {
+        if let Some(s) = self.to_unicode.get(&code) {
+            return s.clone();
+        }
+        if let Some(s) = self.encoding.get(&code).and_then(|x| glyph_to_unicode(x)) {
+            return s;
+        }
+        if self.subtype == "Type0" {
+            return '\u{FFFD}'.into();
+        }
+        char::from_u32(code).map_or_else(String::new, Into::into)
+    }
+
+    fn load_char_procs(&mut self, pdf: &PDF, dict: &HashMap<String, Value>) {
+        self.resources = dict.get("Resources").and_then(|x| pdf.resolve_dict(x)).cloned();
+        let Some(procs) = dict.get("CharProcs").and_then(|x| pdf.resolve_dict(x)) else { return };
+        for (&code, name) in &self.encoding {
+            let Some(&Value::Ref(m, n)) = procs.get(name) else { continue };
+            let Some(obj) = pdf.get(&(m, n)) else { continue };
+            let data = obj.stream().to_vec();
+            // The glyph width is the first operand of the leading d0 or d1.
+            let mut operands = Vec::new();
+            for token in lexer(&data) {
+                match token {
+                    Token::Number(n) => operands.push(n),
+                    Token::Operator(op) => {
+                        if op == "d0" || op == "d1" {
+                            if let Some(&wx) = operands.first() {
+                                self.proc_widths.insert(code, wx * self.font_matrix.a);
+                            }
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            self.char_procs.insert(code, data);
+        }
+    }
+
+    pub fn resources(&self) -> Option<&HashMap<String, Value>> {
+        self.resources.as_ref()
+    }
+
+    // The content stream drawing a Type 3 glyph.
+    pub fn char_proc(&self, code: u32) -> Option<&[u8]> {
+        self.char_procs.get(&code).map(|x| &x[..])
+    }
+
+    pub fn font_matrix(&self) -> Matrix {
+        self.font_matrix
+    }
+
+    pub fn parse_program(&self) -> Option<Program<'_>> {
+        Program::parse(&self.program.as_ref()?.data)
+    }
+
+    fn is_symbolic(&self) -> bool {
+        self.flags & 4 != 0 && self.flags & 32 == 0
+    }
+
+    // Glyph of a character code, following the rules for simple TrueType and
+    // Type 1 fonts and CID to GID mapping for composite fonts.
+    pub fn glyph_id(&self, program: &Program, code: u32) -> Option<u16> {
+        if self.subtype == "Type0" {
+            return match &self.cid_to_gid {
+                Some(map) => map.get(code as usize).cloned(),
+                None => program.gid_for_cid(code),
+            };
+        }
+        let name = self.differences.get(&code).or_else(|| match self.has_base_encoding || !self.is_symbolic() {
+            true => self.encoding.get(&code),
+            false => None,
+        });
+        if program.has_cmaps() && program.cff.is_none() {
+            if !self.is_symbolic() {
+                if let Some(name) = name {
+                    let unicode = glyph_to_unicode(name).and_then(|x| x.chars().next());
+                    let mac = (0 ..= 255).find(|&x| mac_roman_encoding(x) == Some(name));
+                    let gid = unicode.and_then(|x| program.cmap(3, 1, x as u32))
+                        .or_else(|| mac.and_then(|x| program.cmap(1, 0, x as u32)))
+                        .or_else(|| program.gid_by_name(name));
+                    if gid.is_some() {
+                        return gid;
+                    }
+                }
+            }
+            for c in [code, 0xF000 + code, 0xF100 + code, 0xF200 + code] {
+                if let Some(gid) = program.cmap(3, 0, c) {
+                    return Some(gid);
+                }
+            }
+            return program.cmap(1, 0, code)
+                .or_else(|| program.cmap(3, 1, code))
+                .or_else(|| u16::try_from(code).ok().filter(|&x| (x as usize) < program.num_glyphs()));
+        }
+        if let Some(gid) = name.and_then(|x| program.gid_by_name(x)) {
+            return Some(gid);
+        }
+        u8::try_from(code).ok().and_then(|x| program.builtin_encoding(x))
+            .or_else(|| self.encoding.get(&code).and_then(|x| program.gid_by_name(x)))
+            .or_else(|| glyph_to_unicode(name?).and_then(|x| x.chars().next()).and_then(|x| program.cmap(3, 1, x as u32)))
+    }
+
+    pub fn glyph_outline(&self, program: &Program, code: u32) -> Vec<Segment> {
+        self.glyph_id(program, code).map(|x| program.outline(x)).unwrap_or_default()
+    }
+}
+
+pub fn load_fonts(pdf: &PDF, resources: &HashMap<String, Value>) -> HashMap<String, Font> {
+    let mut ret = HashMap::new();
+    if let Some(fonts) = resources.get("Font").and_then(|x| pdf.resolve_dict(x)) {
+        for (k, v) in fonts {
+            if let Some(dict) = pdf.resolve_dict(v) {
+                ret.insert(k.clone(), Font::load(pdf, dict));
+            }
+        }
+    }
+    ret
+}
+
+// Returns the encoding, its differences and whether a base encoding was named.
+fn simple_encoding(pdf: &PDF, dict: &HashMap<String, Value>) -> (HashMap<u32, String>, HashMap<u32, String>, bool) {
+    let mut ret = HashMap::new();
+    let mut base: fn(u8) -> Option<&'static str> = standard_encoding;
+    let mut has_base = false;
+    let mut differences = None;
+    match dict.get("Encoding").map(|x| pdf.resolve(x)) {
+        Some(Value::Key(name)) => if let Some(x) = get_encoding(name) {
+            base = x;
+            has_base = true;
+        },
+        Some(Value::Dict(encoding)) => {
+            if let Some(Value::Key(name)) = encoding.get("BaseEncoding") {
+                if let Some(x) = get_encoding(name) {
+                    base = x;
+                    has_base = true;
+                }
+            }
+            if let Some(Value::List(list)) = encoding.get("Differences").map(|x| pdf.resolve(x)) {
+                differences = Some(list);
+            }
+        }
+        _ => {}
+    }
+    for code in 0 ..= 255u8 {
+        if let Some(name) = base(code) {
+            ret.insert(code as u32, name.to_string());
+        }
+    }
+    let mut changed = HashMap::new();
+    if let Some(list) = differences {
+        let mut code = 0;
+        for x in list {
+            match pdf.resolve(x) {
+                &Value::Number(n) => code = n as u32,
+                Value::Key(name) => {
+                    ret.insert(code, name.clone());
+                    changed.insert(code, name.clone());
+                    code += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    (ret, changed, has_base)
+}
+
+fn parse_cid_widths(pdf: &PDF, list: &[Value]) -> HashMap<u32, f64> {
+    let mut ret = HashMap::new();
+    let list = list.iter().map(|x| pdf.resolve(x)).collect::<Vec<_>>();
+    let mut i = 0;
+    while i < list.len() {
+        match (list[i], list.get(i + 1), list.get(i + 2)) {
+            (&Value::Number(first), Some(Value::List(widths)), _) => {
+                for (j, w) in widths.iter().enumerate() {
+                    if let Value::Number(w) = pdf.resolve(w) {
+                        ret.insert(first as u32 + j as u32, w / 1000.);
+                    }
+                }
+                i += 2;
+            }
+            (&Value::Number(first), Some(&&Value::Number(last)), Some(&&Value::Number(w))) => {
+                for cid in first as u32 ..= last as u32 {
+                    ret.insert(cid, w / 1000.);
+                }
+                i += 3;
+            }
+            _ => break,
+        }
+    }
+    ret
+}
+
+fn bytes_to_code(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |n, &x| n.wrapping_mul(256) + x as u32)
+}
+
+fn utf16_to_string(bytes: &[u8]) -> String {
+    let units = bytes.chunks(2).map(|x| bytes_to_code(x) as u16).collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+pub fn parse_to_unicode(stream: &[u8]) -> (HashMap<u32, String>, Option<usize>) {
+    let mut ret = HashMap::new();
+    let mut code_length = None;
+    let mut operands = Vec::new();
+    for token in lexer(stream) {
+        let op = if let Token::Operator(op) = token {
+            op
+        } else {
+            operands.push(token);
+            continue;
+        };
+        match &*op {
+            "endcodespacerange" => {
+                if let Some(Token::BytesLiteral(x)) = operands.first() {
+                    code_length = Some(x.len());
+                }
+            }
+            "endbfchar" => {
+                for pair in operands.chunks(2) {
+                    if let [Token::BytesLiteral(src), Token::BytesLiteral(dst)] = pair {
+                        ret.insert(bytes_to_code(src), utf16_to_string(dst));
+                    }
+                }
+            }
+            "endbfrange" => {
+                let mut iter = operands.iter();
+                while let (Some(Token::BytesLiteral(lo)), Some(Token::BytesLiteral(hi))) = (iter.next(), iter.next()) {
+                    let (lo, hi) = (bytes_to_code(lo), bytes_to_code(hi));
+                    match iter.next() {
+                        Some(Token::BytesLiteral(dst)) if !dst.is_empty() => {
+                            let mut units = dst.chunks(2).map(|x| bytes_to_code(x) as u16).collect::<Vec<_>>();
+                            for code in lo ..= hi.min(lo + 0xffff) {
+                                ret.insert(code, String::from_utf16_lossy(&units));
+                                if let Some(last) = units.last_mut() {
+                                    *last = last.wrapping_add(1);
+                                }
+                            }
+                        }
+                        Some(Token::ListStart) => {
+                            let mut code = lo;
+                            for x in iter.by_ref() {
+                                match x {
+                                    Token::BytesLiteral(dst) => {
+                                        ret.insert(code, utf16_to_string(dst));
+                                        code += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                        }
+                        _ => break,
+                    }
+                }
+            }
+            _ => {}
+        }
+        operands.clear();
+    }
+    (ret, code_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_unicode() {
+        let (map, len) = parse_to_unicode(b"1 begincodespacerange
+<0000> <ffff>
+endcodespacerange
+2 beginbfchar
+<0596> <4F60>
+<0FB3> <597D>
+endbfchar
+2 beginbfrange
+<0020> <0022> <0041>
+<0030> <0031> [<0078> <00660069>]
+endbfrange");
+        assert_eq!(len, Some(2));
+        assert_eq!(map[&0x0596], "\u{4F60}");
+        assert_eq!(map[&0x0FB3], "\u{597D}");
+        assert_eq!(map[&0x0021], "B");
+        assert_eq!(map[&0x0030], "x");
+        assert_eq!(map[&0x0031], "fi");
+    }
+
+    #[test]
+    fn test_bold() {
+        let pdf = crate::parser::test_pdf(&[
+            ("<< /Type /Font /Subtype /Type1 /BaseFont /ABCDEF+Roboto-Medium >>", b""),
+            ("<< /Type /Font /Subtype /Type1 /BaseFont /ABCDEF+Roboto-SemiBold >>", b""),
+        ]);
+        assert!(!Font::load(&pdf, pdf.get(&(1, 0)).unwrap().dict()).bold);
+        assert!(Font::load(&pdf, pdf.get(&(2, 0)).unwrap().dict()).bold);
+    }
+
+    #[test]
+    fn test_type3() {
+        let pdf = crate::parser::test_page_pdf("<< /Font << /F1 5 0 R >> >>", b"", &[
+            ("<< /Type /Font /Subtype /Type3 /FontBBox [0 0 750 750] /FontMatrix [0.002 0 0 0.001 0 0] /CharProcs << /Aring 6 0 R /dagger 7 0 R >> /Encoding << /Differences [97 /Aring /dagger] >> /FirstChar 97 /LastChar 97 /Widths [400] >>", b""),
+            ("<< >>", b"400 0 0 0 750 750 d1 0 0 750 750 re f"),
+            ("<< >>", b"300 0 d0 0 0 m 375 750 l 750 0 l f"),
+        ]);
+        let font = Font::load(&pdf, pdf.get(&(5, 0)).unwrap().dict());
+        // /Widths first, then the width set by d0 or d1, both through the font matrix.
+        assert_eq!(font.width(97), 0.8);
+        assert_eq!(font.width(98), 0.6);
+        assert_eq!(font.to_unicode(97), "\u{C5}");
+        assert_eq!(font.to_unicode(98), "\u{2020}");
+        assert!(font.char_proc(98).unwrap().ends_with(b"l f"));
+    }
+}
@@ -0,0 +1,219 @@
+use super::parser::{Value, PDF};
+use super::util::{number, numbers};
+use postscript::calculator::Program;
+
+// A PDF function mapping m inputs to n outputs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Function {
+    // Type 0: samples on a grid, interpolated linearly.
+    Sampled { domain: Vec<f64>, range: Vec<f64>, size: Vec<usize>, bits: usize, encode: Vec<f64>, decode: Vec<f64>, samples: Vec<u8> },
+    // Type 2: C0 + x^N (C1 - C0), for a single input.
+    Exponential { domain: Vec<f64>, c0: Vec<f64>, c1: Vec<f64>, n: f64 },
+    // Type 3: single input functions over the subdomains split at `bounds`.
+    Stitching { domain: Vec<f64>, functions: Vec<Function>, bounds: Vec<f64>, encode: Vec<f64> },
+    // Type 4: a PostScript calculator program.
+    PostScript { domain: Vec<f64>, range: Vec<f64>, program: Program },
+    // An array of single output functions, taken together, as shadings allow.
+    Array(Vec<Function>),
+}
+
+impl Function {
+    pub fn load(pdf: &PDF, value: &Value) -> Option<Function> {
+        Function::load_nested(pdf, value, 0)
+    }
+
+    fn load_nested(pdf: &PDF, value: &Value, depth: usize) -> Option<Function> {
+        if depth > 8 {
+            return None;
+        }
+        if let Value::List(list) = pdf.resolve(value) {
+            let functions = list.iter().map(|x| Function::load_nested(pdf, x, depth + 1)).collect::<Option<Vec<_>>>()?;
+            return Some(Function::Array(functions));
+        }
+        // Types 0 and 4 are streams, which are only reached through references.
+        let stream = match value {
+            &Value::Ref(m, n) => pdf.get(&(m, n)).map_or(&[][..], |x| x.stream()),
+            _ => &[],
+        };
+        let dict = pdf.resolve_dict(value)?;
+        let domain = numbers(pdf, dict.get("Domain")).filter(|x| x.len() >= 2)?;
+        let range = numbers(pdf, dict.get("Range"));
+        match number(pdf, dict.get("FunctionType"))? as u8 {
+            0 => {
+                let range = range?;
+                let size = numbers(pdf, dict.get("Size"))?.into_iter().map(|x| x as usize).collect::<Vec<_>>();
+                let bits = number(pdf, dict.get("BitsPerSample"))? as usize;
+                if size.len() * 2 != domain.len() || size.len() > 8 || size.contains(&0) || !matches!(bits, 1 | 2 | 4 | 8 | 12 | 16 | 24 | 32) {
+                    return None;
+                }
+                let encode = numbers(pdf, dict.get("Encode")).unwrap_or(size.iter().flat_map(|&x| [0., (x - 1) as f64]).collect());
+                let decode = numbers(pdf, dict.get("Decode")).unwrap_or(range.clone());
+                let bits_needed = size.iter().try_fold(range.len() / 2 * bits, |a, &x| a.checked_mul(x))?;
+                if encode.len() < domain.len() || decode.len() < range.len() || stream.len() * 8 < bits_needed {
+                    return None;
+                }
+                Some(Function::Sampled { domain, range, size, bits, encode, decode, samples: stream.to_vec() })
+            }
+            2 => Some(Function::Exponential {
+                domain,
+                c0: numbers(pdf, dict.get("C0")).unwrap_or(vec![0.]),
+                c1: numbers(pdf, dict.get("C1")).unwrap_or(vec![1.]),
+                n: number(pdf, dict.get("N"))?,
+            }),
+            3 => {
+                let functions = match pdf.resolve(dict.get("Functions")?) {
+                    Value::List(list) => list.iter().map(|x| Function::load_nested(pdf, x, depth + 1)).collect::<Option<Vec<_>>>()?,
+                    _ => return None,
+                };
+                let bounds = numbers(pdf, dict.get("Bounds"))?;
+                let encode = numbers(pdf, dict.get("Encode"))?;
+                if functions.is_empty() || bounds.len() + 1 != functions.len() || encode.len() != functions.len() * 2 {
+                    return None;
+                }
+                Some(Function::Stitching { domain, functions, bounds, encode })
+            }
+            4 => Some(Function::PostScript { domain, range: range?, program: Program::parse(stream)? }),
+            _ => None,
+        }
+    }
+
+    pub fn eval(&self, input: &[f64]) -> Vec<f64> {
+        let arg = |domain: &[f64], i: usize| clip(input.get(i).copied().unwrap_or(0.), domain, i);
+        match self {
+            Function::Sampled { domain, range, size, bits, encode, decode, samples } => {
+                let n = range.len() / 2;
+                // For each input, the two grid positions around it and the weight of the second.
+                let positions = size.iter().enumerate().map(|(i, &size)| {
+                    let e = interpolate(arg(domain, i), domain[i * 2], domain[i * 2 + 1], encode[i * 2], encode[i * 2 + 1]);
+                    let e = e.clamp(0., (size - 1) as f64);
+                    let low = e.floor() as usize;
+                    (low, (low + 1).min(size - 1), e - low as f64)
+                }).collect::<Vec<_>>();
+                let max = ((1u64 << bits) - 1) as f64;
+                let mut ret = vec![0.; n];
+                // Multilinear interpolation between the corners of the cell.
+                for corner in 0 .. 1usize << positions.len() {
+                    let mut index = 0;
+                    let mut stride = 1;
+                    let mut weight = 1.;
+                    for (i, &(low, high, t)) in positions.iter().enumerate() {
+                        let high_side = corner >> i & 1 == 1;
+                        index += if high_side { high } else { low } * stride;
+                        weight *= if high_side { t } else { 1. - t };
+                        stride *= size[i];
+                    }
+                    if weight == 0. {
+                        continue;
+                    }
+                    for (j, value) in ret.iter_mut().enumerate() {
+                        *value += weight * sample(samples, (index * n + j) * bits, *bits) as f64;
+                    }
+                }
+                ret.iter().enumerate().map(|(j, &x)| {
+                    clip(interpolate(x, 0., max, decode[j * 2], decode[j * 2 + 1]), range, j)
+                }).collect()
+            }
+            Function::Exponential { domain, c0, c1, n } => {
+                let t = arg(domain, 0).powf(*n);
+                c0.iter().zip(c1).map(|(a, b)| a + t * (b - a)).collect()
+            }
+            Function::Stitching { domain, functions, bounds, encode } => {
+                let x = arg(domain, 0);
+                let i = bounds.iter().position(|&b| x < b).unwrap_or(bounds.len());
+                let low = if i == 0 { domain[0] } else { bounds[i - 1] };
+                let high = bounds.get(i).copied().unwrap_or(domain[1]);
+                functions[i].eval(&[interpolate(x, low, high, encode[i * 2], encode[i * 2 + 1])])
+            }
+            Function::PostScript { domain, range, program } => {
+                let args = (0 .. domain.len() / 2).map(|i| arg(domain, i)).collect::<Vec<_>>();
+                let n = range.len() / 2;
+                let out = program.run(&args).unwrap_or_default();
+                // Outputs are the top n entries of the stack.
+                let out = &out[out.len().saturating_sub(n)..];
+                (0 .. n).map(|j| clip(out.get(j).copied().unwrap_or(range[j * 2]), range, j)).collect()
+            }
+            Function::Array(functions) => functions.iter().flat_map(|x| x.eval(input)).collect(),
+        }
+    }
+}
+
+// Maps x from [x0, x1] to [y0, y1].
+fn interpolate(x: f64, x0: f64, x1: f64, y0: f64, y1: f64) -> f64 {
+    if x1 == x0 { y0 } else { y0 + (x - x0) * (y1 - y0) / (x1 - x0) }
+}
+
+// Reads the sample of `bits` bits at a bit offset, most significant bit first.
+fn sample(data: &[u8], offset: usize, bits: usize) -> u32 {
+    let mut ret = 0u64;
+    for i in offset / 8 .. (offset + bits).div_ceil(8) {
+        ret = ret << 8 | data.get(i).copied().unwrap_or(0) as u64;
+    }
+    let extra = (offset + bits).div_ceil(8) * 8 - offset - bits;
+    ((ret >> extra) & ((1u64 << bits) - 1)) as u32
+}
+
+// Clips the i-th value to its interval in a list of pairs of bounds.
+fn clip(x: f64, bounds: &[f64], i: usize) -> f64 {
+    match bounds.get(i * 2 .. i * 2 + 2) {
+        Some(&[min, max]) => x.max(min).min(max),
+        _ => x,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(sample(&[0b1011_0000], 2, 2), 0b11);
+        assert_eq!(sample(&[0x12, 0x34, 0x56], 12, 12), 0x456);
+        assert_eq!(sample(&[0x12, 0x34, 0x56, 0x78], 0, 32), 0x12345678);
+    }
+
+    #[test]
+    fn test_sampled() {
+        // Two inputs on a 2x2 grid, the first varying fastest.
+        let f = Function::Sampled {
+            domain: vec![0., 1., 0., 1.],
+            range: vec![0., 1.],
+            size: vec![2, 2],
+            bits: 8,
+            encode: vec![0., 1., 0., 1.],
+            decode: vec![0., 1.],
+            samples: vec![0, 255, 255, 255],
+        };
+        assert_eq!(f.eval(&[0., 0.]), vec![0.]);
+        assert_eq!(f.eval(&[0.5, 0.]), vec![0.5]);
+        assert_eq!(f.eval(&[0.5, 0.5]), vec![0.75]);
+        assert_eq!(f.eval(&[2., -1.]), vec![1.]);
+    }
+
+    #[test]
+    fn test_stitching() {
+        let ramp = |c0: f64, c1: f64| Function::Exponential { domain: vec![0., 1.], c0: vec![c0], c1: vec![c1], n: 1. };
+        let f = Function::Stitching {
+            domain: vec![0., 1.],
+            functions: vec![ramp(0., 1.), ramp(1., 0.)],
+            bounds: vec![0.5],
+            encode: vec![0., 1., 0., 1.],
+        };
+        assert_eq!(f.eval(&[0.25]), vec![0.5]);
+        assert_eq!(f.eval(&[0.5]), vec![1.]);
+        assert_eq!(f.eval(&[0.75]), vec![0.5]);
+        assert_eq!(f.eval(&[1.5]), vec![0.]);
+        let both = Function::Array(vec![ramp(0., 1.), ramp(1., 0.)]);
+        assert_eq!(both.eval(&[0.25]), vec![0.25, 0.75]);
+    }
+
+    #[test]
+    fn test_postscript() {
+        let f = Function::PostScript {
+            domain: vec![0., 1.],
+            range: vec![0., 1., 0., 1.],
+            program: Program::parse(b"{ dup 2 mul }").unwrap(),
+        };
+        assert_eq!(f.eval(&[0.25]), vec![0.25, 0.5]);
+        assert_eq!(f.eval(&[0.75]), vec![0.75, 1.]);
+    }
+}
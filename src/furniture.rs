@@ -0,0 +1,161 @@
+use super::layout::{Block, get_page_blocks};
+use super::parser::PDF;
+
+const MARGIN: f64 = 0.12;
+const TOLERANCE: f64 = 4.;
+const MIN_SHARE: f64 = 0.3;
+
+// Roman numerals below 400, which is as far as front matter page labels go, largest
+// first, with the number of times each may repeat.
+const NUMERALS: [(&str, usize); 9] = [("c", 3), ("xc", 1), ("l", 1), ("xl", 1), ("x", 3), ("ix", 1), ("v", 1), ("iv", 1), ("i", 3)];
+
+// Whether `s` is a well-formed lowercase roman numeral, read greedily from the largest
+// numeral down with the repeats each allows, so that words such as "civil" or
+// "did" don't pass.
+fn is_roman(s: &str) -> bool {
+    let mut rest = s;
+    for (numeral, repeats) in NUMERALS {
+        for _ in 0 .. repeats {
+            match rest.strip_prefix(numeral) {
+                Some(x) => rest = x,
+                None => break,
+            }
+        }
+    }
+    !s.is_empty() && rest.is_empty()
+}
+
+pub fn normalize(text: &str) -> String {
+    let text = text.trim().to_lowercase();
+    if is_roman(&text) {
+        return "#".into();
+    }
+    let mut ret = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            if !ret.ends_with('#') {
+                ret.push('#');
+            }
+        } else if c.is_whitespace() {
+            if !ret.ends_with(' ') {
+                ret.push(' ');
+            }
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+struct Candidate {
+    page: usize,
+    block: usize,
+    line: usize,
+    text: String,
+    x: f64,
+    y: f64,
+    rotated: bool,
+}
+
+pub fn mark_furniture(pages: &mut [Vec<Block>], media_boxes: &[Option<(f64, f64, f64, f64)>]) {
+    if pages.len() < 2 {
+        return;
+    }
+    let mut candidates = Vec::new();
+    for (p, blocks) in pages.iter().enumerate() {
+        let (y_min, y_max) = match media_boxes.get(p) {
+            Some(&Some((_, y0, _, y1))) => (y0, y1),
+            _ => blocks.iter().map(Block::bbox).fold((f64::MAX, f64::MIN), |(a, b), x| (a.min(x.1), b.max(x.3))),
+        };
+        let margin = (y_max - y_min) * MARGIN;
+        for (b, block) in blocks.iter().enumerate() {
+            for (l, line) in block.lines.iter().enumerate() {
+                let rotated = line.spans[0].angle.abs() > 0.1;
+                if !rotated && line.y0 > y_min + margin && line.y1 < y_max - margin {
+                    continue;
+                }
+                candidates.push(Candidate {
+                    page: p,
+                    block: b,
+                    line: l,
+                    text: normalize(&line.text()),
+                    x: line.x0,
+                    y: line.baseline,
+                    rotated,
+                });
+            }
+        }
+    }
+
+    let threshold = ((pages.len() as f64 * MIN_SHARE).ceil() as usize).max(2);
+    for c in &candidates {
+        let mut matches = candidates.iter()
+            .filter(|d| d.text == c.text && d.rotated == c.rotated)
+            .filter(|d| if c.rotated { (d.x - c.x).abs() <= TOLERANCE } else { (d.y - c.y).abs() <= TOLERANCE })
+            .map(|d| d.page)
+            .collect::<Vec<_>>();
+        matches.dedup();
+        if matches.len() >= threshold {
+            pages[c.page][c.block].lines[c.line].furniture = true;
+        }
+    }
+}
+
+pub fn strip_furniture(blocks: Vec<Block>) -> Vec<Block> {
+    blocks.into_iter()
+        .map(|mut b| {
+            b.lines.retain(|l| !l.furniture);
+            b
+        })
+        .filter(|b| !b.lines.is_empty())
+        .collect()
+}
+
+pub fn get_document_blocks(pdf: &PDF, keep_furniture: bool) -> Vec<Vec<Block>> {
+    let pages = pdf.get_page_list();
+    let media_boxes = pages.iter().map(|x| pdf.get_media_box(x)).collect::<Vec<_>>();
+    let mut ret = pages.iter().map(|x| get_page_blocks(pdf, x)).collect::<Vec<_>>();
+    mark_furniture(&mut ret, &media_boxes);
+    if !keep_furniture {
+        ret = ret.into_iter().map(strip_furniture).collect();
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::layout::get_lines;
+    use super::super::operation::TextPaintingOperation;
+
+    fn span(x: f64, y: f64, text: &str) -> TextPaintingOperation {
+        TextPaintingOperation::test_span(x, y, text)
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(" Page 12 of 30 "), "page # of #");
+        assert_eq!(normalize("xiv"), "#");
+        assert_eq!(normalize("cxcix"), "#");
+        for word in ["did", "mix", "civil", "dim", "mild", "iiii", "vx"] {
+            assert_eq!(normalize(word), word);
+        }
+    }
+
+    #[test]
+    fn test_furniture() {
+        let mut pages = (1 .. 5).map(|i| vec![Block {
+            lines: get_lines(vec![
+                span(100., 770., "Journal of Examples, Vol. 3"),
+                span(100., 400., &format!("Body text {i}")),
+                span(300., 30., &i.to_string()),
+            ]),
+        }]).collect::<Vec<_>>();
+        let boxes = vec![Some((0., 0., 612., 792.)); 4];
+        mark_furniture(&mut pages, &boxes);
+        let kept = strip_furniture(pages.remove(0));
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].lines.len(), 1);
+        assert_eq!(kept[0].lines[0].text(), "Body text 1");
+    }
+}
@@ -0,0 +1,101 @@
+use super::layout::Block;
+
+pub fn escape(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            c if c.is_control() => {}
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+pub fn page_box(blocks: &[Block], media_box: Option<(f64, f64, f64, f64)>) -> (f64, f64, f64, f64) {
+    media_box.unwrap_or_else(|| {
+        blocks.iter().map(Block::bbox).fold((0., 0., 0., 0.), |b, x| (b.0, b.1, b.2.max(x.2), b.3.max(x.3)))
+    })
+}
+
+// Page coordinates with the origin at the top left corner of the page, y pointing down.
+pub fn flip(bbox: (f64, f64, f64, f64), page: (f64, f64, f64, f64)) -> (f64, f64, f64, f64) {
+    (bbox.0 - page.0, page.3 - bbox.3, bbox.2 - page.0, page.3 - bbox.1)
+}
+
+fn title(bbox: (f64, f64, f64, f64)) -> String {
+    format!("bbox {} {} {} {}", bbox.0.round(), bbox.1.round(), bbox.2.round(), bbox.3.round())
+}
+
+pub fn to_hocr(pages: &[Vec<Block>], media_boxes: &[Option<(f64, f64, f64, f64)>]) -> String {
+    let mut ret = String::new();
+    ret.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    ret.push_str("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n");
+    ret.push_str("<html xmlns=\"http://www.w3.org/1999/xhtml\" xml:lang=\"en\" lang=\"en\">\n<head>\n<title></title>\n");
+    ret.push_str("<meta http-equiv=\"Content-Type\" content=\"text/html;charset=utf-8\"/>\n");
+    ret.push_str(&format!("<meta name=\"ocr-system\" content=\"{} {}\"/>\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+    ret.push_str("<meta name=\"ocr-capabilities\" content=\"ocr_page ocr_carea ocr_par ocr_line ocrx_word\"/>\n");
+    ret.push_str("</head>\n<body>\n");
+
+    for (p, blocks) in pages.iter().enumerate() {
+        let page = page_box(blocks, media_boxes.get(p).cloned().flatten());
+        let (n, mut line_id, mut word_id) = (p + 1, 0, 0);
+        ret.push_str(&format!(
+            "<div class=\"ocr_page\" id=\"page_{n}\" title=\"{}; ppageno {p}\">\n",
+            title(flip(page, page)),
+        ));
+        for (b, block) in blocks.iter().enumerate() {
+            let bbox = title(flip(block.bbox(), page));
+            ret.push_str(&format!("<div class=\"ocr_carea\" id=\"block_{n}_{}\" title=\"{bbox}\">\n", b + 1));
+            ret.push_str(&format!("<p class=\"ocr_par\" id=\"par_{n}_{}\" title=\"{bbox}\">\n", b + 1));
+            for line in &block.lines {
+                line_id += 1;
+                let bbox = flip((line.x0, line.y0, line.x1, line.y1), page);
+                ret.push_str(&format!(
+                    "<span class=\"ocr_line\" id=\"line_{n}_{line_id}\" title=\"{}; baseline 0 {}; x_size {:.2}\">",
+                    title(bbox),
+                    (page.3 - line.baseline - bbox.3).round(),
+                    line.font_size,
+                ));
+                let words = line.words();
+                for (i, word) in words.iter().enumerate() {
+                    word_id += 1;
+                    if i > 0 {
+                        ret.push(' ');
+                    }
+                    ret.push_str(&format!(
+                        "<span class=\"ocrx_word\" id=\"word_{n}_{word_id}\" title=\"{}\">{}</span>",
+                        title(flip(word.bbox, page)),
+                        escape(&word.text),
+                    ));
+                }
+                ret.push_str("</span>\n");
+            }
+            ret.push_str("</p>\n</div>\n");
+        }
+        ret.push_str("</div>\n");
+    }
+    ret.push_str("</body>\n</html>\n");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::layout::get_lines;
+    use super::super::operation::TextPaintingOperation;
+
+    #[test]
+    fn test_hocr() {
+        let span = TextPaintingOperation { x: 100., y: 700., text: "a <b>".into(), font_size: 10., width: 25., ..Default::default() };
+        let pages = vec![vec![Block { lines: get_lines(vec![span]) }]];
+        let hocr = to_hocr(&pages, &[Some((0., 0., 612., 792.))]);
+        assert!(hocr.contains("<div class=\"ocr_page\" id=\"page_1\" title=\"bbox 0 0 612 792; ppageno 0\">"));
+        assert!(hocr.contains("<span class=\"ocrx_word\" id=\"word_1_1\" title=\"bbox 100 84 105 94\">a</span>"));
+        assert!(hocr.contains("title=\"bbox 110 84 125 94\">&lt;b&gt;</span>"));
+    }
+}
@@ -0,0 +1,154 @@
+use super::content::{get_page_content, load_content_fonts};
+use super::font::Font;
+use super::hocr::escape;
+use super::otf::wrap_cff;
+use super::svg::graphics_to_svg;
+use super::parser::{Object, PDF};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+pub fn css_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn web_font(font: &Font) -> Option<(&'static str, &'static str, Cow<'_, [u8]>)> {
+    let program = font.program.as_ref()?;
+    match (&*program.kind, &*program.subtype) {
+        ("FontFile2", _) => Some(("font/ttf", "truetype", Cow::from(&program.data[..]))),
+        ("FontFile3", "OpenType") => Some(("font/otf", "opentype", Cow::from(&program.data[..]))),
+        ("FontFile3", _) => Some(("font/otf", "opentype", Cow::from(wrap_cff(&program.data)?))),
+        _ => None,
+    }
+}
+
+fn fallback_family(font: &Font) -> &'static str {
+    let name = font.base_font.to_lowercase();
+    if ["courier", "mono", "cmtt"].iter().any(|x| name.contains(x)) {
+        "monospace"
+    } else if ["helvetica", "arial", "sans", "cmss"].iter().any(|x| name.contains(x)) {
+        "sans-serif"
+    } else {
+        "serif"
+    }
+}
+
+// @font-face rules for the embedded fonts, with families named after `prefix`, and
+// the CSS of every font by its name in `fonts`.
+pub fn font_styles(fonts: &HashMap<String, Font>, prefix: &str) -> (String, HashMap<String, String>) {
+    let mut style = String::new();
+    let mut font_styles = HashMap::new();
+    let mut names = fonts.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        let font = &fonts[name];
+        let mut family = fallback_family(font).to_string();
+        if let Some((mime, format, data)) = web_font(font) {
+            let id = format!("{prefix}-{}", name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+            style.push_str(&format!(
+                "@font-face {{ font-family: \"{id}\"; src: url(data:{mime};base64,{}) format(\"{format}\"); }}\n",
+                STANDARD.encode(data),
+            ));
+            family = format!("'{id}', {family}");
+        }
+        let mut css = format!("font-family: {family}");
+        if font.bold {
+            css.push_str("; font-weight: bold");
+        }
+        if font.italic {
+            css.push_str("; font-style: italic");
+        }
+        font_styles.insert(name.clone(), css);
+    }
+    (style, font_styles)
+}
+
+pub struct HtmlPage {
+    pub style: String,
+    pub body: String,
+}
+
+pub fn page_to_html(pdf: &PDF, index: usize, page: &Object) -> HtmlPage {
+    let fonts = load_content_fonts(pdf, pdf.get_page_resources(page));
+    let content = get_page_content(pdf, page);
+    let media_box = pdf.get_media_box(page).unwrap_or((0., 0., 612., 792.));
+    let (width, height) = (media_box.2 - media_box.0, media_box.3 - media_box.1);
+    let n = index + 1;
+
+    let (style, font_styles) = font_styles(&fonts, &format!("p{n}"));
+
+    let mut body = format!(
+        "<div class=\"page\" id=\"page-{n}\" style=\"width: {width:.2}pt; height: {height:.2}pt",
+    );
+    // Type 3 glyphs are drawn as vectors, leaving their text transparent but selectable.
+    let drawn = content.glyphs.iter().map(|x| &*x.font).collect::<HashSet<_>>();
+    if let Some(svg) = graphics_to_svg(&content, media_box) {
+        body.push_str(&format!("; background-image: url(data:image/svg+xml;base64,{})", STANDARD.encode(svg)));
+    }
+    body.push_str("\">\n");
+    for span in &content.texts {
+        if span.text.trim().is_empty() {
+            continue;
+        }
+        let ascent = fonts.get(&span.font_key).map_or(0.8, |x| x.ascent);
+        let font_style = font_styles.get(&span.font_key).map_or("font-family: serif", |x| x);
+        let color = if span.render_mode == 3 || span.render_mode == 7 || drawn.contains(&*span.font_key) {
+            "transparent".into()
+        } else {
+            css_color(span.rgb)
+        };
+        body.push_str(&format!(
+            "<span style=\"left: {:.2}pt; top: {:.2}pt; font-size: {:.2}pt; {font_style}; color: {color}; transform: rotate({:.4}rad) translateY(-{ascent:.3}em)\">{}</span>\n",
+            span.x - media_box.0,
+            media_box.3 - span.y,
+            span.font_size,
+            -span.angle,
+            escape(&span.text),
+        ));
+    }
+    body.push_str("</div>\n");
+    HtmlPage { style, body }
+}
+
+const STYLE: &str = "\
+.page { position: relative; overflow: hidden; margin: 1em auto; background-color: white; background-size: 100% 100%; box-shadow: 0 0 4px #888; }
+.page span { position: absolute; white-space: pre; line-height: 1; transform-origin: 0 0; }
+";
+
+pub fn to_html(pages: &[HtmlPage]) -> String {
+    let mut ret = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n");
+    ret.push_str(STYLE);
+    for page in pages {
+        ret.push_str(&page.style);
+    }
+    ret.push_str("</style>\n</head>\n<body>\n");
+    for page in pages {
+        ret.push_str(&page.body);
+    }
+    ret.push_str("</body>\n</html>\n");
+    ret
+}
+
+pub fn get_page_html(pdf: &PDF, index: usize) -> Option<String> {
+    let page = pdf.get_page(index)?;
+    Some(to_html(&[page_to_html(pdf, index, page)]))
+}
+
+pub fn get_document_html(pdf: &PDF) -> String {
+    let pages = pdf.get_page_list().into_iter().enumerate().map(|(i, x)| page_to_html(pdf, i, x)).collect::<Vec<_>>();
+    to_html(&pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorSpace;
+
+    #[test]
+    fn test_css_color() {
+        assert_eq!(css_color(ColorSpace::DeviceGray.rgb(&[0.5])), "#808080");
+        assert_eq!(css_color(ColorSpace::DeviceRGB.rgb(&[1., 0., 0.])), "#ff0000");
+        assert_eq!(css_color(ColorSpace::DeviceCMYK.rgb(&[0., 0., 0., 1.])), "#000000");
+    }
+}
@@ -0,0 +1,401 @@
+use super::color::{Color, ColorSpace};
+use super::content::{get_page_content, PlacedImage};
+#[cfg(feature = "jbig2")]
+use super::filter::decode_parms;
+use super::filter::{decode_stream, filters};
+#[cfg(feature = "jbig2")]
+use super::jbig2;
+#[cfg(feature = "jpx")]
+use super::jpx;
+use super::parser::{Object, Value, PDF};
+use super::util::{number, numbers};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use postscript::parser::types::Operand;
+use std::collections::HashMap;
+use std::io::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageData {
+    // DCT encoded data, passed through as is.
+    Jpeg(Vec<u8>),
+    Gray(Vec<u8>),
+    // Rows of 1 bit samples, 1 for white, starting on byte boundaries.
+    Bilevel(Vec<u8>),
+    Rgb(Vec<u8>),
+    Rgba(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: ImageData,
+}
+
+// Values of the samples of a row, with `bits` bits each.
+fn samples(row: &[u8], bits: usize, n: usize) -> impl Iterator<Item = u32> + '_ {
+    (0 .. n).map(move |i| match bits {
+        8 => row.get(i).map_or(0, |&x| x as u32),
+        16 => row.get(i * 2 .. i * 2 + 2).map_or(0, |x| u16::from_be_bytes([x[0], x[1]]) as u32),
+        _ => {
+            let bit = i * bits;
+            let byte = row.get(bit / 8).map_or(0, |&x| x as u32);
+            (byte >> (8 - bits - bit % 8)) & ((1 << bits) - 1)
+        }
+    })
+}
+
+impl Image {
+    // Decodes samples with the filters of `dict` left to apply; `fill` is the paint of stencil masks.
+    fn decode(pdf: &PDF, dict: &HashMap<String, Value>, data: &[u8], resources: &HashMap<String, Value>, fill: &Color) -> Option<Image> {
+        let width = number(pdf, dict.get("Width"))? as usize;
+        let height = number(pdf, dict.get("Height"))? as usize;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (data, applied) = decode_stream(pdf, dict, data);
+        let filters = filters(pdf, dict);
+        // The filters left are image formats, which only make sense as the last filter.
+        let data = match filters.get(applied ..).unwrap_or_default() {
+            [] => data,
+            [filter] => match filter.as_str() {
+                "DCTDecode" => return Some(Image { width, height, data: ImageData::Jpeg(data) }),
+                #[cfg(feature = "jbig2")]
+                "JBIG2Decode" => {
+                    let globals = match decode_parms(pdf, dict, applied).and_then(|x| x.get("JBIG2Globals")) {
+                        Some(&Value::Ref(m, n)) => pdf.get(&(m, n)).map(|x| x.stream()),
+                        _ => None,
+                    };
+                    jbig2::decode(&data, globals, width, height).ok()?
+                }
+                #[cfg(feature = "jpx")]
+                "JPXDecode" => return Image::from_jpx(pdf, dict, &data, resources, fill),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let mask = matches!(dict.get("ImageMask").map(|x| pdf.resolve(x)), Some(Value::Bool(true)));
+        let (space, bits) = if mask {
+            (ColorSpace::DeviceGray, 1)
+        } else {
+            let space = ColorSpace::load(pdf, dict.get("ColorSpace")?, resources)?;
+            (space, number(pdf, dict.get("BitsPerComponent")).unwrap_or(8.) as usize)
+        };
+        if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+            return None;
+        }
+        let n = space.components();
+        let max = ((1u32 << bits) - 1) as f64;
+        let decode = match dict.get("Decode").map(|x| pdf.resolve(x)) {
+            Some(Value::List(list)) => list.iter().filter_map(|x| match pdf.resolve(x) {
+                &Value::Number(n) => Some(n),
+                _ => None,
+            }).collect(),
+            _ => match space {
+                ColorSpace::Indexed { .. } => vec![0., max],
+                _ => space.ranges(),
+            },
+        };
+        if decode.len() < n * 2 {
+            return None;
+        }
+        // Color key masking leaves out samples within the ranges given for every component.
+        let key = match dict.get("Mask").map(|x| pdf.resolve(x)) {
+            Some(Value::List(_)) => numbers(pdf, dict.get("Mask")).filter(|x| x.len() >= n * 2 && !mask),
+            _ => None,
+        };
+        let mut alpha = Vec::new();
+        let paint = fill.rgb;
+        let stride = (width * n * bits).div_ceil(8);
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        let mut values = vec![0.; n];
+        for y in 0 .. height {
+            let row = data.get(y * stride ..).unwrap_or_default();
+            let mut row = samples(row, bits, width * n);
+            for _ in 0 .. width {
+                let mut keyed = key.is_some();
+                for (i, value) in values.iter_mut().enumerate() {
+                    let sample = row.next().unwrap_or(0) as f64;
+                    *value = decode[i * 2] + sample * (decode[i * 2 + 1] - decode[i * 2]) / max;
+                    keyed &= key.as_ref().is_some_and(|k| (k[i * 2] ..= k[i * 2 + 1]).contains(&sample));
+                }
+                if key.is_some() {
+                    alpha.push(if keyed { 0 } else { 255 });
+                }
+                if mask {
+                    // A sample decoding to 0 is painted.
+                    pixels.extend([paint[0], paint[1], paint[2], if values[0] < 0.5 { 255 } else { 0 }]);
+                } else if let ColorSpace::DeviceGray = space {
+                    pixels.push((values[0].clamp(0., 1.) * 255.).round() as u8);
+                } else {
+                    pixels.extend(space.rgb(&values));
+                }
+            }
+        }
+        let data = match space {
+            _ if mask => ImageData::Rgba(pixels),
+            // Black and white images stay 1 bit.
+            ColorSpace::DeviceGray if bits == 1 && pixels.iter().all(|&x| x == 0 || x == 255) => {
+                ImageData::Bilevel(pixels.chunks(width).flat_map(|row| {
+                    row.chunks(8).map(|x| x.iter().enumerate().fold(0, |a, (i, &x)| a | (x & 0x80) >> i))
+                }).collect())
+            }
+            ColorSpace::DeviceGray => ImageData::Gray(pixels),
+            _ => ImageData::Rgb(pixels),
+        };
+        let image = Image { width, height, data };
+        if key.is_some() {
+            return image.with_alpha(&alpha, None);
+        }
+        Some(image)
+    }
+
+    // JPEG 2000 data decoded as 8 bit samples, in the color space of the image or else
+    // of the data. Channels after the colors are opacity, as with SMaskInData.
+    #[cfg(feature = "jpx")]
+    fn from_jpx(pdf: &PDF, dict: &HashMap<String, Value>, data: &[u8], resources: &HashMap<String, Value>, fill: &Color) -> Option<Image> {
+        let jpx = jpx::decode(data).ok()?;
+        let mut dict = dict.clone();
+        dict.remove("Filter");
+        if !dict.contains_key("ColorSpace") {
+            let name = match jpx.colors {
+                1 => "DeviceGray",
+                4 => "DeviceCMYK",
+                _ => "DeviceRGB",
+            };
+            dict.insert("ColorSpace".into(), Value::Key(name.into()));
+        }
+        let n = ColorSpace::load(pdf, &dict["ColorSpace"], resources)?.components();
+        if jpx.channels < n {
+            return None;
+        }
+        dict.insert("Width".into(), Value::Number(jpx.width as f64));
+        dict.insert("Height".into(), Value::Number(jpx.height as f64));
+        dict.insert("BitsPerComponent".into(), Value::Number(8.));
+        let samples = jpx.pixels.chunks(jpx.channels).flat_map(|x| &x[.. n]).copied().collect::<Vec<_>>();
+        let image = Image::decode(pdf, &dict, &samples, resources, fill)?;
+        if jpx.channels == n {
+            return Some(image);
+        }
+        image.with_alpha(&jpx.pixels.chunks(jpx.channels).map(|x| x[n]).collect::<Vec<_>>(), None)
+    }
+
+    // The image with its samples multiplied by `alpha`, one value per pixel. Colors
+    // premultiplied with the `matte` color are restored first.
+    fn with_alpha(&self, alpha: &[u8], matte: Option<[u8; 3]>) -> Option<Image> {
+        let mut pixels = self.to_rgba()?;
+        for (pixel, &a) in pixels.chunks_mut(4).zip(alpha) {
+            if let Some(matte) = matte.filter(|_| a > 0) {
+                for (c, m) in pixel.iter_mut().zip(matte) {
+                    let value = m as f64 + (*c as f64 - m as f64) * 255. / a as f64;
+                    *c = value.round().clamp(0., 255.) as u8;
+                }
+            }
+            pixel[3] = (pixel[3] as u16 * a as u16 / 255) as u8;
+        }
+        Some(Image { data: ImageData::Rgba(pixels), ..*self })
+    }
+
+    // An image XObject, with the alpha of its soft mask or stencil mask.
+    pub fn from_xobject(pdf: &PDF, object: &Object, resources: &HashMap<String, Value>, fill: &Color) -> Option<Image> {
+        let dict = object.dict();
+        let image = Image::decode(pdf, dict, object.stream(), resources, fill)?;
+        let (mask, soft) = match (dict.get("SMask"), dict.get("Mask")) {
+            (Some(&Value::Ref(m, n)), _) => (pdf.get(&(m, n)), true),
+            (_, Some(&Value::Ref(m, n))) => (pdf.get(&(m, n)), false),
+            _ => (None, false),
+        };
+        let Some(mask) = mask else { return Some(image) };
+        let Some(alpha) = mask_alpha(pdf, mask, soft, image.width, image.height) else { return Some(image) };
+        let matte = numbers(pdf, mask.dict().get("Matte")).filter(|_| soft).and_then(|matte| {
+            Some(ColorSpace::load(pdf, dict.get("ColorSpace")?, resources)?.rgb(&matte))
+        });
+        image.with_alpha(&alpha, matte).or(Some(image))
+    }
+
+    // An inline image, with keys and names already expanded by the lexer.
+    pub fn from_inline(pdf: &PDF, dict: &[(String, Operand)], data: &[u8], resources: &HashMap<String, Value>, fill: &Color) -> Option<Image> {
+        let dict = dict.iter().map(|(k, v)| (k.clone(), operand_value(v))).collect::<HashMap<_, _>>();
+        Image::decode(pdf, &dict, data, resources, fill)
+    }
+
+    // Samples as 8 bit RGBA, decoding JPEG data.
+    pub fn to_rgba(&self) -> Option<Vec<u8>> {
+        let pixels = match &self.data {
+            ImageData::Jpeg(data) => {
+                let mut decoder = jpeg_decoder::Decoder::new(&data[..]);
+                let pixels = decoder.decode().ok()?;
+                let info = decoder.info()?;
+                if (info.width as usize, info.height as usize) != (self.width, self.height) {
+                    return None;
+                }
+                match info.pixel_format {
+                    jpeg_decoder::PixelFormat::L8 => pixels.iter().flat_map(|&x| [x, x, x, 255]).collect(),
+                    jpeg_decoder::PixelFormat::L16 => pixels.chunks(2).flat_map(|x| [x[0], x[0], x[0], 255]).collect(),
+                    jpeg_decoder::PixelFormat::RGB24 => pixels.chunks(3).flat_map(|x| [x[0], x[1], x[2], 255]).collect(),
+                    jpeg_decoder::PixelFormat::CMYK32 => pixels.chunks(4).flat_map(|x| {
+                        let [r, g, b] = ColorSpace::DeviceCMYK.rgb(&x.iter().map(|&x| x as f64 / 255.).collect::<Vec<_>>());
+                        [r, g, b, 255]
+                    }).collect(),
+                }
+            }
+            ImageData::Gray(x) => x.iter().flat_map(|&x| [x, x, x, 255]).collect(),
+            ImageData::Bilevel(x) => x.chunks(self.width.div_ceil(8)).flat_map(|row| {
+                (0 .. self.width).map(|i| if row[i / 8] >> (7 - i % 8) & 1 == 1 { 255 } else { 0 })
+            }).flat_map(|x| [x, x, x, 255]).collect(),
+            ImageData::Rgb(x) => x.chunks(3).flat_map(|x| [x[0], x[1], x[2], 255]).collect(),
+            ImageData::Rgba(x) => x.clone(),
+        };
+        Some(pixels)
+    }
+
+    // The extension of files holding the image as written by `file_data`.
+    pub fn extension(&self) -> &'static str {
+        match self.data {
+            ImageData::Jpeg(_) => "jpg",
+            _ => "png",
+        }
+    }
+
+    pub fn file_data(&self) -> Vec<u8> {
+        match &self.data {
+            ImageData::Jpeg(data) => data.clone(),
+            _ => self.to_png().unwrap_or_default(),
+        }
+    }
+
+    pub fn to_png(&self) -> Option<Vec<u8>> {
+        let (color_type, depth, pixels) = match &self.data {
+            ImageData::Jpeg(_) => return None,
+            ImageData::Gray(x) => (0, 8, x),
+            ImageData::Bilevel(x) => (0, 1, x),
+            ImageData::Rgb(x) => (2, 8, x),
+            ImageData::Rgba(x) => (6, 8, x),
+        };
+        Some(encode_png(self.width, self.height, color_type, depth, pixels))
+    }
+
+    pub fn data_uri(&self) -> String {
+        match &self.data {
+            ImageData::Jpeg(data) => format!("data:image/jpeg;base64,{}", STANDARD.encode(data)),
+            _ => format!("data:image/png;base64,{}", STANDARD.encode(self.to_png().unwrap_or_default())),
+        }
+    }
+}
+
+// Opacity of every pixel of a width by height image from a soft mask, or from the
+// painted area of a stencil mask, sampling the mask at the nearest pixel.
+fn mask_alpha(pdf: &PDF, mask: &Object, soft: bool, width: usize, height: usize) -> Option<Vec<u8>> {
+    let dict = mask.dict();
+    let image = Image::decode(pdf, dict, mask.stream(), &HashMap::new(), &Color::default())?;
+    let pixels = image.to_rgba()?;
+    // Soft masks are gray images; stencil masks are painted where they are opaque.
+    let channel = if soft { 0 } else { 3 };
+    let alpha = (0 .. height).flat_map(|y| (0 .. width).map(move |x| (x, y))).map(|(x, y)| {
+        let (column, row) = (x * image.width / width, y * image.height / height);
+        pixels[(row * image.width + column) * 4 + channel]
+    });
+    Some(alpha.collect())
+}
+
+impl PDF {
+    // Images painted on the page, in painting order, with the CTM placing each.
+    pub fn images(&self, page: &Object) -> Vec<PlacedImage> {
+        get_page_content(self, page).images
+    }
+}
+
+fn operand_value(operand: &Operand) -> Value {
+    match operand {
+        Operand::Number(n) => Value::Number(*n),
+        Operand::Bool(x) => Value::Bool(*x),
+        Operand::Key(s) => Value::Key(s.clone()),
+        Operand::String(s) => Value::String(s.iter().map(|&x| x as char).collect()),
+        Operand::Array(items) => Value::List(items.iter().map(operand_value).collect()),
+        Operand::Dict(entries) => Value::Dict(entries.iter().map(|(k, v)| (k.clone(), operand_value(v))).collect()),
+    }
+}
+
+// Writes samples of `depth` bits as a PNG of the given color type: 0 gray, 2 RGB or
+// 6 RGBA. Rows start on byte boundaries.
+pub fn encode_png(width: usize, height: usize, color_type: u8, depth: u8, pixels: &[u8]) -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        out.extend((data.len() as u32).to_be_bytes());
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(data);
+        out.extend(kind);
+        out.extend(data);
+        out.extend(crc.sum().to_be_bytes());
+    }
+    let channels = match color_type {
+        0 => 1,
+        2 => 3,
+        _ => 4,
+    };
+    let mut header = Vec::new();
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    header.extend([depth, color_type, 0, 0, 0]);
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    for row in pixels.chunks((width * channels * depth as usize).div_ceil(8)).take(height) {
+        // Filter type None for every scanline.
+        let _ = encoder.write_all(&[0]);
+        let _ = encoder.write_all(row);
+    }
+    let mut ret = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut ret, b"IHDR", &header);
+    chunk(&mut ret, b"IDAT", &encoder.finish().unwrap_or_default());
+    chunk(&mut ret, b"IEND", &[]);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::test_pdf;
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        assert_eq!(samples(&[0b1011_0000], 1, 4).collect::<Vec<_>>(), vec![1, 0, 1, 1]);
+        assert_eq!(samples(&[0x4f, 0x60], 4, 3).collect::<Vec<_>>(), vec![4, 15, 6]);
+        assert_eq!(samples(&[0x12, 0x34], 16, 1).collect::<Vec<_>>(), vec![0x1234]);
+    }
+
+    #[test]
+    fn test_alpha() {
+        let image = Image { width: 2, height: 1, data: ImageData::Rgb(vec![255, 0, 0, 255, 128, 128]) };
+        let masked = image.with_alpha(&[255, 0], None).unwrap();
+        assert_eq!(masked.data, ImageData::Rgba(vec![255, 0, 0, 255, 255, 128, 128, 0]));
+        // Colors premultiplied against white are restored.
+        let matte = image.with_alpha(&[255, 127], Some([255, 255, 255])).unwrap();
+        assert_eq!(matte.data, ImageData::Rgba(vec![255, 0, 0, 255, 255, 0, 0, 127]));
+        assert_eq!((matte.extension(), image.extension()), ("png", "png"));
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let png = encode_png(1, 1, 0, 8, &[0x80]);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x01\0\0\0\x01\x08\0"));
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+        let image = Image { width: 9, height: 1, data: ImageData::Bilevel(vec![0b1010_0000, 0x80]) };
+        assert!(image.to_png().unwrap()[16 ..].starts_with(b"\0\0\0\x09\0\0\0\x01\x01\0"));
+        assert_eq!(image.to_rgba().unwrap().chunks(4).map(|x| x[0]).collect::<Vec<_>>(), vec![255, 0, 255, 0, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_predicted() {
+        // Rows of a 2 by 2 RGB image differenced from the left and from above.
+        let rows = [1, 10, 20, 30, 5, 5, 5, 2, 2, 2, 2, 0, 0, 0];
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&rows).unwrap();
+        let data = encoder.finish().unwrap();
+        let pdf = test_pdf(&[
+            ("<< /Type /Catalog >>", b""),
+            ("<< /Subtype /Image /Width 2 /Height 2 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode /DecodeParms << /Predictor 15 /Colors 3 /Columns 2 >> >>", &data),
+        ]);
+        let image = Image::from_xobject(&pdf, pdf.get(&(2, 0)).unwrap(), &HashMap::new(), &Color::default()).unwrap();
+        assert_eq!(image.data, ImageData::Rgb(vec![10, 20, 30, 15, 25, 35, 12, 22, 32, 15, 25, 35]));
+    }
+}
@@ -0,0 +1,723 @@
+// JBIG2 embedded streams as in JBIG2Decode, with the segments of JBIG2Globals first.
+// Generic, refinement and text regions and symbol dictionaries are decoded when
+// arithmetic coded, and generic regions when MMR coded as well; Huffman coded segments,
+// halftones and patterns aren't supported.
+
+use super::ccitt;
+use super::mq::{Context, Decoder};
+use super::util::{u16_at, u32_at};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Bitmaps larger than this many pixels are taken as corrupt.
+const MAX_PIXELS: usize = 1 << 28;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Bitmap {
+    width: usize,
+    height: usize,
+    // One byte per pixel, 1 for black.
+    pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    fn new(width: usize, height: usize, value: u8) -> Option<Bitmap> {
+        let size = width.checked_mul(height).filter(|&x| x <= MAX_PIXELS)?;
+        Some(Bitmap { width, height, pixels: vec![value; size] })
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8 {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return 0;
+        }
+        self.pixels[y as usize * self.width + x as usize]
+    }
+
+    // Draws `other` with its top left corner at (x, y), combining pixels with OR, AND,
+    // XOR, XNOR or REPLACE for operators 0 to 4.
+    fn combine(&mut self, other: &Bitmap, x: i64, y: i64, op: u8) {
+        for row in 0 .. other.height as i64 {
+            let ty = y + row;
+            if ty < 0 || ty >= self.height as i64 {
+                continue;
+            }
+            for column in 0 .. other.width as i64 {
+                let tx = x + column;
+                if tx < 0 || tx >= self.width as i64 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[ty as usize * self.width + tx as usize];
+                let value = other.pixels[row as usize * other.width + column as usize];
+                *pixel = match op {
+                    0 => *pixel | value,
+                    1 => *pixel & value,
+                    2 => *pixel ^ value,
+                    3 => 1 ^ *pixel ^ value,
+                    _ => value,
+                };
+            }
+        }
+    }
+
+    // The area of the given size at (x, y).
+    fn crop(&self, x: i64, y: i64, width: usize, height: usize) -> Option<Bitmap> {
+        let mut ret = Bitmap::new(width, height, 0)?;
+        ret.combine(self, -x, -y, 4);
+        Some(ret)
+    }
+}
+
+// Pixels around the one decoded making up the context of generic regions, by template,
+// before the adaptive ones.
+const GENERIC_TEMPLATES: [&[(i64, i64)]; 4] = [
+    &[(-1, -2), (0, -2), (1, -2), (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1), (-4, 0), (-3, 0), (-2, 0), (-1, 0)],
+    &[(-1, -2), (0, -2), (1, -2), (2, -2), (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1), (-3, 0), (-2, 0), (-1, 0)],
+    &[(-1, -2), (0, -2), (1, -2), (-2, -1), (-1, -1), (0, -1), (1, -1), (-2, 0), (-1, 0)],
+    &[(-3, -1), (-2, -1), (-1, -1), (0, -1), (1, -1), (-4, 0), (-3, 0), (-2, 0), (-1, 0)],
+];
+// Contexts of the typical prediction bit, which are also contexts of ordinary pixels.
+const PREDICTION_CONTEXTS: [usize; 4] = [0x9b25, 0x0795, 0x00e5, 0x0195];
+
+// Pixels of the region decoded and of the reference, by refinement template, before the
+// adaptive ones.
+type Template = &'static [(i64, i64)];
+const REFINEMENT_TEMPLATES: [(Template, Template); 2] = [
+    (&[(0, -1), (1, -1), (-1, 0)], &[(0, -1), (1, -1), (-1, 0), (0, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]),
+    (&[(-1, -1), (0, -1), (1, -1), (-1, 0)], &[(0, -1), (-1, 0), (0, 0), (1, 0), (0, 1), (1, 1)]),
+];
+
+// The arithmetic decoder of a segment with its adaptive contexts.
+struct Arithmetic<'a> {
+    decoder: Decoder<'a>,
+    generic: Vec<Context>,
+    refinement: Vec<Context>,
+    // Contexts of integers and symbol IDs, by decoding procedure.
+    integers: HashMap<&'static str, Vec<Context>>,
+}
+
+impl Arithmetic<'_> {
+    fn new(data: &[u8]) -> Arithmetic<'_> {
+        Arithmetic { decoder: Decoder::new(data), generic: vec![0; 1 << 16], refinement: vec![0; 1 << 14], integers: HashMap::new() }
+    }
+
+    // An integer, or None for out-of-band.
+    fn integer(&mut self, procedure: &'static str) -> Option<i64> {
+        let contexts = self.integers.entry(procedure).or_insert_with(|| vec![0; 512]);
+        let decoder = &mut self.decoder;
+        let mut previous = 1usize;
+        let mut bits = |n: usize| {
+            let mut value = 0i64;
+            for _ in 0 .. n {
+                let bit = decoder.decode(&mut contexts[previous]) as usize;
+                previous = if previous < 256 { previous << 1 | bit } else { ((previous << 1 | bit) & 511) | 256 };
+                value = value << 1 | bit as i64;
+            }
+            value
+        };
+        let sign = bits(1);
+        let value = if bits(1) == 0 {
+            bits(2)
+        } else if bits(1) == 0 {
+            bits(4) + 4
+        } else if bits(1) == 0 {
+            bits(6) + 20
+        } else if bits(1) == 0 {
+            bits(8) + 84
+        } else if bits(1) == 0 {
+            bits(12) + 340
+        } else {
+            bits(32) + 4436
+        };
+        match (sign, value) {
+            (0, _) => Some(value),
+            (_, 0) => None,
+            _ => Some(-value),
+        }
+    }
+
+    fn symbol_id(&mut self, length: u32) -> usize {
+        let contexts = self.integers.entry("IAID").or_insert_with(|| vec![0; 1 << (length + 1)]);
+        let mut previous = 1usize;
+        for _ in 0 .. length {
+            let bit = self.decoder.decode(&mut contexts[previous]) as usize;
+            previous = previous << 1 | bit;
+        }
+        previous & ((1 << length) - 1)
+    }
+
+    // A generic region with the adaptive pixels `at`, and typical prediction if asked.
+    fn generic(&mut self, width: usize, height: usize, template: u8, prediction: bool, at: &[(i64, i64)]) -> Option<Bitmap> {
+        let mut bitmap = Bitmap::new(width, height, 0)?;
+        let template = template.min(3) as usize;
+        let mut pixels = GENERIC_TEMPLATES[template].to_vec();
+        pixels.extend(at);
+        pixels.sort_by_key(|&(x, y)| (y, x));
+        let mut typical = 0;
+        for y in 0 .. height {
+            if prediction {
+                typical ^= self.decoder.decode(&mut self.generic[PREDICTION_CONTEXTS[template]]);
+                if typical == 1 {
+                    // The row is the same as the one above.
+                    if y > 0 {
+                        bitmap.pixels.copy_within((y - 1) * width .. y * width, y * width);
+                    }
+                    continue;
+                }
+            }
+            for x in 0 .. width {
+                let context = pixels.iter().fold(0, |a, &(dx, dy)| a << 1 | bitmap.get(x as i64 + dx, y as i64 + dy) as usize);
+                bitmap.pixels[y * width + x] = self.decoder.decode(&mut self.generic[context & 0xffff]);
+            }
+        }
+        Some(bitmap)
+    }
+
+    // A refinement of `reference`, which is offset by (dx, dy) from the region.
+    fn refinement(&mut self, width: usize, height: usize, template: u8, reference: &Bitmap, (dx, dy): (i64, i64), at: &[(i64, i64); 2]) -> Option<Bitmap> {
+        let mut bitmap = Bitmap::new(width, height, 0)?;
+        let (coding, referenced) = REFINEMENT_TEMPLATES[template.min(1) as usize];
+        let (mut coding, mut referenced) = (coding.to_vec(), referenced.to_vec());
+        if template == 0 {
+            coding.push(at[0]);
+            referenced.push(at[1]);
+        }
+        for y in 0 .. height as i64 {
+            for x in 0 .. width as i64 {
+                let context = coding.iter().fold(0, |a, &(cx, cy)| a << 1 | bitmap.get(x + cx, y + cy) as usize);
+                let context = referenced.iter().fold(context, |a, &(rx, ry)| a << 1 | reference.get(x + rx - dx, y + ry - dy) as usize);
+                bitmap.pixels[(y * width as i64 + x) as usize] = self.decoder.decode(&mut self.refinement[context & 0x3fff]);
+            }
+        }
+        Some(bitmap)
+    }
+
+    fn text_region(&mut self, region: &TextRegion, symbols: &[Rc<Bitmap>]) -> Option<Bitmap> {
+        let mut bitmap = Bitmap::new(region.width, region.height, region.default_pixel)?;
+        let mut strip = -self.integer("IADT")? * region.strip_size;
+        let mut first_s = 0;
+        let mut count = 0;
+        while count < region.instances {
+            strip += self.integer("IADT")? * region.strip_size;
+            first_s += self.integer("IAFS")?;
+            let mut s = first_s;
+            loop {
+                let t = strip + if region.strip_size > 1 { self.integer("IAIT")? } else { 0 };
+                let id = self.symbol_id(region.code_length);
+                let mut symbol = symbols.get(id)?.clone();
+                if region.refinement && self.integer("IARI")? != 0 {
+                    let (dw, dh) = (self.integer("IARDW")?, self.integer("IARDH")?);
+                    let (dx, dy) = (self.integer("IARDX")?, self.integer("IARDY")?);
+                    let width = usize::try_from(symbol.width as i64 + dw).ok()?;
+                    let height = usize::try_from(symbol.height as i64 + dh).ok()?;
+                    let offset = ((dw >> 1) + dx, (dh >> 1) + dy);
+                    symbol = Rc::new(self.refinement(width, height, region.refinement_template, &symbol, offset, &region.refinement_at)?);
+                }
+                let (width, height) = (symbol.width as i64, symbol.height as i64);
+                // S runs along rows, or down columns when transposed, and the reference
+                // corner of the symbol is put at (S, T).
+                let size = if region.transposed { height } else { width };
+                let far = if region.transposed { region.corner & 1 == 0 } else { region.corner & 2 != 0 };
+                if far {
+                    s += size - 1;
+                }
+                let (x, y) = if region.transposed { (t, s) } else { (s, t) };
+                let x = x - if region.corner & 2 != 0 { width - 1 } else { 0 };
+                let y = y - if region.corner & 1 == 0 { height - 1 } else { 0 };
+                bitmap.combine(&symbol, x, y, region.op);
+                if !far {
+                    s += size - 1;
+                }
+                count += 1;
+                match self.integer("IADS") {
+                    Some(ds) if count < region.instances => s += ds + region.ds_offset,
+                    _ => break,
+                }
+            }
+        }
+        Some(bitmap)
+    }
+
+    fn symbol_dictionary(&mut self, dictionary: &SymbolDictionary, input: &[Rc<Bitmap>]) -> Option<Vec<Rc<Bitmap>>> {
+        let mut symbols = input.to_vec();
+        let total = input.len() + dictionary.new_symbols;
+        let code_length = code_length(total);
+        let mut height = 0i64;
+        while symbols.len() < total {
+            height += self.integer("IADH")?;
+            let mut width = 0i64;
+            while let Some(dw) = self.integer("IADW") {
+                width += dw;
+                if symbols.len() >= total || width < 0 || height < 0 {
+                    return None;
+                }
+                let (w, h) = (width as usize, height as usize);
+                let symbol = if !dictionary.refinement {
+                    self.generic(w, h, dictionary.template, false, &dictionary.at)?
+                } else {
+                    let instances = self.integer("IAAI")?;
+                    if instances > 1 {
+                        let region = TextRegion {
+                            width: w,
+                            height: h,
+                            default_pixel: 0,
+                            code_length,
+                            instances: instances as usize,
+                            strip_size: 1,
+                            transposed: false,
+                            ds_offset: 0,
+                            corner: 1,
+                            op: 0,
+                            refinement: true,
+                            refinement_template: dictionary.refinement_template,
+                            refinement_at: dictionary.refinement_at,
+                        };
+                        self.text_region(&region, &symbols)?
+                    } else {
+                        let id = self.symbol_id(code_length);
+                        let offset = (self.integer("IARDX")?, self.integer("IARDY")?);
+                        let reference = symbols.get(id)?.clone();
+                        self.refinement(w, h, dictionary.refinement_template, &reference, offset, &dictionary.refinement_at)?
+                    }
+                };
+                symbols.push(Rc::new(symbol));
+            }
+        }
+        // Runs of symbols alternately left out and exported, starting with input symbols.
+        let mut exported = Vec::new();
+        let mut export = false;
+        let mut i = 0;
+        while i < total {
+            let run = usize::try_from(self.integer("IAEX")?).ok()?;
+            if run > total - i {
+                return None;
+            }
+            if export {
+                exported.extend(symbols[i .. i + run].iter().cloned());
+            }
+            i += run;
+            export = !export;
+        }
+        Some(exported)
+    }
+}
+
+// Bits of symbol IDs among n symbols.
+fn code_length(n: usize) -> u32 {
+    (usize::BITS - n.saturating_sub(1).leading_zeros()).min(24)
+}
+
+struct TextRegion {
+    width: usize,
+    height: usize,
+    default_pixel: u8,
+    // Bits of symbol IDs.
+    code_length: u32,
+    instances: usize,
+    strip_size: i64,
+    transposed: bool,
+    ds_offset: i64,
+    // Bit 0 set for top corners, bit 1 for right ones.
+    corner: u8,
+    op: u8,
+    refinement: bool,
+    refinement_template: u8,
+    refinement_at: [(i64, i64); 2],
+}
+
+struct SymbolDictionary {
+    new_symbols: usize,
+    template: u8,
+    at: Vec<(i64, i64)>,
+    refinement: bool,
+    refinement_template: u8,
+    refinement_at: [(i64, i64); 2],
+}
+
+struct Segment<'a> {
+    number: u32,
+    kind: u8,
+    referred: Vec<u32>,
+    data: &'a [u8],
+}
+
+// Adaptive pixels as pairs of signed bytes.
+fn adaptive(data: &[u8], at: usize, n: usize) -> Option<Vec<(i64, i64)>> {
+    (0 .. n).map(|i| Some((*data.get(at + i * 2)? as i8 as i64, *data.get(at + i * 2 + 1)? as i8 as i64))).collect()
+}
+
+// Segments of the sequential organization, header after header with their data.
+fn read_segments(data: &[u8]) -> Vec<Segment<'_>> {
+    let mut ret = Vec::new();
+    let mut at = 0;
+    while at < data.len() {
+        let Some(segment) = read_segment(data, &mut at) else { break };
+        ret.push(segment);
+    }
+    ret
+}
+
+fn read_segment<'a>(data: &'a [u8], at: &mut usize) -> Option<Segment<'a>> {
+    let number = u32_at(data, *at)?;
+    let flags = *data.get(*at + 4)?;
+    let mut count = (*data.get(*at + 5)? >> 5) as usize;
+    *at += 6;
+    if count == 7 {
+        // The long form, with the retention bits after the count.
+        count = (u32_at(data, *at - 1)? & 0x1fff_ffff) as usize;
+        *at += 3 + (count + 1).div_ceil(8);
+    }
+    let size = match number {
+        0 ..= 256 => 1,
+        257 ..= 65536 => 2,
+        _ => 4,
+    };
+    let referred = (0 .. count.min(1 << 16)).map(|i| {
+        let bytes = data.get(*at + i * size .. *at + (i + 1) * size)?;
+        Some(bytes.iter().fold(0u32, |a, &x| a << 8 | x as u32))
+    }).collect::<Option<Vec<_>>>()?;
+    *at += count * size + if flags & 0x40 != 0 { 4 } else { 1 };
+    let mut length = u32_at(data, *at)? as usize;
+    *at += 4;
+    let kind = flags & 0x3f;
+    if length == 0xffff_ffff {
+        // Only immediate generic regions may leave their length unknown; they end with
+        // a marker followed by the row count.
+        if kind != 38 {
+            return None;
+        }
+        let mmr = data.get(*at + 17)? & 1 == 1;
+        let mut end = if mmr { vec![0, 0] } else { vec![0xff, 0xac] };
+        end.extend(data.get(*at + 4 .. *at + 8)?);
+        length = data[*at ..].windows(end.len()).position(|x| x == end)? + end.len();
+    }
+    let segment = data.get(*at .. at.checked_add(length)?.min(data.len()))?;
+    *at += length;
+    Some(Segment { number, kind, referred, data: segment })
+}
+
+// The region segment information field: size, position and combination operator.
+fn region_info(data: &[u8]) -> Option<(usize, usize, i64, i64, u8)> {
+    let (width, height) = (u32_at(data, 0)? as usize, u32_at(data, 4)? as usize);
+    let (x, y) = (u32_at(data, 8)? as i32 as i64, u32_at(data, 12)? as i32 as i64);
+    Some((width, height, x, y, data.get(16)? & 7))
+}
+
+#[derive(Default)]
+struct Document {
+    page: Option<Bitmap>,
+    // Exported symbols of dictionaries and intermediate regions, by segment number.
+    symbols: HashMap<u32, Vec<Rc<Bitmap>>>,
+    regions: HashMap<u32, Bitmap>,
+}
+
+impl Document {
+    fn process(&mut self, segment: &Segment, height: usize) -> Option<()> {
+        let data = segment.data;
+        match segment.kind {
+            0 => {
+                let flags = u16_at(data, 0)?;
+                let (refinement, template, refinement_template) = (flags & 2 != 0, (flags >> 10 & 3) as u8, (flags >> 12 & 1) as u8);
+                let n = if template == 0 { 4 } else { 1 };
+                let at = adaptive(data, 2, n)?;
+                let mut at_end = 2 + n * 2;
+                let mut refinement_at = [(0, 0); 2];
+                if refinement && refinement_template == 0 {
+                    let pixels = adaptive(data, at_end, 2)?;
+                    refinement_at = [pixels[0], pixels[1]];
+                    at_end += 4;
+                }
+                let dictionary = SymbolDictionary {
+                    new_symbols: u32_at(data, at_end + 4)? as usize,
+                    template,
+                    at,
+                    refinement,
+                    refinement_template,
+                    refinement_at,
+                };
+                let input = segment.referred.iter().filter_map(|x| self.symbols.get(x)).flatten().cloned().collect::<Vec<_>>();
+                let mut arithmetic = Arithmetic::new(data.get(at_end + 8 ..)?);
+                let symbols = arithmetic.symbol_dictionary(&dictionary, &input)?;
+                self.symbols.insert(segment.number, symbols);
+            }
+            4 | 6 | 7 => {
+                let (width, height, x, y, op) = region_info(data)?;
+                let flags = u16_at(data, 17)?;
+                let refinement = flags & 2 != 0;
+                let refinement_template = (flags >> 15) as u8;
+                let mut at = 19;
+                let mut refinement_at = [(0, 0); 2];
+                if refinement && refinement_template == 0 {
+                    let pixels = adaptive(data, at, 2)?;
+                    refinement_at = [pixels[0], pixels[1]];
+                    at += 4;
+                }
+                let symbols = segment.referred.iter().filter_map(|x| self.symbols.get(x)).flatten().cloned().collect::<Vec<_>>();
+                let region = TextRegion {
+                    width,
+                    height,
+                    default_pixel: (flags >> 9 & 1) as u8,
+                    code_length: code_length(symbols.len()),
+                    instances: u32_at(data, at)? as usize,
+                    strip_size: 1 << (flags >> 2 & 3),
+                    transposed: flags & 0x40 != 0,
+                    // A signed 5 bit value.
+                    ds_offset: ((flags << 1) as i16 >> 11) as i64,
+                    corner: (flags >> 4 & 3) as u8,
+                    op: (flags >> 7 & 3) as u8,
+                    refinement,
+                    refinement_template,
+                    refinement_at,
+                };
+                let bitmap = Arithmetic::new(data.get(at + 4 ..)?).text_region(&region, &symbols)?;
+                self.place(segment, bitmap, x, y, op);
+            }
+            36 | 38 | 39 => {
+                let (width, mut height, x, y, op) = region_info(data)?;
+                if height == 0xffff_ffff {
+                    // The row count follows the end marker of regions of unknown length.
+                    height = u32_at(data, data.len().checked_sub(4)?)? as usize;
+                }
+                let flags = *data.get(17)?;
+                if flags & 1 == 1 {
+                    // MMR coded regions are fax data coded two-dimensionally, 1 for black.
+                    let mut bitmap = Bitmap::new(width, height, 0)?;
+                    let params = ccitt::Params { k: -1, columns: width, rows: height, black_is_1: true, ..ccitt::Params::default() };
+                    let rows = ccitt::decode(data.get(18 ..)?, &params, height);
+                    let stride = width.div_ceil(8);
+                    for (i, pixel) in bitmap.pixels.iter_mut().enumerate() {
+                        let (x, y) = (i % width, i / width);
+                        *pixel = rows[y * stride + x / 8] >> (7 - x % 8) & 1;
+                    }
+                    self.place(segment, bitmap, x, y, op);
+                    return Some(());
+                }
+                let template = flags >> 1 & 3;
+                let at = adaptive(data, 18, if template == 0 { 4 } else { 1 })?;
+                let start = 18 + at.len() * 2;
+                let bitmap = Arithmetic::new(data.get(start ..)?).generic(width, height, template, flags & 8 != 0, &at)?;
+                self.place(segment, bitmap, x, y, op);
+            }
+            40 | 42 | 43 => {
+                let (width, height, x, y, op) = region_info(data)?;
+                let flags = *data.get(17)?;
+                let template = flags & 1;
+                let at = if template == 0 { adaptive(data, 18, 2)? } else { vec![(0, 0); 2] };
+                let start = if template == 0 { 22 } else { 18 };
+                // The reference is an intermediate region, or else the page under the region.
+                let reference = match segment.referred.first().and_then(|x| self.regions.remove(x)) {
+                    Some(bitmap) => bitmap,
+                    None => self.page.as_ref()?.crop(x, y, width, height)?,
+                };
+                let mut arithmetic = Arithmetic::new(data.get(start ..)?);
+                let bitmap = arithmetic.refinement(width, height, template, &reference, (0, 0), &[at[0], at[1]])?;
+                self.place(segment, bitmap, x, y, op);
+            }
+            48 => {
+                let width = u32_at(data, 0)? as usize;
+                // Striped pages of unknown height take the height of the image.
+                let height = match u32_at(data, 4)? {
+                    0xffff_ffff => height,
+                    x => x as usize,
+                };
+                let flags = *data.get(16)?;
+                self.page = Some(Bitmap::new(width, height, flags >> 2 & 1)?);
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    // Keeps intermediate regions for refinement, and draws the others on the page.
+    fn place(&mut self, segment: &Segment, bitmap: Bitmap, x: i64, y: i64, op: u8) {
+        if matches!(segment.kind, 4 | 36 | 40) {
+            self.regions.insert(segment.number, bitmap);
+        } else if let Some(page) = &mut self.page {
+            page.combine(&bitmap, x, y, op);
+        }
+    }
+}
+
+// The feature a segment needs that isn't supported, if any.
+fn unsupported(segment: &Segment) -> Option<&'static str> {
+    let flags = |at| segment.data.get(at).copied().unwrap_or(0);
+    match segment.kind {
+        0 if flags(1) & 1 == 1 => Some("Huffman coded symbol dictionaries"),
+        4 | 6 | 7 if flags(18) & 1 == 1 => Some("Huffman coded text regions"),
+        16 => Some("pattern dictionaries"),
+        20 | 22 | 23 => Some("halftone regions"),
+        40 | 42 | 43 if flags(17) & 2 != 0 => Some("typical prediction of refinement regions"),
+        _ => None,
+    }
+}
+
+// Decodes to rows of 1 bit samples, with 0 for black as in DeviceGray.
+pub fn decode(data: &[u8], globals: Option<&[u8]>, width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let mut document = Document::default();
+    let segments = globals.map(read_segments).unwrap_or_default().into_iter().chain(read_segments(data));
+    for segment in segments {
+        if let Some(feature) = unsupported(&segment) {
+            return Err(format!("{feature} aren't supported"));
+        }
+        // Segments that fail to decode leave their region blank.
+        document.process(&segment, height);
+    }
+    let page = document.page.ok_or("no page information segment")?;
+    let stride = width.div_ceil(8);
+    let mut ret = vec![0; stride * height];
+    for y in 0 .. height {
+        for x in 0 .. width {
+            if page.get(x as i64, y as i64) == 0 {
+                ret[y * stride + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mq::Encoder;
+
+    fn encode_generic(bitmap: &Bitmap, template: u8, at: &[(i64, i64)]) -> Vec<u8> {
+        let mut pixels = GENERIC_TEMPLATES[template as usize].to_vec();
+        pixels.extend(at);
+        pixels.sort_by_key(|&(x, y)| (y, x));
+        let mut contexts = vec![0; 1 << 16];
+        let mut encoder = Encoder::default();
+        for y in 0 .. bitmap.height as i64 {
+            for x in 0 .. bitmap.width as i64 {
+                let context = pixels.iter().fold(0, |a, &(dx, dy)| a << 1 | bitmap.get(x + dx, y + dy) as usize);
+                encoder.encode(&mut contexts[context], bitmap.get(x, y));
+            }
+        }
+        encoder.flush()
+    }
+
+    fn letter() -> Bitmap {
+        let rows = ["..####..", ".#....#.", "#......#", "########", "#......#", "#......#"];
+        let pixels = rows.iter().flat_map(|x| x.bytes().map(|x| (x == b'#') as u8)).collect();
+        Bitmap { width: 8, height: 6, pixels }
+    }
+
+    #[test]
+    fn test_generic_region() {
+        let bitmap = letter();
+        let at = [(3, -1), (-3, -1), (2, -2), (-2, -2)];
+        let encoded = encode_generic(&bitmap, 0, &at);
+        assert_eq!(Arithmetic::new(&encoded).generic(8, 6, 0, false, &at), Some(bitmap.clone()));
+
+        // The same region as the immediate generic region segment of a page.
+        let mut data = vec![0, 0, 0, 0, 48, 0, 1, 0, 0, 0, 19];
+        data.extend([0, 0, 0, 10, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 1, 38, 0, 1]);
+        data.extend((26 + encoded.len() as u32).to_be_bytes());
+        data.extend([0, 0, 0, 8, 0, 0, 0, 6, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0]);
+        data.extend(at.iter().flat_map(|&(x, y)| [x as u8, y as u8]));
+        data.extend(&encoded);
+        let decoded = decode(&data, None, 10, 8).unwrap();
+        // Rows are 2 bytes, with the letter at (1, 1) in black, so 0 bits.
+        assert_eq!(&decoded[2 .. 4], &[0b1110_0001, 0b1100_0000]);
+        assert_eq!(&decoded[8 .. 10], &[0b1000_0000, 0b0100_0000]);
+        assert_eq!(&decoded[14 .. 16], &[0xff, 0xc0]);
+    }
+
+    #[test]
+    fn test_mmr_region() {
+        // Three rows of fax data, the last white, on a page of the same size.
+        let mmr = [0x2f, 0x5b, 0x8c, 0, 0x40, 0x04];
+        let mut data = vec![0, 0, 0, 0, 48, 0, 1, 0, 0, 0, 19];
+        data.extend([0, 0, 0, 8, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 1, 38, 0, 1]);
+        data.extend((18 + mmr.len() as u32).to_be_bytes());
+        data.extend([0, 0, 0, 8, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        data.extend(mmr);
+        assert_eq!(decode(&data, None, 8, 3).unwrap(), [0b1100_0111, 0b1110_0011, 0xff]);
+        // A halftone region fails the image rather than leaving its area blank.
+        data.extend([0, 0, 0, 2, 22, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(decode(&data, None, 8, 3), Err("halftone regions aren't supported".into()));
+    }
+
+    fn encode_integer(encoder: &mut Encoder, contexts: &mut [Context], value: Option<i64>) {
+        let mut previous = 1usize;
+        let mut bits = |value: i64, n: usize| {
+            for i in (0 .. n).rev() {
+                let bit = (value >> i & 1) as u8;
+                encoder.encode(&mut contexts[previous], bit);
+                previous = if previous < 256 { previous << 1 | bit as usize } else { ((previous << 1 | bit as usize) & 511) | 256 };
+            }
+        };
+        let magnitude = value.unwrap_or(0).abs();
+        bits((value.is_none() || magnitude < 0) as i64, 1);
+        match magnitude {
+            0 ..= 3 => bits(magnitude, 3),
+            4 ..= 19 => bits(0b10 << 4 | (magnitude - 4), 6),
+            _ => bits(0b110 << 6 | (magnitude - 20), 9),
+        }
+    }
+
+    #[test]
+    fn test_text_region() {
+        // A dictionary of one symbol, drawn twice by a text region.
+        let mut encoder = Encoder::default();
+        let mut contexts = HashMap::<&str, Vec<Context>>::new();
+        let mut integer = |encoder: &mut Encoder, procedure, value| {
+            encode_integer(encoder, contexts.entry(procedure).or_insert_with(|| vec![0; 512]), value);
+        };
+        integer(&mut encoder, "IADH", Some(6));
+        integer(&mut encoder, "IADW", Some(8));
+        let at = [(3, -1), (-3, -1), (2, -2), (-2, -2)];
+        let letter = letter();
+        let mut pixels = GENERIC_TEMPLATES[0].to_vec();
+        pixels.extend(at);
+        pixels.sort_by_key(|&(x, y)| (y, x));
+        let mut generic = vec![0; 1 << 16];
+        for y in 0 .. 6 {
+            for x in 0 .. 8 {
+                let context = pixels.iter().fold(0, |a, &(dx, dy)| a << 1 | letter.get(x + dx, y + dy) as usize);
+                encoder.encode(&mut generic[context], letter.get(x, y));
+            }
+        }
+        integer(&mut encoder, "IADW", None);
+        integer(&mut encoder, "IAEX", Some(0));
+        integer(&mut encoder, "IAEX", Some(1));
+        let dictionary = encoder.flush();
+
+        let mut encoder = Encoder::default();
+        let mut contexts = HashMap::<&str, Vec<Context>>::new();
+        let mut integer = |encoder: &mut Encoder, procedure, value| {
+            encode_integer(encoder, contexts.entry(procedure).or_insert_with(|| vec![0; 512]), value);
+        };
+        for (procedure, value) in [("IADT", Some(0)), ("IADT", Some(1)), ("IAFS", Some(1)), ("IADS", Some(2)), ("IADS", None)] {
+            integer(&mut encoder, procedure, value);
+        }
+        let text = encoder.flush();
+
+        let mut data = vec![0, 0, 0, 0, 48, 0, 1, 0, 0, 0, 19];
+        data.extend([0, 0, 0, 20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 1, 0, 0, 1]);
+        data.extend((18 + dictionary.len() as u32).to_be_bytes());
+        data.extend([0, 0, 3, 0xff, 0xfd, 0xff, 2, 0xfe, 0xfe, 0xfe, 0, 0, 0, 1, 0, 0, 0, 1]);
+        data.extend(&dictionary);
+        data.extend([0, 0, 0, 2, 6, 0x20, 1, 1]);
+        data.extend((23 + text.len() as u32).to_be_bytes());
+        data.extend([0, 0, 0, 20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 2]);
+        data.extend(&text);
+        let decoded = decode(&data, None, 20, 8).unwrap();
+        // The letters are at (1, 1) and (10, 1), so their bars span 1 to 8 and 10 to 17.
+        assert_eq!(&decoded[12 .. 15], &[0b1000_0000, 0b0100_0000, 0b0011_0000]);
+        assert_eq!(&decoded[3 .. 6], &[0b1110_0001, 0b1111_0000, 0b1111_0000]);
+    }
+
+    #[test]
+    fn test_segment_header() {
+        // Segment 300 referring to segments 2 and 299, associated with page 1.
+        let data = [0, 0, 1, 44, 0, 0x40, 0, 2, 1, 43, 1, 0, 0, 0, 2, 7, 9];
+        let mut at = 0;
+        let segment = read_segment(&data, &mut at).unwrap();
+        assert_eq!((segment.number, segment.kind, segment.referred, segment.data), (300, 0, vec![2, 299], &[7, 9][..]));
+        assert_eq!(code_length(1), 0);
+        assert_eq!(code_length(5), 3);
+        assert_eq!(code_length(8), 3);
+    }
+}
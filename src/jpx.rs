@@ -0,0 +1,1304 @@
+// JPEG 2000 images as in JPXDecode: JP2 files or bare codestreams, decoded to 8 bit
+// channels. Packed packet headers and region of interest shifts aren't supported, and
+// make decoding fail.
+
+use super::mq::{Context, Decoder};
+use super::util::{u16_at, u32_at};
+use std::collections::HashMap;
+
+// Samples larger than this many per component are taken as corrupt.
+const MAX_SAMPLES: usize = 1 << 28;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jpx {
+    pub width: usize,
+    pub height: usize,
+    // Channels per pixel in `pixels`, of which the first `colors` are color channels
+    // and the rest opacity.
+    pub channels: usize,
+    pub colors: usize,
+    pub pixels: Vec<u8>,
+}
+
+fn ceil_div(a: i64, b: i64) -> i64 {
+    -(-a).div_euclid(b)
+}
+
+// Boxes of a JP2 file as their types and contents.
+fn boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut ret = Vec::new();
+    let mut at = 0;
+    while let (Some(length), Some(kind)) = (u32_at(data, at).map(|x| x as usize), data.get(at + 4 .. at + 8)) {
+        let (start, end) = match length {
+            0 => (at + 8, data.len()),
+            1 => match data.get(at + 8 .. at + 16) {
+                Some(x) => (at + 16, at.saturating_add(u64::from_be_bytes(x.try_into().unwrap()) as usize)),
+                None => break,
+            },
+            _ => (at + 8, at + length),
+        };
+        let Some(contents) = data.get(start .. end.min(data.len())) else { break };
+        ret.push((kind, contents));
+        if end <= at {
+            break;
+        }
+        at = end;
+    }
+    ret
+}
+
+// Color information of a JP2 header: the enumerated color space and the palette with
+// the mapping of its columns to channels.
+#[derive(Default)]
+struct Header {
+    space: Option<u32>,
+    palette: Vec<Vec<u8>>,
+    mapping: Vec<(usize, Option<usize>)>,
+}
+
+fn read_header(data: &[u8]) -> Header {
+    let mut header = Header::default();
+    for (kind, contents) in boxes(data) {
+        match kind {
+            b"colr" if contents.first() == Some(&1) => header.space = u32_at(contents, 3),
+            b"pclr" => {
+                let (entries, columns) = (u16_at(contents, 0).unwrap_or(0) as usize, contents.get(2).copied().unwrap_or(0) as usize);
+                let depths = contents.get(3 .. 3 + columns).unwrap_or_default();
+                let mut at = 3 + columns;
+                header.palette = vec![Vec::new(); columns];
+                for _ in 0 .. entries {
+                    for (column, &depth) in header.palette.iter_mut().zip(depths) {
+                        let bits = (depth & 0x7f) as u32 + 1;
+                        let size = bits.div_ceil(8) as usize;
+                        let value = contents.get(at .. at + size).unwrap_or_default().iter().fold(0u64, |a, &x| a << 8 | x as u64);
+                        column.push((value * 255 / ((1u64 << bits) - 1)) as u8);
+                        at += size;
+                    }
+                }
+            }
+            b"cmap" => {
+                header.mapping = contents.chunks_exact(4).map(|x| {
+                    (u16_at(x, 0).unwrap_or(0) as usize, if x[2] == 1 { Some(x[3] as usize) } else { None })
+                }).collect();
+            }
+            _ => {}
+        }
+    }
+    header
+}
+
+#[derive(Debug, Clone)]
+struct Component {
+    precision: u32,
+    dx: usize,
+    dy: usize,
+}
+
+// Coding style of a tile component.
+#[derive(Debug, Clone)]
+struct Style {
+    levels: usize,
+    xcb: u32,
+    ycb: u32,
+    // Code-block style bits: arithmetic coding bypass, context reset, termination on
+    // every pass, vertically causal contexts, predictable termination and segmentation
+    // symbols.
+    block: u8,
+    reversible: bool,
+    // Exponents of precinct sizes by resolution, 15 when not given.
+    precincts: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone)]
+struct Coding {
+    order: u8,
+    layers: usize,
+    mct: bool,
+    sop: bool,
+    eph: bool,
+    style: Style,
+}
+
+#[derive(Debug, Clone)]
+struct Quantization {
+    // 0 for none, 1 for scalar derived and 2 for scalar expounded.
+    style: u8,
+    guard: u32,
+    // Exponents and mantissas by subband.
+    steps: Vec<(u32, u32)>,
+}
+
+// Coding and quantization of the main header or of a tile, with the values for single
+// components first.
+#[derive(Debug, Clone, Default)]
+struct Params {
+    coding: Option<Coding>,
+    styles: HashMap<usize, Style>,
+    quantization: Option<Quantization>,
+    quantizations: HashMap<usize, Quantization>,
+}
+
+fn read_style(data: &[u8], custom: bool) -> Option<Style> {
+    let levels = *data.first()? as usize;
+    if levels > 32 {
+        return None;
+    }
+    let precincts = (0 ..= levels).map(|i| match data.get(5 + i).filter(|_| custom) {
+        Some(&x) => ((x & 15) as u32, (x >> 4) as u32),
+        None => (15, 15),
+    }).collect();
+    let (xcb, ycb) = (*data.get(1)? as u32 + 2, *data.get(2)? as u32 + 2);
+    if xcb > 10 || ycb > 10 || xcb + ycb > 12 {
+        return None;
+    }
+    Some(Style { levels, xcb, ycb, block: *data.get(3)?, reversible: *data.get(4)? == 1, precincts })
+}
+
+fn read_quantization(data: &[u8]) -> Option<Quantization> {
+    let flags = *data.first()?;
+    let style = flags & 31;
+    let steps = match style {
+        0 => data[1 ..].iter().map(|&x| ((x >> 3) as u32, 0)).collect(),
+        _ => data[1 ..].chunks_exact(2).map(|x| {
+            let value = u16::from_be_bytes([x[0], x[1]]) as u32;
+            (value >> 11, value & 0x7ff)
+        }).collect::<Vec<_>>(),
+    };
+    if steps.is_empty() {
+        return None;
+    }
+    Some(Quantization { style, guard: (flags >> 5) as u32, steps })
+}
+
+struct Codestream<'a> {
+    // The image area and the tiling on the reference grid.
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    tile_width: usize,
+    tile_height: usize,
+    tile_x0: usize,
+    tile_y0: usize,
+    components: Vec<Component>,
+    main: Params,
+    tiles: HashMap<usize, (Params, Vec<&'a [u8]>)>,
+    // A feature of the codestream that isn't supported.
+    unsupported: Option<&'static str>,
+}
+
+impl<'a> Codestream<'a> {
+    fn read(data: &'a [u8]) -> Option<Codestream<'a>> {
+        if u16_at(data, 0)? != 0xff4f {
+            return None;
+        }
+        let mut stream = Codestream {
+            x0: 0,
+            y0: 0,
+            x1: 0,
+            y1: 0,
+            tile_width: 0,
+            tile_height: 0,
+            tile_x0: 0,
+            tile_y0: 0,
+            components: Vec::new(),
+            main: Params::default(),
+            tiles: HashMap::new(),
+            unsupported: None,
+        };
+        let mut at = 2;
+        // The tile of the tile-part being read, and where the tile-part ends.
+        let mut tile: Option<(usize, usize)> = None;
+        while at + 2 <= data.len() {
+            let marker = u16_at(data, at)?;
+            at += 2;
+            match marker {
+                // SOD: the data of a tile-part up to its end.
+                0xff93 => {
+                    let (index, end) = tile.take()?;
+                    let end = end.min(data.len());
+                    stream.tiles.entry(index).or_default().1.push(data.get(at .. end)?);
+                    at = end;
+                    continue;
+                }
+                0xffd9 => break,
+                _ => {}
+            }
+            let length = u16_at(data, at)? as usize;
+            let segment = data.get(at + 2 .. at + length)?;
+            let params = match tile {
+                Some((index, _)) => &mut stream.tiles.entry(index).or_default().0,
+                None => &mut stream.main,
+            };
+            let component = |data: &[u8]| match stream.components.len() {
+                0 ..= 256 => Some((*data.first()? as usize, 1)),
+                _ => Some((u16_at(data, 0)? as usize, 2)),
+            };
+            match marker {
+                // SIZ
+                0xff51 => {
+                    let count = u16_at(segment, 34)? as usize;
+                    stream.x1 = u32_at(segment, 2)? as usize;
+                    stream.y1 = u32_at(segment, 6)? as usize;
+                    stream.x0 = u32_at(segment, 10)? as usize;
+                    stream.y0 = u32_at(segment, 14)? as usize;
+                    stream.tile_width = u32_at(segment, 18)? as usize;
+                    stream.tile_height = u32_at(segment, 22)? as usize;
+                    stream.tile_x0 = u32_at(segment, 26)? as usize;
+                    stream.tile_y0 = u32_at(segment, 30)? as usize;
+                    stream.components = (0 .. count).map(|i| {
+                        let x = segment.get(36 + i * 3 .. 39 + i * 3)?;
+                        Some(Component { precision: (x[0] & 0x7f) as u32 + 1, dx: x[1].max(1) as usize, dy: x[2].max(1) as usize })
+                    }).collect::<Option<_>>()?;
+                    if stream.components.iter().any(|x| x.precision > 32) {
+                        return None;
+                    }
+                }
+                // COD
+                0xff52 => {
+                    let flags = *segment.first()?;
+                    params.coding = Some(Coding {
+                        order: *segment.get(1)?,
+                        layers: u16_at(segment, 2)? as usize,
+                        mct: *segment.get(4)? == 1,
+                        sop: flags & 2 != 0,
+                        eph: flags & 4 != 0,
+                        style: read_style(segment.get(5 ..)?, flags & 1 != 0)?,
+                    });
+                }
+                // COC
+                0xff53 => {
+                    let (index, size) = component(segment)?;
+                    let style = read_style(segment.get(size + 1 ..)?, segment.get(size)? & 1 != 0)?;
+                    params.styles.insert(index, style);
+                }
+                // QCD
+                0xff5c => params.quantization = Some(read_quantization(segment)?),
+                // QCC
+                0xff5d => {
+                    let (index, size) = component(segment)?;
+                    params.quantizations.insert(index, read_quantization(segment.get(size ..)?)?);
+                }
+                // PPM and PPT
+                0xff60 | 0xff61 => stream.unsupported = Some("packed packet headers"),
+                // RGN, shifting the region by the last byte.
+                0xff5e if segment.last().is_some_and(|&x| x > 0) => stream.unsupported = Some("region of interest shifts"),
+                // SOT: the tile index and length of the tile-part.
+                0xff90 => {
+                    let length = u32_at(segment, 2)? as usize;
+                    let end = if length == 0 { usize::MAX } else { at - 2 + length };
+                    tile = Some((u16_at(segment, 0)? as usize, end));
+                }
+                _ => {}
+            }
+            at += length;
+        }
+        if stream.components.is_empty() || stream.x1 <= stream.x0 || stream.y1 <= stream.y0 || stream.tile_width == 0 || stream.tile_height == 0 {
+            return None;
+        }
+        Some(stream)
+    }
+
+    // The coding style of a component of a tile, by precedence of the markers.
+    fn style(&self, tile: &Params, c: usize) -> Option<Style> {
+        let main = &self.main;
+        let style = tile.styles.get(&c).or(tile.coding.as_ref().map(|x| &x.style));
+        style.or(main.styles.get(&c)).or(main.coding.as_ref().map(|x| &x.style)).cloned()
+    }
+
+    fn quantization(&self, tile: &Params, c: usize) -> Option<Quantization> {
+        let main = &self.main;
+        let quantization = tile.quantizations.get(&c).or(tile.quantization.as_ref());
+        quantization.or(main.quantizations.get(&c)).or(main.quantization.as_ref()).cloned()
+    }
+}
+
+// A tag tree of values decoded as needed, from the root down to the leaves.
+struct TagTree {
+    // Widths of levels from the leaves up.
+    widths: Vec<usize>,
+    values: Vec<Vec<u32>>,
+    lows: Vec<Vec<u32>>,
+}
+
+impl TagTree {
+    fn new(mut width: usize, mut height: usize) -> TagTree {
+        let mut tree = TagTree { widths: Vec::new(), values: Vec::new(), lows: Vec::new() };
+        loop {
+            tree.widths.push(width);
+            tree.values.push(vec![u32::MAX; width * height]);
+            tree.lows.push(vec![0; width * height]);
+            if width <= 1 && height <= 1 {
+                break;
+            }
+            (width, height) = (width.div_ceil(2), height.div_ceil(2));
+        }
+        tree
+    }
+
+    // Decodes the leaf at (x, y) up to `threshold`, and whether its value is below it.
+    fn decode(&mut self, bits: &mut Bits, x: usize, y: usize, threshold: u32) -> Option<bool> {
+        let mut low = 0;
+        for level in (0 .. self.widths.len()).rev() {
+            let i = (y >> level) * self.widths[level] + (x >> level);
+            low = low.max(self.lows[level][i]);
+            while low < threshold && low < self.values[level][i] {
+                if bits.read(1)? == 1 {
+                    self.values[level][i] = low;
+                } else {
+                    low += 1;
+                }
+            }
+            self.lows[level][i] = low;
+        }
+        Some(self.values[0][y * self.widths[0] + x] < threshold)
+    }
+}
+
+// Bits of packet headers, where bytes after 0xff hold 7 bits.
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u32,
+    left: u32,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, n: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0 .. n {
+            if self.left == 0 {
+                self.left = if self.byte == 0xff { 7 } else { 8 };
+                self.byte = *self.data.get(self.position)? as u32;
+                self.position += 1;
+            }
+            self.left -= 1;
+            value = value << 1 | (self.byte >> self.left & 1);
+        }
+        Some(value)
+    }
+
+    fn align(&mut self) {
+        self.left = 0;
+        if self.byte == 0xff {
+            self.position += 1;
+            self.byte = 0;
+        }
+    }
+
+    // Skips the marker if it comes next.
+    fn skip_marker(&mut self, marker: u8, length: usize) {
+        if self.data.get(self.position .. self.position + 2) == Some(&[0xff, marker]) {
+            self.position += length;
+        }
+    }
+}
+
+// Raw bits of passes bypassing arithmetic coding.
+struct Raw<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    left: u32,
+}
+
+impl Raw<'_> {
+    fn read(&mut self) -> u8 {
+        if self.left == 0 {
+            self.left = if self.byte == 0xff { 7 } else { 8 };
+            self.byte = self.data.get(self.position).copied().unwrap_or(0xff);
+            self.position += 1;
+        }
+        self.left -= 1;
+        self.byte >> self.left & 1
+    }
+}
+
+enum Source<'a> {
+    Arithmetic(Decoder<'a>),
+    Raw(Raw<'a>),
+}
+
+impl Source<'_> {
+    fn bit(&mut self, contexts: &mut [Context; 19], label: usize) -> u8 {
+        match self {
+            Source::Arithmetic(decoder) => decoder.decode(&mut contexts[label]),
+            Source::Raw(raw) => raw.read(),
+        }
+    }
+}
+
+const LL: u8 = 0;
+const HL: u8 = 1;
+const LH: u8 = 2;
+const HH: u8 = 3;
+
+// Contexts of run lengths and of uniform bits, after those of zero coding, sign coding
+// and magnitude refinement.
+const RUN: usize = 17;
+const UNIFORM: usize = 18;
+
+const SIGNIFICANT: u8 = 1;
+const NEGATIVE: u8 = 2;
+const VISITED: u8 = 4;
+const REFINED: u8 = 8;
+
+fn initial_contexts() -> [Context; 19] {
+    let mut contexts = [0; 19];
+    contexts[0] = 4 << 1;
+    contexts[RUN] = 3 << 1;
+    contexts[UNIFORM] = 46 << 1;
+    contexts
+}
+
+// The embedded block coder decoding the bit planes of a code-block.
+struct Block {
+    width: usize,
+    height: usize,
+    kind: u8,
+    causal: bool,
+    // Flags of coefficients, with a border of one coefficient.
+    flags: Vec<u8>,
+    magnitudes: Vec<u32>,
+    // Bit planes decoded, counting those known to be zero.
+    planes: Vec<u8>,
+    contexts: [Context; 19],
+}
+
+impl Block {
+    fn new(width: usize, height: usize, kind: u8, causal: bool, zero_planes: u8) -> Block {
+        Block {
+            width,
+            height,
+            kind,
+            causal,
+            flags: vec![0; (width + 2) * (height + 2)],
+            magnitudes: vec![0; width * height],
+            planes: vec![zero_planes; width * height],
+            contexts: initial_contexts(),
+        }
+    }
+
+    fn flag(&self, x: usize, y: usize, dx: isize, dy: isize) -> u8 {
+        // Vertically causal contexts leave out the stripe below.
+        if dy == 1 && self.causal && y % 4 == 3 {
+            return 0;
+        }
+        let i = (y as isize + 1 + dy) as usize * (self.width + 2) + (x as isize + 1 + dx) as usize;
+        self.flags[i]
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + 1) * (self.width + 2) + x + 1
+    }
+
+    fn zero_context(&self, x: usize, y: usize) -> usize {
+        let s = |dx, dy| (self.flag(x, y, dx, dy) & SIGNIFICANT) as u32;
+        let (mut h, mut v) = (s(-1, 0) + s(1, 0), s(0, -1) + s(0, 1));
+        let d = s(-1, -1) + s(1, -1) + s(-1, 1) + s(1, 1);
+        if self.kind == HH {
+            return match (d, h + v) {
+                (0, x) => x.min(2) as usize,
+                (1, x) => 3 + x.min(2) as usize,
+                (2, 0) => 6,
+                (2, _) => 7,
+                _ => 8,
+            };
+        }
+        if self.kind == HL {
+            (h, v) = (v, h);
+        }
+        match (h, v, d) {
+            (2, _, _) => 8,
+            (1, 1 ..= 2, _) => 7,
+            (1, 0, 1 ..) => 6,
+            (1, 0, 0) => 5,
+            (0, 2, _) => 4,
+            (0, 1, _) => 3,
+            (0, 0, 2 ..) => 2,
+            (0, 0, 1) => 1,
+            _ => 0,
+        }
+    }
+
+    fn decode_sign(&mut self, source: &mut Source, x: usize, y: usize) -> u8 {
+        let s = |dx, dy| match self.flag(x, y, dx, dy) {
+            f if f & SIGNIFICANT == 0 => 0,
+            f if f & NEGATIVE != 0 => -1,
+            _ => 1,
+        };
+        let h = (s(-1, 0) + s(1, 0)).clamp(-1, 1);
+        let v = (s(0, -1) + s(0, 1)).clamp(-1, 1);
+        let (label, xor) = match (h, v) {
+            (1, 1) => (13, 0),
+            (1, 0) => (12, 0),
+            (1, -1) => (11, 0),
+            (0, 1) => (10, 0),
+            (0, 0) => (9, 0),
+            (0, -1) => (10, 1),
+            (-1, 1) => (11, 1),
+            (-1, 0) => (12, 1),
+            _ => (13, 1),
+        };
+        match source {
+            Source::Raw(raw) => raw.read(),
+            Source::Arithmetic(decoder) => decoder.decode(&mut self.contexts[label]) ^ xor,
+        }
+    }
+
+    // Makes the coefficient significant, decoding its sign.
+    fn significant(&mut self, source: &mut Source, x: usize, y: usize) {
+        let sign = self.decode_sign(source, x, y);
+        let i = self.index(x, y);
+        self.flags[i] |= SIGNIFICANT | if sign == 1 { NEGATIVE } else { 0 };
+        self.magnitudes[y * self.width + x] = 1;
+    }
+
+    // Coefficients in stripe order: stripes of four rows, column by column.
+    fn stripes(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = (self.width, self.height);
+        (0 .. height).step_by(4).flat_map(move |y0| (0 .. width).flat_map(move |x| (y0 .. (y0 + 4).min(height)).map(move |y| (x, y))))
+    }
+
+    fn significance_pass(&mut self, source: &mut Source) {
+        for (x, y) in self.stripes() {
+            let i = self.index(x, y);
+            if self.flags[i] & SIGNIFICANT != 0 {
+                continue;
+            }
+            let label = self.zero_context(x, y);
+            if label == 0 {
+                continue;
+            }
+            if source.bit(&mut self.contexts, label) == 1 {
+                self.significant(source, x, y);
+            }
+            self.flags[i] |= VISITED;
+            self.planes[y * self.width + x] += 1;
+        }
+    }
+
+    fn refinement_pass(&mut self, source: &mut Source) {
+        for (x, y) in self.stripes() {
+            let i = self.index(x, y);
+            if self.flags[i] & (SIGNIFICANT | VISITED) != SIGNIFICANT {
+                continue;
+            }
+            let label = match self.flags[i] & REFINED != 0 {
+                true => 16,
+                false if self.neighbors(x, y) == 0 => 14,
+                false => 15,
+            };
+            let bit = source.bit(&mut self.contexts, label) as u32;
+            let j = y * self.width + x;
+            self.magnitudes[j] = self.magnitudes[j] << 1 | bit;
+            self.planes[j] += 1;
+            self.flags[i] |= REFINED;
+        }
+    }
+
+    fn neighbors(&self, x: usize, y: usize) -> u8 {
+        [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].iter().map(|&(dx, dy)| self.flag(x, y, dx, dy) & SIGNIFICANT).sum()
+    }
+
+    fn cleanup_pass(&mut self, source: &mut Source, segmentation: bool) {
+        for y0 in (0 .. self.height).step_by(4) {
+            for x in 0 .. self.width {
+                let rows = (y0 + 4).min(self.height);
+                let mut y = y0;
+                // Runs of four coefficients without significant neighbors are coded at once.
+                let run = rows == y0 + 4 && (y0 .. rows).all(|y| self.flags[self.index(x, y)] & (SIGNIFICANT | VISITED) == 0 && self.zero_context(x, y) == 0);
+                if run {
+                    if source.bit(&mut self.contexts, RUN) == 0 {
+                        for y in y0 .. rows {
+                            self.planes[y * self.width + x] += 1;
+                        }
+                        continue;
+                    }
+                    let skip = (source.bit(&mut self.contexts, UNIFORM) << 1 | source.bit(&mut self.contexts, UNIFORM)) as usize;
+                    for y in y0 .. y0 + skip {
+                        self.planes[y * self.width + x] += 1;
+                    }
+                    y = y0 + skip;
+                    self.significant(source, x, y);
+                    self.planes[y * self.width + x] += 1;
+                    y += 1;
+                }
+                for y in y .. rows {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (SIGNIFICANT | VISITED) == 0 {
+                        let label = self.zero_context(x, y);
+                        if source.bit(&mut self.contexts, label) == 1 {
+                            self.significant(source, x, y);
+                        }
+                        self.planes[y * self.width + x] += 1;
+                    }
+                }
+            }
+        }
+        if segmentation {
+            for _ in 0 .. 4 {
+                source.bit(&mut self.contexts, UNIFORM);
+            }
+        }
+        for flag in self.flags.iter_mut() {
+            *flag &= !VISITED;
+        }
+    }
+
+    // Decodes the coding passes of the codeword segments, each with its pass count.
+    fn decode(&mut self, segments: &[(Vec<u8>, usize)], style: u8) {
+        let mut pass = 0;
+        for (data, passes) in segments {
+            let mut source = if style & 1 != 0 && pass >= 10 && pass % 3 != 0 {
+                Source::Raw(Raw { data, position: 0, byte: 0, left: 0 })
+            } else {
+                Source::Arithmetic(Decoder::new(data))
+            };
+            for _ in 0 .. *passes {
+                match pass % 3 {
+                    0 => self.cleanup_pass(&mut source, style & 32 != 0),
+                    1 => self.significance_pass(&mut source),
+                    _ => self.refinement_pass(&mut source),
+                }
+                if style & 2 != 0 {
+                    self.contexts = initial_contexts();
+                }
+                pass += 1;
+            }
+        }
+    }
+}
+
+// Whether a codeword segment ends with the given pass, for a code-block style.
+fn segment_ends(style: u8, pass: usize) -> bool {
+    if style & 4 != 0 {
+        return true;
+    }
+    style & 1 != 0 && pass >= 9 && (pass - 9) % 3 != 1
+}
+
+// The code-blocks of a subband in a precinct, with their tag trees.
+struct PrecinctBand {
+    blocks: Vec<usize>,
+    x0: usize,
+    y0: usize,
+    inclusion: TagTree,
+    zero_planes: TagTree,
+}
+
+struct CodeBlock {
+    // Indices in the code-block grid of the subband, and the area in the subband.
+    column: usize,
+    row: usize,
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    included: bool,
+    zero_planes: u32,
+    lblock: u32,
+    passes: usize,
+    segments: Vec<(Vec<u8>, usize)>,
+}
+
+struct Band {
+    kind: u8,
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    blocks: Vec<CodeBlock>,
+    precincts: Vec<PrecinctBand>,
+    // Bit planes, and the quantization step.
+    planes: u32,
+    step: f32,
+}
+
+struct Resolution {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    // Precinct size exponents and the precinct grid.
+    ppx: u32,
+    ppy: u32,
+    columns: usize,
+    rows: usize,
+    bands: Vec<Band>,
+}
+
+struct TileComponent {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    style: Style,
+    resolutions: Vec<Resolution>,
+}
+
+impl TileComponent {
+    fn new(component: &Component, style: Style, quantization: &Quantization, (tx0, ty0, tx1, ty1): (usize, usize, usize, usize)) -> Option<TileComponent> {
+        let (x0, y0) = (tx0.div_ceil(component.dx), ty0.div_ceil(component.dy));
+        let (x1, y1) = (tx1.div_ceil(component.dx), ty1.div_ceil(component.dy));
+        let levels = style.levels;
+        let mut resolutions = Vec::new();
+        for r in 0 ..= levels {
+            let scale = 1 << (levels - r);
+            let (ppx, ppy) = style.precincts[r];
+            let resolution_x0 = x0.div_ceil(scale);
+            let resolution_y0 = y0.div_ceil(scale);
+            let resolution_x1 = x1.div_ceil(scale);
+            let resolution_y1 = y1.div_ceil(scale);
+            let (columns, rows) = if resolution_x1 > resolution_x0 && resolution_y1 > resolution_y0 {
+                (resolution_x1.div_ceil(1 << ppx) - (resolution_x0 >> ppx), resolution_y1.div_ceil(1 << ppy) - (resolution_y0 >> ppy))
+            } else {
+                (0, 0)
+            };
+            // Code-blocks don't cross precincts, whose size in subbands is halved above
+            // the lowest resolution.
+            let shift = (r > 0) as u32;
+            let (xcb, ycb) = (style.xcb.min(ppx.saturating_sub(shift)), style.ycb.min(ppy.saturating_sub(shift)));
+            let kinds: &[u8] = if r == 0 { &[LL] } else { &[HL, LH, HH] };
+            let mut bands = Vec::new();
+            for &kind in kinds {
+                let (xo, yo) = ((kind & 1) as i64, (kind >> 1) as i64);
+                let band_scale = if r == 0 { scale as i64 } else { 2 * scale as i64 };
+                let coordinate = |x: usize, o: i64| ceil_div(x as i64 * 2 - o * band_scale, 2 * band_scale) as usize;
+                let (bx0, by0, bx1, by1) = (coordinate(x0, xo), coordinate(y0, yo), coordinate(x1, xo), coordinate(y1, yo));
+                // The step of quantization and the bit planes of the subband.
+                let index = if r == 0 { 0 } else { 3 * (r - 1) + kind as usize };
+                let (exponent, mantissa) = match quantization.style {
+                    1 => {
+                        let (exponent, mantissa) = quantization.steps[0];
+                        (exponent.checked_sub(r.saturating_sub(1) as u32)?, mantissa)
+                    }
+                    _ => *quantization.steps.get(index)?,
+                };
+                let gain = [0, 1, 1, 2][kind as usize];
+                let step = if style.reversible {
+                    1.
+                } else {
+                    2f32.powi(component.precision as i32 + gain - exponent as i32) * (1. + mantissa as f32 / 2048.)
+                };
+                let mut band = Band { kind, x0: bx0, y0: by0, x1: bx1, y1: by1, blocks: Vec::new(), precincts: Vec::new(), planes: (quantization.guard + exponent).saturating_sub(1), step };
+                let (precinct_width, precinct_height) = (1 << (ppx - shift.min(ppx)), 1 << (ppy - shift.min(ppy)));
+                let mut grid = vec![Vec::new(); columns * rows];
+                if bx1 > bx0 && by1 > by0 {
+                    for row in by0 >> ycb .. by1.div_ceil(1 << ycb) {
+                        for column in bx0 >> xcb .. bx1.div_ceil(1 << xcb) {
+                            let block = CodeBlock {
+                                column,
+                                row,
+                                x0: (column << xcb).max(bx0),
+                                y0: (row << ycb).max(by0),
+                                x1: ((column + 1) << xcb).min(bx1),
+                                y1: ((row + 1) << ycb).min(by1),
+                                included: false,
+                                zero_planes: 0,
+                                lblock: 3,
+                                passes: 0,
+                                segments: Vec::new(),
+                            };
+                            let px = (block.x0 / precinct_width).checked_sub(resolution_x0 >> ppx)?;
+                            let py = (block.y0 / precinct_height).checked_sub(resolution_y0 >> ppy)?;
+                            grid.get_mut(py * columns + px)?.push(band.blocks.len());
+                            band.blocks.push(block);
+                        }
+                    }
+                }
+                band.precincts = grid.into_iter().map(|blocks| {
+                    let (x0, y0) = blocks.first().map_or((0, 0), |&i| (band.blocks[i].column, band.blocks[i].row));
+                    let (x1, y1) = blocks.last().map_or((0, 0), |&i| (band.blocks[i].column + 1, band.blocks[i].row + 1));
+                    PrecinctBand { blocks, x0, y0, inclusion: TagTree::new(x1 - x0, y1 - y0), zero_planes: TagTree::new(x1 - x0, y1 - y0) }
+                }).collect();
+                bands.push(band);
+            }
+            resolutions.push(Resolution { x0: resolution_x0, y0: resolution_y0, x1: resolution_x1, y1: resolution_y1, ppx, ppy, columns, rows, bands });
+        }
+        Some(TileComponent { x0, y0, x1, y1, style, resolutions })
+    }
+
+    // Reads the header and body of the packet of a precinct for a layer from `bits`.
+    fn read_packet(&mut self, bits: &mut Bits, r: usize, precinct: usize, layer: usize, eph: bool) -> Option<()> {
+        let block_style = self.style.block;
+        let resolution = &mut self.resolutions[r];
+        if bits.read(1)? == 0 {
+            bits.align();
+            if eph {
+                bits.skip_marker(0x92, 2);
+            }
+            return Some(());
+        }
+        // Code-blocks with new passes, and the lengths of their new segments.
+        let mut included = Vec::new();
+        for (b, band) in resolution.bands.iter_mut().enumerate() {
+            let Some(area) = band.precincts.get_mut(precinct) else { continue };
+            for &i in &area.blocks {
+                let block = &mut band.blocks[i];
+                let (x, y) = (block.column - area.x0, block.row - area.y0);
+                let first = !block.included;
+                let inclusion = if block.included { bits.read(1)? == 1 } else { area.inclusion.decode(bits, x, y, layer as u32 + 1)? };
+                if !inclusion {
+                    continue;
+                }
+                if first {
+                    block.included = true;
+                    let mut threshold = 1;
+                    while !area.zero_planes.decode(bits, x, y, threshold)? {
+                        threshold += 1;
+                        if threshold > 64 {
+                            return None;
+                        }
+                    }
+                    block.zero_planes = area.zero_planes.values[0][y * area.zero_planes.widths[0] + x];
+                }
+                let passes = match bits.read(1)? {
+                    0 => 1,
+                    _ => match bits.read(1)? {
+                        0 => 2,
+                        _ => match bits.read(2)? {
+                            x @ 0 ..= 2 => x as usize + 3,
+                            _ => match bits.read(5)? {
+                                x @ 0 ..= 30 => x as usize + 6,
+                                _ => bits.read(7)? as usize + 37,
+                            },
+                        },
+                    },
+                };
+                while bits.read(1)? == 1 {
+                    block.lblock += 1;
+                }
+                let mut pieces = Vec::new();
+                let mut start = block.passes;
+                for pass in block.passes .. block.passes + passes {
+                    if segment_ends(block_style, pass) || pass + 1 == block.passes + passes {
+                        let count = pass + 1 - start;
+                        let length = bits.read(block.lblock + count.ilog2())? as usize;
+                        pieces.push((count, length));
+                        start = pass + 1;
+                    }
+                }
+                included.push((b, i, pieces));
+            }
+        }
+        bits.align();
+        if eph {
+            bits.skip_marker(0x92, 2);
+        }
+        for (b, i, pieces) in included {
+            let block = &mut resolution.bands[b].blocks[i];
+            for (count, length) in pieces {
+                let data = bits.data.get(bits.position .. bits.position + length)?;
+                bits.position += length;
+                match block.segments.last_mut() {
+                    Some((segment, passes)) if !segment_ends(block_style, block.passes - 1) => {
+                        segment.extend(data);
+                        *passes += count;
+                    }
+                    _ => block.segments.push((data.to_vec(), count)),
+                }
+                block.passes += count;
+            }
+        }
+        Some(())
+    }
+
+    // Decodes the code-blocks and transforms the subbands back to samples.
+    fn samples(&self) -> Vec<f32> {
+        let mut low = Vec::new();
+        let mut width = 0;
+        for resolution in &self.resolutions {
+            let mut bands = resolution.bands.iter().map(|band| self.band(band));
+            if resolution.bands[0].kind == LL {
+                low = bands.next().unwrap_or_default();
+            } else {
+                let (hl, lh, hh) = (bands.next().unwrap_or_default(), bands.next().unwrap_or_default(), bands.next().unwrap_or_default());
+                low = synthesize(&low, &hl, &lh, &hh, width, resolution, self.style.reversible);
+            }
+            width = resolution.x1 - resolution.x0;
+        }
+        low
+    }
+
+    fn band(&self, band: &Band) -> Vec<f32> {
+        let width = band.x1 - band.x0;
+        let mut coefficients = vec![0.; width * (band.y1 - band.y0)];
+        let style = self.style.block;
+        for block in &band.blocks {
+            if block.segments.is_empty() || block.zero_planes > band.planes {
+                continue;
+            }
+            let (w, h) = (block.x1 - block.x0, block.y1 - block.y0);
+            let mut decoder = Block::new(w, h, band.kind, style & 8 != 0, block.zero_planes as u8);
+            // Passes beyond the bit planes of the subband are left out.
+            let limit = (band.planes - block.zero_planes) as usize * 3;
+            let mut segments = Vec::new();
+            let mut count = 0;
+            for (data, passes) in &block.segments {
+                let passes = (*passes).min(limit.saturating_sub(count));
+                segments.push((data.clone(), passes));
+                count += passes;
+            }
+            decoder.decode(&segments, style);
+            let half = if self.style.reversible { 0. } else { 0.5 };
+            for y in 0 .. h {
+                for x in 0 .. w {
+                    let magnitude = decoder.magnitudes[y * w + x];
+                    if magnitude == 0 {
+                        continue;
+                    }
+                    let shift = band.planes as i32 - decoder.planes[y * w + x] as i32;
+                    let mut value = (magnitude as f32 + half) * band.step * 2f32.powi(shift);
+                    if decoder.flags[decoder.index(x, y)] & NEGATIVE != 0 {
+                        value = -value;
+                    }
+                    coefficients[(block.y0 - band.y0 + y) * width + block.x0 - band.x0 + x] = value;
+                }
+            }
+        }
+        coefficients
+    }
+}
+
+// Rebuilds a resolution from the lower one and the three subbands of its details.
+fn synthesize(low: &[f32], hl: &[f32], lh: &[f32], hh: &[f32], low_width: usize, resolution: &Resolution, reversible: bool) -> Vec<f32> {
+    let (x0, y0) = (resolution.x0, resolution.y0);
+    let (width, height) = (resolution.x1 - x0, resolution.y1 - y0);
+    let high_width = resolution.x1 / 2 - x0 / 2;
+    let mut samples = vec![0.; width * height];
+    let get = |band: &[f32], columns: usize, x: usize, y: usize| band.get(y * columns + x).copied().unwrap_or(0.);
+    for y in 0 .. height {
+        let (row, low_row) = ((y0 + y) / 2 - y0 / 2, (y0 + y) / 2 - y0.div_ceil(2));
+        let even_row = (y0 + y) % 2 == 0;
+        for x in 0 .. width {
+            let (column, low_column) = ((x0 + x) / 2 - x0 / 2, (x0 + x) / 2 - x0.div_ceil(2));
+            samples[y * width + x] = match ((x0 + x) % 2 == 0, even_row) {
+                (true, true) => get(low, low_width, low_column, low_row),
+                (false, true) => get(hl, high_width, column, low_row),
+                (true, false) => get(lh, low_width, low_column, row),
+                (false, false) => get(hh, high_width, column, row),
+            };
+        }
+    }
+    let mut line = Vec::with_capacity(width.max(height));
+    for y in 0 .. height {
+        line.clear();
+        line.extend(&samples[y * width .. (y + 1) * width]);
+        lift(&mut line, x0 % 2 == 1, reversible);
+        samples[y * width .. (y + 1) * width].copy_from_slice(&line);
+    }
+    for x in 0 .. width {
+        line.clear();
+        line.extend((0 .. height).map(|y| samples[y * width + x]));
+        lift(&mut line, y0 % 2 == 1, reversible);
+        for (y, &value) in line.iter().enumerate() {
+            samples[y * width + x] = value;
+        }
+    }
+    samples
+}
+
+// The inverse 5/3 or 9/7 wavelet of interleaved samples, the first at an odd position
+// if `odd`, with symmetric extension at both ends.
+fn lift(x: &mut [f32], odd: bool, reversible: bool) {
+    let n = x.len();
+    if n == 1 {
+        if odd {
+            x[0] /= 2.;
+        }
+        return;
+    }
+    let reflect = |i: isize| {
+        let period = 2 * (n as isize - 1);
+        let i = i.rem_euclid(period);
+        (if i >= n as isize { period - i } else { i }) as usize
+    };
+    // Applies `f` to the samples of one parity with the sum of their neighbors.
+    let step = |x: &mut [f32], even: bool, f: &dyn Fn(f32, f32) -> f32| {
+        let first = (odd == even) as usize;
+        for i in (first .. n).step_by(2) {
+            let sum = x[reflect(i as isize - 1)] + x[reflect(i as isize + 1)];
+            x[i] = f(x[i], sum);
+        }
+    };
+    if reversible {
+        step(x, true, &|x, sum| x - ((sum + 2.) / 4.).floor());
+        step(x, false, &|x, sum| x + (sum / 2.).floor());
+    } else {
+        const ALPHA: f32 = -1.586_134_3;
+        const BETA: f32 = -0.052_980_117;
+        const GAMMA: f32 = 0.882_911_1;
+        const DELTA: f32 = 0.443_506_87;
+        const K: f32 = 1.230_174_1;
+        for (i, value) in x.iter_mut().enumerate() {
+            *value *= if (i % 2 == 0) != odd { K } else { 1. / K };
+        }
+        step(x, true, &|x, sum| x - DELTA * sum);
+        step(x, false, &|x, sum| x - GAMMA * sum);
+        step(x, true, &|x, sum| x - BETA * sum);
+        step(x, false, &|x, sum| x - ALPHA * sum);
+    }
+}
+
+// Decodes the packets of a tile and returns the samples of its components.
+fn decode_tile(stream: &Codestream, index: usize, params: &Params, parts: &[&[u8]]) -> Option<Vec<(TileComponent, Vec<f32>)>> {
+    let columns = (stream.x1 - stream.tile_x0).div_ceil(stream.tile_width);
+    let (p, q) = (index % columns, index / columns);
+    let tx0 = (stream.tile_x0 + p * stream.tile_width).max(stream.x0);
+    let ty0 = (stream.tile_y0 + q * stream.tile_height).max(stream.y0);
+    let tx1 = (stream.tile_x0 + (p + 1) * stream.tile_width).min(stream.x1);
+    let ty1 = (stream.tile_y0 + (q + 1) * stream.tile_height).min(stream.y1);
+    if tx1 <= tx0 || ty1 <= ty0 {
+        return None;
+    }
+    let coding = params.coding.as_ref().or(stream.main.coding.as_ref())?;
+    let mut components = stream.components.iter().enumerate().map(|(c, component)| {
+        let style = stream.style(params, c)?;
+        TileComponent::new(component, style, &stream.quantization(params, c)?, (tx0, ty0, tx1, ty1))
+    }).collect::<Option<Vec<_>>>()?;
+    // Packets as components, resolutions, precincts and layers, in progression order.
+    let mut packets = Vec::new();
+    for (c, tile) in components.iter().enumerate() {
+        let component = &stream.components[c];
+        let levels = tile.style.levels;
+        for (r, resolution) in tile.resolutions.iter().enumerate() {
+            for precinct in 0 .. resolution.columns * resolution.rows {
+                let (px, py) = (precinct % resolution.columns, precinct / resolution.columns);
+                // Where the precinct starts on the reference grid, within the tile.
+                let x = (((resolution.x0 >> resolution.ppx) + px) << (resolution.ppx as usize + levels - r)) * component.dx;
+                let y = (((resolution.y0 >> resolution.ppy) + py) << (resolution.ppy as usize + levels - r)) * component.dy;
+                let (x, y) = (x.max(tx0), y.max(ty0));
+                for layer in 0 .. coding.layers {
+                    let key = match coding.order {
+                        0 => [layer, r, c, precinct, 0],
+                        1 => [r, layer, c, precinct, 0],
+                        2 => [r, y, x, c, layer],
+                        3 => [y, x, c, r, layer],
+                        _ => [c, y, x, r, layer],
+                    };
+                    packets.push((key, c, r, precinct, layer));
+                }
+            }
+        }
+    }
+    packets.sort_by_key(|x| x.0);
+    let data = parts.concat();
+    let mut bits = Bits { data: &data, position: 0, byte: 0, left: 0 };
+    for (_, c, r, precinct, layer) in packets {
+        if bits.position >= data.len() {
+            break;
+        }
+        bits.left = 0;
+        if coding.sop {
+            bits.skip_marker(0x91, 6);
+        }
+        if components[c].read_packet(&mut bits, r, precinct, layer, coding.eph).is_none() {
+            break;
+        }
+    }
+    let mut samples = components.iter().map(|x| x.samples()).collect::<Vec<_>>();
+    // The inverse multiple component transformation.
+    if coding.mct && samples.len() >= 3 && samples[1].len() == samples[0].len() && samples[2].len() == samples[0].len() {
+        let reversible = components[0].style.reversible;
+        let (first, rest) = samples.split_at_mut(1);
+        let (second, third) = rest.split_at_mut(1);
+        for ((y, cb), cr) in first[0].iter_mut().zip(second[0].iter_mut()).zip(third[0].iter_mut()) {
+            let (r, g, b) = if reversible {
+                let g = *y - ((*cb + *cr) / 4.).floor();
+                (*cr + g, g, *cb + g)
+            } else {
+                (*y + 1.402 * *cr, *y - 0.344_136 * *cb - 0.714_136 * *cr, *y + 1.772 * *cb)
+            };
+            (*y, *cb, *cr) = (r, g, b);
+        }
+    }
+    Some(components.into_iter().zip(samples).collect())
+}
+
+pub fn decode(data: &[u8]) -> Result<Jpx, String> {
+    let (codestream, header) = if data.get(4 .. 8) == Some(b"jP  ") {
+        let boxes = boxes(data);
+        let header = boxes.iter().find(|x| x.0 == b"jp2h").map_or_else(Header::default, |x| read_header(x.1));
+        (boxes.iter().find(|x| x.0 == b"jp2c").ok_or("no codestream box")?.1, header)
+    } else {
+        (data, Header::default())
+    };
+    let stream = Codestream::read(codestream).ok_or("damaged codestream header")?;
+    if let Some(feature) = stream.unsupported {
+        return Err(format!("{feature} aren't supported"));
+    }
+    let (width, height) = (stream.x1 - stream.x0, stream.y1 - stream.y0);
+    if width.checked_mul(height).is_none_or(|x| x > MAX_SAMPLES) {
+        return Err(format!("image of {width} by {height} samples too large"));
+    }
+    // Samples of components in 8 bits, at the full size of the image.
+    let mut planes = vec![vec![0u8; width * height]; stream.components.len()];
+    let empty = (Params::default(), Vec::new());
+    let columns = (stream.x1 - stream.tile_x0).div_ceil(stream.tile_width);
+    let rows = (stream.y1 - stream.tile_y0).div_ceil(stream.tile_height);
+    for index in 0 .. columns * rows {
+        let (params, parts) = stream.tiles.get(&index).unwrap_or(&empty);
+        let Some(tile) = decode_tile(&stream, index, params, parts) else { continue };
+        for ((tile, samples), (plane, component)) in tile.iter().zip(planes.iter_mut().zip(&stream.components)) {
+            let max = ((1u64 << component.precision) - 1) as f32;
+            // Samples are level shifted, which maps signed samples to the same range.
+            let shift = (1u64 << (component.precision - 1)) as f32;
+            let tile_width = tile.x1 - tile.x0;
+            // Component samples cover dx by dy pixels of the reference grid.
+            for y in (tile.y0 * component.dy).max(stream.y0) .. (tile.y1 * component.dy).min(stream.y1) {
+                for x in (tile.x0 * component.dx).max(stream.x0) .. (tile.x1 * component.dx).min(stream.x1) {
+                    let (cx, cy) = ((x / component.dx).max(tile.x0) - tile.x0, (y / component.dy).max(tile.y0) - tile.y0);
+                    let value = samples.get(cy * tile_width + cx).copied().unwrap_or(0.) + shift;
+                    plane[(y - stream.y0) * width + x - stream.x0] = (value.round().clamp(0., max) * 255. / max).round() as u8;
+                }
+            }
+        }
+    }
+    // Palette columns and components mapped to channels.
+    if !header.palette.is_empty() {
+        let mapping = match header.mapping.is_empty() {
+            true => (0 .. header.palette.len()).map(|i| (0, Some(i))).collect(),
+            false => header.mapping.clone(),
+        };
+        planes = mapping.iter().map(|&(c, column)| {
+            let plane = planes.get(c)?;
+            Some(match column {
+                Some(column) => plane.iter().map(|&x| header.palette.get(column)?.get(x as usize).copied()).collect::<Option<_>>()?,
+                None => plane.clone(),
+            })
+        }).collect::<Option<_>>().ok_or("palette mapping out of range")?;
+    }
+    let channels = planes.len();
+    let mut pixels = (0 .. width * height).flat_map(|i| planes.iter().map(move |x| x[i])).collect::<Vec<_>>();
+    // sYCC samples are converted to RGB.
+    if header.space == Some(18) && channels >= 3 {
+        for pixel in pixels.chunks_mut(channels) {
+            let (y, cb, cr) = (pixel[0] as f32, pixel[1] as f32 - 128., pixel[2] as f32 - 128.);
+            let rgb = [y + 1.402 * cr, y - 0.344_136 * cb - 0.714_136 * cr, y + 1.772 * cb];
+            for (c, value) in pixel.iter_mut().zip(rgb) {
+                *c = value.round().clamp(0., 255.) as u8;
+            }
+        }
+    }
+    // Enumerated color spaces give the color channels: CMYK, sRGB, gray or sYCC.
+    let colors = match header.space {
+        Some(12) => 4,
+        Some(16 | 18) => 3,
+        Some(17) => 1,
+        _ => match channels {
+            1 | 2 => 1,
+            4 => 4,
+            _ => 3,
+        },
+    };
+    Ok(Jpx { width, height, channels, colors: colors.min(channels), pixels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mq::Encoder;
+
+    // The forward 5/3 wavelet, the first sample at an odd position if `odd`.
+    fn analyze(x: &mut [f32], odd: bool) {
+        let n = x.len() as isize;
+        let reflect = |i: isize| {
+            let i = i.rem_euclid(2 * (n - 1));
+            (if i >= n { 2 * (n - 1) - i } else { i }) as usize
+        };
+        for i in (odd as usize ^ 1 .. x.len()).step_by(2) {
+            x[i] -= ((x[reflect(i as isize - 1)] + x[reflect(i as isize + 1)]) / 2.).floor();
+        }
+        for i in (odd as usize .. x.len()).step_by(2) {
+            x[i] += ((x[reflect(i as isize - 1)] + x[reflect(i as isize + 1)] + 2.) / 4.).floor();
+        }
+    }
+
+    #[test]
+    fn test_lift() {
+        let samples = [12., -3., 40., 41., 7., 0., 255., 128., 9.];
+        for odd in [false, true] {
+            for n in 2 .. samples.len() {
+                let mut x = samples[.. n].to_vec();
+                analyze(&mut x, odd);
+                lift(&mut x, odd, true);
+                assert_eq!(x, &samples[.. n]);
+            }
+        }
+        // Details of zero leave the low pass samples, as the 9/7 wavelet keeps DC.
+        let mut x = [10., 0., 10., 0., 10.];
+        lift(&mut x, false, false);
+        assert!(x.iter().all(|&x| (x - 10.).abs() < 1e-4), "{x:?}");
+    }
+
+    // Packs bits given as a string of 0 and 1, padding the last byte with zeros.
+    fn pack(bits: &str) -> Vec<u8> {
+        let bits = bits.bytes().filter(|&x| x != b' ').map(|x| x - b'0').collect::<Vec<_>>();
+        bits.chunks(8).map(|x| x.iter().enumerate().fold(0, |a, (i, &b)| a | b << (7 - i))).collect()
+    }
+
+    #[test]
+    fn test_tag_tree() {
+        // Leaves 1 and 3 under a root of 1, as in the example of the standard.
+        let data = pack("0 11 001");
+        let mut bits = Bits { data: &data, position: 0, byte: 0, left: 0 };
+        let mut tree = TagTree::new(2, 1);
+        assert_eq!(tree.decode(&mut bits, 0, 0, 1), Some(false));
+        assert_eq!(tree.decode(&mut bits, 0, 0, 2), Some(true));
+        assert_eq!(tree.decode(&mut bits, 1, 0, 4), Some(true));
+        assert_eq!((tree.values[0][0], tree.values[0][1]), (1, 3));
+    }
+
+    // A codestream of one tile and one gray component of 8 bits with the 5/3 wavelet,
+    // one layer and one code-block per subband.
+    fn codestream(width: u8, height: u8, levels: u8, packets: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xff, 0x4f, 0xff, 0x51, 0, 41, 0, 0];
+        for value in [width as u32, height as u32, 0, 0, width as u32, height as u32, 0, 0] {
+            data.extend(value.to_be_bytes());
+        }
+        data.extend([0, 1, 7, 1, 1]);
+        data.extend([0xff, 0x52, 0, 12, 0, 0, 0, 1, 0, levels, 4, 4, 0, 1]);
+        let bands = 1 + 3 * levels;
+        data.extend([0xff, 0x5c, 0, 3 + bands, 0x20]);
+        data.extend((0 .. bands).map(|i| if i == 0 { 8 << 3 } else { 9 << 3 }));
+        data.extend([0xff, 0x90, 0, 10, 0, 0]);
+        data.extend((14 + packets.len() as u32).to_be_bytes());
+        data.extend([0, 1, 0xff, 0x93]);
+        data.extend(packets);
+        data.extend([0xff, 0xd9]);
+        data
+    }
+
+    #[test]
+    fn test_decode() {
+        // Empty packets leave every sample at the middle of the range.
+        let image = decode(&codestream(3, 2, 0, &[0])).unwrap();
+        assert_eq!(image, Jpx { width: 3, height: 2, channels: 1, colors: 1, pixels: vec![128; 6] });
+
+        // A coefficient of 72 in the lowest subband: 1001000 after a zero bit plane, of
+        // eight with one guard bit. The cleanup pass makes it significant and positive, and the
+        // magnitude refinement passes of six more bit planes follow.
+        let mut contexts = initial_contexts();
+        let mut encoder = Encoder::default();
+        encoder.encode(&mut contexts[0], 1);
+        encoder.encode(&mut contexts[9], 0);
+        for (i, bit) in [0, 0, 1, 0, 0, 0].into_iter().enumerate() {
+            encoder.encode(&mut contexts[if i == 0 { 14 } else { 16 }], bit);
+        }
+        let block = encoder.flush();
+        // Included at the first layer, one zero bit plane, 19 passes and the length.
+        let mut packets = pack(&format!("1 1 01 1111 01101 0 {:07b}", block.len()));
+        packets.extend(&block);
+        // The packet of the second resolution is empty.
+        packets.push(0);
+        let image = decode(&codestream(2, 2, 1, &packets)).unwrap();
+        assert_eq!(image.pixels, vec![200; 4]);
+
+        // Region of interest shifts fail the image.
+        let mut data = codestream(3, 2, 0, &[0]);
+        let at = data.windows(2).position(|x| x == [0xff, 0x90]).unwrap();
+        data.splice(at .. at, [0xff, 0x5e, 0, 5, 0, 0, 3]);
+        assert_eq!(decode(&data), Err("region of interest shifts aren't supported".into()));
+    }
+}
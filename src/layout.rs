@@ -0,0 +1,348 @@
+use super::operation::TextPaintingOperation;
+use super::parser::{Object, PDF};
+use super::text::get_page_texts;
+
+const ASCENT: f64 = 0.8;
+const DESCENT: f64 = 0.2;
+const WORD_GAP: f64 = 0.15;
+const LINE_GAP: f64 = 1.5;
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub x0: f64,
+    pub x1: f64,
+    pub y0: f64,
+    pub y1: f64,
+    pub baseline: f64,
+    pub font_size: f64,
+    pub spans: Vec<TextPaintingOperation>,
+    pub furniture: bool,
+}
+
+impl Line {
+    fn new(span: TextPaintingOperation) -> Line {
+        Line {
+            x0: span.x,
+            x1: span.x + span.width,
+            y0: span.y - DESCENT * span.font_size,
+            y1: span.y + ASCENT * span.font_size,
+            baseline: span.y,
+            font_size: span.font_size,
+            spans: vec![span],
+            furniture: false,
+        }
+    }
+
+    fn push(&mut self, span: TextPaintingOperation) {
+        self.x0 = self.x0.min(span.x);
+        self.x1 = self.x1.max(span.x + span.width);
+        self.y0 = self.y0.min(span.y - DESCENT * span.font_size);
+        self.y1 = self.y1.max(span.y + ASCENT * span.font_size);
+        if span.font_size > self.font_size {
+            self.font_size = span.font_size;
+            self.baseline = span.y;
+        }
+        self.spans.push(span);
+    }
+
+    fn vertical_overlap(&self, span: &TextPaintingOperation) -> f64 {
+        let (y0, y1) = (span.y - DESCENT * span.font_size, span.y + ASCENT * span.font_size);
+        let overlap = self.y1.min(y1) - self.y0.max(y0);
+        overlap / (self.y1 - self.y0).min(y1 - y0).max(f64::EPSILON)
+    }
+
+    pub fn text(&self) -> String {
+        let mut ret = String::new();
+        let mut prev: Option<&TextPaintingOperation> = None;
+        for span in &self.spans {
+            if let Some(prev) = prev {
+                let gap = span.x - (prev.x + prev.width);
+                let size = prev.font_size.min(span.font_size);
+                if gap > WORD_GAP * size && !ret.ends_with(' ') && !span.text.starts_with(' ') {
+                    ret.push(' ');
+                }
+            }
+            ret.push_str(&span.text);
+            prev = Some(span);
+        }
+        ret.trim().into()
+    }
+
+    pub fn words(&self) -> Vec<Word> {
+        let mut ret: Vec<Word> = Vec::new();
+        let mut current: Option<Word> = None;
+        let mut prev: Option<&TextPaintingOperation> = None;
+        for span in &self.spans {
+            if let Some(prev) = prev {
+                let gap = span.x - (prev.x + prev.width);
+                if gap > WORD_GAP * prev.font_size.min(span.font_size) {
+                    ret.extend(current.take());
+                }
+            }
+            for (i, c) in span.text.chars().enumerate() {
+                if c.is_whitespace() {
+                    ret.extend(current.take());
+                    continue;
+                }
+                let word = current.get_or_insert(Word {
+                    text: String::new(),
+                    bbox: (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+                });
+                word.extend(c, span.quad(i, i));
+            }
+            prev = Some(span);
+        }
+        ret.extend(current);
+        ret
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Word {
+    pub text: String,
+    pub bbox: (f64, f64, f64, f64),
+}
+
+impl Word {
+    fn extend(&mut self, c: char, quad: [(f64, f64); 4]) {
+        self.text.push(c);
+        for (x, y) in quad {
+            self.bbox = (self.bbox.0.min(x), self.bbox.1.min(y), self.bbox.2.max(x), self.bbox.3.max(y));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    pub lines: Vec<Line>,
+}
+
+impl Block {
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
+        self.lines.iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(x0, y0, x1, y1), l| (x0.min(l.x0), y0.min(l.y0), x1.max(l.x1), y1.max(l.y1)),
+        )
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.iter().map(Line::text).collect::<Vec<_>>().join("\n")
+    }
+}
+
+pub fn get_lines(spans: Vec<TextPaintingOperation>) -> Vec<Line> {
+    let (mut spans, others): (Vec<_>, Vec<_>) = spans.into_iter()
+        .filter(|x| !x.text.trim().is_empty())
+        .partition(|x| x.angle.abs() <= 0.1);
+    let mut ret: Vec<Line> = Vec::new();
+    let mut rotated: Vec<Line> = Vec::new();
+
+    for span in others {
+        match rotated.last_mut() {
+            Some(line) if line.spans[0].angle == span.angle => line.spans.push(span),
+            _ => rotated.push(Line::new(span)),
+        }
+    }
+
+    spans.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+    for span in spans {
+        let candidate = ret.iter_mut()
+            .filter(|l| {
+                let gap = span.x - l.x1;
+                gap < LINE_GAP * span.font_size.max(l.font_size) && gap > -0.5 * span.font_size
+            })
+            .map(|l| (l.vertical_overlap(&span), l))
+            .filter(|(overlap, _)| *overlap > 0.5)
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        match candidate {
+            Some((_, line)) => line.push(span),
+            None => ret.push(Line::new(span)),
+        }
+    }
+
+    ret.extend(rotated);
+    ret
+}
+
+fn sort_lines(lines: &mut [Line]) {
+    lines.sort_by(|a, b| b.baseline.total_cmp(&a.baseline).then(a.x0.total_cmp(&b.x0)));
+}
+
+fn median_font_size(lines: &[Line]) -> f64 {
+    let mut sizes = lines.iter().map(|x| x.font_size).collect::<Vec<_>>();
+    sizes.sort_by(f64::total_cmp);
+    sizes.get(sizes.len() / 2).cloned().unwrap_or(10.)
+}
+
+fn find_vertical_gap(lines: &[Line], min_gap: f64) -> Option<f64> {
+    let mut intervals = lines.iter().map(|x| (x.x0, x.x1)).collect::<Vec<_>>();
+    intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut best: Option<(f64, f64)> = None;
+    let mut end = intervals.first()?.1;
+    for &(x0, x1) in &intervals[1..] {
+        if x0 - end >= min_gap && best.is_none_or(|(w, _)| x0 - end > w) {
+            best = Some((x0 - end, (x0 + end) / 2.));
+        }
+        end = end.max(x1);
+    }
+    best.map(|x| x.1)
+}
+
+fn find_gutter(lines: &[Line], min_gap: f64) -> Option<(f64, f64)> {
+    let x0 = lines.iter().map(|x| x.x0).fold(f64::MAX, f64::min);
+    let x1 = lines.iter().map(|x| x.x1).fold(f64::MIN, f64::max);
+    let width = (x1 - x0).ceil() as usize;
+    if width == 0 || width > 100_000 {
+        return None;
+    }
+    let mut coverage = vec![0usize; width + 1];
+    for line in lines {
+        let start = (line.x0 - x0).floor() as usize;
+        let end = ((line.x1 - x0).ceil() as usize).min(width);
+        for c in &mut coverage[start..end] {
+            *c += 1;
+        }
+    }
+    let peak = *coverage.iter().max()?;
+    let threshold = peak / 4;
+
+    let mut best: Option<(usize, usize, usize)> = None;
+    let mut i = 0;
+    while i < coverage.len() {
+        if coverage[i] > threshold {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < coverage.len() && coverage[i] <= threshold {
+            i += 1;
+        }
+        if start == 0 || i == coverage.len() || ((i - start) as f64) < min_gap {
+            continue;
+        }
+        let low = *coverage[start..i].iter().min().unwrap();
+        if best.is_none_or(|(l, s, e)| low < l || (low == l && i - start > e - s)) {
+            best = Some((low, start, i));
+        }
+    }
+    let (_, start, end) = best?;
+    let (g0, g1) = (x0 + start as f64, x0 + end as f64);
+    let left = lines.iter().filter(|x| x.x1 <= g0).count();
+    let right = lines.iter().filter(|x| x.x0 >= g1).count();
+    if left < 2 || right < 2 {
+        return None;
+    }
+    Some((g0, g1))
+}
+
+fn split_blocks(mut lines: Vec<Line>, out: &mut Vec<Block>) {
+    sort_lines(&mut lines);
+    let mut block = Block::default();
+    for line in lines {
+        if let Some(prev) = block.lines.last() {
+            if prev.baseline - line.baseline > 2. * prev.font_size.max(line.font_size) {
+                out.push(std::mem::take(&mut block));
+            }
+        }
+        block.lines.push(line);
+    }
+    if !block.lines.is_empty() {
+        out.push(block);
+    }
+}
+
+fn segment(lines: Vec<Line>, out: &mut Vec<Block>, depth: usize) {
+    if lines.len() <= 1 || depth > 32 {
+        split_blocks(lines, out);
+        return;
+    }
+
+    let min_gap = median_font_size(&lines);
+
+    if let Some(x) = find_vertical_gap(&lines, min_gap) {
+        let (left, right) = lines.into_iter().partition(|l| l.x1 <= x);
+        segment(left, out, depth + 1);
+        segment(right, out, depth + 1);
+        return;
+    }
+
+    if let Some((g0, g1)) = find_gutter(&lines, min_gap) {
+        let mut lines = lines;
+        sort_lines(&mut lines);
+        let mut band = Vec::new();
+        for line in lines {
+            if line.x0 < g1 && line.x1 > g0 {
+                segment(std::mem::take(&mut band), out, depth + 1);
+                out.push(Block { lines: vec![line] });
+            } else {
+                band.push(line);
+            }
+        }
+        segment(band, out, depth + 1);
+        return;
+    }
+
+    split_blocks(lines, out);
+}
+
+pub fn reading_order(lines: Vec<Line>) -> Vec<Block> {
+    let (horizontal, rotated): (Vec<_>, Vec<_>) = lines.into_iter().partition(|l| l.spans[0].angle.abs() <= 0.1);
+    let mut ret = Vec::new();
+    segment(horizontal, &mut ret, 0);
+    for line in rotated {
+        ret.push(Block { lines: vec![line] });
+    }
+    ret
+}
+
+pub fn get_page_blocks(pdf: &PDF, page: &Object) -> Vec<Block> {
+    reading_order(get_lines(get_page_texts(pdf, page)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(x: f64, y: f64, text: &str) -> TextPaintingOperation {
+        TextPaintingOperation::test_span(x, y, text)
+    }
+
+    fn texts(blocks: &[Block]) -> Vec<String> {
+        blocks.iter().flat_map(|b| b.lines.iter().map(Line::text)).collect()
+    }
+
+    #[test]
+    fn test_words() {
+        let lines = get_lines(vec![span(100., 700., "Attention"), span(148., 700., "Is"), span(162., 700., "All"), span(300., 700., "Far")]);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text(), "Attention Is All");
+        assert_eq!(lines[1].text(), "Far");
+        let words = lines[0].words();
+        assert_eq!(words.iter().map(|x| x.text.as_str()).collect::<Vec<_>>(), vec!["Attention", "Is", "All"]);
+        assert_eq!(words[1].bbox, (148., 698., 158., 708.));
+    }
+
+    #[test]
+    fn test_two_columns() {
+        let mut spans = vec![span(150., 760., "A full width title spanning both columns")];
+        for i in 0 .. 5 {
+            let y = 700. - i as f64 * 12.;
+            spans.push(span(50., y, &format!("left column line {i}")));
+            spans.push(span(320., y, &format!("right column line {i}")));
+        }
+        spans.push(span(50., 600., "a full width footer line that crosses the gutter between columns"));
+        for i in 0 .. 3 {
+            let y = 560. - i as f64 * 12.;
+            spans.push(span(50., y, &format!("left again {i}")));
+            spans.push(span(320., y, &format!("right again {i}")));
+        }
+        let order = texts(&reading_order(get_lines(spans)));
+        assert_eq!(order[0], "A full width title spanning both columns");
+        assert_eq!(&order[1..6], &(0 .. 5).map(|i| format!("left column line {i}")).collect::<Vec<_>>()[..]);
+        assert_eq!(&order[6..11], &(0 .. 5).map(|i| format!("right column line {i}")).collect::<Vec<_>>()[..]);
+        assert!(order[11].starts_with("a full width footer"));
+        assert_eq!(order[12], "left again 0");
+        assert_eq!(order[15], "right again 0");
+    }
+}
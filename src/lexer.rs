@@ -189,7 +189,7 @@ impl State {
                     return 1;
                 }
                 Ctx::Key(_, key_content) => {
-                    token.replace(Token::Key(key_content));
+                    token.replace(Token::Key(decode_name(key_content)));
                     return 0;
                 }
                 Ctx::None => {}
@@ -450,26 +450,6 @@ impl State {
         self.index += size;
         size
     }
-    pub fn get_flate_stream(&mut self, size: usize, buf: &mut Vec<u8>) -> usize {
-        if self.store.len() < size + self.index {
-            return 0;
-        }
-
-        fn decode(data: &[u8], buf: &mut Vec<u8>) -> usize {
-            use std::io::Read;
-            use flate2::bufread::ZlibDecoder;
-        
-            let mut decoder = ZlibDecoder::new(data);
-            
-            decoder.read_to_end(buf).unwrap()
-        }
-
-        decode(&self.store[..][self.index .. self.index + size], buf);
-
-        self.index += size;
-
-        size        
-    }
     pub fn get_ascii_line(&mut self) -> Option<String> {
         let mut i = 0;
         let mut ret = String::new();
@@ -506,6 +486,29 @@ impl State {
     }
 }
 
+fn decode_name(name: String) -> String {
+    if !name.contains('#') {
+        return name;
+    }
+    let mut ret = String::new();
+    let mut rest = &*name;
+    while let Some(pos) = rest.find('#') {
+        ret.push_str(&rest[..pos]);
+        match rest.get(pos + 1 .. pos + 3).and_then(|x| u8::from_str_radix(x, 16).ok()) {
+            Some(byte) => {
+                ret.push(byte as char);
+                rest = &rest[pos + 3..];
+            }
+            None => {
+                ret.push('#');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
+
 fn parse_number(src: &[u8]) -> Option<(usize, f64)> {
     let len = src.iter().position(|x| x != &b'.' && x != &b'-' && !x.is_ascii_digit()).unwrap_or(src.len());
     src[..len].iter().map(|&x| x as char).collect::<String>().parse().ok().map(|x| (len, x))
@@ -578,6 +581,7 @@ mod tests {
         assert_eq!(&state.next().unwrap(), "a");
         assert_eq!(&state.next().unwrap(), "b");
         assert_eq!(&parse(b"/abc").get_next_token().unwrap(), "abc");
+        assert_eq!(&parse(b"/Identity#2DH ").get_next_token().unwrap(), "Identity-H");
         assert_eq!(&parse(b"(I love you)").get_next_token().unwrap(), "I love you");
     }
     #[test]
@@ -8,3 +8,38 @@ pub use parser::{Value, Object, PDF};
 
 pub mod operation;
 pub mod text;
+pub mod encoding;
+pub mod font;
+pub mod layout;
+pub mod path;
+pub mod content;
+pub mod furniture;
+pub mod table;
+pub mod search;
+pub mod model;
+pub mod hocr;
+pub mod alto;
+pub mod markdown;
+pub mod html;
+pub mod cff;
+pub mod otf;
+pub mod embedded;
+pub mod truetype;
+pub mod program;
+pub mod lint;
+pub mod function;
+pub mod color;
+pub mod pattern;
+pub mod image;
+pub mod filter;
+pub mod ccitt;
+#[cfg(any(feature = "jbig2", feature = "jpx"))]
+pub mod mq;
+#[cfg(feature = "jbig2")]
+pub mod jbig2;
+#[cfg(feature = "jpx")]
+pub mod jpx;
+pub mod svg;
+pub mod transparency;
+pub mod render;
+pub mod util;
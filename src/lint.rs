@@ -0,0 +1,17 @@
+use super::parser::{Object, PDF};
+use postscript::parser::type_check::{check, Diagnostic, ResourceNames};
+
+pub fn page_resource_names(pdf: &PDF, page: &Object) -> ResourceNames {
+    let mut ret = ResourceNames::new();
+    for (category, value) in pdf.get_page_resources(page) {
+        if let Some(dict) = pdf.resolve_dict(value) {
+            ret.insert(category.clone(), dict.keys().cloned().collect());
+        }
+    }
+    ret
+}
+
+// Diagnostics for the page's content streams, with offsets into their concatenation.
+pub fn lint_page(pdf: &PDF, page: &Object) -> Vec<Diagnostic> {
+    check(&pdf.get_page_contents(page), Some(&page_resource_names(pdf, page)))
+}
@@ -11,6 +11,37 @@ fn main() {
 
     let options = cli::parse_options();
 
+    if let Some(("to-markdown", sub)) = options.subcommand() {
+        let file_path = sub.get_one::<String>("FILE").expect("Require file name");
+        let content = std::fs::read(file_path).unwrap();
+        let pdf = parse(&content).unwrap();
+        print!("{}", pdf_parser::markdown::to_markdown(&pdf));
+        return;
+    }
+
+    if let Some(("extract-images", sub)) = options.subcommand() {
+        let file_path = sub.get_one::<String>("FILE").expect("Require file name");
+        let dir = sub.get_one::<String>("DIR").expect("Require directory");
+        let page = sub.get_one::<String>("page").and_then(|x| x.parse::<usize>().ok());
+        let content = std::fs::read(file_path).unwrap();
+        let pdf = parse(&content).unwrap();
+        std::fs::create_dir_all(dir).unwrap();
+        for (i, object) in pdf.get_page_list().into_iter().enumerate() {
+            if page.is_some_and(|n| n != i + 1) {
+                continue;
+            }
+            for (j, placed) in pdf.images(object).iter().enumerate() {
+                let image = &placed.image;
+                let name = format!("page-{}-image-{}.{}", i + 1, j + 1, image.extension());
+                let path = std::path::Path::new(dir).join(name);
+                std::fs::write(&path, image.file_data()).unwrap();
+                let m = &placed.matrix;
+                println!("{} {}x{} bbox {:?} ctm [{} {} {} {} {} {}]", path.display(), image.width, image.height, placed.bbox(), m.a, m.b, m.c, m.d, m.e, m.f);
+            }
+        }
+        return;
+    }
+
     let file_path = options.get_one::<String>("FILE").expect("Require file name");
     let mut file = File::open(file_path).unwrap();
     let mut content = Vec::new();
@@ -82,47 +113,20 @@ fn main() {
 
     #[cfg(feature = "slint")]
     {
-        use pdf_parser::text::handle_text_operation;
+        use pdf_parser::text::get_page_texts;
         use slint_ui::{TextItem, run};
-        use pdf_parser::operation::TextState;
-        use postscript::parser::parse;
-        let (first_page, mb) = pdf.get_first_page().unwrap();
-        let window_size = mb.unwrap();
-        let (_, window_height) = window_size;
-        println!("window size {mb:?}");
+        let first_page = pdf.get_page(0).unwrap();
+        let (x0, y0, x1, y1) = pdf.get_media_box(first_page).unwrap();
+        let window_size = (x1 - x0, y1 - y0);
+        println!("window size {window_size:?}");
         let mut texts = Vec::new();
-        for obj in first_page {
-            println!("{obj:?}");
-            let stream = obj.stream();
-            if stream.is_ascii() {
-                println!("{}", String::from_utf8(stream.into()).unwrap());
-            }
-            let state = lexer(stream);
-            let mut parser = parse(state).into_iter();
-            while let Some(x) = parser.next() {
-                // println!("{x:?}");
-                if x.op == "BT" {
-                    let mut text_state = TextState::default();
-                    while let Some(x) = parser.next() {
-                        if x.op == "ET" {
-                            break;
-                        }
-                        println!("{x:?}");
-                        println!("before: {:?}", text_state.get_pos());
-                        handle_text_operation(x, &mut text_state, &babel);
-                        println!("after: {:?}", text_state.get_pos());
-                    }
-                    for op in text_state.drain() {
-                        println!("{op:?}");
-                        texts.push(TextItem {
-                            x: op.x as _,
-                            y: (window_height - op.y) as _,
-                            size: op.font_size as _,
-                            text: op.text.into(),
-                        });
-                    }
-                }
-            }
+        for op in get_page_texts(&pdf, first_page) {
+            texts.push(TextItem {
+                x: (op.x - x0) as _,
+                y: (y1 - op.y) as _,
+                size: op.font_size as _,
+                text: op.text.into(),
+            });
         }
 
         run(texts, window_size);
@@ -188,40 +192,142 @@ fn main() {
         }
     }
 
-    if !options.get_flag("texts") {
-        return;
+    if options.get_flag("layout") {
+        use pdf_parser::furniture::get_document_blocks;
+        let pages = get_document_blocks(&pdf, !options.get_flag("no_furniture"));
+        for (i, blocks) in pages.into_iter().enumerate() {
+            println!("Page {}", i + 1);
+            for block in blocks {
+                println!("{}\n", block.text());
+            }
+        }
     }
 
+    if let Some(format) = options.get_one::<String>("export") {
+        use pdf_parser::furniture::get_document_blocks;
+        let pages = get_document_blocks(&pdf, !options.get_flag("no_furniture"));
+        let media_boxes = pdf.get_page_list().into_iter().map(|x| pdf.get_media_box(x)).collect::<Vec<_>>();
+        match format.as_str() {
+            "hocr" => print!("{}", pdf_parser::hocr::to_hocr(&pages, &media_boxes)),
+            "alto" => print!("{}", pdf_parser::alto::to_alto(&pages, &media_boxes)),
+            "markdown" => print!("{}", pdf_parser::markdown::to_markdown(&pdf)),
+            "html" => match options.get_one::<String>("page").and_then(|x| x.parse::<usize>().ok()) {
+                Some(n) => print!("{}", pdf_parser::html::get_page_html(&pdf, n.saturating_sub(1)).unwrap_or_default()),
+                None => print!("{}", pdf_parser::html::get_document_html(&pdf)),
+            },
+            _ => println!("Unknown export format {format}"),
+        }
+    }
 
+    if options.get_flag("json") {
+        use pdf_parser::model::{get_document_model, to_json};
+        println!("{}", to_json(&get_document_model(&pdf)));
+    }
 
-    for (.., obj) in pdf.get_references() {
-        if !obj.dict().get("Type").map_or(false, |x| x == "Text") {
-            continue;
+    if let Some(format) = options.get_one::<String>("tables") {
+        use pdf_parser::table::get_page_tables;
+        for (i, page) in pdf.get_page_list().into_iter().enumerate() {
+            for (j, table) in get_page_tables(&pdf, page).into_iter().enumerate() {
+                println!("Page {} table {}", i + 1, j + 1);
+                match format.as_str() {
+                    "json" => println!("{}", table.to_json()),
+                    _ => println!("{}", table.to_csv()),
+                }
+            }
         }
-        let lexer = lexer(obj.stream());
-        println!("Text {obj:?}");
-        for line in collect_texts(lexer, &babel) {
-            println!("{line}");
+    }
+
+    if options.get_flag("fonts") {
+        for font in pdf_parser::embedded::get_embedded_fonts(&pdf) {
+            println!(
+                "{} {} subset={} type={} program={}{} encoding={} .{}",
+                font.id.0,
+                font.name,
+                font.subset_prefix.as_deref().unwrap_or("-"),
+                font.font_type,
+                font.kind,
+                if font.subtype.is_empty() { String::new() } else { format!("/{}", font.subtype) },
+                font.encoding,
+                font.extension(),
+            );
         }
     }
 
+    if let Some(dir) = options.get_one::<String>("extract_fonts") {
+        std::fs::create_dir_all(dir).unwrap();
+        for font in pdf_parser::embedded::get_embedded_fonts(&pdf) {
+            let path = std::path::Path::new(dir).join(font.file_name());
+            let path = path.with_extension(font.extension());
+            std::fs::write(&path, font.file_data()).unwrap();
+            println!("{}", path.display());
+            if font.extension() == "cff" {
+                if let Some(data) = font.opentype() {
+                    let path = path.with_extension("otf");
+                    std::fs::write(&path, data).unwrap();
+                    println!("{}", path.display());
+                }
+            }
+        }
+    }
 
-    for c in pdf.get_contents() {
-        let lexer = lexer(c);
-        for line in collect_texts(lexer, &babel) {
-            println!("{line}");
+    if let Some(dir) = options.get_one::<String>("svg") {
+        std::fs::create_dir_all(dir).unwrap();
+        let page = options.get_one::<String>("page").and_then(|x| x.parse::<usize>().ok());
+        for (i, object) in pdf.get_page_list().into_iter().enumerate() {
+            if page.is_some_and(|n| n != i + 1) {
+                continue;
+            }
+            let path = std::path::Path::new(dir).join(format!("page-{}.svg", i + 1));
+            std::fs::write(&path, pdf_parser::svg::page_to_svg(&pdf, object)).unwrap();
+            println!("{}", path.display());
         }
     }
 
-    for (_, name, obj) in pdf.get_references() {
-        if name != "Contents" {
-            continue;
+    if let Some(dir) = options.get_one::<String>("png") {
+        std::fs::create_dir_all(dir).unwrap();
+        let page = options.get_one::<String>("page").and_then(|x| x.parse::<usize>().ok());
+        let dpi = options.get_one::<String>("dpi").and_then(|x| x.parse::<f64>().ok()).unwrap_or(72.);
+        for (i, object) in pdf.get_page_list().into_iter().enumerate() {
+            if page.is_some_and(|n| n != i + 1) {
+                continue;
+            }
+            let path = std::path::Path::new(dir).join(format!("page-{}.png", i + 1));
+            std::fs::write(&path, pdf_parser::render::render_page(&pdf, object, dpi).to_png()).unwrap();
+            println!("{}", path.display());
         }
-        let lexer = lexer(obj.stream());
-        println!("{obj:?}");
-        for line in collect_texts(lexer, &babel) {
-            println!("{line}");
+    }
+
+    if options.get_flag("lint") {
+        for (i, page) in pdf.get_page_list().into_iter().enumerate() {
+            for diagnostic in pdf_parser::lint::lint_page(&pdf, page) {
+                println!("Page {} offset {}: {}", i + 1, diagnostic.offset, diagnostic.message);
+            }
+        }
+    }
+
+    if let Some(query) = options.get_one::<String>("search") {
+        use pdf_parser::search::SearchOptions;
+        let search_options = SearchOptions {
+            ignore_case: options.get_flag("ignore_case"),
+            regex: options.get_flag("regex"),
+        };
+        match pdf.search_with(query, search_options) {
+            Ok(hits) => {
+                for hit in hits {
+                    println!("Page {}: {:?} {:?}", hit.page + 1, hit.text, hit.quads);
+                }
+            }
+            Err(e) => println!("{e}"),
         }
     }
 
+    if options.get_flag("texts") {
+        use pdf_parser::text::get_page_texts;
+        for (i, page) in pdf.get_page_list().into_iter().enumerate() {
+            println!("Page {}", i + 1);
+            for span in get_page_texts(&pdf, page) {
+                println!("{}", span.text);
+            }
+        }
+    }
 }
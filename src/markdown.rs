@@ -0,0 +1,379 @@
+use super::content::{get_page_content, load_content_fonts};
+use super::furniture::get_document_blocks;
+use super::layout::{Block, Line};
+use super::parser::PDF;
+use super::table::{Table, find_tables};
+use std::collections::{HashMap, HashSet};
+
+const BULLETS: &[char] = &['•', '◦', '▪', '‣', '∙', '·', '–', '-', '*', '○', '■', '□'];
+
+pub struct MarkdownPage {
+    pub blocks: Vec<Block>,
+    pub tables: Vec<Table>,
+    pub bold_fonts: HashSet<String>,
+    // Areas of images, text over which labels a figure rather than heading a section.
+    pub figures: Vec<(f64, f64, f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Style {
+    // Font size in half points.
+    size: i64,
+    bold: bool,
+}
+
+fn line_style(line: &Line, bold_fonts: &HashSet<String>) -> Style {
+    let (mut bold, mut total) = (0, 0);
+    for span in &line.spans {
+        let n = span.text.chars().filter(|c| !c.is_whitespace()).count();
+        total += n;
+        if bold_fonts.contains(&span.font_key) {
+            bold += n;
+        }
+    }
+    Style {
+        size: (line.font_size * 2.).round() as i64,
+        bold: bold * 2 > total,
+    }
+}
+
+// Returns the list marker and the text after it, if the line starts a list item.
+fn list_item(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.chars();
+    let first = chars.next()?;
+    if BULLETS.contains(&first) {
+        let rest = chars.as_str();
+        if rest.starts_with(char::is_whitespace) {
+            return Some(("-".into(), rest.trim_start()));
+        }
+        return None;
+    }
+    let (head, rest) = text.split_once(char::is_whitespace)?;
+    let label = head.strip_prefix('(').unwrap_or(head);
+    let number = label.strip_suffix(['.', ')'])?;
+    if !number.is_empty() && number.len() <= 3 && number.chars().all(|c| c.is_ascii_digit()) {
+        return Some((format!("{number}."), rest.trim_start()));
+    }
+    if number.len() == 1 && number.chars().all(|c| c.is_ascii_lowercase()) && !rest.is_empty() {
+        return Some(("-".into(), text));
+    }
+    None
+}
+
+fn join_lines(lines: &[String]) -> String {
+    let mut ret = String::new();
+    for text in lines {
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let next_word = text.split_whitespace().next().unwrap_or_default();
+        let broken = ret.ends_with('-')
+            && ret.chars().rev().nth(1).is_some_and(char::is_alphabetic)
+            && text.starts_with(char::is_lowercase);
+        if broken {
+            if !next_word.contains('-') {
+                ret.pop();
+            }
+        } else if !ret.is_empty() {
+            ret.push(' ');
+        }
+        ret.extend(text.chars().filter(|c| !c.is_control()));
+    }
+    ret
+}
+
+fn escape_cell(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect::<String>().replace('|', "\\|")
+}
+
+pub fn table_to_markdown(table: &Table) -> String {
+    let grid = table.grid();
+    let mut ret = String::new();
+    for (i, row) in grid.iter().enumerate() {
+        let cells = row.iter().map(|x| escape_cell(x)).collect::<Vec<_>>();
+        ret.push_str(&format!("| {} |\n", cells.join(" | ")));
+        if i == 0 {
+            ret.push_str(&format!("|{}\n", " --- |".repeat(row.len())));
+        }
+    }
+    ret.trim_end().into()
+}
+
+struct Run<'a> {
+    style: Style,
+    lines: Vec<&'a Line>,
+}
+
+fn runs<'a>(block: &'a Block, bold_fonts: &HashSet<String>) -> Vec<Run<'a>> {
+    let mut ret: Vec<Run> = Vec::new();
+    for line in &block.lines {
+        let style = line_style(line, bold_fonts);
+        match ret.last_mut() {
+            Some(run) if run.style == style => run.lines.push(line),
+            _ => ret.push(Run { style, lines: vec![line] }),
+        }
+    }
+    ret
+}
+
+fn is_heading(run: &Run, body: i64) -> bool {
+    if run.lines.iter().any(|x| x.spans[0].angle.abs() > 0.1) {
+        return false;
+    }
+    let text = join_lines(&run.lines.iter().map(|x| x.text()).collect::<Vec<_>>());
+    let length = text.chars().count();
+    if length == 0 || text.chars().all(|c| !c.is_alphabetic()) {
+        return false;
+    }
+    if run.style.size as f64 > body as f64 * 1.15 {
+        return run.lines.len() <= 3 && length <= 200;
+    }
+    run.style.bold && run.style.size >= body && run.lines.len() <= 2 && length <= 100 && !text.ends_with('.')
+}
+
+// Lines whose text covers less than half of their width, as labels spread over a chart.
+fn sparse(run: &Run) -> bool {
+    run.lines.iter().any(|x| x.spans.iter().map(|x| x.width).sum::<f64>() < 0.5 * (x.x1 - x.x0))
+}
+
+// Runs centered over lines of their block of about their width without being aligned
+// with them, as bylines over affiliations are, where section headings line up with or
+// are centered over the text that follows.
+fn centered(run: &Run, block: &Block) -> bool {
+    let size = run.lines[0].font_size;
+    let (x0, x1) = (run.lines[0].x0, run.lines[0].x1);
+    block.lines.iter().filter(|x| !run.lines.iter().any(|y| std::ptr::eq(*y, *x))).any(|x| {
+        (x.x0 - x0).abs() > size && ((x.x0 + x.x1) / 2. - (x0 + x1) / 2.).abs() < size && x.x1 - x.x0 < 2. * (x1 - x0)
+    })
+}
+
+fn in_figure(run: &Run, figures: &[(f64, f64, f64, f64)]) -> bool {
+    run.lines.iter().any(|line| {
+        let (x, y) = ((line.x0 + line.x1) / 2., (line.y0 + line.y1) / 2.);
+        line.spans.iter().any(|x| x.in_figure) || figures.iter().any(|f| x >= f.0 && x <= f.2 && y >= f.1 && y <= f.3)
+    })
+}
+
+fn paragraphs(lines: &[&Line]) -> Vec<String> {
+    let mut ret = Vec::new();
+    let left = lines.iter().map(|x| x.x0).fold(f64::MAX, f64::min);
+    let right = lines.iter().map(|x| x.x1).fold(f64::MIN, f64::max);
+    let mut current: Vec<String> = Vec::new();
+    let mut items: Vec<String> = Vec::new();
+    // Left edge of the current list item and the indentations seen in the list.
+    let mut list: Option<(f64, Vec<f64>)> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let text = line.text();
+        let size = line.font_size;
+        if let Some((marker, rest)) = list_item(&text) {
+            if list.is_none() && !current.is_empty() {
+                ret.push(join_lines(&current));
+            } else if !current.is_empty() {
+                items.push(join_lines(&current));
+            }
+            current.clear();
+            let (x0, indents) = list.get_or_insert((line.x0, Vec::new()));
+            if !indents.iter().any(|x| (x - line.x0).abs() < 0.5 * size) {
+                indents.push(line.x0);
+            }
+            *x0 = line.x0;
+            let level = indents.iter().filter(|&&x| x < line.x0 - 0.5 * size).count();
+            current.push(format!("{}{marker} {rest}", "  ".repeat(level)));
+            continue;
+        }
+        if let Some((x0, _)) = list {
+            if line.x0 > x0 + 0.5 * size {
+                current.push(text);
+                continue;
+            }
+            list = None;
+            items.push(join_lines(&current));
+            current.clear();
+            ret.push(items.join("\n"));
+            items.clear();
+        }
+        let indented = line.x0 > left + 0.8 * size;
+        let previous_short = i > 0 && lines[i - 1].x1 < right - 3. * size && lines[i - 1].text().ends_with(['.', ':', '!', '?']);
+        if (indented || previous_short) && !current.is_empty() {
+            ret.push(join_lines(&current));
+            current.clear();
+        }
+        current.push(text);
+    }
+    if !current.is_empty() {
+        let text = join_lines(&current);
+        if list.is_some() {
+            items.push(text);
+        } else {
+            ret.push(text);
+        }
+    }
+    if !items.is_empty() {
+        ret.push(items.join("\n"));
+    }
+    ret
+}
+
+pub fn render_markdown(pages: &[MarkdownPage]) -> String {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for page in pages {
+        for line in page.blocks.iter().flat_map(|x| &x.lines) {
+            let style = line_style(line, &page.bold_fonts);
+            *counts.entry(style.size).or_default() += line.text().chars().count();
+        }
+    }
+    let body = counts.iter().max_by_key(|&(size, n)| (n, -size)).map_or(20, |(&size, _)| size);
+
+    let heading = |run: &Run, block: &Block, page: &MarkdownPage| {
+        is_heading(run, body) && !sparse(run) && !in_figure(run, &page.figures) && !centered(run, block)
+    };
+    let mut occurrences: HashMap<Style, usize> = HashMap::new();
+    for page in pages {
+        for block in &page.blocks {
+            for run in runs(block, &page.bold_fonts) {
+                if heading(&run, block, page) {
+                    *occurrences.entry(run.style).or_default() += 1;
+                }
+            }
+        }
+    }
+    // Where headings of some style recur, styles seen once are bylines and callouts
+    // rather than headings, except for the largest one, which is the title.
+    let mut heading_styles = occurrences.keys().cloned().collect::<Vec<_>>();
+    heading_styles.sort_by_key(|x| (-x.size, !x.bold));
+    if occurrences.values().any(|&n| n > 2) {
+        heading_styles = heading_styles.iter().enumerate().filter(|&(i, x)| i == 0 || occurrences[x] > 1).map(|x| *x.1).collect();
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    for page in pages {
+        let mut placed = vec![false; page.tables.len()];
+        for block in &page.blocks {
+            let mut lines = Vec::new();
+            for line in &block.lines {
+                let x = (line.x0 + line.x1) / 2.;
+                let y = (line.y0 + line.y1) / 2.;
+                let table = page.tables.iter().position(|t| x >= t.bbox.0 && x <= t.bbox.2 && y >= t.bbox.1 && y <= t.bbox.3);
+                match table {
+                    Some(t) => {
+                        if !placed[t] {
+                            placed[t] = true;
+                            out.push(table_to_markdown(&page.tables[t]));
+                        }
+                    }
+                    None => lines.push(line.clone()),
+                }
+            }
+            let block = Block { lines };
+            for run in runs(&block, &page.bold_fonts) {
+                let level = heading_styles.iter().position(|x| *x == run.style).filter(|_| heading(&run, &block, page));
+                if let Some(level) = level {
+                    let level = level.min(5) + 1;
+                    let text = join_lines(&run.lines.iter().map(|x| x.text()).collect::<Vec<_>>());
+                    out.push(format!("{} {text}", "#".repeat(level)));
+                } else {
+                    out.extend(paragraphs(&run.lines));
+                }
+            }
+        }
+        for (i, table) in page.tables.iter().enumerate() {
+            if !placed[i] {
+                out.push(table_to_markdown(table));
+            }
+        }
+    }
+    let mut ret = out.into_iter().filter(|x| !x.is_empty()).collect::<Vec<_>>().join("\n\n");
+    ret.push('\n');
+    ret
+}
+
+pub fn to_markdown(pdf: &PDF) -> String {
+    let blocks = get_document_blocks(pdf, false);
+    let pages = pdf.get_page_list().into_iter().zip(blocks).map(|(page, blocks)| {
+        let fonts = load_content_fonts(pdf, pdf.get_page_resources(page));
+        let content = get_page_content(pdf, page);
+        let (x0, y0, x1, y1) = pdf.get_media_box(page).unwrap_or((0., 0., 612., 792.));
+        // Images covering most of the page are scans or backgrounds rather than figures.
+        let figures = content.images.iter().map(|x| x.bbox())
+            .filter(|f| (f.2 - f.0) * (f.3 - f.1) < 0.5 * (x1 - x0) * (y1 - y0))
+            .collect();
+        MarkdownPage {
+            blocks,
+            tables: find_tables(&content),
+            bold_fonts: fonts.into_iter().filter(|(_, x)| x.bold).map(|(k, _)| k).collect(),
+            figures,
+        }
+    }).collect::<Vec<_>>();
+    render_markdown(&pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::layout::{get_lines, reading_order};
+    use super::super::operation::TextPaintingOperation;
+
+    fn span(x: f64, y: f64, size: f64, font: &str, text: &str) -> TextPaintingOperation {
+        TextPaintingOperation {
+            font_size: size,
+            width: text.len() as f64 * size / 2.,
+            font: font.into(),
+            font_key: font.into(),
+            ..TextPaintingOperation::test_span(x, y, text)
+        }
+    }
+
+    #[test]
+    fn test_list_item() {
+        assert_eq!(list_item("• first"), Some(("-".into(), "first")));
+        assert_eq!(list_item("2) second"), Some(("2.".into(), "second")));
+        assert_eq!(list_item("-3 is negative"), None);
+        assert_eq!(list_item("1 Introduction"), None);
+    }
+
+    #[test]
+    fn test_markdown() {
+        let spans = vec![
+            span(100., 700., 16., "F1", "A Title"),
+            span(100., 670., 10., "F2", "1 Introduction"),
+            span(100., 650., 10., "F1", "This is a para-"),
+            span(100., 638., 10., "F1", "graph that continues with English-"),
+            span(100., 626., 10., "F1", "to-German."),
+            span(100., 614., 10., "F1", "• one"),
+            span(100., 602., 10., "F1", "• two which wraps"),
+            span(110., 590., 10., "F1", "onto another line"),
+            span(100., 578., 10., "F1", "After the list."),
+        ];
+        let page = MarkdownPage {
+            blocks: reading_order(get_lines(spans)),
+            tables: Vec::new(),
+            bold_fonts: ["F2".into()].into(),
+            figures: Vec::new(),
+        };
+        assert_eq!(render_markdown(&[page]), "# A Title\n\n## 1 Introduction\n\nThis is a paragraph that continues with English-to-German.\n\n- one\n- two which wraps onto another line\n\nAfter the list.\n");
+    }
+
+    #[test]
+    fn test_heading_levels() {
+        let mut spans = vec![
+            span(100., 700., 20., "F2", "A Title"),
+            span(100., 670., 11., "F2", "A Byline"),
+            TextPaintingOperation { in_figure: true, ..span(100., 640., 14., "F2", "A Label") },
+        ];
+        for i in 0 .. 3 {
+            let y = 600. - i as f64 * 100.;
+            spans.push(span(100., y, 12., "F2", &format!("{} Section", i + 1)));
+            spans.push(span(100., y - 20., 10., "F1", "Some text of the section."));
+            spans.push(span(100., y - 32., 10., "F1", "More text of the section."));
+        }
+        let page = MarkdownPage {
+            blocks: reading_order(get_lines(spans)),
+            tables: Vec::new(),
+            bold_fonts: ["F2".into()].into(),
+            figures: Vec::new(),
+        };
+        let headings = render_markdown(&[page]).lines().filter(|x| x.starts_with('#')).map(String::from).collect::<Vec<_>>();
+        assert_eq!(headings, ["# A Title", "## 1 Section", "## 2 Section", "## 3 Section"]);
+    }
+}
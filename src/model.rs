@@ -0,0 +1,98 @@
+use super::content::{get_page_content, load_content_fonts};
+use super::operation::TextPaintingOperation;
+use super::parser::{Object, PDF};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Span {
+    pub text: String,
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+    pub font: String,
+    pub base_font: String,
+    pub size: f64,
+    pub color_space: String,
+    pub fill_color: Vec<f64>,
+    // The fill color in sRGB.
+    pub rgb: [u8; 3],
+    pub render_mode: u8,
+    pub bbox: (f64, f64, f64, f64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    pub index: usize,
+    pub media_box: Option<(f64, f64, f64, f64)>,
+    pub spans: Vec<Span>,
+}
+
+impl Page {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+pub fn span_bbox(span: &TextPaintingOperation, ascent: f64, descent: f64) -> (f64, f64, f64, f64) {
+    let (sin, cos) = span.angle.sin_cos();
+    let corners = [(0., descent), (span.width, descent), (span.width, ascent), (0., ascent)]
+        .map(|(u, v)| (u, v * span.font_size))
+        .map(|(u, v)| (span.x + u * cos - v * sin, span.y + u * sin + v * cos));
+    corners.iter().fold((f64::MAX, f64::MAX, f64::MIN, f64::MIN), |b, &(x, y)| {
+        (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y))
+    })
+}
+
+pub fn get_page_model(pdf: &PDF, index: usize, page: &Object) -> Page {
+    let fonts = load_content_fonts(pdf, pdf.get_page_resources(page));
+    let spans = get_page_content(pdf, page).texts.into_iter().map(|x| {
+        let font = fonts.get(&x.font_key);
+        let (ascent, descent) = font.map_or((0.8, -0.2), |f| (f.ascent, f.descent));
+        Span {
+            bbox: span_bbox(&x, ascent, descent),
+            base_font: font.map(|f| f.base_font.clone()).unwrap_or_default(),
+            size: x.font_size,
+            x: x.x,
+            y: x.y,
+            angle: x.angle,
+            text: x.text,
+            font: x.font,
+            color_space: x.color_space,
+            fill_color: x.fill_color,
+            rgb: x.rgb,
+            render_mode: x.render_mode,
+        }
+    }).collect();
+    Page {
+        index,
+        media_box: pdf.get_media_box(page),
+        spans,
+    }
+}
+
+pub fn get_document_model(pdf: &PDF) -> Vec<Page> {
+    pdf.get_page_list().into_iter().enumerate().map(|(i, x)| get_page_model(pdf, i, x)).collect()
+}
+
+pub fn to_json(pages: &[Page]) -> String {
+    #[derive(Serialize)]
+    struct Document<'a> {
+        pages: &'a [Page],
+    }
+    serde_json::to_string_pretty(&Document { pages }).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bbox() {
+        let span = TextPaintingOperation { x: 100., y: 700., font_size: 10., width: 50., ..Default::default() };
+        assert_eq!(span_bbox(&span, 0.8, -0.2), (100., 698., 150., 708.));
+        let span = TextPaintingOperation { angle: std::f64::consts::FRAC_PI_2, ..span };
+        let (x0, y0, x1, y1) = span_bbox(&span, 0.8, -0.2);
+        assert!((x0 - 92.).abs() < 1e-9 && (x1 - 102.).abs() < 1e-9);
+        assert!((y0 - 700.).abs() < 1e-9 && (y1 - 750.).abs() < 1e-9);
+    }
+}
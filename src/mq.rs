@@ -0,0 +1,272 @@
+// The MQ arithmetic decoder of JBIG2 and JPEG 2000.
+
+// Probability estimates by state: Qe, next state after an MPS, after an LPS, and
+// whether an LPS switches the meaning of MPS.
+const STATES: [(u32, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true), (0x3401, 2, 6, false), (0x1801, 3, 9, false), (0x0ac1, 4, 12, false),
+    (0x0521, 5, 29, false), (0x0221, 38, 33, false), (0x5601, 7, 6, true), (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false), (0x3801, 10, 14, false), (0x3001, 11, 17, false), (0x2401, 12, 18, false),
+    (0x1c01, 13, 20, false), (0x1601, 29, 21, false), (0x5601, 15, 14, true), (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false), (0x4801, 18, 16, false), (0x3801, 19, 17, false), (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false), (0x2801, 22, 19, false), (0x2401, 23, 20, false), (0x2201, 24, 21, false),
+    (0x1c01, 25, 22, false), (0x1801, 26, 23, false), (0x1601, 27, 24, false), (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false), (0x1101, 30, 27, false), (0x0ac1, 31, 28, false), (0x09c1, 32, 29, false),
+    (0x08a1, 33, 30, false), (0x0521, 34, 31, false), (0x0441, 35, 32, false), (0x02a1, 36, 33, false),
+    (0x0221, 37, 34, false), (0x0141, 38, 35, false), (0x0111, 39, 36, false), (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false), (0x0025, 42, 39, false), (0x0015, 43, 40, false), (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false), (0x0001, 45, 43, false), (0x5601, 46, 46, false),
+];
+
+// A context is its state index shifted left once, with the MPS in the low bit.
+pub type Context = u8;
+
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    high: u32,
+    low: u32,
+    a: u32,
+    ct: u32,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Decoder<'a> {
+        let mut ret = Decoder { data, position: 0, high: data.first().copied().unwrap_or(0) as u32, low: 0, a: 0, ct: 0 };
+        ret.byte_in();
+        ret.high = ((ret.high << 7) & 0xffff) | ((ret.low >> 9) & 0x7f);
+        ret.low = (ret.low << 7) & 0xffff;
+        ret.ct -= 7;
+        ret.a = 0x8000;
+        ret
+    }
+
+    // Reads the next byte, past the end of data as if 0xff bytes followed.
+    fn byte_in(&mut self) {
+        let byte = |i: usize| self.data.get(i).copied().unwrap_or(0) as u32;
+        if self.data.get(self.position) == Some(&0xff) {
+            // A marker stops the data; otherwise the byte after 0xff holds 7 bits.
+            if byte(self.position + 1) > 0x8f {
+                self.low += 0xff00;
+                self.ct = 8;
+            } else {
+                self.position += 1;
+                self.low += byte(self.position) << 9;
+                self.ct = 7;
+            }
+        } else {
+            self.position += 1;
+            self.low += if self.position < self.data.len() { byte(self.position) << 8 } else { 0xff00 };
+            self.ct = 8;
+        }
+        if self.low > 0xffff {
+            self.high += self.low >> 16;
+            self.low &= 0xffff;
+        }
+    }
+
+    pub fn decode(&mut self, context: &mut Context) -> u8 {
+        let (mut index, mut mps) = ((*context >> 1) as usize, *context & 1);
+        let (qe, next_mps, next_lps, switch) = STATES[index.min(STATES.len() - 1)];
+        let mut a = self.a - qe;
+        let bit;
+        if self.high < qe {
+            if a < qe {
+                a = qe;
+                bit = mps;
+                index = next_mps as usize;
+            } else {
+                a = qe;
+                bit = 1 ^ mps;
+                if switch {
+                    mps = bit;
+                }
+                index = next_lps as usize;
+            }
+        } else {
+            self.high -= qe;
+            if a & 0x8000 != 0 {
+                self.a = a;
+                return mps;
+            }
+            if a < qe {
+                bit = 1 ^ mps;
+                if switch {
+                    mps = bit;
+                }
+                index = next_lps as usize;
+            } else {
+                bit = mps;
+                index = next_mps as usize;
+            }
+        }
+        // Renormalization.
+        loop {
+            if self.ct == 0 {
+                self.byte_in();
+            }
+            a <<= 1;
+            self.high = ((self.high << 1) & 0xffff) | ((self.low >> 15) & 1);
+            self.low = (self.low << 1) & 0xffff;
+            self.ct -= 1;
+            if a & 0x8000 != 0 {
+                break;
+            }
+        }
+        self.a = a;
+        *context = (index << 1) as u8 | mps;
+        bit
+    }
+}
+
+// The encoder matching the decoder, from the JBIG2 specification, to make test data.
+#[cfg(test)]
+pub struct Encoder {
+    a: u32,
+    c: u32,
+    ct: u32,
+    b: i32,
+    out: Vec<u8>,
+}
+
+#[cfg(test)]
+impl Default for Encoder {
+    fn default() -> Encoder {
+        Encoder { a: 0x8000, c: 0, ct: 12, b: -1, out: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+impl Encoder {
+    fn byte_out(&mut self) {
+        if self.b == 0xff {
+            self.push();
+            self.b = (self.c >> 20) as i32;
+            self.c &= 0xfffff;
+            self.ct = 7;
+        } else if self.c < 0x8000000 {
+            self.push();
+            self.b = (self.c >> 19) as i32;
+            self.c &= 0x7ffff;
+            self.ct = 8;
+        } else {
+            self.b += 1;
+            if self.b == 0xff {
+                self.c &= 0x7ffffff;
+                self.push();
+                self.b = (self.c >> 20) as i32;
+                self.c &= 0xfffff;
+                self.ct = 7;
+            } else {
+                self.push();
+                self.b = (self.c >> 19) as i32;
+                self.c &= 0x7ffff;
+                self.ct = 8;
+            }
+        }
+    }
+
+    fn push(&mut self) {
+        if self.b >= 0 {
+            self.out.push(self.b as u8);
+        }
+    }
+
+    fn renormalize(&mut self) {
+        loop {
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.ct == 0 {
+                self.byte_out();
+            }
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+    }
+
+    pub fn encode(&mut self, context: &mut Context, bit: u8) {
+        let (index, mps) = ((*context >> 1) as usize, *context & 1);
+        let (qe, nmps, nlps, switch) = STATES[index];
+        if bit == mps {
+            self.a -= qe;
+            if self.a & 0x8000 == 0 {
+                if self.a < qe {
+                    self.a = qe;
+                } else {
+                    self.c += qe;
+                }
+                *context = nmps << 1 | mps;
+                self.renormalize();
+            } else {
+                self.c += qe;
+            }
+        } else {
+            self.a -= qe;
+            if self.a < qe {
+                self.c += qe;
+            } else {
+                self.a = qe;
+            }
+            *context = nlps << 1 | if switch { 1 - mps } else { mps };
+            self.renormalize();
+        }
+    }
+
+    pub fn flush(mut self) -> Vec<u8> {
+        let temp = self.c + self.a;
+        self.c |= 0xffff;
+        if self.c >= temp {
+            self.c -= 0x8000;
+        }
+        self.c <<= self.ct;
+        self.byte_out();
+        self.c <<= self.ct;
+        self.byte_out();
+        self.push();
+        self.out.extend([0xff, 0xac]);
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // The test sequence of the JBIG2 specification, decoded with a single context.
+        let encoded = [
+            0x84, 0xc7, 0x3b, 0xfc, 0xe1, 0xa1, 0x43, 0x04, 0x02, 0x20, 0x00, 0x00, 0x41, 0x0d, 0xbb, 0x86,
+            0xf4, 0x31, 0x7f, 0xff, 0x88, 0xff, 0x37, 0x47, 0x1a, 0xdb, 0x6a, 0xdf, 0xff, 0xac,
+        ];
+        let expected = [
+            0x00, 0x02, 0x00, 0x51, 0x00, 0x00, 0x00, 0xc0, 0x03, 0x52, 0x87, 0x2a, 0xaa, 0xaa, 0xaa, 0xaa,
+            0x82, 0xc0, 0x20, 0x00, 0xfc, 0xd7, 0x9e, 0xf6, 0xbf, 0x7f, 0xed, 0x90, 0x4f, 0x46, 0xa3, 0xbf,
+        ];
+        let mut decoder = Decoder::new(&encoded);
+        let mut context = 0;
+        let decoded = (0 .. expected.len()).map(|_| {
+            (0 .. 8).fold(0u8, |a, _| a << 1 | decoder.decode(&mut context))
+        }).collect::<Vec<_>>();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_encoder() {
+        // The encoder reproduces the test sequence the decoder is checked against.
+        let input = [0x00u8, 0x02, 0x00, 0x51, 0x00, 0x00, 0x00, 0xc0, 0x03, 0x52, 0x87, 0x2a];
+        let mut encoder = Encoder::default();
+        let mut context = 0;
+        for byte in input {
+            for i in (0 .. 8).rev() {
+                encoder.encode(&mut context, byte >> i & 1);
+            }
+        }
+        let encoded = encoder.flush();
+        let mut decoder = Decoder::new(&encoded);
+        let mut context = 0;
+        let decoded = input.iter().map(|_| (0 .. 8).fold(0u8, |a, _| a << 1 | decoder.decode(&mut context))).collect::<Vec<_>>();
+        assert_eq!(decoded, input);
+    }
+}
@@ -1,3 +1,6 @@
+use super::color::{Color, ColorSpace};
+use super::pattern::Pattern;
+use std::rc::Rc;
 
 #[allow(unused)]
 enum TextStateOperator {
@@ -14,45 +17,299 @@ enum TextPaintingOperator {
     Tj, TJ
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct TextPaintingOperation {
     pub x: f64,
     pub y: f64,
     pub text: String,
     pub font_size: f64,
+    pub width: f64,
+    pub angle: f64,
+    // The resource name of the font.
+    pub font: String,
+    // The name of the font among those of `load_content_fonts`, other than `font` for
+    // fonts of forms named as another font of the page is.
+    pub font_key: String,
+    pub offsets: Vec<f64>,
+    pub render_mode: u8,
+    pub color_space: String,
+    pub fill_color: Vec<f64>,
+    pub rgb: [u8; 3],
+    // Painted by a form XObject that paints graphics as well, as included figures are.
+    pub in_figure: bool,
 }
 
-#[derive(Default)]
+impl TextPaintingOperation {
+    pub fn char_range(&self, i: usize) -> (f64, f64) {
+        let n = self.text.chars().count();
+        if self.offsets.len() != n {
+            let w = self.width / n.max(1) as f64;
+            return (i as f64 * w, (i + 1) as f64 * w);
+        }
+        let start = self.offsets[i];
+        let same = self.offsets.iter().filter(|&&x| x == start).count();
+        let first = self.offsets.iter().position(|&x| x == start).unwrap_or(i);
+        let end = self.offsets.iter().cloned().find(|&x| x > start).unwrap_or(self.width);
+        let w = (end - start) / same as f64;
+        (start + (i - first) as f64 * w, start + (i - first + 1) as f64 * w)
+    }
+
+    // A horizontal span of 10pt text, each character 5pt wide.
+    #[cfg(test)]
+    pub fn test_span(x: f64, y: f64, text: &str) -> TextPaintingOperation {
+        TextPaintingOperation {
+            x,
+            y,
+            text: text.into(),
+            font_size: 10.,
+            width: text.len() as f64 * 5.,
+            ..Default::default()
+        }
+    }
+
+    // Corners in QuadPoints order: top left, top right, bottom left, bottom right.
+    pub fn quad(&self, first: usize, last: usize) -> [(f64, f64); 4] {
+        let (x0, _) = self.char_range(first);
+        let (_, x1) = self.char_range(last);
+        let (sin, cos) = self.angle.sin_cos();
+        let point = |u: f64, v: f64| (self.x + u * cos - v * sin, self.y + u * sin + v * cos);
+        let (bottom, top) = (-0.2 * self.font_size, 0.8 * self.font_size);
+        [point(x0, top), point(x1, top), point(x0, bottom), point(x1, bottom)]
+    }
+}
+
+#[derive(Clone)]
 pub struct TextState {
-    x: f64,
-    y: f64,
     font_size: f64,
+    font: String,
+    font_key: String,
+    char_spacing: f64,
+    word_spacing: f64,
+    horizontal_scaling: f64,
+    leading: f64,
+    rise: f64,
+    render_mode: u8,
+    fill: Color,
+    fill_space: Rc<ColorSpace>,
+    tm: Matrix,
+    tlm: Matrix,
+    ctm: Matrix,
     paintings: Vec<TextPaintingOperation>,
 }
 
+impl Default for TextState {
+    fn default() -> Self {
+        TextState {
+            font_size: 0.,
+            font: String::new(),
+            font_key: String::new(),
+            char_spacing: 0.,
+            word_spacing: 0.,
+            horizontal_scaling: 1.,
+            leading: 0.,
+            rise: 0.,
+            render_mode: 0,
+            fill: Color::default(),
+            fill_space: Rc::new(ColorSpace::DeviceGray),
+            tm: Matrix::default(),
+            tlm: Matrix::default(),
+            ctm: Matrix::default(),
+            paintings: Vec::new(),
+        }
+    }
+}
+
 impl TextState {
     pub fn set_font_size(&mut self, size: f64) {
         self.font_size = size;
     }
     pub fn get_font_size(&self) -> f64 {
-        self.font_size 
+        self.font_size
+    }
+    pub fn set_font(&mut self, font: &str, size: f64) {
+        self.font = font.into();
+        self.font_key = font.into();
+        self.font_size = size;
+    }
+    pub fn get_font(&self) -> &str {
+        &self.font
+    }
+    pub fn set_font_key(&mut self, key: String) {
+        self.font_key = key;
+    }
+    pub fn get_font_key(&self) -> &str {
+        &self.font_key
+    }
+    pub fn set_char_spacing(&mut self, x: f64) {
+        self.char_spacing = x;
+    }
+    pub fn set_word_spacing(&mut self, x: f64) {
+        self.word_spacing = x;
+    }
+    pub fn set_horizontal_scaling(&mut self, percent: f64) {
+        self.horizontal_scaling = percent / 100.;
+    }
+    pub fn set_leading(&mut self, x: f64) {
+        self.leading = x;
+    }
+    pub fn get_leading(&self) -> f64 {
+        self.leading
+    }
+    pub fn set_rise(&mut self, x: f64) {
+        self.rise = x;
+    }
+    pub fn set_render_mode(&mut self, mode: u8) {
+        self.render_mode = mode;
+    }
+    pub fn get_render_mode(&self) -> u8 {
+        self.render_mode
+    }
+    pub fn set_color_space(&mut self, name: &str, space: Rc<ColorSpace>) {
+        self.fill = space.color(name, space.initial_color());
+        self.fill_space = space;
+    }
+    pub fn set_fill_color(&mut self, components: Vec<f64>) {
+        self.fill = self.fill_space.color(&self.fill.space, components);
+    }
+    pub fn set_fill_pattern(&mut self, components: Vec<f64>, pattern: Option<Rc<Pattern>>) {
+        self.fill = self.fill_space.color(&self.fill.space, components).with_pattern(pattern);
+    }
+    pub fn get_fill_color(&self) -> &Color {
+        &self.fill
+    }
+    pub fn get_fill_space(&self) -> Rc<ColorSpace> {
+        self.fill_space.clone()
+    }
+    pub fn begin_text(&mut self) {
+        self.tm = Matrix::default();
+        self.tlm = Matrix::default();
+    }
+    pub fn set_matrix(&mut self, m: Matrix) {
+        self.tm = m;
+        self.tlm = m;
+    }
+    pub fn next_line(&mut self, x: f64, y: f64) {
+        self.tlm = Matrix::translate(x, y).multiply(&self.tlm);
+        self.tm = self.tlm;
+    }
+    pub fn concat_ctm(&mut self, m: Matrix) {
+        self.ctm = m.multiply(&self.ctm);
+    }
+    pub fn get_ctm(&self) -> Matrix {
+        self.ctm
     }
     pub fn set_pos(&mut self, x: f64, y: f64) {
-        self.x = x;
-        self.y = y;
+        self.tm.e = x;
+        self.tm.f = y;
+        self.tlm = self.tm;
     }
     pub fn get_pos(&self) -> (f64, f64) {
-        (self.x, self.y)
+        (self.tm.e, self.tm.f)
+    }
+    pub fn advance(&mut self, tx: f64) {
+        self.tm = Matrix::translate(tx * self.horizontal_scaling, 0.).multiply(&self.tm);
+    }
+    pub fn glyph_advance(&self, width: f64, is_space: bool) -> f64 {
+        width * self.font_size + self.char_spacing + if is_space { self.word_spacing } else { 0. }
+    }
+    pub fn rendering_matrix(&self) -> Matrix {
+        Matrix::new(self.font_size * self.horizontal_scaling, 0., 0., self.font_size, 0., self.rise)
+            .multiply(&self.tm)
+            .multiply(&self.ctm)
     }
     pub fn push(&mut self, s: String) {
-        self.paintings.push(TextPaintingOperation { x: self.x, y: self.y, text: s, font_size: self.font_size })
+        let start = self.rendering_matrix();
+        self.push_span(s, start, 0., Vec::new())
+    }
+    pub fn push_span(&mut self, text: String, start: Matrix, width: f64, offsets: Vec<f64>) {
+        let (x, y) = start.apply(0., 0.);
+        let font_size = (start.c * start.c + start.d * start.d).sqrt();
+        self.paintings.push(TextPaintingOperation {
+            x,
+            y,
+            text,
+            font_size,
+            width,
+            angle: start.b.atan2(start.a),
+            font: self.font.clone(),
+            font_key: self.font_key.clone(),
+            offsets,
+            render_mode: self.render_mode,
+            color_space: self.fill.space.clone(),
+            fill_color: self.fill.components.clone(),
+            rgb: self.fill.rgb,
+            in_figure: false,
+        })
+    }
+    pub fn save(&self) -> TextState {
+        TextState { paintings: Vec::new(), ..self.clone() }
+    }
+    pub fn restore(&mut self, saved: TextState) {
+        let paintings = std::mem::take(&mut self.paintings);
+        *self = TextState { paintings, ..saved };
+    }
+    pub fn paintings(&self) -> &[TextPaintingOperation] {
+        &self.paintings
+    }
+
+    // Marks the text painted from index `start` on as part of a figure.
+    pub fn mark_figure(&mut self, start: usize) {
+        for painting in self.paintings.iter_mut().skip(start) {
+            painting.in_figure = true;
+        }
     }
+
     pub fn drain(&mut self) -> Vec<TextPaintingOperation> {
         self.paintings.drain(..).collect()
     }
 }
 
-#[allow(unused)]
-struct Tm {
-    a: f64, b: f64, c: f64, d: f64, e: f64, f: f64,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+    pub a: f64, pub b: f64, pub c: f64, pub d: f64, pub e: f64, pub f: f64,
+}
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Matrix::new(1., 0., 0., 1., 0., 0.)
+    }
+}
+
+impl Matrix {
+    pub fn new(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) -> Matrix {
+        Matrix { a, b, c, d, e, f }
+    }
+    pub fn translate(x: f64, y: f64) -> Matrix {
+        Matrix::new(1., 0., 0., 1., x, y)
+    }
+    pub fn scale(x: f64, y: f64) -> Matrix {
+        Matrix::new(x, 0., 0., y, 0., 0.)
+    }
+    pub fn multiply(&self, other: &Matrix) -> Matrix {
+        Matrix {
+            a: self.a * other.a + self.b * other.c,
+            b: self.a * other.b + self.b * other.d,
+            c: self.c * other.a + self.d * other.c,
+            d: self.c * other.b + self.d * other.d,
+            e: self.e * other.a + self.f * other.c + other.e,
+            f: self.e * other.b + self.f * other.d + other.f,
+        }
+    }
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f)
+    }
+    pub fn invert(&self) -> Option<Matrix> {
+        let det = self.a * self.d - self.b * self.c;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        Some(Matrix {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
 }
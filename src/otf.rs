@@ -0,0 +1,256 @@
+use super::cff::Cff;
+use super::encoding::glyph_to_unicode;
+
+fn put16(out: &mut Vec<u8>, x: u16) {
+    out.extend_from_slice(&x.to_be_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, x: u32) {
+    out.extend_from_slice(&x.to_be_bytes());
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |acc, x| {
+        let mut word = [0; 4];
+        word[.. x.len()].copy_from_slice(x);
+        acc.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+// Lays out an sfnt file with the tables sorted by tag and the head checksum fixed up.
+pub fn build_sfnt(version: [u8; 4], tables: &mut [([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    tables.sort_by_key(|x| x.0);
+    let n = tables.len() as u16;
+    let power = if n == 0 { 0 } else { 15 - n.leading_zeros() as u16 };
+    let search_range = 16 << power;
+    let mut out = version.to_vec();
+    put16(&mut out, n);
+    put16(&mut out, search_range);
+    put16(&mut out, power);
+    put16(&mut out, n * 16 - search_range);
+    let mut offset = 12 + 16 * tables.len();
+    let mut head = None;
+    for (tag, data) in tables.iter() {
+        out.extend_from_slice(tag);
+        put32(&mut out, checksum(data));
+        put32(&mut out, offset as u32);
+        put32(&mut out, data.len() as u32);
+        if tag == b"head" {
+            head = Some(offset);
+        }
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, data) in tables.iter() {
+        out.extend_from_slice(data);
+        out.resize((out.len() + 3) & !3, 0);
+    }
+    if let Some(at) = head {
+        let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&out));
+        out[at + 8 .. at + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    out
+}
+
+// Format 4 subtable mapping every code to its glyph, one segment per code.
+pub fn cmap(mapping: &[(u16, u16)]) -> Vec<u8> {
+    let count = mapping.len() as u16 + 1;
+    let power = 15 - count.leading_zeros() as u16;
+    let mut out = Vec::new();
+    put16(&mut out, 0);
+    put16(&mut out, 1);
+    put16(&mut out, 3);
+    put16(&mut out, 1);
+    put32(&mut out, 12);
+    put16(&mut out, 4);
+    put16(&mut out, 16 + 8 * count);
+    put16(&mut out, 0);
+    put16(&mut out, count * 2);
+    put16(&mut out, 2 << power);
+    put16(&mut out, power);
+    put16(&mut out, count * 2 - (2 << power));
+    for &(code, _) in mapping {
+        put16(&mut out, code);
+    }
+    put16(&mut out, 0xFFFF);
+    put16(&mut out, 0);
+    for &(code, _) in mapping {
+        put16(&mut out, code);
+    }
+    put16(&mut out, 0xFFFF);
+    for &(code, glyph) in mapping {
+        put16(&mut out, glyph.wrapping_sub(code));
+    }
+    put16(&mut out, 1);
+    for _ in 0 ..= mapping.len() {
+        put16(&mut out, 0);
+    }
+    out
+}
+
+fn name(family: &str) -> Vec<u8> {
+    let records = [(1, family.to_string()), (2, "Regular".into()), (3, family.to_string()), (4, family.to_string()), (6, family.to_string())];
+    let mut out = Vec::new();
+    let mut strings = Vec::new();
+    put16(&mut out, 0);
+    put16(&mut out, records.len() as u16);
+    put16(&mut out, 6 + 12 * records.len() as u16);
+    for (id, text) in records {
+        let bytes = text.encode_utf16().flat_map(|x| x.to_be_bytes()).collect::<Vec<_>>();
+        for x in [3, 1, 0x409, id, bytes.len() as u16, strings.len() as u16] {
+            put16(&mut out, x);
+        }
+        strings.extend(bytes);
+    }
+    out.extend(strings);
+    out
+}
+
+// Wraps a bare CFF program into an OpenType font with the tables browsers and
+// font tools require, mapping glyph names to Unicode where possible.
+pub fn wrap_cff(data: &[u8]) -> Option<Vec<u8>> {
+    let cff = Cff::parse(data)?;
+    let glyphs = cff.charstrings.len();
+    if glyphs == 0 || glyphs > 0xFFFF {
+        return None;
+    }
+    let (x0, y0, x1, y1) = cff.bbox();
+    let (x0, y0, x1, y1) = (x0 as i16, y0 as i16, x1 as i16, y1 as i16);
+    let widths = cff.widths().iter().map(|x| x.round().max(0.) as u16).collect::<Vec<_>>();
+    let max_width = widths.iter().cloned().max().unwrap_or(0);
+    let family = cff.name.rsplit('+').next().unwrap_or_default().to_string();
+
+    let mut mapping = Vec::new();
+    for (gid, name) in cff.glyph_names().into_iter().enumerate().skip(1) {
+        let code = name.and_then(|x| glyph_to_unicode(&x)).and_then(|x| {
+            let mut chars = x.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        });
+        if let Some(code) = code.map(|x| x as u32).filter(|&x| x < 0xFFFF) {
+            mapping.push((code as u16, gid as u16));
+        }
+    }
+    mapping.sort();
+    mapping.dedup_by_key(|x| x.0);
+
+    let mut head = Vec::new();
+    put32(&mut head, 0x10000);
+    put32(&mut head, 0x10000);
+    put32(&mut head, 0);
+    put32(&mut head, 0x5F0F3CF5);
+    put16(&mut head, 3);
+    put16(&mut head, cff.units_per_em() as u16);
+    head.extend_from_slice(&[0; 16]);
+    for x in [x0, y0, x1, y1] {
+        put16(&mut head, x as u16);
+    }
+    for x in [0, 3, 2, 0, 0] {
+        put16(&mut head, x);
+    }
+
+    let mut hhea = Vec::new();
+    put32(&mut hhea, 0x10000);
+    for x in [y1, y0, 0] {
+        put16(&mut hhea, x as u16);
+    }
+    put16(&mut hhea, max_width);
+    for x in [x0, 0, x1, 1, 0, 0, 0, 0, 0, 0, 0] {
+        put16(&mut hhea, x as u16);
+    }
+    put16(&mut hhea, glyphs as u16);
+
+    let mut hmtx = Vec::new();
+    for &width in &widths {
+        put16(&mut hmtx, width);
+        put16(&mut hmtx, 0);
+    }
+
+    let mut maxp = Vec::new();
+    put32(&mut maxp, 0x5000);
+    put16(&mut maxp, glyphs as u16);
+
+    let mut os2 = Vec::new();
+    let average = widths.iter().map(|&x| x as usize).sum::<usize>() / glyphs;
+    put16(&mut os2, 3);
+    put16(&mut os2, average as u16);
+    for x in [400, 5, 0, 650, 600, 0, 75, 650, 600, 0, 350, 50, 250, 0] {
+        put16(&mut os2, x);
+    }
+    os2.extend_from_slice(&[0; 10]);
+    os2.extend_from_slice(&[0; 16]);
+    os2.extend_from_slice(b"NONE");
+    put16(&mut os2, 0x40);
+    put16(&mut os2, mapping.first().map_or(0x20, |x| x.0));
+    put16(&mut os2, mapping.last().map_or(0x20, |x| x.0));
+    for x in [y1, y0, 0] {
+        put16(&mut os2, x as u16);
+    }
+    put16(&mut os2, y1.max(0) as u16);
+    put16(&mut os2, (-y0).max(0) as u16);
+    put32(&mut os2, 1);
+    put32(&mut os2, 0);
+    for x in [0, 0, 0, 0x20, 0] {
+        put16(&mut os2, x);
+    }
+
+    let mut post = Vec::new();
+    put32(&mut post, 0x30000);
+    put32(&mut post, 0);
+    put16(&mut post, (-100i16) as u16);
+    put16(&mut post, 50);
+    post.extend_from_slice(&[0; 20]);
+
+    let mut tables = [
+        (*b"CFF ", data.to_vec()),
+        (*b"OS/2", os2),
+        (*b"cmap", cmap(&mapping)),
+        (*b"head", head),
+        (*b"hhea", hhea),
+        (*b"hmtx", hmtx),
+        (*b"maxp", maxp),
+        (*b"name", name(&family)),
+        (*b"post", post),
+    ];
+    Some(build_sfnt(*b"OTTO", &mut tables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_sfnt() {
+        let mut tables = [(*b"post", vec![1, 2, 3]), (*b"head", vec![0; 54])];
+        let font = build_sfnt(*b"OTTO", &mut tables);
+        assert_eq!(&font[.. 12], &[b'O', b'T', b'T', b'O', 0, 2, 0, 32, 0, 1, 0, 0]);
+        assert_eq!(&font[12 .. 16], b"head");
+        assert_eq!(font.len(), 12 + 32 + 56 + 4);
+        assert_eq!(checksum(&font), 0xB1B0AFBA);
+    }
+
+    #[test]
+    fn test_cmap() {
+        let table = cmap(&[(0x41, 1), (0x42, 2)]);
+        assert_eq!(table.len(), 12 + 16 + 8 * 3);
+        assert_eq!(&table[18 .. 20], &[0, 6]);
+    }
+
+    #[test]
+    fn test_wrap_cff() {
+        let mut cff = vec![1, 0, 4, 1];
+        cff.extend([0, 1, 1, 1, 5, b'T', b'e', b's', b't']);
+        cff.extend([0, 1, 1, 1, 5, 28, 0, 26, 17]);
+        cff.extend([0, 0, 0, 0]);
+        cff.extend([0, 2, 1, 1, 4, 7, 248, 136, 14, 248, 136, 14]);
+        let font = wrap_cff(&cff).unwrap();
+        assert_eq!(&font[.. 6], b"OTTO\0\x09");
+        let table = |tag: &[u8]| {
+            let record = (0 .. 9).map(|i| &font[12 + 16 * i .. 28 + 16 * i]).find(|x| &x[.. 4] == tag).unwrap();
+            let at = u32::from_be_bytes(record[8 .. 12].try_into().unwrap()) as usize;
+            &font[at .. at + u32::from_be_bytes(record[12 .. 16].try_into().unwrap()) as usize]
+        };
+        assert_eq!(table(b"CFF "), &cff[..]);
+        assert_eq!(table(b"hmtx"), &[1, 244, 0, 0, 1, 244, 0, 0]);
+        // The second glyph is "space" in the ISOAdobe charset.
+        assert_eq!(&table(b"cmap")[26 .. 28], &[0, 0x20]);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use super::filter;
 use super::lexer::{Token, self};
 use std::fmt;
 
@@ -46,6 +47,9 @@ impl Object {
     pub fn id(&self) -> (usize, usize) {
         self.id
     }
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
     pub fn stream(&self) -> &[u8] {
         &self.stream
     }
@@ -409,6 +413,140 @@ impl PDF {
     pub fn get(&self, id: &(usize, usize)) -> Option<&Object> {
         self.objects.get(id)
     }
+
+    pub fn resolve<'a>(&'a self, value: &'a Value) -> &'a Value {
+        let mut value = value;
+        for _ in 0 .. 32 {
+            if let &Value::Ref(m, n) = value {
+                value = match self.objects.get(&(m, n)) {
+                    Some(x) => &x.value,
+                    None => return &Value::Null,
+                };
+            } else {
+                break;
+            }
+        }
+        value
+    }
+
+    pub fn resolve_dict<'a>(&'a self, value: &'a Value) -> Option<&'a HashMap<String, Value>> {
+        if let Value::Dict(dict) = self.resolve(value) {
+            Some(dict)
+        } else {
+            None
+        }
+    }
+
+    // Applies the filters of every stream decoded by the shared pipeline, leaving the
+    // image formats after them, with their parameters, in the dictionary of the stream.
+    fn decode_streams(&mut self) {
+        let decoded = self.objects.iter().filter(|(_, x)| !x.stream.is_empty()).filter_map(|(&id, x)| {
+            let (data, applied) = filter::decode_stream(self, x.dict(), &x.stream);
+            (applied > 0).then(|| (id, data, applied, filter::filters(self, x.dict())))
+        }).collect::<Vec<_>>();
+        for (id, data, applied, filters) in decoded {
+            let parms = match self.objects[&id].dict().get("DecodeParms").map(|x| self.resolve(x)) {
+                Some(Value::List(list)) => list.get(applied ..).map(|x| Value::List(x.to_vec())),
+                parms => parms.cloned(),
+            };
+            let object = self.objects.get_mut(&id).unwrap();
+            object.stream = data;
+            let Value::Dict(dict) = &mut object.value else { continue };
+            dict.remove("Filter");
+            dict.remove("DecodeParms");
+            if applied < filters.len() {
+                let rest = filters[applied ..].iter().map(|x| Value::Key(x.clone())).collect();
+                dict.insert("Filter".into(), Value::List(rest));
+                if let Some(parms) = parms {
+                    dict.insert("DecodeParms".into(), parms);
+                }
+            }
+        }
+    }
+
+    pub fn get_page_list(&self) -> Vec<&Object> {
+        fn walk<'a>(pdf: &'a PDF, node: &'a Object, depth: usize, buf: &mut Vec<&'a Object>) {
+            if depth > 64 {
+                return;
+            }
+            match node.dict().get("Kids") {
+                Some(Value::List(kids)) => {
+                    for kid in kids {
+                        if let &Value::Ref(m, n) = kid {
+                            if let Some(x) = pdf.objects.get(&(m, n)) {
+                                walk(pdf, x, depth + 1, buf);
+                            }
+                        }
+                    }
+                }
+                _ => if node.dict().get("Type").map_or(true, |x| x == "Page") {
+                    buf.push(node);
+                }
+            }
+        }
+        let mut ret = Vec::new();
+        if let Some(pages) = self.get_pages() {
+            walk(self, pages, 0, &mut ret);
+        }
+        ret
+    }
+
+    pub fn get_page(&self, n: usize) -> Option<&Object> {
+        self.get_page_list().into_iter().nth(n)
+    }
+
+    pub fn get_inherited<'a>(&'a self, page: &'a Object, key: &str) -> Option<&'a Value> {
+        let mut dict = page.dict();
+        for _ in 0 .. 64 {
+            if let Some(x) = dict.get(key) {
+                return Some(self.resolve(x));
+            }
+            dict = match dict.get("Parent") {
+                Some(&Value::Ref(m, n)) => self.objects.get(&(m, n))?.dict(),
+                _ => return None,
+            };
+        }
+        None
+    }
+
+    pub fn get_page_resources<'a>(&'a self, page: &'a Object) -> &'a HashMap<String, Value> {
+        match self.get_inherited(page, "Resources") {
+            Some(Value::Dict(dict)) => dict,
+            _ => &DUMMY,
+        }
+    }
+
+    pub fn get_media_box(&self, page: &Object) -> Option<(f64, f64, f64, f64)> {
+        use Value::*;
+        if let Some(List(list)) = self.get_inherited(page, "MediaBox") {
+            let list = list.iter().map(|x| self.resolve(x)).collect::<Vec<_>>();
+            if let &[&Number(x1), &Number(y1), &Number(x2), &Number(y2)] = &*list {
+                return Some((x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)));
+            }
+        }
+        None
+    }
+
+    pub fn get_page_contents(&self, page: &Object) -> Vec<u8> {
+        let mut ret = Vec::new();
+        let refs = match page.dict().get("Contents") {
+            Some(&Value::Ref(m, n)) => match self.objects.get(&(m, n)) {
+                Some(Object { value: Value::List(list), .. }) => list.clone(),
+                _ => vec![Value::Ref(m, n)],
+            },
+            Some(Value::List(list)) => list.clone(),
+            _ => Vec::new(),
+        };
+        for x in refs {
+            if let Value::Ref(m, n) = x {
+                if let Some(obj) = self.objects.get(&(m, n)) {
+                    ret.extend(&obj.stream);
+                    ret.push(b'\n');
+                }
+            }
+        }
+        ret
+    }
 }
 
 pub fn parse(source: &[u8]) -> Result<PDF, String> {
@@ -426,10 +564,11 @@ pub fn parse(source: &[u8]) -> Result<PDF, String> {
             while let Some(line) = state.lexer.get_ascii_line() {
                 if line == "trailer" {
                     let meta = state.parse_dict()?;
-                    let pdf = PDF {
+                    let mut pdf = PDF {
                         meta,
                         objects,
                     };
+                    pdf.decode_streams();
                     
                     return Ok(pdf);
                 }
@@ -447,21 +586,12 @@ pub fn parse(source: &[u8]) -> Result<PDF, String> {
         let mut stream = Vec::new();
         let next = state.next_token();
         if next == Some(Token::StreamStart) {
-            let kind = dict.get("Filter");
-            let is_encoded = match kind {
-                Some(Value::Key(x)) if x == "FlateDecode" => true,
-                _ => false,
-            };
             let len = if let Some(Value::Number(n)) = dict.get("Length") {
                 *n as _
             } else {
                 return Err("where's .. length?".into());
             };
-            if is_encoded {
-                state.lexer.get_flate_stream(len, &mut stream);
-            } else {                
-                state.lexer.get_fixed_length_stream(len, &mut stream);
-            }
+            state.lexer.get_fixed_length_stream(len, &mut stream);
             state.expect_stream_end()?;
             state.expect_obj_end()?;
         }
@@ -479,6 +609,41 @@ pub fn parse(source: &[u8]) -> Result<PDF, String> {
     }
 }
 
+// A PDF of `objects`, numbered from 1 with the catalog first, each given as a dictionary
+// and the data of its stream, if any.
+#[cfg(test)]
+pub fn test_pdf(objects: &[(&str, &[u8])]) -> PDF {
+    let mut source = b"%PDF-1.4\n".to_vec();
+    for (i, &(dict, data)) in objects.iter().enumerate() {
+        source.extend(format!("{} 0 obj\n", i + 1).bytes());
+        if data.is_empty() {
+            source.extend(format!("{dict}\nendobj\n").bytes());
+            continue;
+        }
+        let dict = dict.trim_end().strip_suffix(">>").unwrap();
+        source.extend(format!("{dict} /Length {} >>\nstream\n", data.len()).bytes());
+        source.extend(data);
+        source.extend(b"\nendstream\nendobj\n");
+    }
+    source.extend(b"xref\ntrailer\n<< /Root 1 0 R >>\n");
+    parse(&source).unwrap()
+}
+
+// A PDF of a single 100 by 100 page with `resources` and `content`, and `objects`
+// numbered from 5.
+#[cfg(test)]
+pub fn test_page_pdf(resources: &str, content: &[u8], objects: &[(&str, &[u8])]) -> PDF {
+    let page = format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Resources {resources} /Contents 4 0 R >>");
+    let mut all: Vec<(&str, &[u8])> = vec![
+        ("<< /Type /Catalog /Pages 2 0 R >>", b""),
+        ("<< /Type /Pages /Kids [3 0 R] /Count 1 >>", b""),
+        (&page, b""),
+        ("<< >>", content),
+    ];
+    all.extend(objects);
+    test_pdf(&all)
+}
+
 impl State {
 
     fn next_token(&mut self) -> Option<Token> {
@@ -0,0 +1,381 @@
+use super::color::{Color, ColorSpace};
+use super::pattern::Pattern;
+use super::operation::Matrix;
+use super::transparency::Transparency;
+use postscript::parser::content::ContentOp;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+impl Segment {
+    pub fn transform(&self, m: &Matrix) -> Segment {
+        match *self {
+            Segment::MoveTo(x, y) => {
+                let p = m.apply(x, y);
+                Segment::MoveTo(p.0, p.1)
+            }
+            Segment::LineTo(x, y) => {
+                let p = m.apply(x, y);
+                Segment::LineTo(p.0, p.1)
+            }
+            Segment::CurveTo(x1, y1, x2, y2, x3, y3) => {
+                let (a, b, c) = (m.apply(x1, y1), m.apply(x2, y2), m.apply(x3, y3));
+                Segment::CurveTo(a.0, a.1, b.0, b.1, c.0, c.1)
+            }
+            Segment::Close => Segment::Close,
+        }
+    }
+}
+
+// Painting parameters of a path, with lengths in device space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub fill: Color,
+    pub stroke: Color,
+    pub line_cap: u8,
+    pub line_join: u8,
+    pub miter_limit: f64,
+    pub dash: Vec<f64>,
+    pub dash_phase: f64,
+    pub fill_alpha: f64,
+    pub stroke_alpha: f64,
+    pub transparency: Transparency,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fill: Color::default(),
+            stroke: Color::default(),
+            line_cap: 0,
+            line_join: 0,
+            miter_limit: 10.,
+            dash: Vec::new(),
+            dash_phase: 0.,
+            fill_alpha: 1.,
+            stroke_alpha: 1.,
+            transparency: Transparency::default(),
+        }
+    }
+}
+
+// A clipping path in device space. The clipping region is the intersection of
+// every clip in effect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub segments: Vec<Segment>,
+    pub even_odd: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    pub segments: Vec<Segment>,
+    pub stroke: bool,
+    pub fill: bool,
+    pub even_odd: bool,
+    pub line_width: f64,
+    pub style: Style,
+    pub clip: Vec<Clip>,
+}
+
+impl Path {
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
+        let mut ret = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        let mut add = |x: f64, y: f64| {
+            ret = (ret.0.min(x), ret.1.min(y), ret.2.max(x), ret.3.max(y));
+        };
+        for s in &self.segments {
+            match *s {
+                Segment::MoveTo(x, y) | Segment::LineTo(x, y) => add(x, y),
+                Segment::CurveTo(x1, y1, x2, y2, x3, y3) => {
+                    add(x1, y1);
+                    add(x2, y2);
+                    add(x3, y3);
+                }
+                Segment::Close => {}
+            }
+        }
+        ret
+    }
+
+    pub fn subpaths(&self) -> Vec<&[Segment]> {
+        let mut ret = Vec::new();
+        let mut start = 0;
+        for (i, s) in self.segments.iter().enumerate() {
+            if let Segment::MoveTo(..) = s {
+                if i > start {
+                    ret.push(&self.segments[start..i]);
+                }
+                start = i;
+            }
+        }
+        if start < self.segments.len() {
+            ret.push(&self.segments[start..]);
+        }
+        ret
+    }
+
+    pub fn lines(&self) -> Vec<((f64, f64), (f64, f64))> {
+        self.subpaths().into_iter().flat_map(subpath_lines).collect()
+    }
+}
+
+pub fn subpath_lines(subpath: &[Segment]) -> Vec<((f64, f64), (f64, f64))> {
+    let mut ret = Vec::new();
+    let mut start = None;
+    let mut current = None;
+    for s in subpath {
+        match *s {
+            Segment::MoveTo(x, y) => {
+                start = Some((x, y));
+                current = start;
+            }
+            Segment::LineTo(x, y) => {
+                if let Some(p) = current {
+                    ret.push((p, (x, y)));
+                }
+                current = Some((x, y));
+            }
+            Segment::CurveTo(.., x, y) => current = Some((x, y)),
+            Segment::Close => {
+                if let (Some(p), Some(q)) = (current, start) {
+                    if p != q {
+                        ret.push((p, q));
+                    }
+                }
+                current = start;
+            }
+        }
+    }
+    ret
+}
+
+// Line and stroke parameters of the graphics state in user space, with the clip.
+#[derive(Debug, Clone)]
+pub struct GraphicsState {
+    pub line_width: f64,
+    pub line_cap: u8,
+    pub line_join: u8,
+    pub miter_limit: f64,
+    pub dash: Vec<f64>,
+    pub dash_phase: f64,
+    pub stroke: Color,
+    pub stroke_space: Rc<ColorSpace>,
+    pub clip: Vec<Clip>,
+    // Set by ExtGState dictionaries.
+    pub fill_alpha: f64,
+    pub stroke_alpha: f64,
+    pub transparency: Transparency,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState {
+            line_width: 1.,
+            line_cap: 0,
+            line_join: 0,
+            miter_limit: 10.,
+            dash: Vec::new(),
+            dash_phase: 0.,
+            stroke: Color::default(),
+            stroke_space: Rc::new(ColorSpace::DeviceGray),
+            clip: Vec::new(),
+            fill_alpha: 1.,
+            stroke_alpha: 1.,
+            transparency: Transparency::default(),
+        }
+    }
+}
+
+impl GraphicsState {
+    pub fn set_stroke_space(&mut self, name: &str, space: Rc<ColorSpace>) {
+        self.stroke = space.color(name, space.initial_color());
+        self.stroke_space = space;
+    }
+
+    pub fn set_stroke_pattern(&mut self, components: Vec<f64>, pattern: Option<Rc<Pattern>>) {
+        self.stroke = self.stroke_space.color(&self.stroke.space, components).with_pattern(pattern);
+    }
+
+    // Applies line style and stroke color operators, returning whether `op` was one.
+    // CS is left to the caller, which resolves the name of the space.
+    pub fn handle_operation(&mut self, op: &ContentOp) -> bool {
+        use ContentOp::*;
+        match op {
+            &SetLineWidth(x) => self.line_width = x,
+            &SetLineCap(x) => self.line_cap = x as u8,
+            &SetLineJoin(x) => self.line_join = x as u8,
+            &SetMiterLimit(x) => self.miter_limit = x,
+            SetDash(dash, phase) => {
+                self.dash = dash.clone();
+                self.dash_phase = *phase;
+            }
+            &SetStrokeGray(g) => self.set_device_stroke(ColorSpace::DeviceGray, "DeviceGray", vec![g]),
+            &SetStrokeRGB(r, g, b) => self.set_device_stroke(ColorSpace::DeviceRGB, "DeviceRGB", vec![r, g, b]),
+            &SetStrokeCMYK(c, m, y, k) => self.set_device_stroke(ColorSpace::DeviceCMYK, "DeviceCMYK", vec![c, m, y, k]),
+            SetStrokeColor(components) | SetStrokeColorN(components, None) => if !components.is_empty() {
+                self.stroke = self.stroke_space.color(&self.stroke.space, components.clone());
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn set_device_stroke(&mut self, space: ColorSpace, name: &str, components: Vec<f64>) {
+        self.stroke = space.color(name, components);
+        self.stroke_space = Rc::new(space);
+    }
+
+    // An empty path painted with the current state, for outlines added later.
+    pub fn path(&self, fill: &Color, ctm: &Matrix) -> Path {
+        Path {
+            line_width: self.line_width * ctm_scale(ctm),
+            style: self.style(fill, ctm),
+            clip: self.clip.clone(),
+            ..Path::default()
+        }
+    }
+
+    // The style of paths painted now, given the fill color and the CTM.
+    pub fn style(&self, fill: &Color, ctm: &Matrix) -> Style {
+        let scale = ctm_scale(ctm);
+        Style {
+            fill: fill.clone(),
+            stroke: self.stroke.clone(),
+            line_cap: self.line_cap,
+            line_join: self.line_join,
+            miter_limit: self.miter_limit,
+            dash: self.dash.iter().map(|x| x * scale).collect(),
+            dash_phase: self.dash_phase * scale,
+            fill_alpha: self.fill_alpha,
+            stroke_alpha: self.stroke_alpha,
+            transparency: self.transparency.clone(),
+        }
+    }
+}
+
+// How much the CTM scales lengths, on average over both axes.
+fn ctm_scale(ctm: &Matrix) -> f64 {
+    (ctm.a * ctm.d - ctm.b * ctm.c).abs().sqrt()
+}
+
+#[derive(Default)]
+pub struct PathBuilder {
+    segments: Vec<Segment>,
+    start: (f64, f64),
+    current: (f64, f64),
+    // Set by W or W*, with whether the even-odd rule applies.
+    pending_clip: Option<bool>,
+}
+
+impl PathBuilder {
+    // Builds the current path, returning it when painted. Clipping set by W or W*
+    // takes effect in `gs` after the painting operator, so it doesn't clip the path itself.
+    pub fn handle_path_operation(&mut self, op: &ContentOp, ctm: &Matrix, gs: &mut GraphicsState, fill: &Color) -> Option<Path> {
+        match *op {
+            ContentOp::MoveTo(x, y) => {
+                let p = ctm.apply(x, y);
+                self.segments.push(Segment::MoveTo(p.0, p.1));
+                self.start = p;
+                self.current = p;
+            }
+            ContentOp::LineTo(x, y) => {
+                let p = ctm.apply(x, y);
+                self.segments.push(Segment::LineTo(p.0, p.1));
+                self.current = p;
+            }
+            ContentOp::CurveTo(x1, y1, x2, y2, x3, y3) => {
+                let (a, b, c) = (ctm.apply(x1, y1), ctm.apply(x2, y2), ctm.apply(x3, y3));
+                self.segments.push(Segment::CurveTo(a.0, a.1, b.0, b.1, c.0, c.1));
+                self.current = c;
+            }
+            ContentOp::CurveToV(x2, y2, x3, y3) => {
+                let (a, b, c) = (self.current, ctm.apply(x2, y2), ctm.apply(x3, y3));
+                self.segments.push(Segment::CurveTo(a.0, a.1, b.0, b.1, c.0, c.1));
+                self.current = c;
+            }
+            ContentOp::CurveToY(x1, y1, x3, y3) => {
+                let (a, c) = (ctm.apply(x1, y1), ctm.apply(x3, y3));
+                self.segments.push(Segment::CurveTo(a.0, a.1, c.0, c.1, c.0, c.1));
+                self.current = c;
+            }
+            ContentOp::ClosePath => {
+                self.segments.push(Segment::Close);
+                self.current = self.start;
+            }
+            ContentOp::Rectangle(x, y, w, h) => {
+                let points = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)].map(|(x, y)| ctm.apply(x, y));
+                self.segments.push(Segment::MoveTo(points[0].0, points[0].1));
+                for p in &points[1..] {
+                    self.segments.push(Segment::LineTo(p.0, p.1));
+                }
+                self.segments.push(Segment::Close);
+                self.start = points[0];
+                self.current = points[0];
+            }
+            ContentOp::Clip => self.pending_clip = Some(false),
+            ContentOp::ClipEvenOdd => self.pending_clip = Some(true),
+            ContentOp::Stroke | ContentOp::CloseStroke | ContentOp::Fill | ContentOp::FillCompat | ContentOp::FillEvenOdd
+            | ContentOp::FillStroke | ContentOp::FillStrokeEvenOdd | ContentOp::CloseFillStroke
+            | ContentOp::CloseFillStrokeEvenOdd | ContentOp::EndPath => {
+                if matches!(op, ContentOp::CloseStroke | ContentOp::CloseFillStroke | ContentOp::CloseFillStrokeEvenOdd) {
+                    self.segments.push(Segment::Close);
+                }
+                let segments = std::mem::take(&mut self.segments);
+                let clip = gs.clip.clone();
+                if let Some(even_odd) = self.pending_clip.take() {
+                    gs.clip.push(Clip { segments: segments.clone(), even_odd });
+                }
+                if matches!(op, ContentOp::EndPath) || segments.is_empty() {
+                    return None;
+                }
+                return Some(Path {
+                    segments,
+                    stroke: !matches!(op, ContentOp::Fill | ContentOp::FillCompat | ContentOp::FillEvenOdd),
+                    fill: !matches!(op, ContentOp::Stroke | ContentOp::CloseStroke),
+                    even_odd: matches!(op, ContentOp::FillEvenOdd | ContentOp::FillStrokeEvenOdd | ContentOp::CloseFillStrokeEvenOdd),
+                    clip,
+                    ..gs.path(fill, ctm)
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postscript::lexer::parse as lexer;
+    use postscript::parser::parse;
+
+    #[test]
+    fn test_clip_and_style() {
+        let mut builder = PathBuilder::default();
+        let mut gs = GraphicsState::default();
+        let ctm = Matrix::scale(2., 2.);
+        let mut paths = Vec::new();
+        let fill = ColorSpace::DeviceGray.color("DeviceGray", vec![0.5]);
+        for op in parse(lexer(b"0 0 5 5 re W f 2 w [1 2] 0 d 1 0 0 RG 0 0 m 1 1 l S")) {
+            let op = ContentOp::from_operation(&op).unwrap();
+            if !gs.handle_operation(&op) {
+                paths.extend(builder.handle_path_operation(&op, &ctm, &mut gs, &fill));
+            }
+        }
+        // The clip set with W applies after the painting operator only.
+        assert!(paths[0].clip.is_empty());
+        assert_eq!(paths[1].clip, vec![Clip { segments: paths[0].segments.clone(), even_odd: false }]);
+        assert_eq!(paths[1].line_width, 4.);
+        assert_eq!(paths[1].style.dash, vec![2., 4.]);
+        assert_eq!(paths[1].style.stroke.components, vec![1., 0., 0.]);
+        assert_eq!(paths[1].style.stroke.rgb, [255, 0, 0]);
+        assert_eq!(paths[1].style.fill.components, vec![0.5]);
+    }
+}
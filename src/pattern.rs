@@ -0,0 +1,518 @@
+use super::color::ColorSpace;
+use super::function::Function;
+use super::operation::Matrix;
+use super::parser::{Value, PDF};
+use super::util::{number, numbers};
+use std::collections::HashMap;
+
+type Point = (f64, f64);
+
+// A mesh vertex, with color components, or the parametric value when the shading has a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vertex {
+    pub point: Point,
+    pub color: Vec<f64>,
+}
+
+// A tensor-product patch by its 4 by 4 control points, with the colors of the corners
+// p00, p03, p33 and p30.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    points: [[Point; 4]; 4],
+    colors: [Vec<f64>; 4],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadingKind {
+    // Type 1, with `matrix` mapping the domain to shading space.
+    Function { domain: [f64; 4], matrix: Matrix },
+    // Type 2, between two points.
+    Axial { coords: [f64; 4], domain: [f64; 2], extend: [bool; 2] },
+    // Type 3, between two circles.
+    Radial { coords: [f64; 6], domain: [f64; 2], extend: [bool; 2] },
+    // Types 4 and 5.
+    Triangles(Vec<[Vertex; 3]>),
+    // Types 6 and 7, with Coons patches in tensor form.
+    Patches(Vec<Patch>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shading {
+    pub space: ColorSpace,
+    pub function: Option<Function>,
+    // Painted where the shading isn't defined, when used as a pattern.
+    pub background: Option<Vec<f64>>,
+    pub bbox: Option<[f64; 4]>,
+    pub kind: ShadingKind,
+}
+
+// Pattern space is mapped to the space of the content painting with the pattern by `matrix`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Shading { shading: Box<Shading>, matrix: Matrix },
+    // Cells of `step` size repeat the content of the pattern stream `id`. Uncolored
+    // patterns are painted in the color given along with the pattern.
+    Tiling { id: (usize, usize), matrix: Matrix, bbox: [f64; 4], step: (f64, f64), colored: bool },
+}
+
+fn matrix(pdf: &PDF, dict: &HashMap<String, Value>) -> Matrix {
+    match numbers(pdf, dict.get("Matrix")).as_deref() {
+        Some(&[a, b, c, d, e, f]) => Matrix::new(a, b, c, d, e, f),
+        _ => Matrix::default(),
+    }
+}
+
+fn extend(pdf: &PDF, dict: &HashMap<String, Value>) -> [bool; 2] {
+    match dict.get("Extend").map(|x| pdf.resolve(x)) {
+        Some(Value::List(list)) => [0, 1].map(|i| matches!(list.get(i).map(|x| pdf.resolve(x)), Some(Value::Bool(true)))),
+        _ => [false, false],
+    }
+}
+
+// Reads the packed vertex data of mesh shadings.
+struct MeshReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+    coordinate_bits: usize,
+    component_bits: usize,
+    flag_bits: usize,
+    decode: Vec<f64>,
+    components: usize,
+}
+
+impl MeshReader<'_> {
+    fn read(&mut self, bits: usize) -> Option<u64> {
+        if bits == 0 || bits > 32 || self.bit + bits > self.data.len() * 8 {
+            return None;
+        }
+        let mut ret = 0u64;
+        for _ in 0 .. bits {
+            let byte = self.data[self.bit / 8];
+            ret = ret << 1 | (byte >> (7 - self.bit % 8) & 1) as u64;
+            self.bit += 1;
+        }
+        Some(ret)
+    }
+
+    fn align(&mut self) {
+        self.bit = self.bit.div_ceil(8) * 8;
+    }
+
+    fn value(&mut self, bits: usize, i: usize) -> Option<f64> {
+        let raw = self.read(bits)? as f64;
+        let (min, max) = (self.decode[i * 2], self.decode[i * 2 + 1]);
+        Some(min + raw * (max - min) / ((1u64 << bits) - 1) as f64)
+    }
+
+    fn flag(&mut self) -> Option<u64> {
+        self.read(self.flag_bits)
+    }
+
+    fn point(&mut self) -> Option<Point> {
+        Some((self.value(self.coordinate_bits, 0)?, self.value(self.coordinate_bits, 1)?))
+    }
+
+    fn color(&mut self) -> Option<Vec<f64>> {
+        (0 .. self.components).map(|i| self.value(self.component_bits, i + 2)).collect()
+    }
+
+    fn vertex(&mut self) -> Option<Vertex> {
+        Some(Vertex { point: self.point()?, color: self.color()? })
+    }
+}
+
+// Positions of the 12 boundary points of a patch in the 4 by 4 grid, in the order they
+// are given in, and of the 4 inner points of tensor-product patches.
+const BOUNDARY: [(usize, usize); 12] = [(0, 0), (0, 1), (0, 2), (0, 3), (1, 3), (2, 3), (3, 3), (3, 2), (3, 1), (3, 0), (2, 0), (1, 0)];
+const INNER: [(usize, usize); 4] = [(1, 1), (1, 2), (2, 2), (2, 1)];
+
+// The inner control points equivalent to a Coons patch.
+fn coons_inner(p: &mut [[Point; 4]; 4]) {
+    let inner = [(0, 0), (0, 3), (3, 3), (3, 0)].map(|(i, j)| {
+        // Indices of the neighbouring and the opposite rows and columns from the corner.
+        let (i1, i3) = if i == 0 { (1, 3) } else { (2, 0) };
+        let (j1, j3) = if j == 0 { (1, 3) } else { (2, 0) };
+        let terms = [
+            (-4., p[i][j]),
+            (6., p[i][j1]), (6., p[i1][j]),
+            (-2., p[i][j3]), (-2., p[i3][j]),
+            (3., p[i3][j1]), (3., p[i1][j3]),
+            (-1., p[i3][j3]),
+        ];
+        let (x, y) = terms.iter().fold((0., 0.), |a, &(w, q)| (a.0 + w * q.0, a.1 + w * q.1));
+        ((i1, j1), (x / 9., y / 9.))
+    });
+    for ((i, j), q) in inner {
+        p[i][j] = q;
+    }
+}
+
+// Triangles of free-form meshes, which start anew or share an edge with the last one.
+fn decode_free_form(reader: &mut MeshReader) -> Vec<[Vertex; 3]> {
+    let mut ret: Vec<[Vertex; 3]> = Vec::new();
+    let next = |reader: &mut MeshReader| {
+        let flag = reader.flag()?;
+        let vertex = reader.vertex()?;
+        // Each vertex starts on a byte boundary.
+        reader.align();
+        Some((flag, vertex))
+    };
+    while let Some((flag, v)) = next(reader) {
+        let triangle = match (flag, ret.last()) {
+            (1, Some([_, b, c])) => [b.clone(), c.clone(), v],
+            (2, Some([a, _, c])) => [a.clone(), c.clone(), v],
+            _ => {
+                let (Some((_, b)), Some((_, c))) = (next(reader), next(reader)) else { break };
+                [v, b, c]
+            }
+        };
+        ret.push(triangle);
+    }
+    ret
+}
+
+fn decode_patches(reader: &mut MeshReader, tensor: bool) -> Vec<Patch> {
+    let mut ret: Vec<Patch> = Vec::new();
+    while let Some(flag) = reader.flag() {
+        let mut boundary = Vec::with_capacity(12);
+        let mut colors = Vec::with_capacity(4);
+        if flag != 0 {
+            // The new patch shares the edge `flag` of the previous one.
+            let Some(previous) = ret.last() else { break };
+            let f = flag.min(3) as usize;
+            boundary.extend((0 .. 4).map(|k| {
+                let (i, j) = BOUNDARY[(f * 3 + k) % 12];
+                previous.points[i][j]
+            }));
+            colors.push(previous.colors[f].clone());
+            colors.push(previous.colors[(f + 1) % 4].clone());
+        }
+        let Some(points) = (boundary.len() .. 12).map(|_| reader.point()).collect::<Option<Vec<_>>>() else { break };
+        boundary.extend(points);
+        let Some(inner) = (0 .. if tensor { 4 } else { 0 }).map(|_| reader.point()).collect::<Option<Vec<_>>>() else { break };
+        let Some(new_colors) = (colors.len() .. 4).map(|_| reader.color()).collect::<Option<Vec<_>>>() else { break };
+        colors.extend(new_colors);
+        let mut grid = [[(0., 0.); 4]; 4];
+        for (&(i, j), &p) in BOUNDARY.iter().zip(&boundary) {
+            grid[i][j] = p;
+        }
+        if tensor {
+            for (&(i, j), &p) in INNER.iter().zip(&inner) {
+                grid[i][j] = p;
+            }
+        } else {
+            coons_inner(&mut grid);
+        }
+        let Ok(colors) = colors.try_into() else { break };
+        ret.push(Patch { points: grid, colors });
+    }
+    ret
+}
+
+fn decode_mesh(pdf: &PDF, dict: &HashMap<String, Value>, data: &[u8], kind: u8, components: usize) -> Option<ShadingKind> {
+    let decode = numbers(pdf, dict.get("Decode"))?;
+    if decode.len() < 4 + components * 2 {
+        return None;
+    }
+    let mut reader = MeshReader {
+        data,
+        bit: 0,
+        coordinate_bits: number(pdf, dict.get("BitsPerCoordinate"))? as usize,
+        component_bits: number(pdf, dict.get("BitsPerComponent"))? as usize,
+        flag_bits: number(pdf, dict.get("BitsPerFlag")).unwrap_or(8.) as usize,
+        decode,
+        components,
+    };
+    match kind {
+        4 => Some(ShadingKind::Triangles(decode_free_form(&mut reader))),
+        5 => {
+            let per_row = number(pdf, dict.get("VerticesPerRow"))? as usize;
+            if per_row < 2 {
+                return None;
+            }
+            let vertices = std::iter::from_fn(|| reader.vertex()).collect::<Vec<_>>();
+            let rows = vertices.chunks_exact(per_row).collect::<Vec<_>>();
+            let mut triangles = Vec::new();
+            for pair in rows.windows(2) {
+                let (top, bottom) = (pair[0], pair[1]);
+                for j in 0 .. per_row - 1 {
+                    triangles.push([top[j].clone(), top[j + 1].clone(), bottom[j].clone()]);
+                    triangles.push([top[j + 1].clone(), bottom[j + 1].clone(), bottom[j].clone()]);
+                }
+            }
+            Some(ShadingKind::Triangles(triangles))
+        }
+        _ => Some(ShadingKind::Patches(decode_patches(&mut reader, kind == 7))),
+    }
+}
+
+// Weights of the cubic Bernstein polynomials at t.
+fn bernstein(t: f64) -> [f64; 4] {
+    let u = 1. - t;
+    [u * u * u, 3. * u * u * t, 3. * u * t * t, t * t * t]
+}
+
+fn mix(colors: &[(f64, &[f64])]) -> Vec<f64> {
+    let n = colors.iter().map(|x| x.1.len()).min().unwrap_or(0);
+    (0 .. n).map(|k| colors.iter().map(|(w, c)| w * c[k]).sum()).collect()
+}
+
+impl Patch {
+    // Splits the patch into triangles in the space of `m`, small enough there for colors
+    // to be interpolated linearly.
+    fn triangles(&self, m: &Matrix, out: &mut Vec<[Vertex; 3]>) {
+        let points = self.points.map(|row| row.map(|(x, y)| m.apply(x, y)));
+        let (mut x0, mut y0, mut x1, mut y1) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for &(x, y) in points.iter().flatten() {
+            (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+        }
+        let n = ((x1 - x0).max(y1 - y0) / 4.).ceil().clamp(1., 48.) as usize;
+        let vertex = |u: f64, v: f64| {
+            let (bu, bv) = (bernstein(u), bernstein(v));
+            let mut p = (0., 0.);
+            for i in 0 .. 4 {
+                for j in 0 .. 4 {
+                    p.0 += bu[i] * bv[j] * points[i][j].0;
+                    p.1 += bu[i] * bv[j] * points[i][j].1;
+                }
+            }
+            let c = &self.colors;
+            let color = mix(&[((1. - u) * (1. - v), &c[0]), ((1. - u) * v, &c[1]), (u * v, &c[2]), (u * (1. - v), &c[3])]);
+            Vertex { point: p, color }
+        };
+        let grid = (0 ..= n).map(|i| (0 ..= n).map(|j| vertex(i as f64 / n as f64, j as f64 / n as f64)).collect::<Vec<_>>()).collect::<Vec<_>>();
+        for i in 0 .. n {
+            for j in 0 .. n {
+                out.push([grid[i][j].clone(), grid[i][j + 1].clone(), grid[i + 1][j].clone()]);
+                out.push([grid[i][j + 1].clone(), grid[i + 1][j + 1].clone(), grid[i + 1][j].clone()]);
+            }
+        }
+    }
+}
+
+impl Shading {
+    pub fn load(pdf: &PDF, value: &Value, resources: &HashMap<String, Value>) -> Option<Shading> {
+        // Mesh shadings are streams, which are only reached through references.
+        let data = match value {
+            &Value::Ref(m, n) => pdf.get(&(m, n)).map_or(&[][..], |x| x.stream()),
+            _ => &[],
+        };
+        let dict = pdf.resolve_dict(value)?;
+        let space = ColorSpace::load(pdf, dict.get("ColorSpace")?, resources)?;
+        let function = dict.get("Function").and_then(|x| Function::load(pdf, x));
+        let domain = numbers(pdf, dict.get("Domain"));
+        let kind = match number(pdf, dict.get("ShadingType"))? as u8 {
+            1 => ShadingKind::Function {
+                domain: domain.and_then(|x| x.try_into().ok()).unwrap_or([0., 1., 0., 1.]),
+                matrix: matrix(pdf, dict),
+            },
+            kind @ (2 | 3) => {
+                let coords = numbers(pdf, dict.get("Coords"))?;
+                let domain = domain.and_then(|x| x.try_into().ok()).unwrap_or([0., 1.]);
+                let extend = extend(pdf, dict);
+                match kind {
+                    2 => ShadingKind::Axial { coords: coords.try_into().ok()?, domain, extend },
+                    _ => ShadingKind::Radial { coords: coords.try_into().ok()?, domain, extend },
+                }
+            }
+            kind @ 4 ..= 7 => {
+                let components = if function.is_some() { 1 } else { space.components() };
+                decode_mesh(pdf, dict, data, kind, components)?
+            }
+            _ => return None,
+        };
+        // Types 1 to 3 are defined by their function.
+        if function.is_none() && !matches!(kind, ShadingKind::Triangles(_) | ShadingKind::Patches(_)) {
+            return None;
+        }
+        Some(Shading {
+            space,
+            function,
+            background: numbers(pdf, dict.get("Background")),
+            bbox: numbers(pdf, dict.get("BBox")).and_then(|x| x.try_into().ok()),
+            kind,
+        })
+    }
+
+    // The color of values at a point or a vertex, passed through the function if there is one.
+    pub fn rgb(&self, values: &[f64]) -> [u8; 3] {
+        match &self.function {
+            Some(function) => self.space.rgb(&function.eval(values)),
+            None => self.space.rgb(values),
+        }
+    }
+
+    // Values at a point in shading space, for shadings defined everywhere by a function.
+    pub fn values_at(&self, (x, y): Point) -> Option<Vec<f64>> {
+        let parameter = |s: f64, domain: &[f64; 2], extend: &[bool; 2]| {
+            let s = match s {
+                _ if s < 0. && !extend[0] => return None,
+                _ if s > 1. && !extend[1] => return None,
+                _ => s.clamp(0., 1.),
+            };
+            Some(vec![domain[0] + s * (domain[1] - domain[0])])
+        };
+        match &self.kind {
+            ShadingKind::Function { domain, matrix } => {
+                let (u, v) = matrix.invert()?.apply(x, y);
+                let inside = (domain[0] ..= domain[1]).contains(&u) && (domain[2] ..= domain[3]).contains(&v);
+                inside.then(|| vec![u, v])
+            }
+            ShadingKind::Axial { coords: [x0, y0, x1, y1], domain, extend } => {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length = dx * dx + dy * dy;
+                let s = if length == 0. { 0. } else { ((x - x0) * dx + (y - y0) * dy) / length };
+                parameter(s, domain, extend)
+            }
+            ShadingKind::Radial { coords: [x0, y0, r0, x1, y1, r1], domain, extend } => {
+                // The largest s whose circle, centred at c0 + s (c1 - c0) with radius
+                // r0 + s (r1 - r0), goes through the point.
+                let (cx, cy, dr) = (x1 - x0, y1 - y0, r1 - r0);
+                let (px, py) = (x - x0, y - y0);
+                let a = cx * cx + cy * cy - dr * dr;
+                let b = px * cx + py * cy + r0 * dr;
+                let c = px * px + py * py - r0 * r0;
+                let roots = if a.abs() < 1e-12 {
+                    if b == 0. { return None }
+                    vec![c / (2. * b)]
+                } else {
+                    let discriminant = b * b - a * c;
+                    if discriminant < 0. {
+                        return None;
+                    }
+                    let root = discriminant.sqrt();
+                    let (s0, s1) = ((b + root) / a, (b - root) / a);
+                    vec![s0.max(s1), s0.min(s1)]
+                };
+                roots.into_iter()
+                    .filter(|&s| r0 + s * dr >= 0.)
+                    .find_map(|s| parameter(s, domain, extend))
+            }
+            ShadingKind::Triangles(_) | ShadingKind::Patches(_) => None,
+        }
+    }
+
+    // Mesh triangles mapped by `m`.
+    pub fn triangles(&self, m: &Matrix) -> Vec<[Vertex; 3]> {
+        let mut ret = Vec::new();
+        match &self.kind {
+            ShadingKind::Triangles(triangles) => for triangle in triangles {
+                ret.push(triangle.clone().map(|v| {
+                    let (x, y) = m.apply(v.point.0, v.point.1);
+                    Vertex { point: (x, y), color: v.color }
+                }));
+            },
+            ShadingKind::Patches(patches) => for patch in patches {
+                patch.triangles(m, &mut ret);
+            },
+            _ => {}
+        }
+        ret
+    }
+
+    // A color standing for the whole shading where a single one is needed.
+    pub fn representative_rgb(&self) -> [u8; 3] {
+        let values = match &self.kind {
+            ShadingKind::Function { domain, .. } => vec![(domain[0] + domain[1]) / 2., (domain[2] + domain[3]) / 2.],
+            ShadingKind::Axial { domain, .. } | ShadingKind::Radial { domain, .. } => vec![(domain[0] + domain[1]) / 2.],
+            ShadingKind::Triangles(triangles) => triangles.first().map(|x| x[0].color.clone()).unwrap_or_default(),
+            ShadingKind::Patches(patches) => patches.first().map(|x| x.colors[0].clone()).unwrap_or_default(),
+        };
+        self.rgb(&values)
+    }
+}
+
+impl Pattern {
+    pub fn load(pdf: &PDF, value: &Value, resources: &HashMap<String, Value>) -> Option<Pattern> {
+        let dict = pdf.resolve_dict(value)?;
+        let matrix = matrix(pdf, dict);
+        match number(pdf, dict.get("PatternType"))? as u8 {
+            1 => {
+                let &Value::Ref(m, n) = value else { return None };
+                Some(Pattern::Tiling {
+                    id: (m, n),
+                    matrix,
+                    bbox: numbers(pdf, dict.get("BBox"))?.try_into().ok()?,
+                    step: (number(pdf, dict.get("XStep"))?, number(pdf, dict.get("YStep"))?),
+                    colored: number(pdf, dict.get("PaintType")) != Some(2.),
+                })
+            }
+            2 => Some(Pattern::Shading { shading: Box::new(Shading::load(pdf, dict.get("Shading")?, resources)?), matrix }),
+            _ => None,
+        }
+    }
+
+    // The color of text and vector output that can't show the pattern itself.
+    pub fn rgb(&self) -> Option<[u8; 3]> {
+        match self {
+            Pattern::Shading { shading, .. } => Some(shading.representative_rgb()),
+            Pattern::Tiling { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shading(kind: ShadingKind) -> Shading {
+        let ramp = Function::Exponential { domain: vec![0., 1.], c0: vec![0.], c1: vec![1.], n: 1. };
+        Shading { space: ColorSpace::DeviceGray, function: Some(ramp), background: None, bbox: None, kind }
+    }
+
+    #[test]
+    fn test_axial_and_radial() {
+        let axial = shading(ShadingKind::Axial { coords: [0., 0., 10., 0.], domain: [0., 1.], extend: [false, true] });
+        assert_eq!(axial.values_at((2.5, 7.)), Some(vec![0.25]));
+        assert_eq!(axial.values_at((-1., 0.)), None);
+        assert_eq!(axial.values_at((20., 0.)), Some(vec![1.]));
+        assert_eq!(axial.rgb(&[0.5]), [128, 128, 128]);
+        // Circles growing from radius 0 to 10 around the same centre.
+        let radial = shading(ShadingKind::Radial { coords: [0., 0., 0., 0., 0., 10.], domain: [0., 1.], extend: [false, false] });
+        assert_eq!(radial.values_at((0., 5.)), Some(vec![0.5]));
+        assert_eq!(radial.values_at((8., 6.)), Some(vec![1.]));
+        assert_eq!(radial.values_at((11., 0.)), None);
+    }
+
+    #[test]
+    fn test_free_form_mesh() {
+        // 2 bit flags, 4 bit coordinates and a 2 bit component, each vertex padded to 2 bytes.
+        let data = [0x00, 0x00, 0x3c, 0x30, 0x03, 0xe0, 0x7f, 0xd0, 0x95, 0x40];
+        let mut reader = MeshReader {
+            data: &data,
+            bit: 0,
+            coordinate_bits: 4,
+            component_bits: 2,
+            flag_bits: 2,
+            decode: vec![0., 15., 0., 15., 0., 3.],
+            components: 1,
+        };
+        let vertex = |x: f64, y: f64, c: f64| Vertex { point: (x, y), color: vec![c] };
+        let (a, b, c) = (vertex(0., 0., 0.), vertex(15., 0., 3.), vertex(0., 15., 2.));
+        let (d, e) = (vertex(15., 15., 1.), vertex(5., 5., 0.));
+        assert_eq!(decode_free_form(&mut reader), vec![
+            [a, b.clone(), c.clone()],
+            [b.clone(), c, d.clone()],
+            [b, d, e],
+        ]);
+    }
+
+    #[test]
+    fn test_coons_patch() {
+        // A square with straight sides has its inner points at the thirds.
+        let mut grid = [[(0., 0.); 4]; 4];
+        for &(i, j) in &BOUNDARY {
+            grid[i][j] = (i as f64, j as f64);
+        }
+        coons_inner(&mut grid);
+        for &(i, j) in &INNER {
+            let (x, y) = grid[i][j];
+            assert!((x - i as f64).abs() < 1e-9 && (y - j as f64).abs() < 1e-9);
+        }
+        let patch = Patch { points: grid, colors: [vec![0.], vec![1.], vec![1.], vec![0.]] };
+        let mut triangles = Vec::new();
+        patch.triangles(&Matrix::scale(4., 4.), &mut triangles);
+        assert_eq!(triangles.len(), 18);
+        assert_eq!(triangles[0][1], Vertex { point: (0., 4.), color: vec![1. / 3.] });
+    }
+}
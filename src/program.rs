@@ -0,0 +1,196 @@
+use super::cff::Cff;
+use super::encoding::standard_encoding;
+use super::operation::Matrix;
+use super::path::Segment;
+use super::truetype::TrueType;
+use super::util::u16_at;
+use postscript::type1::{self, Type1Font};
+use std::collections::HashMap;
+
+// A parsed embedded font program: Type 1, bare CFF/CFF2, TrueType or OpenType.
+pub struct Program<'a> {
+    pub sfnt: Option<TrueType<'a>>,
+    pub cff: Option<Cff<'a>>,
+    pub type1: Option<Type1Font>,
+    glyph_names: Vec<Option<String>>,
+    names: HashMap<String, u16>,
+    widths: Vec<f64>,
+    encoding: HashMap<u8, u16>,
+}
+
+impl<'a> Program<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Program<'a>> {
+        if data.starts_with(b"%!") || data.first() == Some(&0x80) {
+            return Program::parse_type1(data);
+        }
+        let (sfnt, cff) = match data.get(.. 4)? {
+            b"OTTO" | b"true" | [0, 1, 0, 0] => {
+                let sfnt = TrueType::parse(data)?;
+                let cff = sfnt.table(b"CFF ").or(sfnt.table(b"CFF2")).and_then(Cff::parse);
+                (Some(sfnt), cff)
+            }
+            [1 | 2, 0, ..] => (None, Some(Cff::parse(data)?)),
+            _ => return None,
+        };
+        let glyph_names = match (&cff, &sfnt) {
+            (Some(cff), _) => cff.glyph_names(),
+            (None, Some(sfnt)) => sfnt.glyph_names().to_vec(),
+            _ => Vec::new(),
+        };
+        let mut names = HashMap::new();
+        for (gid, name) in glyph_names.iter().enumerate() {
+            if let Some(name) = name {
+                names.entry(name.clone()).or_insert(gid as u16);
+            }
+        }
+        Some(Program {
+            widths: cff.as_ref().map(|x| x.widths()).unwrap_or_default(),
+            encoding: cff.as_ref().map(|x| x.encoding()).unwrap_or_default(),
+            glyph_names,
+            names,
+            sfnt,
+            cff,
+            type1: None,
+        })
+    }
+
+    // Glyph ids of Type 1 fonts follow the order of the CharStrings dictionary.
+    fn parse_type1(data: &[u8]) -> Option<Program<'a>> {
+        let font = Type1Font::parse(data)?;
+        let names = font.glyph_names.iter().enumerate().map(|(gid, x)| (x.clone(), gid as u16)).collect::<HashMap<_, _>>();
+        let mut encoding = HashMap::new();
+        for code in 0 ..= 255 {
+            let name = match font.standard_encoding {
+                true => standard_encoding(code),
+                false => font.encoding.get(&code).map(|x| x.as_str()),
+            };
+            if let Some(&gid) = name.and_then(|x| names.get(x)) {
+                encoding.insert(code, gid);
+            }
+        }
+        Some(Program {
+            sfnt: None,
+            cff: None,
+            glyph_names: font.glyph_names.iter().cloned().map(Some).collect(),
+            names,
+            widths: Vec::new(),
+            encoding,
+            type1: Some(font),
+        })
+    }
+
+    fn type1_glyph(&self, gid: u16) -> Option<type1::Glyph> {
+        self.type1.as_ref()?.glyph(self.glyph_name(gid)?, standard_encoding)
+    }
+
+    // The weight class the program declares, from 100 for thin to 900 for black.
+    pub fn weight(&self) -> Option<u16> {
+        if let Some(sfnt) = &self.sfnt {
+            return u16_at(sfnt.table(b"OS/2")?, 4);
+        }
+        let name = self.type1.as_ref()?.weight.to_lowercase().replace([' ', '-'], "");
+        Some(match name.as_str() {
+            "thin" | "hairline" => 100,
+            "extralight" | "ultralight" => 200,
+            "light" => 300,
+            "medium" => 500,
+            "semibold" | "demibold" | "demi" => 600,
+            "bold" => 700,
+            "extrabold" | "ultrabold" => 800,
+            "black" | "heavy" => 900,
+            _ => 400,
+        })
+    }
+
+    pub fn num_glyphs(&self) -> usize {
+        if self.type1.is_some() {
+            return self.glyph_names.len();
+        }
+        match (&self.cff, &self.sfnt) {
+            (Some(cff), _) => cff.charstrings.len(),
+            (None, Some(sfnt)) => sfnt.num_glyphs as usize,
+            _ => 0,
+        }
+    }
+
+    pub fn is_cid(&self) -> bool {
+        self.cff.as_ref().is_some_and(|x| x.is_cid())
+    }
+
+    // Maps glyph space to text space, where one unit is the font size.
+    pub fn font_matrix(&self) -> Matrix {
+        if let Some(font) = &self.type1 {
+            let [a, b, c, d, e, f] = font.font_matrix;
+            return Matrix::new(a, b, c, d, e, f);
+        }
+        match (&self.cff, &self.sfnt) {
+            (Some(cff), _) => {
+                let [a, b, c, d, e, f] = cff.font_matrix();
+                Matrix::new(a, b, c, d, e, f)
+            }
+            (None, Some(sfnt)) => Matrix::scale(1. / sfnt.units_per_em as f64, 1. / sfnt.units_per_em as f64),
+            _ => Matrix::default(),
+        }
+    }
+
+    // Glyph outline in text space units.
+    pub fn outline(&self, gid: u16) -> Vec<Segment> {
+        let segments = match (&self.cff, &self.sfnt) {
+            (Some(cff), _) => cff.outline(gid),
+            (None, Some(sfnt)) => sfnt.outline(gid),
+            _ => self.type1_glyph(gid).map(|x| x.segments.iter().map(|&s| match s {
+                type1::Segment::MoveTo(x, y) => Segment::MoveTo(x, y),
+                type1::Segment::LineTo(x, y) => Segment::LineTo(x, y),
+                type1::Segment::CurveTo(x1, y1, x2, y2, x3, y3) => Segment::CurveTo(x1, y1, x2, y2, x3, y3),
+                type1::Segment::Close => Segment::Close,
+            }).collect()).unwrap_or_default(),
+        };
+        let m = self.font_matrix();
+        segments.iter().map(|x| x.transform(&m)).collect()
+    }
+
+    // Advance width in text space units.
+    pub fn advance(&self, gid: u16) -> Option<f64> {
+        if let Some(sfnt) = &self.sfnt {
+            if let Some(width) = sfnt.advance(gid) {
+                return Some(width as f64 / sfnt.units_per_em as f64);
+            }
+        }
+        if self.type1.is_some() {
+            return self.type1_glyph(gid).map(|x| x.width * self.font_matrix().a);
+        }
+        self.widths.get(gid as usize).map(|x| x * self.font_matrix().a)
+    }
+
+    pub fn glyph_name(&self, gid: u16) -> Option<&str> {
+        self.glyph_names.get(gid as usize)?.as_deref()
+    }
+
+    pub fn gid_by_name(&self, name: &str) -> Option<u16> {
+        self.names.get(name).cloned()
+    }
+
+    // The built-in encoding of CFF and Type 1 programs.
+    pub fn builtin_encoding(&self, code: u8) -> Option<u16> {
+        self.encoding.get(&code).cloned()
+    }
+
+    pub fn has_cmaps(&self) -> bool {
+        self.sfnt.as_ref().is_some_and(|x| x.table(b"cmap").is_some())
+    }
+
+    pub fn has_cmap(&self, platform: u16, encoding: u16) -> bool {
+        self.sfnt.as_ref().is_some_and(|x| x.has_cmap(platform, encoding))
+    }
+
+    pub fn cmap(&self, platform: u16, encoding: u16, code: u32) -> Option<u16> {
+        self.sfnt.as_ref()?.cmap(platform, encoding, code)
+    }
+
+    pub fn gid_for_cid(&self, cid: u32) -> Option<u16> {
+        match &self.cff {
+            Some(cff) => cff.gid_for_cid(cid),
+            None => u16::try_from(cid).ok().filter(|&x| (x as usize) < self.num_glyphs()),
+        }
+    }
+}
@@ -0,0 +1,960 @@
+use super::color::Color;
+use super::content::{get_page_content, get_pattern_content, load_content_fonts, Graphic, PageContent, PlacedImage};
+use super::font::Font;
+use super::image::encode_png;
+use super::operation::Matrix;
+use super::path::{Clip, Path, Segment};
+use super::parser::{Object, PDF};
+use super::pattern::{Pattern, Shading, Vertex};
+use super::program::Program;
+use super::transparency::{BlendMode, Group, SoftMask, Transparency};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+type Point = (f64, f64);
+
+// Sub-scanlines sampled per row of pixels; coverage along a row is exact.
+const SUBSAMPLES: usize = 8;
+// Largest distance of flattened curves from the curve, in pixels.
+const TOLERANCE: f64 = 0.2;
+// Tiling patterns painting with tiling patterns, at most.
+const MAX_PATTERN_DEPTH: usize = 4;
+
+// Splits segments into polylines, with whether each was closed.
+fn flatten(segments: &[Segment]) -> Vec<(Vec<Point>, bool)> {
+    let mut ret: Vec<(Vec<Point>, bool)> = Vec::new();
+    let mut current = (0., 0.);
+    for segment in segments {
+        // Drawing on after a Close starts again from the start of the closed subpath.
+        if !matches!(segment, Segment::MoveTo(..)) && ret.last().is_none_or(|x| x.1) {
+            ret.push((vec![current], false));
+        }
+        match *segment {
+            Segment::MoveTo(x, y) => {
+                ret.push((vec![(x, y)], false));
+                current = (x, y);
+            }
+            Segment::LineTo(x, y) => {
+                if let Some(last) = ret.last_mut() {
+                    last.0.push((x, y));
+                }
+                current = (x, y);
+            }
+            Segment::CurveTo(x1, y1, x2, y2, x3, y3) => {
+                let (x0, y0) = current;
+                let dd = f64::max(
+                    (x0 - 2. * x1 + x2).hypot(y0 - 2. * y1 + y2),
+                    (x1 - 2. * x2 + x3).hypot(y1 - 2. * y2 + y3),
+                );
+                let n = (0.75 * dd / TOLERANCE).sqrt().ceil().clamp(1., 256.) as usize;
+                if let Some(last) = ret.last_mut() {
+                    for i in 1 ..= n {
+                        let t = i as f64 / n as f64;
+                        let u = 1. - t;
+                        let (a, b, c, d) = (u * u * u, 3. * u * u * t, 3. * u * t * t, t * t * t);
+                        last.0.push((a * x0 + b * x1 + c * x2 + d * x3, a * y0 + b * y1 + c * y2 + d * y3));
+                    }
+                }
+                current = (x3, y3);
+            }
+            Segment::Close => if let Some(last) = ret.last_mut() {
+                last.1 = true;
+                current = last.0[0];
+            },
+        }
+    }
+    ret
+}
+
+// Coverage of a region of the canvas, between 0 and 1.
+struct Mask {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    alpha: Vec<f32>,
+}
+
+impl Mask {
+    fn full(width: usize, height: usize) -> Mask {
+        Mask { x: 0, y: 0, width, height, alpha: vec![1.; width * height] }
+    }
+
+    fn get(&self, x: usize, y: usize) -> f32 {
+        match (x.checked_sub(self.x), y.checked_sub(self.y)) {
+            (Some(x), Some(y)) if x < self.width && y < self.height => self.alpha[y * self.width + x],
+            _ => 0.,
+        }
+    }
+
+    fn intersect(&self, other: &Mask) -> Option<Mask> {
+        let (x0, y0) = (self.x.max(other.x), self.y.max(other.y));
+        let (x1, y1) = ((self.x + self.width).min(other.x + other.width), (self.y + self.height).min(other.y + other.height));
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        let alpha = (y0 .. y1).flat_map(|y| (x0 .. x1).map(move |x| (x, y))).map(|(x, y)| self.get(x, y) * other.get(x, y)).collect();
+        Some(Mask { x: x0, y: y0, width: x1 - x0, height: y1 - y0, alpha })
+    }
+}
+
+struct Edge {
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+    winding: i32,
+}
+
+// Scan converts polygons into a mask of a `width` by `height` canvas, or None when
+// nothing is covered.
+fn rasterize(polygons: &[Vec<Point>], even_odd: bool, width: usize, height: usize) -> Option<Mask> {
+    let mut edges = Vec::new();
+    let mut bbox = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for polygon in polygons {
+        for (i, &p) in polygon.iter().enumerate() {
+            let q = polygon[(i + 1) % polygon.len()];
+            if p.1 == q.1 || !(p.0.is_finite() && p.1.is_finite() && q.0.is_finite() && q.1.is_finite()) {
+                continue;
+            }
+            bbox = (bbox.0.min(p.0).min(q.0), bbox.1.min(p.1).min(q.1), bbox.2.max(p.0).max(q.0), bbox.3.max(p.1).max(q.1));
+            let (a, b, winding) = if p.1 < q.1 { (p, q, 1) } else { (q, p, -1) };
+            edges.push(Edge { x0: a.0, y0: a.1, x1: b.0, y1: b.1, winding });
+        }
+    }
+    let x0 = bbox.0.floor().max(0.) as usize;
+    let y0 = bbox.1.floor().max(0.) as usize;
+    let x1 = (bbox.2.ceil().max(0.) as usize).min(width);
+    let y1 = (bbox.3.ceil().max(0.) as usize).min(height);
+    if edges.is_empty() || x0 >= x1 || y0 >= y1 {
+        return None;
+    }
+    edges.sort_by(|a, b| a.y0.total_cmp(&b.y0));
+    let w = x1 - x0;
+    let mut alpha = vec![0.; w * (y1 - y0)];
+    // Per row, steps in coverage over whole pixels and coverage of partly covered pixels.
+    let mut steps = vec![0f32; w + 2];
+    let mut partial = vec![0f32; w + 1];
+    let weight = 1. / SUBSAMPLES as f32;
+    let span = |steps: &mut [f32], partial: &mut [f32], a: f64, b: f64| {
+        let (a, b) = ((a - x0 as f64).clamp(0., w as f64), (b - x0 as f64).clamp(0., w as f64));
+        if b <= a {
+            return;
+        }
+        let (i, j) = (a as usize, b as usize);
+        if i == j {
+            partial[i] += (b - a) as f32 * weight;
+        } else {
+            partial[i] += (i as f64 + 1. - a) as f32 * weight;
+            steps[i + 1] += weight;
+            steps[j] -= weight;
+            partial[j] += (b - j as f64) as f32 * weight;
+        }
+    };
+    let mut active: Vec<usize> = Vec::new();
+    let mut next = 0;
+    let mut crossings: Vec<(f64, i32)> = Vec::new();
+    for y in y0 .. y1 {
+        steps.fill(0.);
+        partial.fill(0.);
+        for s in 0 .. SUBSAMPLES {
+            let sy = y as f64 + (s as f64 + 0.5) / SUBSAMPLES as f64;
+            while next < edges.len() && edges[next].y0 <= sy {
+                active.push(next);
+                next += 1;
+            }
+            active.retain(|&i| edges[i].y1 > sy);
+            crossings.clear();
+            for &i in &active {
+                let e = &edges[i];
+                crossings.push((e.x0 + (sy - e.y0) * (e.x1 - e.x0) / (e.y1 - e.y0), e.winding));
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut winding = 0;
+            for (i, &(x, w)) in crossings.iter().enumerate() {
+                winding += w;
+                let inside = if even_odd { winding % 2 != 0 } else { winding != 0 };
+                if let (true, Some(&(next_x, _))) = (inside, crossings.get(i + 1)) {
+                    span(&mut steps, &mut partial, x, next_x);
+                }
+            }
+        }
+        let row = &mut alpha[(y - y0) * w .. (y - y0 + 1) * w];
+        let mut covered = 0.;
+        for (i, value) in row.iter_mut().enumerate() {
+            covered += steps[i];
+            *value = (covered + partial[i]).min(1.);
+        }
+    }
+    Some(Mask { x: x0, y: y0, width: w, height: y1 - y0, alpha })
+}
+
+// Polygon approximating a circle.
+fn circle(center: Point, radius: f64) -> Vec<Point> {
+    let n = (radius * 2.).ceil().clamp(8., 90.) as usize;
+    (0 .. n).map(|i| {
+        let angle = 2. * PI * i as f64 / n as f64;
+        (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+    }).collect()
+}
+
+// Splits a polyline into its dashes.
+fn dash(points: &[Point], closed: bool, pattern: &[f64], phase: f64) -> Vec<Vec<Point>> {
+    let total = pattern.iter().sum::<f64>();
+    if total <= 0. || pattern.iter().any(|&x| x < 0.) {
+        return vec![points.to_vec()];
+    }
+    let mut points = points.to_vec();
+    if closed {
+        points.push(points[0]);
+    }
+    let mut i = 0;
+    let mut left = phase.rem_euclid(total);
+    while left >= pattern[i % pattern.len()] {
+        left -= pattern[i % pattern.len()];
+        i += 1;
+    }
+    left = pattern[i % pattern.len()] - left;
+    let mut ret = Vec::new();
+    let mut current = if i % 2 == 0 { vec![points[0]] } else { Vec::new() };
+    for pair in points.windows(2) {
+        let (p, q) = (pair[0], pair[1]);
+        let length = (q.0 - p.0).hypot(q.1 - p.1);
+        let mut at = 0.;
+        loop {
+            let step = left.min(length - at);
+            at += step;
+            left -= step;
+            let t = if length > 0. { at / length } else { 1. };
+            let point = (p.0 + (q.0 - p.0) * t, p.1 + (q.1 - p.1) * t);
+            if i % 2 == 0 {
+                current.push(point);
+            }
+            if left > 0. {
+                break;
+            }
+            i += 1;
+            left = pattern[i % pattern.len()];
+            if i % 2 == 0 {
+                current = vec![point];
+            } else {
+                ret.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if current.len() > 1 {
+        ret.push(current);
+    }
+    ret
+}
+
+struct StrokeStyle {
+    half_width: f64,
+    cap: u8,
+    join: u8,
+    miter_limit: f64,
+}
+
+fn signed_area(polygon: &[Point]) -> f64 {
+    polygon.iter().enumerate().map(|(i, p)| {
+        let q = polygon[(i + 1) % polygon.len()];
+        p.0 * q.1 - q.0 * p.1
+    }).sum::<f64>() / 2.
+}
+
+// Outline of a stroked polyline as polygons wound the same way, so that they add up
+// under the nonzero rule.
+fn stroke_polyline(points: &[Point], closed: bool, style: &StrokeStyle, out: &mut Vec<Vec<Point>>) {
+    let start = out.len();
+    let hw = style.half_width;
+    let mut points = points.to_vec();
+    points.dedup();
+    if closed && points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() == 1 {
+        let (x, y) = points[0];
+        match style.cap {
+            1 => out.push(circle((x, y), hw)),
+            2 => out.push(vec![(x - hw, y - hw), (x + hw, y - hw), (x + hw, y + hw), (x - hw, y + hw)]),
+            _ => {}
+        }
+        return;
+    }
+    let direction = |p: Point, q: Point| {
+        let length = (q.0 - p.0).hypot(q.1 - p.1);
+        ((q.0 - p.0) / length, (q.1 - p.1) / length)
+    };
+    if !closed && style.cap == 2 {
+        let n = points.len();
+        let (d0, d1) = (direction(points[0], points[1]), direction(points[n - 2], points[n - 1]));
+        points[0] = (points[0].0 - d0.0 * hw, points[0].1 - d0.1 * hw);
+        points[n - 1] = (points[n - 1].0 + d1.0 * hw, points[n - 1].1 + d1.1 * hw);
+    }
+    let n = points.len();
+    let count = if closed { n } else { n - 1 };
+    for i in 0 .. count {
+        let (p, q) = (points[i], points[(i + 1) % n]);
+        let d = direction(p, q);
+        let normal = (-d.1 * hw, d.0 * hw);
+        out.push(vec![
+            (p.0 + normal.0, p.1 + normal.1),
+            (q.0 + normal.0, q.1 + normal.1),
+            (q.0 - normal.0, q.1 - normal.1),
+            (p.0 - normal.0, p.1 - normal.1),
+        ]);
+    }
+    let joints = if closed { 0 .. n } else { 1 .. n - 1 };
+    for i in joints {
+        let p = points[i];
+        let d0 = direction(points[(i + n - 1) % n], p);
+        let d1 = direction(p, points[(i + 1) % n]);
+        let cross = d0.0 * d1.1 - d0.1 * d1.0;
+        let dot = d0.0 * d1.0 + d0.1 * d1.1;
+        if cross.abs() < 1e-9 && dot > 0. {
+            continue;
+        }
+        if style.join == 1 {
+            out.push(circle(p, hw));
+            continue;
+        }
+        // Offsets on the outer side of the turn.
+        let side = if cross > 0. { -hw } else { hw };
+        let a = (-d0.1 * side, d0.0 * side);
+        let b = (-d1.1 * side, d1.0 * side);
+        let ratio = 1. / ((1. + dot) / 2.).sqrt();
+        let mut polygon = vec![p, (p.0 + a.0, p.1 + a.1)];
+        if style.join == 0 && ratio <= style.miter_limit {
+            let (mx, my) = (a.0 + b.0, a.1 + b.1);
+            let length = mx.hypot(my);
+            if length > 0. {
+                polygon.push((p.0 + mx / length * hw * ratio, p.1 + my / length * hw * ratio));
+            }
+        }
+        polygon.push((p.0 + b.0, p.1 + b.1));
+        out.push(polygon);
+    }
+    if !closed && style.cap == 1 {
+        out.push(circle(points[0], hw));
+        out.push(circle(points[n - 1], hw));
+    }
+    for polygon in &mut out[start ..] {
+        if signed_area(polygon) < 0. {
+            polygon.reverse();
+        }
+    }
+}
+
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    // RGBA, row by row from the top.
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    // A white page.
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas { width, height, pixels: vec![255; width * height * 4] }
+    }
+
+    fn transparent(width: usize, height: usize) -> Canvas {
+        Canvas { width, height, pixels: vec![0; width * height * 4] }
+    }
+
+    fn blend(&mut self, x: usize, y: usize, color: [u8; 3], alpha: f32) {
+        if alpha <= 0. {
+            return;
+        }
+        let i = (y * self.width + x) * 4;
+        let below = self.pixels[i + 3] as f32 / 255.;
+        if below >= 1. {
+            for (pixel, &c) in self.pixels[i .. i + 3].iter_mut().zip(&color) {
+                *pixel = (*pixel as f32 * (1. - alpha) + c as f32 * alpha).round() as u8;
+            }
+            return;
+        }
+        let total = alpha + below * (1. - alpha);
+        for (pixel, &c) in self.pixels[i .. i + 3].iter_mut().zip(&color) {
+            *pixel = ((*pixel as f32 * below * (1. - alpha) + c as f32 * alpha) / total).round() as u8;
+        }
+        self.pixels[i + 3] = (total * 255.).round() as u8;
+    }
+
+    // Composites paint in a blend mode: the color painted is the blended one as much as
+    // what is below is opaque.
+    fn composite(&mut self, x: usize, y: usize, color: [u8; 3], alpha: f32, blend: BlendMode) {
+        if blend == BlendMode::Normal || alpha <= 0. {
+            return self.blend(x, y, color, alpha);
+        }
+        let p = self.pixel(x, y);
+        let below = p[3] as f32 / 255.;
+        let source = color.map(|c| c as f32 / 255.);
+        let blended = blend.blend([p[0], p[1], p[2]].map(|c| c as f32 / 255.), source);
+        let mixed = [0, 1, 2].map(|k| ((source[k] * (1. - below) + blended[k] * below).clamp(0., 1.) * 255.).round() as u8);
+        self.blend(x, y, mixed, alpha);
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        encode_png(self.width, self.height, 6, 8, &self.pixels)
+    }
+}
+
+// A transparency group being painted, over the part of the canvas it covers.
+struct Layer {
+    group: Rc<Group>,
+    x: usize,
+    y: usize,
+    canvas: Canvas,
+    // What the group is painted on, transparent for isolated groups.
+    backdrop: Canvas,
+    // The opacity of the content of the group alone.
+    alpha: Vec<f32>,
+}
+
+impl Layer {
+    // Paints a pixel given in canvas coordinates. Where the shape of the paint covers
+    // the pixel, paint in knockout groups replaces what the group painted before.
+    fn put(&mut self, x: usize, y: usize, color: [u8; 3], shape: f32, opacity: f32, blend: BlendMode) {
+        let (Some(x), Some(y)) = (x.checked_sub(self.x), y.checked_sub(self.y)) else { return };
+        if x >= self.canvas.width || y >= self.canvas.height {
+            return;
+        }
+        let i = y * self.canvas.width + x;
+        if !self.group.knockout {
+            self.canvas.composite(x, y, color, shape * opacity, blend);
+            self.alpha[i] += shape * opacity * (1. - self.alpha[i]);
+            return;
+        }
+        let mut pixel = Canvas { width: 1, height: 1, pixels: self.backdrop.pixel(x, y).to_vec() };
+        pixel.composite(0, 0, color, opacity, blend);
+        for (below, &p) in self.canvas.pixels[i * 4 .. i * 4 + 4].iter_mut().zip(&pixel.pixels) {
+            *below = (*below as f32 * (1. - shape) + p as f32 * shape).round() as u8;
+        }
+        self.alpha[i] = self.alpha[i] * (1. - shape) + opacity * shape;
+    }
+}
+
+struct Renderer<'a> {
+    pdf: &'a PDF,
+    fonts: &'a HashMap<String, Font>,
+    canvas: Canvas,
+    // From page space to pixels.
+    device: Matrix,
+    scale: f64,
+    // Clipping regions by the clip paths making them up, None where nothing is left.
+    clips: Vec<(Vec<Clip>, Option<Rc<Mask>>)>,
+    // Tiling patterns this is the cell of.
+    depth: usize,
+    // Groups being painted, outermost first.
+    layers: Vec<Layer>,
+    // The blend mode and soft mask of what is painted now, with the opacity of the soft
+    // mask over the canvas.
+    blend: BlendMode,
+    soft_mask: Option<Rc<Vec<f32>>>,
+    // Soft masks rendered so far, held so that their addresses aren't reused.
+    masks: Vec<(Rc<SoftMask>, Rc<Vec<f32>>)>,
+}
+
+// Interpolates the values of a triangle's vertices over the pixels of `colors`, which
+// is the size of `mask`.
+fn fill_triangle(colors: &mut [Option<[u8; 3]>], mask: &Mask, triangle: &[Vertex; 3], shading: &Shading) {
+    let [(x0, y0), (x1, y1), (x2, y2)] = triangle.each_ref().map(|x| x.point);
+    let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+    if area.abs() < 1e-12 {
+        return;
+    }
+    let column = |x: f64| (x.max(mask.x as f64) as usize).clamp(mask.x, mask.x + mask.width);
+    let row = |y: f64| (y.max(mask.y as f64) as usize).clamp(mask.y, mask.y + mask.height);
+    let (left, right) = (column(x0.min(x1).min(x2).floor()), column(x0.max(x1).max(x2).ceil()));
+    let (top, bottom) = (row(y0.min(y1).min(y2).floor()), row(y0.max(y1).max(y2).ceil()));
+    for y in top .. bottom {
+        for x in left .. right {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            // Barycentric coordinates, with a little slack so that neighbouring
+            // triangles leave no gaps.
+            let b1 = ((px - x0) * (y2 - y0) - (x2 - x0) * (py - y0)) / area;
+            let b2 = ((x1 - x0) * (py - y0) - (px - x0) * (y1 - y0)) / area;
+            let b0 = 1. - b1 - b2;
+            if b0 < -1e-6 || b1 < -1e-6 || b2 < -1e-6 {
+                continue;
+            }
+            let values = (0 .. triangle[0].color.len()).map(|k| {
+                b0 * triangle[0].color[k] + b1 * triangle[1].color[k] + b2 * triangle[2].color[k]
+            }).collect::<Vec<_>>();
+            colors[(y - mask.y) * mask.width + x - mask.x] = Some(shading.rgb(&values));
+        }
+    }
+}
+
+impl<'a> Renderer<'a> {
+    fn new(pdf: &'a PDF, fonts: &'a HashMap<String, Font>, canvas: Canvas, device: Matrix, scale: f64, depth: usize) -> Renderer<'a> {
+        Renderer {
+            pdf, fonts, canvas, device, scale, depth,
+            clips: Vec::new(),
+            layers: Vec::new(),
+            blend: BlendMode::Normal,
+            soft_mask: None,
+            masks: Vec::new(),
+        }
+    }
+
+    // Paints a pixel with the blend mode and soft mask in effect, into the innermost group.
+    fn put(&mut self, x: usize, y: usize, color: [u8; 3], shape: f32, opacity: f32) {
+        let opacity = match &self.soft_mask {
+            Some(mask) => opacity * mask[y * self.canvas.width + x],
+            None => opacity,
+        };
+        match self.layers.last_mut() {
+            Some(layer) => layer.put(x, y, color, shape, opacity, self.blend),
+            None => self.canvas.composite(x, y, color, shape * opacity, self.blend),
+        }
+    }
+
+    // Paints the colors given for pixels of the mask, with their opacity.
+    fn fill_with(&mut self, mask: &Mask, clip: &Mask, alpha: f32, mut paint: impl FnMut(usize, usize) -> Option<([u8; 3], f32)>) {
+        for y in mask.y .. mask.y + mask.height {
+            for x in mask.x .. mask.x + mask.width {
+                let shape = mask.alpha[(y - mask.y) * mask.width + x - mask.x] * clip.get(x, y);
+                if shape <= 0. {
+                    continue;
+                }
+                if let Some((color, opacity)) = paint(x, y) {
+                    self.put(x, y, color, shape, opacity * alpha);
+                }
+            }
+        }
+    }
+
+    // Sets up compositing for content with `transparency`, ending the groups it is not
+    // in and starting those it is in.
+    fn enter(&mut self, transparency: &Transparency) {
+        let mut groups = Vec::new();
+        let mut group = transparency.group.clone();
+        while let Some(current) = group {
+            group = current.transparency.group.clone();
+            groups.push(current);
+        }
+        groups.reverse();
+        let kept = self.layers.iter().zip(&groups).take_while(|(layer, group)| Rc::ptr_eq(&layer.group, group)).count();
+        while self.layers.len() > kept {
+            self.end_group();
+        }
+        for group in groups.into_iter().skip(kept) {
+            self.begin_group(group);
+        }
+        self.blend = transparency.blend;
+        self.soft_mask = self.render_soft_mask(&transparency.soft_mask);
+    }
+
+    fn begin_group(&mut self, group: Rc<Group>) {
+        let (mut x0, mut y0, mut x1, mut y1) = match self.layers.last() {
+            Some(layer) => (layer.x, layer.y, layer.x + layer.canvas.width, layer.y + layer.canvas.height),
+            None => (0, 0, self.canvas.width, self.canvas.height),
+        };
+        if let Some([a, b, c, d]) = group.bbox {
+            let corners = [(a, b), (c, b), (c, d), (a, d)].map(|(x, y)| self.device.apply(x, y));
+            x0 = x0.max(corners.iter().map(|x| x.0).fold(f64::MAX, f64::min).floor().max(0.) as usize);
+            y0 = y0.max(corners.iter().map(|x| x.1).fold(f64::MAX, f64::min).floor().max(0.) as usize);
+            x1 = x1.min(corners.iter().map(|x| x.0).fold(f64::MIN, f64::max).ceil().max(0.) as usize);
+            y1 = y1.min(corners.iter().map(|x| x.1).fold(f64::MIN, f64::max).ceil().max(0.) as usize);
+        }
+        let (width, height) = (x1.saturating_sub(x0), y1.saturating_sub(y0));
+        let mut backdrop = Canvas::transparent(width, height);
+        if !group.isolated {
+            for y in 0 .. height {
+                for x in 0 .. width {
+                    let pixel = match self.layers.last() {
+                        Some(layer) => layer.canvas.pixel(x + x0 - layer.x, y + y0 - layer.y),
+                        None => self.canvas.pixel(x + x0, y + y0),
+                    };
+                    backdrop.pixels[(y * width + x) * 4 ..][.. 4].copy_from_slice(&pixel);
+                }
+            }
+        }
+        let canvas = Canvas { width, height, pixels: backdrop.pixels.clone() };
+        self.layers.push(Layer { group, x: x0, y: y0, canvas, backdrop, alpha: vec![0.; width * height] });
+    }
+
+    // Composites the innermost group with what it is painted on, with the opacity, blend
+    // mode and soft mask of the group as a whole.
+    fn end_group(&mut self) {
+        let Some(layer) = self.layers.pop() else { return };
+        let group = &layer.group;
+        let saved = (self.blend, self.soft_mask.take());
+        self.blend = group.transparency.blend;
+        self.soft_mask = self.render_soft_mask(&group.transparency.soft_mask);
+        let width = layer.canvas.width;
+        for y in 0 .. layer.canvas.height {
+            for x in 0 .. width {
+                let alpha = layer.alpha[y * width + x];
+                if alpha <= 0. {
+                    continue;
+                }
+                let p = layer.canvas.pixel(x, y);
+                let mut color = [p[0], p[1], p[2]];
+                // Takes the backdrop back out of the colors of groups painted on it.
+                let b = layer.backdrop.pixel(x, y);
+                let below = b[3] as f32 / 255.;
+                if below > 0. {
+                    let k = below / alpha - below;
+                    color = [0, 1, 2].map(|i| (p[i] as f32 + (p[i] as f32 - b[i] as f32) * k).clamp(0., 255.).round() as u8);
+                }
+                self.put(x + layer.x, y + layer.y, color, alpha, group.alpha as f32);
+            }
+        }
+        (self.blend, self.soft_mask) = saved;
+    }
+
+    // The opacity a soft mask gives each pixel of the canvas, from its group painted over
+    // the whole canvas.
+    fn render_soft_mask(&mut self, mask: &Option<Rc<SoftMask>>) -> Option<Rc<Vec<f32>>> {
+        let mask = mask.as_ref()?;
+        if let Some((_, values)) = self.masks.iter().find(|x| Rc::ptr_eq(&x.0, mask)) {
+            return Some(values.clone());
+        }
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        let canvas = match mask.luminosity {
+            true => Canvas { width, height, pixels: [mask.backdrop[0], mask.backdrop[1], mask.backdrop[2], 255].repeat(width * height) },
+            false => Canvas::transparent(width, height),
+        };
+        let mut renderer = Renderer::new(self.pdf, self.fonts, canvas, self.device, self.scale, self.depth);
+        renderer.paint_content(&mask.content);
+        let values = renderer.canvas.pixels.chunks(4).map(|p| {
+            let value = match mask.luminosity {
+                true => (0.3 * p[0] as f32 + 0.59 * p[1] as f32 + 0.11 * p[2] as f32) / 255.,
+                false => p[3] as f32 / 255.,
+            };
+            match &mask.transfer {
+                Some(transfer) => transfer.eval(&[value as f64]).first().map_or(value, |&x| x as f32).clamp(0., 1.),
+                None => value,
+            }
+        }).collect::<Vec<_>>();
+        let values = Rc::new(values);
+        self.masks.push((mask.clone(), values.clone()));
+        Some(values)
+    }
+
+    fn clip(&mut self, clip: &[Clip]) -> usize {
+        if let Some(i) = self.clips.iter().position(|x| x.0 == clip) {
+            return i;
+        }
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        let mut mask = Some(Mask::full(width, height));
+        for path in clip {
+            let segments = path.segments.iter().map(|x| x.transform(&self.device)).collect::<Vec<_>>();
+            let polygons = flatten(&segments).into_iter().map(|x| x.0).collect::<Vec<_>>();
+            mask = mask.zip(rasterize(&polygons, path.even_odd, width, height)).and_then(|(a, b)| a.intersect(&b));
+        }
+        self.clips.push((clip.to_vec(), mask.map(Rc::new)));
+        self.clips.len() - 1
+    }
+
+    fn paint_path(&mut self, path: &Path) {
+        let clip = self.clip(&path.clip);
+        if self.clips[clip].1.is_none() {
+            return;
+        }
+        let (width, height) = (self.canvas.width, self.canvas.height);
+        let segments = path.segments.iter().map(|x| x.transform(&self.device)).collect::<Vec<_>>();
+        let subpaths = flatten(&segments);
+        let style = &path.style;
+        self.enter(&style.transparency);
+        if path.fill {
+            let polygons = subpaths.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+            if let Some(mask) = rasterize(&polygons, path.even_odd, width, height) {
+                self.paint(&mask, clip, &style.fill, style.fill_alpha as f32);
+            }
+        }
+        if path.stroke {
+            // Lines are at least a pixel wide, which covers width 0 asking for the thinnest line.
+            let stroke = StrokeStyle {
+                half_width: (path.line_width * self.scale).max(1.) / 2.,
+                cap: style.line_cap,
+                join: style.line_join,
+                miter_limit: style.miter_limit,
+            };
+            let pattern = style.dash.iter().map(|x| x * self.scale).collect::<Vec<_>>();
+            let mut polygons = Vec::new();
+            for (points, closed) in &subpaths {
+                if pattern.is_empty() {
+                    stroke_polyline(points, *closed, &stroke, &mut polygons);
+                    continue;
+                }
+                for dash in dash(points, *closed, &pattern, style.dash_phase * self.scale) {
+                    stroke_polyline(&dash, false, &stroke, &mut polygons);
+                }
+            }
+            if let Some(mask) = rasterize(&polygons, false, width, height) {
+                self.paint(&mask, clip, &style.stroke, style.stroke_alpha as f32);
+            }
+        }
+    }
+
+    fn paint(&mut self, mask: &Mask, clip: usize, color: &Color, alpha: f32) {
+        match color.pattern.as_deref() {
+            Some(Pattern::Shading { shading, matrix }) => self.paint_shading(mask, clip, alpha, shading, matrix),
+            Some(Pattern::Tiling { .. }) => self.paint_tiling(mask, clip, alpha, color),
+            None => if let Some(clip) = self.clips[clip].1.clone() {
+                self.fill_with(mask, &clip, alpha, |_, _| Some((color.rgb, 1.)));
+            },
+        }
+    }
+
+    fn paint_shading(&mut self, mask: &Mask, clip: usize, alpha: f32, shading: &Shading, matrix: &Matrix) {
+        let Some(clip) = self.clips[clip].1.clone() else { return };
+        let m = matrix.multiply(&self.device);
+        let Some(inverse) = m.invert() else { return };
+        let background = shading.background.as_ref().map(|x| shading.space.rgb(x));
+        // Meshes are painted into a buffer first, function based shadings pixel by pixel.
+        let triangles = shading.triangles(&m);
+        let mut mesh = Vec::new();
+        if !triangles.is_empty() {
+            mesh = vec![None; mask.width * mask.height];
+            for triangle in &triangles {
+                fill_triangle(&mut mesh, mask, triangle, shading);
+            }
+        }
+        self.fill_with(mask, &clip, alpha, |x, y| {
+            let (u, v) = inverse.apply(x as f64 + 0.5, y as f64 + 0.5);
+            if let Some([x0, y0, x1, y1]) = shading.bbox {
+                if !(x0.min(x1) ..= x0.max(x1)).contains(&u) || !(y0.min(y1) ..= y0.max(y1)).contains(&v) {
+                    return None;
+                }
+            }
+            let color = match mesh.is_empty() {
+                true => shading.values_at((u, v)).map(|values| shading.rgb(&values)),
+                false => mesh[(y - mask.y) * mask.width + x - mask.x],
+            };
+            color.or(background).map(|x| (x, 1.))
+        });
+    }
+
+    // Paints a cell of the pattern at about the resolution of the page, and repeats it.
+    // Uncolored patterns are painted in the color they come with.
+    fn paint_tiling(&mut self, mask: &Mask, clip: usize, alpha: f32, color: &Color) {
+        let Some(&Pattern::Tiling { id, matrix, bbox, step, colored }) = color.pattern.as_deref() else { return };
+        let Some(object) = self.pdf.get(&id) else { return };
+        let m = matrix.multiply(&self.device);
+        let Some(inverse) = m.invert() else { return };
+        let (xstep, ystep) = (step.0.abs(), step.1.abs());
+        if self.depth >= MAX_PATTERN_DEPTH || !(xstep > 0. && ystep > 0.) {
+            return;
+        }
+        let width = (xstep * m.a.hypot(m.b)).ceil().clamp(1., 1024.) as usize;
+        let height = (ystep * m.c.hypot(m.d)).ceil().clamp(1., 1024.) as usize;
+        let (sx, sy) = (width as f64 / xstep, height as f64 / ystep);
+        let (x0, y0) = (bbox[0].min(bbox[2]), bbox[1].min(bbox[3]));
+        let fonts = load_content_fonts(self.pdf, self.pdf.get_page_resources(object));
+        let device = Matrix::translate(-x0, -y0).multiply(&Matrix::scale(sx, sy));
+        let mut cell = Renderer::new(self.pdf, &fonts, Canvas::transparent(width, height), device, (sx * sy).sqrt(), self.depth + 1);
+        let mut content = get_pattern_content(self.pdf, object);
+        if !colored {
+            let color = Color { pattern: None, ..color.clone() };
+            let paths = content.paths.iter_mut()
+                .chain(content.glyphs.iter_mut().map(|x| &mut x.path))
+                .chain(content.shown.iter_mut().map(|x| &mut x.path));
+            for path in paths {
+                path.style.fill = color.clone();
+                path.style.stroke = color.clone();
+            }
+        }
+        cell.paint_content(&content);
+        let cell = cell.canvas;
+        let Some(clip) = self.clips[clip].1.clone() else { return };
+        self.fill_with(mask, &clip, alpha, |x, y| {
+            let (u, v) = inverse.apply(x as f64 + 0.5, y as f64 + 0.5);
+            let column = (((u - x0).rem_euclid(xstep) * sx) as usize).min(width - 1);
+            let row = (((v - y0).rem_euclid(ystep) * sy) as usize).min(height - 1);
+            let p = &cell.pixels[(row * width + column) * 4 ..][.. 4];
+            Some(([p[0], p[1], p[2]], p[3] as f32 / 255.))
+        });
+    }
+
+    // Paints paths, images and Type 3 glyphs in order, then the glyphs of other fonts.
+    fn paint_content(&mut self, content: &PageContent) {
+        let fonts = self.fonts;
+        for graphic in content.graphics() {
+            match graphic {
+                Graphic::Path(path) => self.paint_path(path),
+                Graphic::Image(image) => self.paint_image(image),
+            }
+        }
+
+        let mut programs: HashMap<&str, Option<Program>> = HashMap::new();
+        let mut outlines: HashMap<(&str, u32), Vec<Segment>> = HashMap::new();
+        for glyph in &content.shown {
+            let Some((name, font)) = fonts.get_key_value(&glyph.font) else { continue };
+            let outline = outlines.entry((name, glyph.code)).or_insert_with(|| {
+                match programs.entry(name).or_insert_with(|| font.parse_program()) {
+                    Some(program) => font.glyph_outline(program, glyph.code),
+                    None => Vec::new(),
+                }
+            });
+            if outline.is_empty() {
+                continue;
+            }
+            let segments = outline.iter().map(|x| x.transform(&glyph.matrix)).collect();
+            self.paint_path(&Path { segments, ..glyph.path.clone() });
+        }
+        self.enter(&Transparency::default());
+    }
+
+    // Samples the image at the centre of every pixel its unit square covers.
+    fn paint_image(&mut self, placed: &PlacedImage) {
+        let i = self.clip(&placed.clip);
+        let Some(clip) = self.clips[i].1.clone() else { return };
+        let Some(pixels) = placed.image.to_rgba() else { return };
+        self.enter(&placed.transparency);
+        let m = placed.matrix.multiply(&self.device);
+        let Some(inverse) = m.invert() else { return };
+        let corners = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)].map(|(x, y)| m.apply(x, y));
+        let x0 = corners.iter().map(|x| x.0).fold(f64::MAX, f64::min).floor().max(0.) as usize;
+        let y0 = corners.iter().map(|x| x.1).fold(f64::MAX, f64::min).floor().max(0.) as usize;
+        let x1 = (corners.iter().map(|x| x.0).fold(f64::MIN, f64::max).ceil().max(0.) as usize).min(self.canvas.width);
+        let y1 = (corners.iter().map(|x| x.1).fold(f64::MIN, f64::max).ceil().max(0.) as usize).min(self.canvas.height);
+        let (width, height) = (placed.image.width, placed.image.height);
+        for y in y0 .. y1 {
+            for x in x0 .. x1 {
+                let (u, v) = inverse.apply(x as f64 + 0.5, y as f64 + 0.5);
+                if !(0. ..1.).contains(&u) || !(0. ..1.).contains(&v) {
+                    continue;
+                }
+                // The first row of the image is at the top of the unit square.
+                let column = ((u * width as f64) as usize).min(width - 1);
+                let row = (((1. - v) * height as f64) as usize).min(height - 1);
+                let p = &pixels[(row * width + column) * 4 ..][.. 4];
+                self.put(x, y, [p[0], p[1], p[2]], clip.get(x, y), p[3] as f32 / 255. * placed.alpha as f32);
+            }
+        }
+    }
+}
+
+// Renders the page at `dpi` pixels per inch. Text is drawn from the outlines of embedded
+// fonts and Type 3 glyph procedures; text in fonts that aren't embedded is left out.
+pub fn render_page(pdf: &PDF, page: &Object, dpi: f64) -> Canvas {
+    let media_box = pdf.get_media_box(page).unwrap_or((0., 0., 612., 792.));
+    let scale = dpi / 72.;
+    let width = ((media_box.2 - media_box.0) * scale).ceil().max(1.) as usize;
+    let height = ((media_box.3 - media_box.1) * scale).ceil().max(1.) as usize;
+    let fonts = load_content_fonts(pdf, pdf.get_page_resources(page));
+    let device = Matrix::new(scale, 0., 0., -scale, -media_box.0 * scale, media_box.3 * scale);
+    let mut renderer = Renderer::new(pdf, &fonts, Canvas::new(width, height), device, scale, 0);
+    let content = get_page_content(pdf, page);
+    renderer.paint_content(&content);
+    renderer.canvas
+}
+
+pub fn get_page_png(pdf: &PDF, index: usize, dpi: f64) -> Option<Vec<u8>> {
+    Some(render_page(pdf, pdf.get_page(index)?, dpi).to_png())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::test_page_pdf;
+
+    fn square(x0: f64, y0: f64, x1: f64, y1: f64) -> Vec<Point> {
+        vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let mask = rasterize(&[square(1., 1., 3.5, 2.)], false, 4, 4).unwrap();
+        assert_eq!((mask.x, mask.y, mask.width, mask.height), (1, 1, 3, 1));
+        assert_eq!(mask.alpha, vec![1., 1., 0.5]);
+        // Even-odd leaves the inner square out, nonzero fills it since both wind the same way.
+        let polygons = [square(0., 0., 4., 4.), square(1., 1., 3., 3.)];
+        assert_eq!(rasterize(&polygons, true, 4, 4).unwrap().get(2, 2), 0.);
+        assert_eq!(rasterize(&polygons, false, 4, 4).unwrap().get(2, 2), 1.);
+    }
+
+    #[test]
+    fn test_dash() {
+        let dashes = dash(&[(0., 0.), (10., 0.)], false, &[3., 2.], 1.);
+        assert_eq!(dashes, vec![
+            vec![(0., 0.), (2., 0.)],
+            vec![(4., 0.), (7., 0.)],
+            vec![(9., 0.), (10., 0.)],
+        ]);
+    }
+
+    #[test]
+    fn test_stroke() {
+        let style = StrokeStyle { half_width: 1., cap: 2, join: 0, miter_limit: 10. };
+        let mut polygons = Vec::new();
+        stroke_polyline(&[(2., 2.), (6., 2.), (6., 6.)], false, &style, &mut polygons);
+        let mask = rasterize(&polygons, false, 8, 8).unwrap();
+        // Square caps extend the line by half its width, and the miter fills the corner.
+        assert_eq!((mask.x, mask.y, mask.width, mask.height), (1, 1, 6, 6));
+        assert_eq!(mask.get(6, 1), 1.);
+        assert_eq!(mask.get(3, 4), 0.);
+    }
+
+    #[test]
+    fn test_composite() {
+        let mut canvas = Canvas { width: 2, height: 1, pixels: vec![128, 128, 128, 255, 0, 0, 0, 0] };
+        canvas.composite(0, 0, [255, 255, 0], 1., BlendMode::Multiply);
+        // Blending needs something below, so on a transparent pixel paint stays as is.
+        canvas.composite(1, 0, [255, 255, 0], 1., BlendMode::Multiply);
+        assert_eq!(canvas.pixels, vec![128, 128, 0, 255, 255, 255, 0, 255]);
+
+        // In knockout groups, paint covering a pixel replaces what was painted there.
+        let group = Rc::new(Group { isolated: true, knockout: true, bbox: None, alpha: 1., transparency: Transparency::default() });
+        let mut layer = Layer { group, x: 0, y: 0, canvas: Canvas::transparent(1, 1), backdrop: Canvas::transparent(1, 1), alpha: vec![0.] };
+        layer.put(0, 0, [255, 0, 0], 1., 0.5, BlendMode::Normal);
+        layer.put(0, 0, [0, 0, 255], 1., 0.5, BlendMode::Normal);
+        assert_eq!(layer.canvas.pixels, vec![0, 0, 255, 128]);
+        assert_eq!(layer.alpha, vec![0.5]);
+    }
+
+    fn render(resources: &str, content: &[u8], objects: &[(&str, &[u8])]) -> Canvas {
+        let pdf = test_page_pdf(resources, content, objects);
+        render_page(&pdf, pdf.get_page_list()[0], 72.)
+    }
+
+    #[test]
+    fn test_soft_masks() {
+        // A luminosity mask of black on the left, over a white backdrop given by /BC.
+        let canvas = render("<< /ExtGState << /M 5 0 R >> >>", b"/M gs 1 0 0 rg 0 0 100 100 re f", &[
+            ("<< /SMask << /S /Luminosity /G 6 0 R /BC [1] >> >>", b""),
+            ("<< /Subtype /Form /BBox [0 0 100 100] /Group << /S /Transparency /CS /DeviceGray >> >>", b"0 g 0 0 50 100 re f"),
+        ]);
+        assert_eq!((canvas.pixel(25, 50), canvas.pixel(75, 50)), ([255, 255, 255, 255], [255, 0, 0, 255]));
+        // An alpha mask, opaque where its group paints.
+        let canvas = render("<< /ExtGState << /M 5 0 R >> >>", b"/M gs 0 0 1 rg 0 0 100 100 re f", &[
+            ("<< /SMask << /S /Alpha /G 6 0 R >> >>", b""),
+            ("<< /Subtype /Form /BBox [0 0 100 100] /Group << /S /Transparency >> >>", b"0 0 50 100 re f"),
+        ]);
+        assert_eq!((canvas.pixel(25, 50), canvas.pixel(75, 50)), ([0, 0, 255, 255], [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_groups() {
+        // Yellow multiplied on green is green, except in an isolated group, where nothing
+        // is below it.
+        let form = "/Subtype /Form /BBox [0 0 50 100] /Resources << /ExtGState << /M << /BM /Multiply >> >> >>";
+        let isolated = format!("<< {form} /Group << /S /Transparency /I true >> >>");
+        let shared = format!("<< {form} /Group << /S /Transparency >> /Matrix [1 0 0 1 50 0] >>");
+        let canvas = render("<< /XObject << /I 5 0 R /S 6 0 R >> >>", b"0 1 0 rg 0 0 100 100 re f /I Do /S Do", &[
+            (&isolated, b"/M gs 1 1 0 rg 0 0 50 100 re f"),
+            (&shared, b"/M gs 1 1 0 rg 0 0 50 100 re f"),
+        ]);
+        assert_eq!((canvas.pixel(25, 50), canvas.pixel(75, 50)), ([255, 255, 0, 255], [0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_constant_alpha() {
+        // Red filled at half opacity and a blue line stroked at a quarter, on white.
+        let content = b"/A gs 1 0 0 rg 0 0 1 RG 10 w 0 0 50 100 re f 75 0 m 75 100 l S";
+        let canvas = render("<< /ExtGState << /A << /ca 0.5 /CA 0.25 >> >> >>", content, &[]);
+        assert_eq!((canvas.pixel(25, 50), canvas.pixel(75, 50)), ([255, 128, 128, 255], [191, 191, 255, 255]));
+    }
+}
+
@@ -0,0 +1,174 @@
+use super::layout::{Block, get_page_blocks};
+use super::operation::TextPaintingOperation;
+use super::parser::PDF;
+use regex::Regex;
+
+pub type Quad = [(f64, f64); 4];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub ignore_case: bool,
+    pub regex: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub page: usize,
+    pub text: String,
+    pub quads: Vec<Quad>,
+}
+
+struct PageText<'a> {
+    text: String,
+    // For every byte of `text`, the span and char index it came from.
+    chars: Vec<Option<(&'a TextPaintingOperation, usize)>>,
+}
+
+impl<'a> PageText<'a> {
+    fn new(blocks: &'a [Block]) -> PageText<'a> {
+        let mut ret = PageText { text: String::new(), chars: Vec::new() };
+        for block in blocks {
+            if !ret.text.is_empty() {
+                ret.push_str("\n\n");
+            }
+            for (i, line) in block.lines.iter().enumerate() {
+                if i > 0 {
+                    ret.push_str(if ret.text.ends_with('-') { "\n" } else { " " });
+                }
+                let mut prev: Option<&TextPaintingOperation> = None;
+                for span in &line.spans {
+                    if let Some(prev) = prev {
+                        let gap = span.x - (prev.x + prev.width);
+                        if gap > 0.15 * prev.font_size.min(span.font_size) && !ret.text.ends_with(' ') {
+                            ret.push_str(" ");
+                        }
+                    }
+                    for (j, c) in span.text.chars().enumerate() {
+                        ret.push(c, Some((span, j)));
+                    }
+                    prev = Some(span);
+                }
+            }
+        }
+        ret
+    }
+
+    fn push(&mut self, c: char, source: Option<(&'a TextPaintingOperation, usize)>) {
+        self.text.push(c);
+        for _ in 0 .. c.len_utf8() {
+            self.chars.push(source);
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.push(c, None);
+        }
+    }
+
+    fn quads(&self, start: usize, end: usize) -> Vec<Quad> {
+        let mut ret = Vec::new();
+        let mut current: Option<(&TextPaintingOperation, usize, usize)> = None;
+        for &(span, i) in self.chars[start..end].iter().flatten() {
+            match current {
+                Some((s, a, b)) if std::ptr::eq(s, span) => current = Some((s, a.min(i), b.max(i))),
+                _ => {
+                    if let Some((s, a, b)) = current {
+                        ret.push(s.quad(a, b));
+                    }
+                    current = Some((span, i, i));
+                }
+            }
+        }
+        if let Some((s, a, b)) = current {
+            ret.push(s.quad(a, b));
+        }
+        ret
+    }
+}
+
+pub fn build_pattern(query: &str, options: SearchOptions) -> Result<Regex, String> {
+    let mut pattern = String::new();
+    if options.ignore_case {
+        pattern.push_str("(?i)");
+    }
+    if options.regex {
+        pattern.push_str(query);
+    } else {
+        let words = query.split_whitespace().map(|word| {
+            word.chars().map(|c| regex::escape(&c.to_string())).collect::<Vec<_>>().join("(?:-?\n)?")
+        }).collect::<Vec<_>>();
+        if words.is_empty() {
+            return Err("Empty query".into());
+        }
+        pattern.push_str(&words.join(r"\s+"));
+    }
+    Regex::new(&pattern).map_err(|e| e.to_string())
+}
+
+pub fn search_blocks(blocks: &[Block], pattern: &Regex, page: usize) -> Vec<Hit> {
+    let text = PageText::new(blocks);
+    pattern.find_iter(&text.text)
+        .filter(|m| !m.is_empty())
+        .map(|m| Hit {
+            page,
+            text: m.as_str().into(),
+            quads: text.quads(m.start(), m.end()),
+        })
+        .collect()
+}
+
+impl PDF {
+    pub fn search(&self, query: &str) -> Vec<Hit> {
+        self.search_with(query, SearchOptions::default()).unwrap_or_default()
+    }
+
+    pub fn search_with(&self, query: &str, options: SearchOptions) -> Result<Vec<Hit>, String> {
+        let pattern = build_pattern(query, options)?;
+        Ok(self.get_page_list().into_iter().enumerate()
+            .flat_map(|(i, page)| search_blocks(&get_page_blocks(self, page), &pattern, i))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::layout::{get_lines, reading_order};
+
+    fn span(x: f64, y: f64, text: &str) -> TextPaintingOperation {
+        let offsets = (0 .. text.len()).map(|i| i as f64 * 5.).collect();
+        TextPaintingOperation { offsets, ..TextPaintingOperation::test_span(x, y, text) }
+    }
+
+    fn blocks() -> Vec<Block> {
+        reading_order(get_lines(vec![
+            span(100., 700., "We use multi-head"),
+            span(100., 688., "attention with a hyphen-"),
+            span(100., 676., "ation rule."),
+        ]))
+    }
+
+    #[test]
+    fn test_search() {
+        let blocks = blocks();
+        let hits = search_blocks(&blocks, &build_pattern("multi-head attention", SearchOptions::default()).unwrap(), 3);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].page, 3);
+        assert_eq!(hits[0].quads.len(), 2);
+        assert_eq!(hits[0].quads[0][0], (135., 708.));
+        assert_eq!(hits[0].quads[1][3], (145., 686.));
+
+        let hits = search_blocks(&blocks, &build_pattern("hyphenation", SearchOptions::default()).unwrap(), 0);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].quads.len(), 2);
+
+        let options = SearchOptions { ignore_case: true, ..Default::default() };
+        assert_eq!(search_blocks(&blocks, &build_pattern("WE USE", options).unwrap(), 0).len(), 1);
+        assert!(search_blocks(&blocks, &build_pattern("WE USE", SearchOptions::default()).unwrap(), 0).is_empty());
+
+        let options = SearchOptions { regex: true, ..Default::default() };
+        let hits = search_blocks(&blocks, &build_pattern(r"\bw\w+", options).unwrap(), 0);
+        assert_eq!(hits.iter().map(|x| x.text.as_str()).collect::<Vec<_>>(), vec!["with"]);
+    }
+}
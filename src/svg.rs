@@ -0,0 +1,252 @@
+use super::content::{get_page_content, load_content_fonts, Graphic, PageContent, PlacedImage};
+use super::hocr::escape;
+use super::html::{css_color, font_styles};
+use super::operation::Matrix;
+use super::path::{Clip, Path, Segment};
+use super::parser::{Object, PDF};
+use std::collections::HashSet;
+
+// Path data in SVG user space, which has its origin at the top left corner of the page.
+pub fn path_data(segments: &[Segment], top: f64, left: f64) -> String {
+    let mut d = String::new();
+    for segment in segments {
+        match *segment {
+            Segment::MoveTo(x, y) => d.push_str(&format!("M{:.2} {:.2}", x - left, top - y)),
+            Segment::LineTo(x, y) => d.push_str(&format!("L{:.2} {:.2}", x - left, top - y)),
+            Segment::CurveTo(x1, y1, x2, y2, x3, y3) => d.push_str(&format!(
+                "C{:.2} {:.2} {:.2} {:.2} {:.2} {:.2}",
+                x1 - left, top - y1, x2 - left, top - y2, x3 - left, top - y3,
+            )),
+            Segment::Close => d.push('Z'),
+        }
+    }
+    d
+}
+
+fn paint_attributes(path: &Path) -> String {
+    let style = &path.style;
+    let mut ret = String::new();
+    if path.fill {
+        ret.push_str(&format!(" fill=\"{}\"", css_color(style.fill.rgb)));
+        if path.even_odd {
+            ret.push_str(" fill-rule=\"evenodd\"");
+        }
+    } else {
+        ret.push_str(" fill=\"none\"");
+    }
+    if !path.stroke {
+        return ret;
+    }
+    ret.push_str(&format!(
+        " stroke=\"{}\" stroke-width=\"{:.2}\"",
+        css_color(style.stroke.rgb),
+        // A width of 0 means the thinnest line the device can render.
+        path.line_width.max(0.1),
+    ));
+    match style.line_cap {
+        1 => ret.push_str(" stroke-linecap=\"round\""),
+        2 => ret.push_str(" stroke-linecap=\"square\""),
+        _ => {}
+    }
+    match style.line_join {
+        1 => ret.push_str(" stroke-linejoin=\"round\""),
+        2 => ret.push_str(" stroke-linejoin=\"bevel\""),
+        _ => if style.miter_limit != 4. {
+            ret.push_str(&format!(" stroke-miterlimit=\"{}\"", style.miter_limit.max(1.)));
+        },
+    }
+    if style.dash.iter().any(|&x| x > 0.) {
+        let dash = style.dash.iter().map(|x| format!("{x:.2}")).collect::<Vec<_>>();
+        ret.push_str(&format!(" stroke-dasharray=\"{}\"", dash.join(" ")));
+        if style.dash_phase != 0. {
+            ret.push_str(&format!(" stroke-dashoffset=\"{:.2}\"", style.dash_phase));
+        }
+    }
+    ret
+}
+
+// Clip paths written so far, each nested in the one for the clips before it.
+struct Clips {
+    defs: String,
+    chains: Vec<Vec<Clip>>,
+    top: f64,
+    left: f64,
+}
+
+impl Clips {
+    // The clip-path attribute for the intersection of `clip`.
+    fn attribute(&mut self, clip: &[Clip]) -> String {
+        match self.id(clip) {
+            Some(id) => format!(" clip-path=\"url(#clip{id})\""),
+            None => String::new(),
+        }
+    }
+
+    fn id(&mut self, clip: &[Clip]) -> Option<usize> {
+        let (last, rest) = clip.split_last()?;
+        if let Some(i) = self.chains.iter().position(|x| x == clip) {
+            return Some(i);
+        }
+        let parent = self.attribute(rest);
+        let rule = if last.even_odd { " clip-rule=\"evenodd\"" } else { "" };
+        let id = self.chains.len();
+        self.defs.push_str(&format!(
+            "<clipPath id=\"clip{id}\"{parent}><path d=\"{}\"{rule}/></clipPath>\n",
+            path_data(&last.segments, self.top, self.left),
+        ));
+        self.chains.push(clip.to_vec());
+        Some(id)
+    }
+}
+
+fn svg_image(placed: &PlacedImage, top: f64, left: f64, clip: String) -> String {
+    // Image space has its first row at the top of the unit square.
+    let m = Matrix::new(1., 0., 0., -1., 0., 1.)
+        .multiply(&placed.matrix)
+        .multiply(&Matrix::new(1., 0., 0., -1., -left, top));
+    format!(
+        "<image width=\"1\" height=\"1\" preserveAspectRatio=\"none\" transform=\"matrix({:.6} {:.6} {:.6} {:.6} {:.2} {:.2})\"{clip} href=\"{}\"/>\n",
+        m.a, m.b, m.c, m.d, m.e, m.f,
+        placed.image.data_uri(),
+    )
+}
+
+// The paths, images and Type 3 glyphs of the page, in painting order.
+fn graphics(content: &PageContent, clips: &mut Clips) -> String {
+    let (top, left) = (clips.top, clips.left);
+    let mut body = String::new();
+    for graphic in content.graphics() {
+        match graphic {
+            Graphic::Path(path) => {
+                let clip = clips.attribute(&path.clip);
+                body.push_str(&format!("<path d=\"{}\"{}{clip}/>\n", path_data(&path.segments, top, left), paint_attributes(path)));
+            }
+            Graphic::Image(image) => {
+                let clip = clips.attribute(&image.clip);
+                body.push_str(&svg_image(image, top, left, clip));
+            }
+        }
+    }
+    body
+}
+
+fn svg_document(width: f64, height: f64, style: &str, clips: &Clips, body: &str) -> String {
+    let mut ret = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}pt\" height=\"{height}pt\" viewBox=\"0 0 {width} {height}\">\n",
+    );
+    ret.push_str("<defs>\n<style>\ntext { white-space: pre; }\n");
+    ret.push_str(style);
+    ret.push_str("</style>\n");
+    ret.push_str(&clips.defs);
+    ret.push_str("</defs>\n");
+    ret.push_str(body);
+    ret.push_str("</svg>\n");
+    ret
+}
+
+// An SVG document of the graphics of the page without its text, for pages whose text
+// is laid out separately, or None when the page has no graphics.
+pub fn graphics_to_svg(content: &PageContent, media_box: (f64, f64, f64, f64)) -> Option<String> {
+    if content.paths.is_empty() && content.images.is_empty() && content.glyphs.is_empty() {
+        return None;
+    }
+    let mut clips = Clips { defs: String::new(), chains: Vec::new(), top: media_box.3, left: media_box.0 };
+    let body = graphics(content, &mut clips);
+    Some(svg_document(media_box.2 - media_box.0, media_box.3 - media_box.1, "", &clips, &body))
+}
+
+// A standalone SVG document of the page, with vector graphics, images as data URIs and
+// text as <text> elements in embedded fonts.
+pub fn page_to_svg(pdf: &PDF, page: &Object) -> String {
+    let fonts = load_content_fonts(pdf, pdf.get_page_resources(page));
+    let content = get_page_content(pdf, page);
+    let media_box = pdf.get_media_box(page).unwrap_or((0., 0., 612., 792.));
+    let (width, height) = (media_box.2 - media_box.0, media_box.3 - media_box.1);
+    let (top, left) = (media_box.3, media_box.0);
+    let (style, font_styles) = font_styles(&fonts, "f");
+    let mut clips = Clips { defs: String::new(), chains: Vec::new(), top, left };
+
+    let mut body = graphics(&content, &mut clips);
+    // Type 3 glyphs are drawn as paths above, leaving their text transparent but selectable.
+    let drawn = content.glyphs.iter().map(|x| &*x.font).collect::<HashSet<_>>();
+    for span in &content.texts {
+        if span.text.trim().is_empty() {
+            continue;
+        }
+        let font_style = font_styles.get(&span.font_key).map_or("font-family: serif", |x| x);
+        let fill = if span.render_mode == 3 || span.render_mode == 7 || drawn.contains(&*span.font_key) {
+            "transparent".into()
+        } else {
+            css_color(span.rgb)
+        };
+        let length = if span.width > 0. { format!(" textLength=\"{:.2}\" lengthAdjust=\"spacingAndGlyphs\"", span.width) } else { String::new() };
+        body.push_str(&format!(
+            "<text transform=\"translate({:.2} {:.2}) rotate({:.2})\" font-size=\"{:.2}\" style=\"{font_style}\" fill=\"{fill}\"{length}>{}</text>\n",
+            span.x - left,
+            top - span.y,
+            -span.angle.to_degrees(),
+            span.font_size,
+            escape(&span.text),
+        ));
+    }
+
+    svg_document(width, height, &style, &clips, &body)
+}
+
+pub fn get_page_svg(pdf: &PDF, index: usize) -> Option<String> {
+    Some(page_to_svg(pdf, pdf.get_page(index)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorSpace;
+    use crate::path::Style;
+
+    #[test]
+    fn test_paint_attributes() {
+        let path = Path {
+            stroke: true,
+            line_width: 2.,
+            style: Style {
+                stroke: ColorSpace::DeviceRGB.color("DeviceRGB", vec![1., 0., 0.]),
+                line_cap: 1,
+                dash: vec![3., 1.],
+                ..Style::default()
+            },
+            ..Path::default()
+        };
+        assert_eq!(
+            paint_attributes(&path),
+            " fill=\"none\" stroke=\"#ff0000\" stroke-width=\"2.00\" stroke-linecap=\"round\" stroke-miterlimit=\"10\" stroke-dasharray=\"3.00 1.00\"",
+        );
+    }
+
+    #[test]
+    fn test_nested_clips() {
+        let square = |x: f64| Clip { segments: vec![Segment::MoveTo(x, 0.), Segment::LineTo(x, 1.), Segment::Close], even_odd: false };
+        let mut clips = Clips { defs: String::new(), chains: Vec::new(), top: 1., left: 0. };
+        assert_eq!(clips.attribute(&[]), "");
+        assert_eq!(clips.attribute(&[square(0.), square(1.)]), " clip-path=\"url(#clip1)\"");
+        assert_eq!(clips.attribute(&[square(0.)]), " clip-path=\"url(#clip0)\"");
+        assert_eq!(clips.defs.lines().nth(1), Some("<clipPath id=\"clip1\" clip-path=\"url(#clip0)\"><path d=\"M1.00 1.00L1.00 0.00Z\"/></clipPath>"));
+    }
+
+    #[test]
+    fn test_graphics_to_svg() {
+        let media_box = (0., 0., 100., 100.);
+        assert_eq!(graphics_to_svg(&PageContent::default(), media_box), None);
+        let path = Path {
+            segments: vec![Segment::MoveTo(10., 10.), Segment::LineTo(20., 10.), Segment::LineTo(20., 20.), Segment::Close],
+            fill: true,
+            even_odd: true,
+            clip: vec![Clip { segments: vec![Segment::MoveTo(0., 0.), Segment::LineTo(50., 0.), Segment::LineTo(50., 50.), Segment::Close], even_odd: false }],
+            style: Style { fill: ColorSpace::DeviceRGB.color("DeviceRGB", vec![0., 0., 1.]), ..Style::default() },
+            ..Path::default()
+        };
+        let content = PageContent { paths: vec![path], ..PageContent::default() };
+        let svg = graphics_to_svg(&content, media_box).unwrap();
+        assert!(svg.contains("<path d=\"M10.00 90.00L20.00 90.00L20.00 80.00Z\" fill=\"#0000ff\" fill-rule=\"evenodd\" clip-path=\"url(#clip0)\"/>"));
+        assert!(svg.contains("<clipPath id=\"clip0\">"));
+    }
+}
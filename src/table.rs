@@ -0,0 +1,496 @@
+use super::content::{PageContent, get_page_content};
+use super::operation::TextPaintingOperation;
+use super::path::{Path, subpath_lines};
+use super::parser::{Object, PDF};
+use serde::Serialize;
+
+const TOLERANCE: f64 = 2.;
+const THIN: f64 = 3.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ruling {
+    pos: f64,
+    start: f64,
+    end: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Cell {
+    pub row: usize,
+    pub col: usize,
+    pub row_span: usize,
+    pub col_span: usize,
+    pub bbox: (f64, f64, f64, f64),
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Table {
+    pub bbox: (f64, f64, f64, f64),
+    pub rows: usize,
+    pub cols: usize,
+    pub cells: Vec<Cell>,
+}
+
+impl Table {
+    pub fn grid(&self) -> Vec<Vec<String>> {
+        let mut ret = vec![vec![String::new(); self.cols]; self.rows];
+        for cell in &self.cells {
+            let x = &mut ret[cell.row][cell.col];
+            if !x.is_empty() {
+                x.push(' ');
+            }
+            x.push_str(&cell.text);
+        }
+        ret
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut ret = String::new();
+        for row in self.grid() {
+            let fields = row.iter().map(|x| {
+                if x.contains([',', '"', '\n']) {
+                    format!("\"{}\"", x.replace('"', "\"\""))
+                } else {
+                    x.clone()
+                }
+            }).collect::<Vec<_>>();
+            ret.push_str(&fields.join(","));
+            ret.push('\n');
+        }
+        ret
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+fn merge_rulings(mut rulings: Vec<Ruling>) -> Vec<Ruling> {
+    rulings.sort_by(|a, b| a.pos.total_cmp(&b.pos).then(a.start.total_cmp(&b.start)));
+    let mut ret: Vec<Ruling> = Vec::new();
+    for r in rulings {
+        match ret.iter_mut().rev().find(|x| (x.pos - r.pos).abs() <= TOLERANCE && r.start <= x.end + TOLERANCE) {
+            Some(x) => x.end = x.end.max(r.end),
+            None => ret.push(r),
+        }
+    }
+    ret
+}
+
+pub fn get_rulings(paths: &[Path]) -> (Vec<Ruling>, Vec<Ruling>) {
+    let mut horizontal = Vec::new();
+    let mut vertical = Vec::new();
+    for path in paths {
+        for subpath in path.subpaths() {
+            let lines = subpath_lines(subpath);
+            if lines.is_empty() {
+                continue;
+            }
+            let (x0, y0, x1, y1) = lines.iter().fold((f64::MAX, f64::MAX, f64::MIN, f64::MIN), |b, &(p, q)| {
+                (b.0.min(p.0).min(q.0), b.1.min(p.1).min(q.1), b.2.max(p.0).max(q.0), b.3.max(p.1).max(q.1))
+            });
+            if path.fill && y1 - y0 <= THIN && x1 - x0 > y1 - y0 {
+                horizontal.push(Ruling { pos: (y0 + y1) / 2., start: x0, end: x1 });
+                continue;
+            }
+            if path.fill && x1 - x0 <= THIN && y1 - y0 > x1 - x0 {
+                vertical.push(Ruling { pos: (x0 + x1) / 2., start: y0, end: y1 });
+                continue;
+            }
+            for ((ax, ay), (bx, by)) in lines {
+                if (ay - by).abs() <= TOLERANCE / 2. && (ax - bx).abs() > THIN {
+                    horizontal.push(Ruling { pos: (ay + by) / 2., start: ax.min(bx), end: ax.max(bx) });
+                } else if (ax - bx).abs() <= TOLERANCE / 2. && (ay - by).abs() > THIN {
+                    vertical.push(Ruling { pos: (ax + bx) / 2., start: ay.min(by), end: ay.max(by) });
+                }
+            }
+        }
+    }
+    (merge_rulings(horizontal), merge_rulings(vertical))
+}
+
+fn crosses(h: &Ruling, v: &Ruling) -> bool {
+    v.pos >= h.start - TOLERANCE && v.pos <= h.end + TOLERANCE
+        && h.pos >= v.start - TOLERANCE && h.pos <= v.end + TOLERANCE
+}
+
+fn framed(hs: &[Ruling], vs: &[Ruling]) -> bool {
+    let left = hs.iter().map(|x| x.start).fold(f64::MAX, f64::min);
+    let right = hs.iter().map(|x| x.end).fold(f64::MIN, f64::max);
+    let bottom = vs.iter().map(|x| x.start).fold(f64::MAX, f64::min);
+    let top = vs.iter().map(|x| x.end).fold(f64::MIN, f64::max);
+    let near = |rulings: &[Ruling], pos: f64| rulings.iter().any(|x| (x.pos - pos).abs() <= THIN);
+    near(vs, left) && near(vs, right) && near(hs, bottom) && near(hs, top)
+}
+
+fn cluster(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(f64::total_cmp);
+    let mut ret: Vec<f64> = Vec::new();
+    for v in values {
+        match ret.last() {
+            Some(&last) if v - last <= THIN => {}
+            _ => ret.push(v),
+        }
+    }
+    ret
+}
+
+fn group_lines<'a>(spans: &[&'a TextPaintingOperation]) -> Vec<Vec<&'a TextPaintingOperation>> {
+    let mut spans = spans.to_vec();
+    spans.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+    let mut ret: Vec<Vec<&TextPaintingOperation>> = Vec::new();
+    let mut extent = (f64::MAX, f64::MIN);
+    for span in spans {
+        let middle = span.y + 0.3 * span.font_size;
+        match ret.last_mut() {
+            Some(line) if middle >= extent.0 && middle <= extent.1 => line.push(span),
+            _ => {
+                ret.push(vec![span]);
+                extent = (f64::MAX, f64::MIN);
+            }
+        }
+        extent = (extent.0.min(span.y - 0.2 * span.font_size), extent.1.max(span.y + 0.8 * span.font_size));
+    }
+    for line in &mut ret {
+        line.sort_by(|a, b| a.x.total_cmp(&b.x));
+    }
+    ret
+}
+
+fn cell_text(spans: &[&TextPaintingOperation]) -> String {
+    let mut ret = String::new();
+    for line in group_lines(spans) {
+        let mut prev: Option<&TextPaintingOperation> = None;
+        for span in line {
+            let gap = prev.map_or(f64::MAX, |x| span.x - (x.x + x.width));
+            if !ret.is_empty() && gap > 0.15 * span.font_size {
+                ret.push(' ');
+            }
+            ret.push_str(span.text.trim());
+            prev = Some(span);
+        }
+    }
+    ret
+}
+
+fn inside(span: &TextPaintingOperation, bbox: (f64, f64, f64, f64)) -> bool {
+    let x = span.x + span.width / 2.;
+    let y = span.y + span.font_size * 0.3;
+    x >= bbox.0 && x <= bbox.2 && y >= bbox.1 && y <= bbox.3
+}
+
+fn lattice_table(hs: &[Ruling], vs: &[Ruling], texts: &[TextPaintingOperation]) -> Option<Table> {
+    let xs = cluster(vs.iter().map(|x| x.pos).collect());
+    let mut ys = cluster(hs.iter().map(|x| x.pos).collect());
+    ys.reverse();
+    if xs.len() < 2 || ys.len() < 2 {
+        return None;
+    }
+    let (rows, cols) = (ys.len() - 1, xs.len() - 1);
+    let v_ruled = |x: f64, r: usize| vs.iter().any(|v| {
+        (v.pos - x).abs() <= THIN && v.start <= ys[r + 1] + TOLERANCE && v.end >= ys[r] - TOLERANCE
+    });
+    let h_ruled = |y: f64, c: usize| hs.iter().any(|h| {
+        (h.pos - y).abs() <= THIN && h.start <= xs[c] + TOLERANCE && h.end >= xs[c + 1] - TOLERANCE
+    });
+    let mut assigned = vec![vec![false; cols]; rows];
+    let mut cells = Vec::new();
+    for r in 0 .. rows {
+        for c in 0 .. cols {
+            if assigned[r][c] {
+                continue;
+            }
+            let mut col_span = 1;
+            while c + col_span < cols && !v_ruled(xs[c + col_span], r) && !assigned[r][c + col_span] {
+                col_span += 1;
+            }
+            let mut row_span = 1;
+            while r + row_span < rows && (c .. c + col_span).all(|cc| !h_ruled(ys[r + row_span], cc) && !assigned[r + row_span][cc]) {
+                row_span += 1;
+            }
+            for row in assigned.iter_mut().skip(r).take(row_span) {
+                for x in row.iter_mut().skip(c).take(col_span) {
+                    *x = true;
+                }
+            }
+            let bbox = (xs[c], ys[r + row_span], xs[c + col_span], ys[r]);
+            let spans = texts.iter().filter(|x| inside(x, bbox)).collect::<Vec<_>>();
+            cells.push(Cell { row: r, col: c, row_span, col_span, bbox, text: cell_text(&spans) });
+        }
+    }
+    Some(Table {
+        bbox: (xs[0], ys[rows], xs[cols], ys[0]),
+        rows,
+        cols,
+        cells,
+    })
+}
+
+fn low_runs(coverage: &[usize], from: usize, threshold: usize) -> Vec<(usize, usize)> {
+    let mut ret = Vec::new();
+    let mut i = from;
+    while i < coverage.len() {
+        if coverage[i] > threshold {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < coverage.len() && coverage[i] <= threshold {
+            i += 1;
+        }
+        ret.push((start, i));
+    }
+    ret
+}
+
+fn aligned_table(bbox: (f64, f64, f64, f64), rules: &[f64], texts: &[TextPaintingOperation]) -> Option<Table> {
+    let spans = texts.iter().filter(|x| inside(x, bbox) && !x.text.trim().is_empty()).collect::<Vec<_>>();
+    let lines = group_lines(&spans);
+    if lines.len() < 2 {
+        return None;
+    }
+
+    let mut sizes = lines.iter().flatten().map(|x| x.font_size).collect::<Vec<_>>();
+    sizes.sort_by(f64::total_cmp);
+    let size = sizes[sizes.len() / 2];
+
+    let phrases = lines.iter().map(|line| {
+        let mut ret: Vec<Vec<&TextPaintingOperation>> = Vec::new();
+        for &span in line {
+            match ret.last_mut() {
+                Some(phrase) if span.x - phrase.iter().map(|x| x.x + x.width).fold(f64::MIN, f64::max) < 0.5 * size => phrase.push(span),
+                _ => ret.push(vec![span]),
+            }
+        }
+        ret
+    }).collect::<Vec<_>>();
+
+    let x0 = bbox.0.floor();
+    let width = (bbox.2 - x0).ceil() as usize + 1;
+    let mut coverage = vec![0usize; width];
+    for line in &phrases {
+        for phrase in line {
+            let start = ((phrase[0].x - x0).floor().max(0.) as usize).min(width);
+            let end = phrase.iter().map(|x| x.x + x.width).fold(f64::MIN, f64::max);
+            let end = ((end - x0).ceil().max(0.) as usize).min(width);
+            for c in &mut coverage[start..end] {
+                *c += 1;
+            }
+        }
+    }
+    let threshold = (lines.len() / 4).max(1);
+    let first = coverage.iter().position(|&x| x > 0)?;
+    let last = coverage.iter().rposition(|&x| x > 0)?;
+    let mut separators = Vec::new();
+    for (start, end) in low_runs(&coverage[..=last], first, threshold) {
+        if ((end - start) as f64) < 0.5 * size {
+            continue;
+        }
+        let mut found = Vec::new();
+        if (end - start) as f64 > 2. * size {
+            let low = *coverage[start..end].iter().min().unwrap();
+            found = low_runs(&coverage[..end], start, low.max(1))
+                .into_iter()
+                .filter(|(a, b)| (b - a) as f64 >= 0.5 * size)
+                .collect();
+        }
+        if found.is_empty() {
+            found.push((start, end));
+        }
+        for (a, b) in found {
+            if !rules.iter().any(|&x| x >= x0 + a as f64 && x <= x0 + b as f64) {
+                separators.push(x0 + (a + b) as f64 / 2.);
+            }
+        }
+    }
+
+    separators.extend(rules.iter().filter(|&&x| x > bbox.0 + THIN && x < bbox.2 - THIN));
+    separators.sort_by(f64::total_cmp);
+    let mut edges = vec![bbox.0];
+    edges.extend(&separators);
+    edges.push(bbox.2);
+    let column_of = |x: f64| edges.windows(2).position(|w| x < w[1]).unwrap_or(edges.len() - 2);
+
+    let mut cells = Vec::new();
+    let mut tops = vec![bbox.3];
+    for (r, line) in phrases.iter().enumerate() {
+        let baseline = line.iter().flatten().map(|x| x.y).fold(f64::MAX, f64::min);
+        let bottom = match phrases.get(r + 1) {
+            Some(next) => (baseline + next.iter().flatten().map(|x| x.y + x.font_size).fold(f64::MIN, f64::max)) / 2.,
+            None => bbox.1,
+        };
+        tops.push(bottom);
+        for phrase in line {
+            let start = column_of(phrase[0].x);
+            let end = column_of(phrase.iter().map(|x| x.x + x.width).fold(f64::MIN, f64::max) - 0.1);
+            cells.push(Cell {
+                row: r,
+                col: start,
+                row_span: 1,
+                col_span: end.max(start) - start + 1,
+                bbox: (edges[start], bottom, edges[end.max(start) + 1], tops[r]),
+                text: cell_text(phrase),
+            });
+        }
+    }
+
+    let cols = edges.len() - 1;
+    let used = (0 .. cols).filter(|&c| cells.iter().any(|x| x.col == c && x.col_span == 1)).collect::<Vec<_>>();
+    if used.len() < 2 {
+        return None;
+    }
+    let remap = |c: usize| used.iter().filter(|&&x| x < c).count().min(used.len() - 1);
+    for cell in &mut cells {
+        let (start, end) = (remap(cell.col), remap(cell.col + cell.col_span - 1));
+        cell.col = start;
+        cell.col_span = end - start + 1;
+    }
+
+    Some(Table {
+        bbox,
+        rows: phrases.len(),
+        cols: used.len(),
+        cells,
+    })
+}
+
+pub fn find_tables(content: &PageContent) -> Vec<Table> {
+    let (hs, vs) = get_rulings(&content.paths);
+    let mut ret = Vec::new();
+
+    let n = hs.len() + vs.len();
+    let mut parent = (0 .. n).collect::<Vec<_>>();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut i = i;
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for (i, h) in hs.iter().enumerate() {
+        for (j, v) in vs.iter().enumerate() {
+            if crosses(h, v) {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, hs.len() + j));
+                parent[a] = b;
+            }
+        }
+    }
+    let mut used = vec![false; hs.len()];
+    let roots = (0 .. n).map(|i| find(&mut parent, i)).collect::<Vec<_>>();
+    let mut components = roots.clone();
+    components.sort();
+    components.dedup();
+    for root in components {
+        let h = (0 .. hs.len()).filter(|&i| roots[i] == root).collect::<Vec<_>>();
+        let v = (0 .. vs.len()).filter(|&j| roots[hs.len() + j] == root).collect::<Vec<_>>();
+        if h.len() < 2 || v.len() < 2 {
+            continue;
+        }
+        let hr = h.iter().map(|&i| hs[i]).collect::<Vec<_>>();
+        let vr = v.iter().map(|&j| vs[j]).collect::<Vec<_>>();
+        if !framed(&hr, &vr) {
+            continue;
+        }
+        if let Some(table) = lattice_table(&hr, &vr, &content.texts) {
+            if table.cells.iter().filter(|x| !x.text.is_empty()).count() >= 2 {
+                ret.push(table);
+            }
+            for i in h {
+                used[i] = true;
+            }
+        }
+    }
+
+    let mut free = hs.iter().enumerate().filter(|(i, _)| !used[*i]).map(|(_, x)| *x).collect::<Vec<_>>();
+    free.sort_by(|a, b| (b.end - b.start).total_cmp(&(a.end - a.start)));
+    let mut taken = vec![false; free.len()];
+    for i in 0 .. free.len() {
+        if taken[i] {
+            continue;
+        }
+        let rule = free[i];
+        let group = (i .. free.len())
+            .filter(|&j| !taken[j])
+            .filter(|&j| (free[j].start - rule.start).abs() <= 5. && (free[j].end - rule.end).abs() <= 5.)
+            .collect::<Vec<_>>();
+        if group.len() < 2 {
+            continue;
+        }
+        let top = group.iter().map(|&j| free[j].pos).fold(f64::MIN, f64::max);
+        let bottom = group.iter().map(|&j| free[j].pos).fold(f64::MAX, f64::min);
+        for j in (0 .. free.len()).filter(|&j| free[j].pos >= bottom - TOLERANCE && free[j].pos <= top + TOLERANCE) {
+            if free[j].start >= rule.start - 5. && free[j].end <= rule.end + 5. {
+                taken[j] = true;
+            }
+        }
+        let rules = vs.iter().filter(|v| v.start < top && v.end > bottom).map(|v| v.pos).collect::<Vec<_>>();
+        if let Some(table) = aligned_table((rule.start, bottom, rule.end, top), &rules, &content.texts) {
+            ret.push(table);
+        }
+    }
+
+    ret.sort_by(|a, b| b.bbox.3.total_cmp(&a.bbox.3));
+    ret
+}
+
+pub fn get_page_tables(pdf: &PDF, page: &Object) -> Vec<Table> {
+    find_tables(&get_page_content(pdf, page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::path::Segment;
+
+    fn span(x: f64, y: f64, text: &str) -> TextPaintingOperation {
+        TextPaintingOperation::test_span(x, y, text)
+    }
+
+    fn line(x0: f64, y0: f64, x1: f64, y1: f64) -> Path {
+        Path {
+            segments: vec![Segment::MoveTo(x0, y0), Segment::LineTo(x1, y1)],
+            stroke: true,
+            fill: false,
+            even_odd: false,
+            line_width: 1.,
+            ..Path::default()
+        }
+    }
+
+    #[test]
+    fn test_lattice() {
+        let mut paths = Vec::new();
+        for y in [100., 120., 140.] {
+            paths.push(line(0., y, 200., y));
+        }
+        paths.push(line(0., 100., 0., 140.));
+        paths.push(line(100., 100., 100., 120.));
+        paths.push(line(200., 100., 200., 140.));
+        let texts = vec![span(80., 125., "Header"), span(10., 105., "a"), span(110., 105., "b")];
+        let tables = find_tables(&PageContent { texts, paths, ..PageContent::default() });
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0];
+        assert_eq!((table.rows, table.cols), (2, 2));
+        assert_eq!(table.cells[0].col_span, 2);
+        assert_eq!(table.to_csv(), "Header,\na,b\n");
+    }
+
+    #[test]
+    fn test_aligned() {
+        let paths = vec![line(0., 200., 300., 200.), line(0., 160., 300., 160.), line(0., 100., 300., 100.)];
+        let texts = vec![
+            span(10., 185., "Model"), span(150., 185., "BLEU"),
+            span(10., 145., "ByteNet"), span(150., 145., "23.75"), span(240., 145., "1.0"),
+            span(10., 125., "GNMT"), span(150., 125., "24.6"), span(240., 125., "2.3"),
+            span(10., 105., "MoE"), span(150., 105., "26.03"), span(240., 105., "2.0"),
+        ];
+        let tables = find_tables(&PageContent { texts, paths, ..PageContent::default() });
+        assert_eq!(tables.len(), 1);
+        let grid = tables[0].grid();
+        assert_eq!(grid.len(), 4);
+        assert_eq!(grid[0], vec!["Model", "BLEU", ""]);
+        assert_eq!(grid[2], vec!["GNMT", "24.6", "2.3"]);
+    }
+}
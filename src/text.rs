@@ -1,62 +1,93 @@
-use super::operation::TextState;
-use postscript::parser::{Operation, get_one_string};
-use postscript::lexer::Token::*;
-use std::collections::HashMap;    
+use super::operation::{TextState, Matrix, TextPaintingOperation};
+use super::font::Font;
+use super::parser::{Object, PDF};
+use super::content::get_page_content;
+use postscript::parser::content::{ContentOp, TextElement};
+use std::collections::HashMap;
 
-pub fn handle_text_operation(op: Operation, state: &mut TextState, babel: &HashMap<u16, char>) {
-    match &*op.op {
-        "Tf" => {
-            let size = if let Some(&Number(n)) = op.tokens.get(1) {
-                n
-            } else {
-                panic!("wat");
-            };
-            state.set_font_size(size);
+// Returns the code and rendering matrix of every glyph shown.
+fn show_string(bytes: &[u8], state: &mut TextState, font: Option<&Font>) -> Vec<(u32, Matrix)> {
+    let start = state.rendering_matrix();
+    let (x0, y0) = start.apply(0., 0.);
+    let distance = |state: &TextState| {
+        let (x, y) = state.rendering_matrix().apply(0., 0.);
+        ((x - x0).powi(2) + (y - y0).powi(2)).sqrt()
+    };
+    let mut text = String::new();
+    let mut offsets = Vec::new();
+    let mut shown = Vec::new();
+    match font {
+        Some(font) => {
+            for glyph in font.decode(bytes) {
+                shown.push((glyph.code, state.rendering_matrix()));
+                let offset = distance(state);
+                offsets.extend(glyph.text.chars().map(|_| offset));
+                text.push_str(&glyph.text);
+                let tx = state.glyph_advance(glyph.width, glyph.is_space);
+                state.advance(tx);
+            }
+        }
+        None => {
+            text = String::from_utf8_lossy(bytes).into();
+            let tx = state.glyph_advance(0.5, false);
+            for _ in text.chars() {
+                offsets.push(distance(state));
+                state.advance(tx);
+            }
         }
-        "Tm" => {
-            let e = if let Some(&Number(e)) = op.tokens.get(4) {
-                e
-            } else {
-                panic!("wat");
-            };
-            let f = if let Some(&Number(f)) = op.tokens.get(4) {
-                f
-            } else {
-                panic!("wat");
-            };
-            state.set_pos(e, f);
+    }
+    let width = distance(state);
+    state.push_span(text, start, width, offsets);
+    shown
+}
 
+pub fn handle_text_operation(op: &ContentOp, state: &mut TextState, fonts: &HashMap<String, Font>) -> Vec<(u32, Matrix)> {
+    use ContentOp::*;
+    let mut shown = Vec::new();
+    match op {
+        BeginText => state.begin_text(),
+        SetFont(name, size) => state.set_font(name, *size),
+        &SetCharSpacing(x) => state.set_char_spacing(x),
+        &SetWordSpacing(x) => state.set_word_spacing(x),
+        &SetHorizontalScaling(x) => state.set_horizontal_scaling(x),
+        &SetLeading(x) => state.set_leading(x),
+        &SetRise(x) => state.set_rise(x),
+        &SetRenderMode(x) => state.set_render_mode(x as u8),
+        &SetTextMatrix([a, b, c, d, e, f]) => state.set_matrix(Matrix::new(a, b, c, d, e, f)),
+        &MoveText(x, y) => state.next_line(x, y),
+        &MoveTextSetLeading(x, y) => {
+            state.set_leading(-y);
+            state.next_line(x, y);
         }
-        "Td" => {
-            let x_offset = if let Some(Number(x)) = op.tokens.get(0) {
-                x
-            } else {
-                panic!("wat");
-            };
-            let y_offset = if let Some(Number(y)) = op.tokens.get(1) {
-                y
-            } else {
-                panic!("wat");
-            };
-            let (x, y) = state.get_pos();
-            state.set_pos(x + x_offset, y + y_offset);
+        NextLine => state.next_line(0., -state.get_leading()),
+        ShowText(bytes) => shown = show_string(bytes, state, fonts.get(state.get_font_key())),
+        NextLineShowText(bytes) => {
+            state.next_line(0., -state.get_leading());
+            shown = show_string(bytes, state, fonts.get(state.get_font_key()));
         }
-        "TJ" => {
-            for operand in op.tokens {
-                if let Number(n) = &operand {
-                    let size = state.get_font_size();
-                    let (x, y) = state.get_pos();
-                    state.set_pos(x - n / 1000. * size, y);
-                }
-                if let Some(s) = get_one_string(operand, babel) {
-                    let size = state.get_font_size();
-                    let (x, y) = state.get_pos();
-                    let len = s.len();
-                    state.push(s);
-                    state.set_pos(x + len as f64 * size, y);
+        NextLineSpacingShowText(aw, ac, bytes) => {
+            state.set_word_spacing(*aw);
+            state.set_char_spacing(*ac);
+            state.next_line(0., -state.get_leading());
+            shown = show_string(bytes, state, fonts.get(state.get_font_key()));
+        }
+        ShowTextArray(elements) => {
+            let font = fonts.get(state.get_font_key());
+            for element in elements {
+                match element {
+                    &TextElement::Adjust(n) => {
+                        let tx = -n / 1000. * state.get_font_size();
+                        state.advance(tx);
+                    }
+                    TextElement::Text(bytes) => shown.extend(show_string(bytes, state, font)),
                 }
             }
         }
-        _ => {},
+        _ => {}
     }
+    shown
+}
+
+pub fn get_page_texts(pdf: &PDF, page: &Object) -> Vec<TextPaintingOperation> {
+    get_page_content(pdf, page).texts
 }
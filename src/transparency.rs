@@ -0,0 +1,194 @@
+// The transparent imaging model: blend modes, and the soft masks and transparency
+// groups painted content is composited through.
+
+use super::content::PageContent;
+use super::function::Function;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+fn luminosity(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn set_luminosity(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - luminosity(c);
+    let c = c.map(|x| x + d);
+    // Clips the color into range, keeping its luminosity.
+    let l = luminosity(c);
+    let (min, max) = (c[0].min(c[1]).min(c[2]), c[0].max(c[1]).max(c[2]));
+    if min < 0. {
+        c.map(|x| l + (x - l) * l / (l - min))
+    } else if max > 1. {
+        c.map(|x| l + (x - l) * (1. - l) / (max - l))
+    } else {
+        c
+    }
+}
+
+fn saturation(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_saturation(c: [f32; 3], s: f32) -> [f32; 3] {
+    let (min, max) = (c[0].min(c[1]).min(c[2]), c[0].max(c[1]).max(c[2]));
+    if max > min {
+        c.map(|x| (x - min) * s / (max - min))
+    } else {
+        [0.; 3]
+    }
+}
+
+impl BlendMode {
+    pub fn from_name(name: &str) -> Option<BlendMode> {
+        use BlendMode::*;
+        Some(match name {
+            "Normal" | "Compatible" => Normal,
+            "Multiply" => Multiply,
+            "Screen" => Screen,
+            "Overlay" => Overlay,
+            "Darken" => Darken,
+            "Lighten" => Lighten,
+            "ColorDodge" => ColorDodge,
+            "ColorBurn" => ColorBurn,
+            "HardLight" => HardLight,
+            "SoftLight" => SoftLight,
+            "Difference" => Difference,
+            "Exclusion" => Exclusion,
+            "Hue" => Hue,
+            "Saturation" => Saturation,
+            "Color" => Color,
+            "Luminosity" => Luminosity,
+            _ => return None,
+        })
+    }
+
+    // Blends a source color with the backdrop, with components between 0 and 1.
+    pub fn blend(self, backdrop: [f32; 3], source: [f32; 3]) -> [f32; 3] {
+        use BlendMode::*;
+        let separable = |f: fn(f32, f32) -> f32| [0, 1, 2].map(|i| f(backdrop[i], source[i]));
+        let hard_light = |b: f32, s: f32| if s <= 0.5 { b * 2. * s } else { let s = 2. * s - 1.; b + s - b * s };
+        match self {
+            Normal => source,
+            Multiply => separable(|b, s| b * s),
+            Screen => separable(|b, s| b + s - b * s),
+            Overlay => [0, 1, 2].map(|i| hard_light(source[i], backdrop[i])),
+            Darken => separable(f32::min),
+            Lighten => separable(f32::max),
+            ColorDodge => separable(|b, s| if b <= 0. { 0. } else if s >= 1. { 1. } else { (b / (1. - s)).min(1.) }),
+            ColorBurn => separable(|b, s| if b >= 1. { 1. } else if s <= 0. { 0. } else { 1. - ((1. - b) / s).min(1.) }),
+            HardLight => [0, 1, 2].map(|i| hard_light(backdrop[i], source[i])),
+            SoftLight => separable(|b, s| if s <= 0.5 {
+                b - (1. - 2. * s) * b * (1. - b)
+            } else {
+                let d = if b <= 0.25 { ((16. * b - 12.) * b + 4.) * b } else { b.sqrt() };
+                b + (2. * s - 1.) * (d - b)
+            }),
+            Difference => separable(|b, s| (b - s).abs()),
+            Exclusion => separable(|b, s| b + s - 2. * b * s),
+            Hue => set_luminosity(set_saturation(source, saturation(backdrop)), luminosity(backdrop)),
+            Saturation => set_luminosity(set_saturation(backdrop, saturation(source)), luminosity(backdrop)),
+            Color => set_luminosity(source, luminosity(backdrop)),
+            Luminosity => set_luminosity(backdrop, luminosity(source)),
+        }
+    }
+}
+
+// What paint composites through besides its opacity: the blend mode and soft mask of
+// the graphics state, and the transparency group it is painted in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transparency {
+    pub blend: BlendMode,
+    pub soft_mask: Option<Rc<SoftMask>>,
+    pub group: Option<Rc<Group>>,
+}
+
+// A transparency group, whose content is composited together before the result is
+// composited with what is below it.
+#[derive(Debug)]
+pub struct Group {
+    // Isolated groups are painted on a transparent backdrop rather than on what is
+    // below them; in knockout groups, content replaces rather than covers the content
+    // painted before it.
+    pub isolated: bool,
+    pub knockout: bool,
+    // The area of the group in page space, from its bounding box.
+    pub bbox: Option<[f64; 4]>,
+    // How the group as a whole is composited.
+    pub alpha: f64,
+    pub transparency: Transparency,
+}
+
+// Opacity from the luminosity or the alpha of a group painted for the purpose.
+#[derive(Debug)]
+pub struct SoftMask {
+    pub luminosity: bool,
+    pub content: PageContent,
+    // The color the group is painted on, for luminosity masks.
+    pub backdrop: [u8; 3],
+    pub transfer: Option<Function>,
+}
+
+// Groups and masks are the same when they come from painting the same content.
+impl PartialEq for Group {
+    fn eq(&self, other: &Group) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialEq for SoftMask {
+    fn eq(&self, other: &SoftMask) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend() {
+        let (b, s) = ([0.25, 0.5, 0.75], [0.5, 0.5, 0.25]);
+        assert_eq!(BlendMode::Multiply.blend(b, s), [0.125, 0.25, 0.1875]);
+        assert_eq!(BlendMode::Screen.blend(b, s), [0.625, 0.75, 0.8125]);
+        assert_eq!(BlendMode::Darken.blend(b, s), [0.25, 0.5, 0.25]);
+        assert_eq!(BlendMode::Difference.blend(b, s), [0.25, 0., 0.5]);
+        // Overlay is hard light with the colors swapped.
+        assert_eq!(BlendMode::Overlay.blend(b, s), BlendMode::HardLight.blend(s, b));
+        assert_eq!(BlendMode::ColorDodge.blend([0., 0.5, 0.5], [1., 1., 0.5]), [0., 1., 1.]);
+        assert_eq!(BlendMode::ColorBurn.blend([1., 0.5, 0.5], [0., 0., 0.5]), [1., 0., 0.]);
+        assert_eq!(BlendMode::from_name("Compatible"), Some(BlendMode::Normal));
+    }
+
+    #[test]
+    fn test_non_separable() {
+        let gray = [0.5; 3];
+        let red = [1., 0., 0.];
+        // Gray has no hue or saturation to give, and keeps its luminosity.
+        assert_eq!(BlendMode::Saturation.blend(red, gray), [0.3; 3]);
+        let color = BlendMode::Color.blend(gray, red);
+        assert!((luminosity(color) - 0.5).abs() < 1e-6);
+        assert!(color[0] > color[1] && color[1] == color[2]);
+        assert!((luminosity(BlendMode::Luminosity.blend(red, gray)) - 0.5).abs() < 1e-6);
+        assert_eq!(BlendMode::Hue.blend(gray, red), gray);
+    }
+}
@@ -0,0 +1,395 @@
+use super::path::Segment;
+use super::util::{u16_at, u32_at};
+use std::collections::HashMap;
+
+fn i16_at(data: &[u8], pos: usize) -> Option<i16> {
+    u16_at(data, pos).map(|x| x as i16)
+}
+
+// Converts one closed contour of quadratic points into segments.
+fn contour_segments(points: &[(f64, f64, bool)], out: &mut Vec<Segment>) {
+    let n = points.len();
+    if n == 0 {
+        return;
+    }
+    let mid = |a: (f64, f64, bool), b: (f64, f64, bool)| ((a.0 + b.0) / 2., (a.1 + b.1) / 2.);
+    let first = match points.iter().position(|x| x.2) {
+        Some(i) => i,
+        None => {
+            let (x, y) = mid(points[0], points[1 % n]);
+            out.push(Segment::MoveTo(x, y));
+            let mut current = (x, y);
+            for i in 1 ..= n {
+                let control = points[i % n];
+                let end = mid(control, points[(i + 1) % n]);
+                push_quad(out, current, (control.0, control.1), end);
+                current = end;
+            }
+            out.push(Segment::Close);
+            return;
+        }
+    };
+    let start = (points[first].0, points[first].1);
+    out.push(Segment::MoveTo(start.0, start.1));
+    let mut current = start;
+    let mut control: Option<(f64, f64)> = None;
+    for i in 1 ..= n {
+        let p = points[(first + i) % n];
+        match (p.2, control) {
+            (true, None) => {
+                out.push(Segment::LineTo(p.0, p.1));
+                current = (p.0, p.1);
+            }
+            (true, Some(c)) => {
+                push_quad(out, current, c, (p.0, p.1));
+                current = (p.0, p.1);
+                control = None;
+            }
+            (false, None) => control = Some((p.0, p.1)),
+            (false, Some(c)) => {
+                let end = ((c.0 + p.0) / 2., (c.1 + p.1) / 2.);
+                push_quad(out, current, c, end);
+                current = end;
+                control = Some((p.0, p.1));
+            }
+        }
+    }
+    out.push(Segment::Close);
+}
+
+fn push_quad(out: &mut Vec<Segment>, p0: (f64, f64), q: (f64, f64), p: (f64, f64)) {
+    out.push(Segment::CurveTo(
+        p0.0 + 2. / 3. * (q.0 - p0.0), p0.1 + 2. / 3. * (q.1 - p0.1),
+        p.0 + 2. / 3. * (q.0 - p.0), p.1 + 2. / 3. * (q.1 - p.1),
+        p.0, p.1,
+    ));
+}
+
+pub struct TrueType<'a> {
+    pub tables: HashMap<[u8; 4], &'a [u8]>,
+    pub units_per_em: u16,
+    pub num_glyphs: u16,
+    long_loca: bool,
+    h_metrics: usize,
+    names: Vec<Option<String>>,
+}
+
+impl<'a> TrueType<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<TrueType<'a>> {
+        let count = u16_at(data, 4)? as usize;
+        let mut tables = HashMap::new();
+        for i in 0 .. count {
+            let record = 12 + 16 * i;
+            let tag: [u8; 4] = data.get(record .. record + 4)?.try_into().ok()?;
+            let at = u32_at(data, record + 8)? as usize;
+            let length = u32_at(data, record + 12)? as usize;
+            if let Some(table) = data.get(at .. (at + length).min(data.len())) {
+                tables.insert(tag, table);
+            }
+        }
+        let head = tables.get(b"head");
+        let mut font = TrueType {
+            units_per_em: head.and_then(|x| u16_at(x, 18)).filter(|&x| x > 0).unwrap_or(1000),
+            long_loca: head.and_then(|x| i16_at(x, 50)) == Some(1),
+            num_glyphs: tables.get(b"maxp").and_then(|x| u16_at(x, 4)).unwrap_or(0),
+            h_metrics: tables.get(b"hhea").and_then(|x| u16_at(x, 34)).unwrap_or(0) as usize,
+            names: Vec::new(),
+            tables,
+        };
+        font.names = font.post_names();
+        Some(font)
+    }
+
+    pub fn table(&self, tag: &[u8; 4]) -> Option<&'a [u8]> {
+        self.tables.get(tag).cloned()
+    }
+
+    fn glyph_data(&self, gid: u16) -> Option<&'a [u8]> {
+        let loca = self.table(b"loca")?;
+        let gid = gid as usize;
+        let (start, end) = if self.long_loca {
+            (u32_at(loca, gid * 4)? as usize, u32_at(loca, gid * 4 + 4)? as usize)
+        } else {
+            (u16_at(loca, gid * 2)? as usize * 2, u16_at(loca, gid * 2 + 2)? as usize * 2)
+        };
+        self.table(b"glyf")?.get(start .. end).filter(|x| !x.is_empty())
+    }
+
+    // Glyph outline in font units, quadratic curves raised to cubic ones.
+    pub fn outline(&self, gid: u16) -> Vec<Segment> {
+        let mut ret = Vec::new();
+        self.add_outline(gid, [1., 0., 0., 1., 0., 0.], 0, &mut ret);
+        ret
+    }
+
+    fn add_outline(&self, gid: u16, m: [f64; 6], depth: usize, out: &mut Vec<Segment>) -> Option<()> {
+        let data = self.glyph_data(gid)?;
+        let contours = i16_at(data, 0)?;
+        if contours < 0 {
+            return if depth < 8 { self.add_composite(data, m, depth, out) } else { None };
+        }
+        let contours = contours as usize;
+        let ends = (0 .. contours).map(|i| u16_at(data, 10 + 2 * i).map(|x| x as usize)).collect::<Option<Vec<_>>>()?;
+        let count = ends.last().map_or(0, |x| x + 1);
+        let mut pos = 10 + 2 * contours;
+        pos += 2 + u16_at(data, pos)? as usize;
+        let mut flags = Vec::with_capacity(count);
+        while flags.len() < count {
+            let flag = *data.get(pos)?;
+            pos += 1;
+            flags.push(flag);
+            if flag & 8 != 0 {
+                let repeat = *data.get(pos)?;
+                pos += 1;
+                for _ in 0 .. repeat {
+                    flags.push(flag);
+                }
+            }
+        }
+        flags.truncate(count);
+        let mut coordinates = [Vec::with_capacity(count), Vec::with_capacity(count)];
+        for (axis, (short, same)) in [(2, 16), (4, 32)].into_iter().enumerate() {
+            let mut value = 0i32;
+            for &flag in &flags {
+                if flag & short != 0 {
+                    let delta = *data.get(pos)? as i32;
+                    pos += 1;
+                    value += if flag & same != 0 { delta } else { -delta };
+                } else if flag & same == 0 {
+                    value += i16_at(data, pos)? as i32;
+                    pos += 2;
+                }
+                coordinates[axis].push(value as f64);
+            }
+        }
+        let mut start = 0;
+        for end in ends {
+            let points = (start ..= end.min(count.saturating_sub(1))).map(|i| {
+                let (x, y) = (coordinates[0][i], coordinates[1][i]);
+                (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5], flags[i] & 1 != 0)
+            }).collect::<Vec<_>>();
+            contour_segments(&points, out);
+            start = end + 1;
+        }
+        Some(())
+    }
+
+    fn add_composite(&self, data: &[u8], m: [f64; 6], depth: usize, out: &mut Vec<Segment>) -> Option<()> {
+        let mut pos = 10;
+        loop {
+            let flags = u16_at(data, pos)?;
+            let gid = u16_at(data, pos + 2)?;
+            pos += 4;
+            let (dx, dy) = if flags & 1 != 0 {
+                pos += 4;
+                (i16_at(data, pos - 4)? as f64, i16_at(data, pos - 2)? as f64)
+            } else {
+                pos += 2;
+                (*data.get(pos - 2)? as i8 as f64, *data.get(pos - 1)? as i8 as f64)
+            };
+            // Point-matched components are placed at the origin.
+            let (dx, dy) = if flags & 2 != 0 { (dx, dy) } else { (0., 0.) };
+            let f2dot14 = |pos: usize| i16_at(data, pos).map(|x| x as f64 / 16384.);
+            let (a, b, c, d) = if flags & 8 != 0 {
+                pos += 2;
+                let s = f2dot14(pos - 2)?;
+                (s, 0., 0., s)
+            } else if flags & 0x40 != 0 {
+                pos += 4;
+                (f2dot14(pos - 4)?, 0., 0., f2dot14(pos - 2)?)
+            } else if flags & 0x80 != 0 {
+                pos += 8;
+                (f2dot14(pos - 8)?, f2dot14(pos - 6)?, f2dot14(pos - 4)?, f2dot14(pos - 2)?)
+            } else {
+                (1., 0., 0., 1.)
+            };
+            let component = [
+                a * m[0] + b * m[2], a * m[1] + b * m[3],
+                c * m[0] + d * m[2], c * m[1] + d * m[3],
+                dx * m[0] + dy * m[2] + m[4], dx * m[1] + dy * m[3] + m[5],
+            ];
+            self.add_outline(gid, component, depth + 1, out);
+            if flags & 0x20 == 0 {
+                return Some(());
+            }
+        }
+    }
+
+    pub fn advance(&self, gid: u16) -> Option<u16> {
+        let hmtx = self.table(b"hmtx")?;
+        let i = (gid as usize).min(self.h_metrics.checked_sub(1)?);
+        u16_at(hmtx, i * 4)
+    }
+
+    pub fn has_cmap(&self, platform: u16, encoding: u16) -> bool {
+        self.cmap_subtable(platform, encoding).is_some()
+    }
+
+    fn cmap_subtable(&self, platform: u16, encoding: u16) -> Option<&'a [u8]> {
+        let cmap = self.table(b"cmap")?;
+        (0 .. u16_at(cmap, 2)? as usize).find_map(|i| {
+            let record = 4 + 8 * i;
+            if u16_at(cmap, record)? == platform && u16_at(cmap, record + 2)? == encoding {
+                cmap.get(u32_at(cmap, record + 4)? as usize ..)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Glyph of `code` in the (platform, encoding) subtable, glyph 0 counting as missing.
+    pub fn cmap(&self, platform: u16, encoding: u16, code: u32) -> Option<u16> {
+        let table = self.cmap_subtable(platform, encoding)?;
+        let gid = match u16_at(table, 0)? {
+            0 => *table.get(6 + code as usize).filter(|_| code < 256)? as u16,
+            4 => {
+                let code = u16::try_from(code).ok()?;
+                let segments = u16_at(table, 6)? as usize;
+                let ends = 14;
+                let starts = 16 + segments;
+                let deltas = starts + segments;
+                let offsets = deltas + segments;
+                let i = (0 .. segments / 2).find(|&i| u16_at(table, ends + 2 * i).is_some_and(|x| x >= code))?;
+                let start = u16_at(table, starts + 2 * i)?;
+                if start > code {
+                    return None;
+                }
+                let delta = u16_at(table, deltas + 2 * i)?;
+                match u16_at(table, offsets + 2 * i)? {
+                    0 => code.wrapping_add(delta),
+                    offset => {
+                        let at = offsets + 2 * i + offset as usize + 2 * (code - start) as usize;
+                        match u16_at(table, at)? {
+                            0 => 0,
+                            gid => gid.wrapping_add(delta),
+                        }
+                    }
+                }
+            }
+            6 => {
+                let first = u16_at(table, 6)? as u32;
+                let count = u16_at(table, 8)? as u32;
+                if code < first || code >= first + count {
+                    return None;
+                }
+                u16_at(table, 10 + 2 * (code - first) as usize)?
+            }
+            12 => {
+                let groups = u32_at(table, 12)? as usize;
+                (0 .. groups).find_map(|i| {
+                    let group = 16 + 12 * i;
+                    let (start, end) = (u32_at(table, group)?, u32_at(table, group + 4)?);
+                    if (start ..= end).contains(&code) {
+                        Some((u32_at(table, group + 8)? + code - start) as u16)
+                    } else {
+                        None
+                    }
+                })?
+            }
+            _ => return None,
+        };
+        Some(gid).filter(|&x| x != 0)
+    }
+
+    fn post_names(&self) -> Vec<Option<String>> {
+        let Some(post) = self.table(b"post") else { return Vec::new() };
+        match u32_at(post, 0) {
+            Some(0x10000) => MAC_GLYPH_NAMES.iter().map(|x| Some(x.to_string())).collect(),
+            Some(0x20000) => {
+                let count = u16_at(post, 32).unwrap_or(0) as usize;
+                let mut strings = Vec::new();
+                let mut pos = 34 + 2 * count;
+                while let Some(&length) = post.get(pos) {
+                    let Some(bytes) = post.get(pos + 1 .. pos + 1 + length as usize) else { break };
+                    strings.push(String::from_utf8_lossy(bytes).into_owned());
+                    pos += 1 + length as usize;
+                }
+                (0 .. count).map(|i| {
+                    let index = u16_at(post, 34 + 2 * i)? as usize;
+                    match MAC_GLYPH_NAMES.get(index) {
+                        Some(name) => Some(name.to_string()),
+                        None => strings.get(index - MAC_GLYPH_NAMES.len()).cloned(),
+                    }
+                }).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn glyph_name(&self, gid: u16) -> Option<String> {
+        self.names.get(gid as usize).cloned().flatten()
+    }
+
+    pub fn glyph_names(&self) -> &[Option<String>] {
+        &self.names
+    }
+}
+
+pub const MAC_GLYPH_NAMES: &[&str] = &[
+    ".notdef", ".null", "nonmarkingreturn", "space", "exclam", "quotedbl", "numbersign", "dollar", "percent",
+    "ampersand", "quotesingle", "parenleft", "parenright", "asterisk", "plus", "comma", "hyphen", "period",
+    "slash", "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "colon",
+    "semicolon", "less", "equal", "greater", "question", "at", "A", "B", "C", "D", "E", "F", "G", "H", "I",
+    "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z", "bracketleft",
+    "backslash", "bracketright", "asciicircum", "underscore", "grave", "a", "b", "c", "d", "e", "f", "g", "h",
+    "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z", "braceleft",
+    "bar", "braceright", "asciitilde", "Adieresis", "Aring", "Ccedilla", "Eacute", "Ntilde", "Odieresis",
+    "Udieresis", "aacute", "agrave", "acircumflex", "adieresis", "atilde", "aring", "ccedilla", "eacute",
+    "egrave", "ecircumflex", "edieresis", "iacute", "igrave", "icircumflex", "idieresis", "ntilde", "oacute",
+    "ograve", "ocircumflex", "odieresis", "otilde", "uacute", "ugrave", "ucircumflex", "udieresis", "dagger",
+    "degree", "cent", "sterling", "section", "bullet", "paragraph", "germandbls", "registered", "copyright",
+    "trademark", "acute", "dieresis", "notequal", "AE", "Oslash", "infinity", "plusminus", "lessequal",
+    "greaterequal", "yen", "mu", "partialdiff", "summation", "product", "pi", "integral", "ordfeminine",
+    "ordmasculine", "Omega", "ae", "oslash", "questiondown", "exclamdown", "logicalnot", "radical", "florin",
+    "approxequal", "Delta", "guillemotleft", "guillemotright", "ellipsis", "nonbreakingspace", "Agrave",
+    "Atilde", "Otilde", "OE", "oe", "endash", "emdash", "quotedblleft", "quotedblright", "quoteleft",
+    "quoteright", "divide", "lozenge", "ydieresis", "Ydieresis", "fraction", "currency", "guilsinglleft",
+    "guilsinglright", "fi", "fl", "daggerdbl", "periodcentered", "quotesinglbase", "quotedblbase",
+    "perthousand", "Acircumflex", "Ecircumflex", "Aacute", "Edieresis", "Egrave", "Iacute", "Icircumflex",
+    "Idieresis", "Igrave", "Oacute", "Ocircumflex", "apple", "Ograve", "Uacute", "Ucircumflex", "Ugrave",
+    "dotlessi", "circumflex", "tilde", "macron", "breve", "dotaccent", "ring", "cedilla", "hungarumlaut",
+    "ogonek", "caron", "Lslash", "lslash", "Scaron", "scaron", "Zcaron", "zcaron", "brokenbar", "Eth", "eth",
+    "Yacute", "yacute", "Thorn", "thorn", "minus", "multiply", "onesuperior", "twosuperior", "threesuperior",
+    "onehalf", "onequarter", "threequarters", "franc", "Gbreve", "gbreve", "Idotaccent", "Scedilla",
+    "scedilla", "Cacute", "cacute", "Ccaron", "ccaron", "dcroat",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::otf::{build_sfnt, cmap};
+
+    #[test]
+    fn test_truetype() {
+        let mut head = vec![0; 54];
+        head[18 .. 20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0; 36];
+        hhea[35] = 2;
+        let mut glyf = vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 1, 1, 0];
+        glyf.extend([0, 0, 0, 100, 255, 206, 0, 0, 0, 0, 0, 100, 0]);
+        let mut tables = [
+            (*b"head", head),
+            (*b"maxp", vec![0, 0, 0x50, 0, 0, 2]),
+            (*b"hhea", hhea),
+            (*b"hmtx", vec![1, 244, 0, 0, 2, 88, 0, 0]),
+            (*b"loca", vec![0, 0, 0, 0, 0, 15]),
+            (*b"glyf", glyf),
+            (*b"cmap", cmap(&[(0x41, 1)])),
+        ];
+        let data = build_sfnt([0, 1, 0, 0], &mut tables);
+        let font = TrueType::parse(&data).unwrap();
+        assert_eq!((font.units_per_em, font.num_glyphs), (1000, 2));
+        assert_eq!(font.advance(1), Some(600));
+        assert_eq!(font.cmap(3, 1, 0x41), Some(1));
+        assert_eq!(font.cmap(3, 1, 0x42), None);
+        assert!(font.outline(0).is_empty());
+        let outline = font.outline(1);
+        assert_eq!(outline[.. 2], [Segment::MoveTo(0., 0.), Segment::LineTo(100., 0.)]);
+        let Segment::CurveTo(x1, y1, x2, y2, x3, y3) = outline[2] else { panic!() };
+        assert!((x1 - 200. / 3.).abs() < 1e-9 && (y1 - 200. / 3.).abs() < 1e-9);
+        assert!((x2 - 100. / 3.).abs() < 1e-9 && (y2 - 200. / 3.).abs() < 1e-9);
+        assert_eq!((x3, y3), (0., 0.));
+        assert_eq!(outline[3], Segment::Close);
+        assert_eq!(MAC_GLYPH_NAMES.len(), 258);
+    }
+}
@@ -0,0 +1,33 @@
+// Readers of big-endian integers in binary data, and of values of PDF dictionaries,
+// shared by the modules decoding fonts, images, colors and functions.
+
+use super::parser::{Value, PDF};
+
+pub fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos .. pos + 2)?.try_into().ok()?))
+}
+
+pub fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos .. pos + 4)?.try_into().ok()?))
+}
+
+pub fn number(pdf: &PDF, value: Option<&Value>) -> Option<f64> {
+    match pdf.resolve(value?) {
+        &Value::Number(n) => Some(n),
+        _ => None,
+    }
+}
+
+pub fn numbers(pdf: &PDF, value: Option<&Value>) -> Option<Vec<f64>> {
+    match pdf.resolve(value?) {
+        Value::List(list) => list.iter().map(|x| number(pdf, Some(x))).collect(),
+        _ => None,
+    }
+}
+
+pub fn flag(pdf: &PDF, value: Option<&Value>) -> Option<bool> {
+    match pdf.resolve(value?) {
+        &Value::Bool(x) => Some(x),
+        _ => None,
+    }
+}